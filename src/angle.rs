@@ -0,0 +1,105 @@
+use core::ops;
+
+// An angle that knows its unit, so that degrees (as people write them in scenes) and radians
+// (as the math wants them) can't be mixed up. Make one with deg or rad; there is deliberately no
+// conversion from a plain f64, since it can't tell which unit it is in.
+#[derive(Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+pub struct Angle {
+    radians: f64
+}
+
+pub fn deg(degrees: f64) -> Angle {
+    Angle::from_degrees(degrees)
+}
+
+pub fn rad(radians: f64) -> Angle {
+    Angle::from_radians(radians)
+}
+
+impl Angle {
+    pub const fn from_radians(radians: f64) -> Self {
+        Self { radians }
+    }
+
+    pub fn from_degrees(degrees: f64) -> Self {
+        Self { radians: degrees.to_radians() }
+    }
+
+    pub fn radians(self) -> f64 {
+        self.radians
+    }
+
+    pub fn degrees(self) -> f64 {
+        self.radians.to_degrees()
+    }
+
+    pub fn sin(self) -> f64 {
+        self.radians.sin()
+    }
+
+    pub fn cos(self) -> f64 {
+        self.radians.cos()
+    }
+
+    pub fn tan(self) -> f64 {
+        self.radians.tan()
+    }
+}
+
+impl ops::Add for Angle {
+    type Output = Angle;
+    fn add(self, rhs: Angle) -> Angle {
+        Angle::from_radians(self.radians + rhs.radians)
+    }
+}
+
+impl ops::Sub for Angle {
+    type Output = Angle;
+    fn sub(self, rhs: Angle) -> Angle {
+        Angle::from_radians(self.radians - rhs.radians)
+    }
+}
+
+impl ops::Neg for Angle {
+    type Output = Angle;
+    fn neg(self) -> Angle {
+        Angle::from_radians(-self.radians)
+    }
+}
+
+impl ops::Mul<f64> for Angle {
+    type Output = Angle;
+    fn mul(self, rhs: f64) -> Angle {
+        Angle::from_radians(self.radians * rhs)
+    }
+}
+
+impl ops::Div<f64> for Angle {
+    type Output = Angle;
+    fn div(self, rhs: f64) -> Angle {
+        Angle::from_radians(self.radians / rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, PI};
+    use crate::approx_eq;
+
+    #[test]
+    fn degrees_and_radians() {
+        assert!(approx_eq(deg(180.).radians(), PI));
+        assert!(approx_eq(rad(FRAC_PI_2).degrees(), 90.));
+        assert_eq!(Angle::from_radians(PI), rad(PI));
+        assert!(deg(10.) < deg(20.));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert!(approx_eq((deg(30.) + deg(60.)).degrees(), 90.));
+        assert!(approx_eq((deg(30.) - deg(60.)).degrees(), -30.));
+        assert!(approx_eq((-deg(45.) * 2.).degrees(), -90.));
+        assert!(approx_eq((deg(90.) / 3.).sin(), 0.5));
+    }
+}
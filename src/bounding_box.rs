@@ -0,0 +1,194 @@
+use super::matrix::Matrix;
+use super::ray::Ray;
+use super::tuple::Tuple;
+
+// An axis aligned box. A shape that extends forever in some direction (a plane) has infinite
+// bounds, and anything containing it is unbounded too, which is why unbounded objects are
+// kept apart from the others (see Bvh).
+#[derive(Debug, Copy, Clone)]
+pub struct BoundingBox {
+    pub min: Tuple,
+    pub max: Tuple
+}
+
+// Tuple equality subtracts, which doesn't work for infinite coordinates
+impl PartialEq for BoundingBox {
+    fn eq(&self, other: &Self) -> bool {
+        let same = |a: f64, b: f64| a == b || super::approx_eq(a, b);
+        same(self.min.x, other.min.x) && same(self.min.y, other.min.y) && same(self.min.z, other.min.z) &&
+        same(self.max.x, other.max.x) && same(self.max.y, other.max.y) && same(self.max.z, other.max.z)
+    }
+}
+
+impl BoundingBox {
+    pub fn new(min: Tuple, max: Tuple) -> Self {
+        Self { min, max }
+    }
+
+    // Contains nothing, adding a point to it gives a box around just that point
+    pub fn empty() -> Self {
+        Self {
+            min: Tuple::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Tuple::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY)
+        }
+    }
+
+    // Contains everything
+    pub fn infinite() -> Self {
+        Self {
+            min: Tuple::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Tuple::point(f64::INFINITY, f64::INFINITY, f64::INFINITY)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    // False if the box extends forever in any direction. An empty box is bounded.
+    pub fn is_bounded(&self) -> bool {
+        self.is_empty() || [self.min.x, self.min.y, self.min.z, self.max.x, self.max.y, self.max.z].iter().all(|c| c.is_finite())
+    }
+
+    pub fn add_point(&mut self, p: Tuple) {
+        self.min = Tuple::point(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = Tuple::point(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+
+    pub fn merge(&self, other: &BoundingBox) -> BoundingBox {
+        if other.is_empty() {
+            return *self;
+        }
+        let mut merged = *self;
+        merged.add_point(other.min);
+        merged.add_point(other.max);
+        merged
+    }
+
+    // Proportional to the chance that a random ray hits the box, which is what the surface
+    // area heuristic weighs
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+        let d = self.max - self.min;
+        2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn centroid(&self) -> Tuple {
+        Tuple::point((self.min.x + self.max.x) / 2., (self.min.y + self.max.y) / 2., (self.min.z + self.max.z) / 2.)
+    }
+
+    pub fn contains_point(&self, p: Tuple) -> bool {
+        self.min.x <= p.x && p.x <= self.max.x &&
+        self.min.y <= p.y && p.y <= self.max.y &&
+        self.min.z <= p.z && p.z <= self.max.z
+    }
+
+    // The box around the eight transformed corners. Unbounded boxes stay unbounded, since
+    // transforming infinite coordinates gives NaN.
+    pub fn transform(&self, m: &Matrix) -> BoundingBox {
+        if self.is_empty() {
+            return *self;
+        }
+        if !self.is_bounded() {
+            return BoundingBox::infinite();
+        }
+        let mut result = BoundingBox::empty();
+        for &x in &[self.min.x, self.max.x] {
+            for &y in &[self.min.y, self.max.y] {
+                for &z in &[self.min.z, self.max.z] {
+                    result.add_point(*m * Tuple::point(x, y, z));
+                }
+            }
+        }
+        result
+    }
+
+    // Whether the line of the ray passes through the box. Both directions count, since
+    // intersections behind the ray origin are needed to work out refraction.
+    pub fn intersects(&self, ray: &Ray) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let mut t_min = f64::NEG_INFINITY;
+        let mut t_max = f64::INFINITY;
+        let axes = [
+            (ray.origin.x, ray.direction.x, self.min.x, self.max.x),
+            (ray.origin.y, ray.direction.y, self.min.y, self.max.y),
+            (ray.origin.z, ray.direction.z, self.min.z, self.max.z)
+        ];
+        for &(origin, direction, min, max) in axes.iter() {
+            if direction == 0. {
+                if origin < min || origin > max {
+                    return false;
+                }
+                continue;
+            }
+            let t1 = (min - origin) / direction;
+            let t2 = (max - origin) / direction;
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+        t_min <= t_max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plane::Plane;
+    use crate::shape::Shape;
+
+    #[test]
+    fn add_points_to_empty_box() {
+        let mut b = BoundingBox::empty();
+        assert!(b.is_empty() && b.is_bounded());
+
+        b.add_point(Tuple::point(-5., 2., 0.));
+        b.add_point(Tuple::point(7., 0., -3.));
+        assert_eq!(b, BoundingBox::new(Tuple::point(-5., 0., -3.), Tuple::point(7., 2., 0.)));
+    }
+
+    #[test]
+    fn surface_area_and_centroid() {
+        let b = BoundingBox::new(Tuple::point(-1., 0., 2.), Tuple::point(1., 3., 6.));
+
+        assert_eq!(b.surface_area(), 52.);
+        assert_eq!(b.centroid(), Tuple::point(0., 1.5, 4.));
+        assert_eq!(BoundingBox::empty().surface_area(), 0.);
+    }
+
+    #[test]
+    fn infinite_box_is_unbounded() {
+        let b = BoundingBox::infinite();
+
+        assert!(!b.is_bounded() && !b.is_empty());
+        assert_eq!(b, BoundingBox::infinite());
+        assert!(b.contains_point(Tuple::point(1e300, -4., 0.)));
+        assert_eq!(b.merge(&BoundingBox::new(Tuple::point(0., 0., 0.), Tuple::point(1., 1., 1.))), b);
+    }
+
+    #[test]
+    fn transform_bounded_and_unbounded_boxes() {
+        let b = BoundingBox::new(Tuple::point(-1., -1., -1.), Tuple::point(1., 1., 1.));
+        let m = Matrix::translation(1., 0., 0.) * Matrix::scaling(2., 1., 1.);
+
+        assert_eq!(b.transform(&m), BoundingBox::new(Tuple::point(-1., -1., -1.), Tuple::point(3., 1., 1.)));
+        assert_eq!(Plane::new(None, None).bounds().transform(&m), BoundingBox::infinite());
+    }
+
+    #[test]
+    fn line_through_box() {
+        let b = BoundingBox::new(Tuple::point(5., -2., 0.), Tuple::point(11., 4., 7.));
+        let line_hits = |o: Tuple, d: Tuple| b.intersects(&Ray::new(o, d));
+
+        assert!(line_hits(Tuple::point(15., 1., 2.), Tuple::vector(-1., 0., 0.)));
+        assert!(line_hits(Tuple::point(7., 0., 8.), Tuple::vector(0.2, 0., 1.)));
+        // Behind the origin
+        assert!(line_hits(Tuple::point(0., 0., 3.), Tuple::vector(-1., 0., 0.)));
+        assert!(!line_hits(Tuple::point(8., 6., -1.), Tuple::vector(0., 0., 1.)));
+        assert!(!line_hits(Tuple::point(0., 0., 0.), Tuple::vector(0., 1., 1.)));
+        assert!(!BoundingBox::empty().intersects(&Ray::new(Tuple::point(0., 0., 0.), Tuple::vector(1., 0., 0.))));
+    }
+}
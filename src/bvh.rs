@@ -0,0 +1,686 @@
+use std::thread;
+
+use super::bounding_box::BoundingBox;
+use super::ray::Ray;
+use super::shape::Shape;
+use super::tuple::Tuple;
+
+pub const DEFAULT_BVH_BINS: usize = 12;
+// Leaves hold at most this many objects, unless their centers coincide so they can't be split
+const MAX_LEAF_SIZE: usize = 4;
+// The cost of visiting a node relative to intersecting an object
+const TRAVERSAL_COST: f64 = 0.125;
+// Below this many objects per thread, working out their bounds, codes or subtrees isn't worth
+// starting threads for
+const MIN_OBJECTS_PER_THREAD: usize = 4096;
+// Bits per axis in a Morton code
+const MORTON_BITS: u32 = 21;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum BvhSplit {
+    // Halve the objects along the axis where their centers are furthest apart
+    Median,
+    // The surface area heuristic: among evenly spaced candidate planes, split where the chance of
+    // a ray hitting each half times the number of objects in it is the least
+    #[default]
+    SurfaceArea,
+    // A linear BVH: objects are sorted along a Morton (Z order) curve through their centers and
+    // split where the codes first differ. Much faster to build than SurfaceArea, for very large
+    // scenes, but rays visit more nodes.
+    Morton
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BvhOptions {
+    pub split: BvhSplit,
+    // Candidate split planes per axis for the surface area heuristic
+    pub bins: usize,
+    // Visit the nearer child first, going by the sign of the ray direction along the axis the
+    // node was split on, so that objects come roughly front to back and shadow rays find what
+    // blocks them sooner
+    pub ordered_traversal: bool
+}
+
+impl Default for BvhOptions {
+    fn default() -> Self {
+        Self { split: BvhSplit::SurfaceArea, bins: DEFAULT_BVH_BINS, ordered_traversal: true }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Leaf { bounds: BoundingBox, objects: Vec<usize> },
+    // children[0] holds the objects with the smaller centers along axis
+    Interior { bounds: BoundingBox, axis: usize, children: [usize; 2] }
+}
+
+impl Node {
+    fn bounds(&self) -> &BoundingBox {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Item {
+    index: usize,
+    bounds: BoundingBox,
+    centroid: Tuple,
+    // Only for BvhSplit::Morton
+    code: u64
+}
+
+fn axis_value(t: Tuple, axis: usize) -> f64 {
+    match axis {
+        0 => t.x,
+        1 => t.y,
+        _ => t.z
+    }
+}
+
+fn centroid_bounds(items: &[Item]) -> BoundingBox {
+    let mut b = BoundingBox::empty();
+    for i in items {
+        b.add_point(i.centroid);
+    }
+    b
+}
+
+// How many threads to share the work on count objects between
+fn thread_count(count: usize) -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get()).min(count / MIN_OBJECTS_PER_THREAD).max(1)
+}
+
+// The parent space bounds of all objects, worked out on several threads for large scenes
+fn object_bounds<S: AsRef<dyn Shape> + Sync>(objects: &[S], threads: usize) -> Vec<BoundingBox> {
+    if threads == 1 {
+        return objects.iter().map(|o| o.as_ref().parent_space_bounds()).collect();
+    }
+    let chunk_size = objects.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let handles: Vec<_> = objects.chunks(chunk_size).map(|chunk| {
+            scope.spawn(move || chunk.iter().map(|o| o.as_ref().parent_space_bounds()).collect::<Vec<BoundingBox>>())
+        }).collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    })
+}
+
+// Calls f on threads parts of items at the same time, returning what it returns for each part
+fn in_parallel_chunks<T: Send, R: Send, F: Fn(&mut [T]) -> R + Sync>(items: &mut [T], threads: usize, f: F) -> Vec<R> {
+    if threads <= 1 || items.is_empty() {
+        return vec![f(items)];
+    }
+    let chunk_size = items.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks_mut(chunk_size).map(|chunk| scope.spawn(move || f(chunk))).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+// Gives every item its Morton code within the bounds of all their centers
+fn assign_morton_codes(items: &mut [Item], threads: usize) {
+    let centers = in_parallel_chunks(items, threads, |chunk| centroid_bounds(chunk))
+        .into_iter()
+        .fold(BoundingBox::empty(), |b, chunk_bounds| b.merge(&chunk_bounds));
+    in_parallel_chunks(items, threads, |chunk| {
+        for i in chunk.iter_mut() {
+            i.code = morton_code(i.centroid, &centers);
+        }
+    });
+}
+
+// Sorts items by Morton code: the two halves are sorted on threads of their own, down to
+// chunks of one thread each, and then merged
+fn sort_by_code(items: &mut [Item], threads: usize) {
+    if threads <= 1 || items.len() < 2 * MIN_OBJECTS_PER_THREAD {
+        items.sort_unstable_by_key(|i| i.code);
+        return;
+    }
+    let middle = items.len() / 2;
+    let (left, right) = items.split_at_mut(middle);
+    thread::scope(|scope| {
+        scope.spawn(|| sort_by_code(right, threads - threads / 2));
+        sort_by_code(left, threads / 2);
+    });
+    let mut merged = Vec::with_capacity(items.len());
+    let (mut l, mut r) = (0, middle);
+    while l < middle && r < items.len() {
+        if items[r].code < items[l].code {
+            merged.push(items[r]);
+            r += 1;
+        } else {
+            merged.push(items[l]);
+            l += 1;
+        }
+    }
+    merged.extend_from_slice(&items[l..middle]);
+    merged.extend_from_slice(&items[r..]);
+    items.copy_from_slice(&merged);
+}
+
+// Every other bit of the low 21 bits of v moved up to make room for two more, e g 0b111 becomes
+// 0b1001001
+fn spread_bits(v: u64) -> u64 {
+    let mut x = v & 0x1f_ffff;
+    x = (x | x << 32) & 0x001f_0000_0000_ffff;
+    x = (x | x << 16) & 0x001f_0000_ff00_00ff;
+    x = (x | x << 8) & 0x100f_00f0_0f00_f00f;
+    x = (x | x << 4) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x << 2) & 0x1249_2492_4924_9249;
+    x
+}
+
+// Interleaves the bits of the position of p within bounds, x highest. Points close together
+// along the resulting curve are close together in space.
+fn morton_code(p: Tuple, bounds: &BoundingBox) -> u64 {
+    let scale = ((1u64 << MORTON_BITS) - 1) as f64;
+    let quantize = |c: f64, min: f64, max: f64| {
+        if max > min { ((c - min) / (max - min) * scale).clamp(0., scale) as u64 } else { 0 }
+    };
+    let x = quantize(p.x, bounds.min.x, bounds.max.x);
+    let y = quantize(p.y, bounds.min.y, bounds.max.y);
+    let z = quantize(p.z, bounds.min.z, bounds.max.z);
+    spread_bits(x) << 2 | spread_bits(y) << 1 | spread_bits(z)
+}
+
+// A bounding volume hierarchy over the objects of a scene, for finding the objects a ray may hit
+// without testing all of them. Objects are referred to by their index in the list the tree was
+// built from. Unbounded objects (planes) would make the bounds of every node containing them
+// infinite, so they are kept out of the tree in a list of their own and are candidates for
+// every ray.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    unbounded: Vec<usize>,
+    object_count: usize,
+    options: BvhOptions
+}
+
+impl Bvh {
+    // Large scenes are built on as many threads as there are cores: the bounds and Morton codes
+    // of the objects are worked out and sorted in parts, and subtrees with enough objects in
+    // them are built on threads of their own. The tree is the same however many threads build it.
+    pub fn new<S: AsRef<dyn Shape> + Sync>(objects: &[S], options: BvhOptions) -> Self {
+        Self::new_on_threads(objects, options, thread_count(objects.len()))
+    }
+
+    fn new_on_threads<S: AsRef<dyn Shape> + Sync>(objects: &[S], options: BvhOptions, threads: usize) -> Self {
+        let mut items = vec![];
+        let mut unbounded = vec![];
+        for (index, bounds) in object_bounds(objects, threads).into_iter().enumerate() {
+            if bounds.is_bounded() {
+                items.push(Item { index, bounds, centroid: bounds.centroid(), code: 0 });
+            } else {
+                unbounded.push(index);
+            }
+        }
+        if options.split == BvhSplit::Morton {
+            assign_morton_codes(&mut items, threads);
+            sort_by_code(&mut items, threads);
+        }
+        let mut nodes = vec![];
+        if !items.is_empty() {
+            build(&mut nodes, &mut items, &options, threads);
+        }
+        Self { nodes, unbounded, object_count: objects.len(), options }
+    }
+
+    // The number of objects the tree was built from, bounded or not
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    // The objects kept out of the tree
+    pub fn unbounded_count(&self) -> usize {
+        self.unbounded.len()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn options(&self) -> BvhOptions {
+        self.options
+    }
+
+    // Estimated work per ray, as the expected number of objects intersected plus the weighted
+    // number of nodes visited by a random ray that hits the root. Lower is better; this is what
+    // the surface area heuristic minimizes, one split at a time.
+    pub fn expected_cost(&self) -> f64 {
+        let root_area = match self.nodes.first() {
+            Some(root) if root.bounds().surface_area() > 0. => root.bounds().surface_area(),
+            _ => return self.unbounded.len() as f64
+        };
+        self.unbounded.len() as f64 + self.nodes.iter().map(|n| {
+            let p = n.bounds().surface_area() / root_area;
+            match n {
+                Node::Leaf { objects, .. } => p * objects.len() as f64,
+                Node::Interior { .. } => p * TRAVERSAL_COST
+            }
+        }).sum::<f64>()
+    }
+
+    // Updates the bounds of every node after objects have moved, keeping the tree as it is. Much
+    // cheaper than building a new tree, but the tree gets worse the further objects move from
+    // where they were when it was built; expected_cost tells when a rebuild is worth it.
+    // objects must be the objects the tree was built from, in the same order.
+    pub fn refit<S: AsRef<dyn Shape>>(&mut self, objects: &[S]) {
+        assert_eq!(objects.len(), self.object_count, "a bvh can only be refitted to the objects it was built from");
+        // Children come after their parent, so going backwards updates them first
+        for n in (0..self.nodes.len()).rev() {
+            let refitted = match &self.nodes[n] {
+                Node::Leaf { objects: indices, .. } => indices.iter().fold(BoundingBox::empty(), |b, &i| b.merge(&objects[i].as_ref().parent_space_bounds())),
+                Node::Interior { children, .. } => self.nodes[children[0]].bounds().merge(self.nodes[children[1]].bounds())
+            };
+            match &mut self.nodes[n] {
+                Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => *bounds = refitted
+            }
+        }
+    }
+
+    // Indices of the objects whose bounds the line of ray passes through, unbounded objects first
+    pub fn candidates(&self, ray: &Ray) -> Vec<usize> {
+        let mut candidates = vec![];
+        self.any_candidate(ray, |i| { candidates.push(i); false });
+        candidates
+    }
+
+    // Calls f with the candidates in the same order as candidates() lists them, until it
+    // returns true. Returns whether it did, without collecting the candidates, so that e g a
+    // shadow ray can stop at the first object in the way.
+    pub fn any_candidate<F: FnMut(usize) -> bool>(&self, ray: &Ray, mut f: F) -> bool {
+        if self.unbounded.iter().any(|&i| f(i)) {
+            return true;
+        }
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let mut stack = vec![0];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !node.bounds().intersects(ray) {
+                continue;
+            }
+            match node {
+                Node::Leaf { objects, .. } => if objects.iter().any(|&i| f(i)) {
+                    return true;
+                },
+                Node::Interior { axis, children, .. } => {
+                    // The last one pushed is visited first
+                    if self.options.ordered_traversal && axis_value(ray.direction, *axis) < 0. {
+                        stack.push(children[0]);
+                        stack.push(children[1]);
+                    } else {
+                        stack.push(children[1]);
+                        stack.push(children[0]);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+}
+
+// Adds the node for items, and the nodes below it, to nodes, returning its index. With more than
+// one thread and enough items, the second child is built into nodes of its own on another thread
+// and appended after the first, so the nodes come in the same order as when built on one thread.
+fn build(nodes: &mut Vec<Node>, items: &mut [Item], options: &BvhOptions, threads: usize) -> usize {
+    let bounds = items.iter().fold(BoundingBox::empty(), |b, i| b.merge(&i.bounds));
+    let index = nodes.len();
+    let split = match options.split {
+        BvhSplit::Median => median_split(items),
+        BvhSplit::SurfaceArea => surface_area_split(items, &bounds, options.bins.max(2)),
+        BvhSplit::Morton => morton_split(items)
+    };
+    match split {
+        None => nodes.push(Node::Leaf { bounds, objects: items.iter().map(|i| i.index).collect() }),
+        Some((axis, middle)) => {
+            // Replaced once the children have been built
+            nodes.push(Node::Leaf { bounds, objects: vec![] });
+            let (left, right) = items.split_at_mut(middle);
+            let children = if threads > 1 && left.len().min(right.len()) >= MIN_OBJECTS_PER_THREAD {
+                let mut right_nodes = vec![];
+                let left_child = thread::scope(|scope| {
+                    scope.spawn(|| build(&mut right_nodes, right, options, threads - threads / 2));
+                    build(nodes, left, options, threads / 2)
+                });
+                let offset = nodes.len();
+                nodes.extend(right_nodes.into_iter().map(|n| match n {
+                    Node::Interior { bounds, axis, children } => Node::Interior { bounds, axis, children: [children[0] + offset, children[1] + offset] },
+                    leaf => leaf
+                }));
+                [left_child, offset]
+            } else {
+                [build(nodes, left, options, threads), build(nodes, right, options, threads)]
+            };
+            nodes[index] = Node::Interior { bounds, axis, children };
+        }
+    }
+    index
+}
+
+// Reorders items so that the first part goes into one child and the rest into the other,
+// returning the axis and the size of the first part, or None if items should be a leaf
+fn median_split(items: &mut [Item]) -> Option<(usize, usize)> {
+    if items.len() <= MAX_LEAF_SIZE {
+        return None;
+    }
+    let extent = {
+        let b = centroid_bounds(items);
+        b.max - b.min
+    };
+    let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+    if axis_value(extent, axis) <= 0. {
+        return None;
+    }
+    items.sort_by(|a, b| axis_value(a.centroid, axis).total_cmp(&axis_value(b.centroid, axis)));
+    Some((axis, items.len() / 2))
+}
+
+// items are sorted by Morton code. Splits at the highest bit where the codes differ, which halves
+// the space along the axis of that bit, or in the middle if all codes are the same.
+fn morton_split(items: &mut [Item]) -> Option<(usize, usize)> {
+    if items.len() <= MAX_LEAF_SIZE {
+        return None;
+    }
+    let first = items[0].code;
+    let last = items[items.len() - 1].code;
+    if first == last {
+        return Some((0, items.len() / 2));
+    }
+    let bit = 63 - (first ^ last).leading_zeros();
+    let middle = items.partition_point(|i| i.code & (1 << bit) == 0);
+    // x is in the highest of each group of three bits
+    let axis = match bit % 3 {
+        2 => 0,
+        1 => 1,
+        _ => 2
+    };
+    Some((axis, middle))
+}
+
+fn surface_area_split(items: &mut [Item], bounds: &BoundingBox, bins: usize) -> Option<(usize, usize)> {
+    if items.len() == 1 {
+        return None;
+    }
+    let area = bounds.surface_area().max(f64::MIN_POSITIVE);
+    let centers = centroid_bounds(items);
+    let bin_of = |item: &Item, axis: usize| {
+        let min = axis_value(centers.min, axis);
+        let extent = axis_value(centers.max, axis) - min;
+        (((axis_value(item.centroid, axis) - min) / extent * bins as f64) as usize).min(bins - 1)
+    };
+
+    // (cost, axis, first bin of the second child)
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if axis_value(centers.max, axis) - axis_value(centers.min, axis) <= 0. {
+            continue;
+        }
+        let mut counts = vec![0; bins];
+        let mut boxes = vec![BoundingBox::empty(); bins];
+        for i in items.iter() {
+            let b = bin_of(i, axis);
+            counts[b] += 1;
+            boxes[b] = boxes[b].merge(&i.bounds);
+        }
+        // Area and count of everything after each split, swept from the right
+        let mut right = vec![(0., 0); bins];
+        let mut right_box = BoundingBox::empty();
+        let mut right_count = 0;
+        for b in (1..bins).rev() {
+            right_box = right_box.merge(&boxes[b]);
+            right_count += counts[b];
+            right[b] = (right_box.surface_area(), right_count);
+        }
+        let mut left_box = BoundingBox::empty();
+        let mut left_count = 0;
+        for b in 1..bins {
+            left_box = left_box.merge(&boxes[b - 1]);
+            left_count += counts[b - 1];
+            let (right_area, right_count) = right[b];
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST + (left_box.surface_area() * left_count as f64 + right_area * right_count as f64) / area;
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+
+    let (cost, axis, split_bin) = best?;
+    if items.len() <= MAX_LEAF_SIZE && cost >= items.len() as f64 {
+        return None;
+    }
+    items.sort_by_key(|i| bin_of(i, axis) >= split_bin);
+    Some((axis, items.iter().filter(|i| bin_of(i, axis) < split_bin).count()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::plane::Plane;
+    use crate::rng::Rng;
+    use crate::sphere::Sphere;
+    use crate::shape::BoxShape;
+
+    fn sphere_at(x: f64, y: f64, z: f64, radius: f64) -> BoxShape {
+        Sphere::new_boxed(None, Some(Matrix::translation(x, y, z) * Matrix::scaling(radius, radius, radius)))
+    }
+
+    // A dense cluster of small spheres next to a few big, spread out ones
+    fn irregular_scene() -> Vec<BoxShape> {
+        let mut rng = Rng::new(11);
+        let mut objects: Vec<BoxShape> = (0..200).map(|_| {
+            sphere_at(rng.next_f64() * 2., rng.next_f64() * 2., rng.next_f64() * 2., 0.05)
+        }).collect();
+        for i in 0..6 {
+            objects.push(sphere_at(20. + 15. * i as f64, 0., 0., 3.));
+        }
+        objects
+    }
+
+    fn brute_force_hits(objects: &[BoxShape], ray: Ray) -> Vec<usize> {
+        (0..objects.len()).filter(|&i| !objects[i].intersect(ray).is_empty()).collect()
+    }
+
+    fn random_ray(rng: &mut Rng) -> Ray {
+        let origin = Tuple::point(rng.next_f64() * 120. - 10., rng.next_f64() * 10. - 5., -10.);
+        let target = Tuple::point(rng.next_f64() * 3., rng.next_f64() * 3., rng.next_f64() * 3.);
+        Ray::new(origin, target - origin)
+    }
+
+    #[test]
+    fn candidates_include_every_object_hit() {
+        let objects = irregular_scene();
+        let mut rng = Rng::new(5);
+        for split in &[BvhSplit::Median, BvhSplit::SurfaceArea, BvhSplit::Morton] {
+            let bvh = Bvh::new(&objects, BvhOptions { split: *split, ..BvhOptions::default() });
+            for _ in 0..200 {
+                let ray = random_ray(&mut rng);
+                let candidates = bvh.candidates(&ray);
+                assert!(brute_force_hits(&objects, ray).iter().all(|i| candidates.contains(i)));
+            }
+        }
+    }
+
+    #[test]
+    fn any_candidate_stops_at_first_match() {
+        let objects = irregular_scene();
+        let bvh = Bvh::new(&objects, BvhOptions::default());
+        let ray = Ray::new(Tuple::point(-100., 0., 0.), Tuple::vector(1., 0., 0.));
+        let candidates = bvh.candidates(&ray);
+        let mut visited = vec![];
+
+        assert!(candidates.len() > 1);
+        assert!(bvh.any_candidate(&ray, |i| { visited.push(i); i == candidates[1] }));
+        assert_eq!(visited, candidates[..2]);
+        assert!(!bvh.any_candidate(&ray, |_| false));
+    }
+
+    #[test]
+    fn morton_codes_interleave_axes() {
+        let unit = BoundingBox::new(Tuple::point(0., 0., 0.), Tuple::point(1., 1., 1.));
+
+        assert_eq!(spread_bits(0b111), 0b1001001);
+        assert_eq!(morton_code(Tuple::point(0., 0., 0.), &unit), 0);
+        assert_eq!(morton_code(Tuple::point(1., 0., 0.), &unit), spread_bits(0x1f_ffff) << 2);
+        assert_eq!(morton_code(Tuple::point(1., 1., 1.), &unit), (1 << 63) - 1);
+        assert!(morton_code(Tuple::point(0.1, 0.1, 0.1), &unit) < morton_code(Tuple::point(0.6, 0.1, 0.1), &unit));
+    }
+
+    #[test]
+    fn morton_tree_finds_every_object_hit() {
+        let objects = irregular_scene();
+        let bvh = Bvh::new(&objects, BvhOptions { split: BvhSplit::Morton, ..BvhOptions::default() });
+        let mut rng = Rng::new(21);
+        for _ in 0..200 {
+            let ray = random_ray(&mut rng);
+            let candidates = bvh.candidates(&ray);
+            assert!(brute_force_hits(&objects, ray).iter().all(|i| candidates.contains(i)));
+        }
+        assert!(bvh.expected_cost() < objects.len() as f64 / 4.);
+    }
+
+    fn scattered_spheres(count: usize) -> Vec<BoxShape> {
+        let mut rng = Rng::new(2);
+        (0..count).map(|_| {
+            sphere_at(rng.next_f64() * 100., rng.next_f64() * 100., rng.next_f64() * 100., 0.1)
+        }).collect()
+    }
+
+    #[test]
+    fn bounds_of_many_objects_are_worked_out_in_parallel() {
+        let objects = scattered_spheres(3 * MIN_OBJECTS_PER_THREAD);
+        let expected: Vec<BoundingBox> = objects.iter().map(|o| o.as_ref().parent_space_bounds()).collect();
+
+        assert_eq!(object_bounds(&objects, 3), expected);
+    }
+
+    #[test]
+    fn codes_sorted_in_parallel_are_sorted() {
+        let objects = scattered_spheres(5 * MIN_OBJECTS_PER_THREAD);
+        let mut items: Vec<Item> = object_bounds(&objects, 1).into_iter().enumerate().map(|(index, bounds)| {
+            Item { index, bounds, centroid: bounds.centroid(), code: 0 }
+        }).collect();
+        assign_morton_codes(&mut items, 4);
+        let mut expected: Vec<u64> = items.iter().map(|i| i.code).collect();
+        expected.sort_unstable();
+        sort_by_code(&mut items, 4);
+
+        assert_eq!(items.iter().map(|i| i.code).collect::<Vec<u64>>(), expected);
+    }
+
+    #[test]
+    fn tree_built_on_several_threads_is_the_same_as_on_one() {
+        let objects = scattered_spheres(6 * MIN_OBJECTS_PER_THREAD);
+        for split in &[BvhSplit::Median, BvhSplit::SurfaceArea, BvhSplit::Morton] {
+            let options = BvhOptions { split: *split, ..BvhOptions::default() };
+            let serial = Bvh::new_on_threads(&objects, options, 1);
+            let parallel = Bvh::new_on_threads(&objects, options, 4);
+
+            assert_eq!(parallel.nodes, serial.nodes);
+            let ray = Ray::new(Tuple::point(50., 50., -10.), Tuple::vector(0., 0., 1.));
+            assert!(parallel.candidates(&ray).len() < 100);
+        }
+    }
+
+    // The point of the Morton split is building trees for millions of objects in seconds, which
+    // takes an optimized build to show: cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn morton_tree_of_a_million_objects_builds_in_seconds() {
+        let objects = scattered_spheres(1_000_000);
+        let start = std::time::Instant::now();
+        let bvh = Bvh::new(&objects, BvhOptions { split: BvhSplit::Morton, ..BvhOptions::default() });
+
+        assert!(start.elapsed().as_secs_f64() < 5., "took {:?}", start.elapsed());
+        assert_eq!(bvh.object_count(), 1_000_000);
+    }
+
+    #[test]
+    fn surface_area_heuristic_beats_median_on_irregular_scene() {
+        let objects = irregular_scene();
+        let median = Bvh::new(&objects, BvhOptions { split: BvhSplit::Median, ..BvhOptions::default() });
+        let sah = Bvh::new(&objects, BvhOptions::default());
+
+        assert!(sah.expected_cost() < median.expected_cost());
+    }
+
+    #[test]
+    fn unbounded_objects_are_always_candidates() {
+        let objects = vec![sphere_at(0., 0., 0., 1.), Plane::new_boxed(None, None), sphere_at(5., 0., 0., 1.)];
+        let bvh = Bvh::new(&objects, BvhOptions::default());
+        let miss = Ray::new(Tuple::point(0., 10., -5.), Tuple::vector(0., 0., 1.));
+
+        assert_eq!(bvh.object_count(), 3);
+        assert_eq!(bvh.candidates(&miss), vec![1]);
+    }
+
+    #[test]
+    fn ordered_traversal_visits_nearer_objects_first() {
+        let objects: Vec<BoxShape> = (0..8).map(|i| sphere_at(3. * i as f64, 0., 0., 1.)).collect();
+        let options = BvhOptions { bins: 8, ..BvhOptions::default() };
+        let bvh = Bvh::new(&objects, options);
+        let leftwards = Ray::new(Tuple::point(30., 0., 0.), Tuple::vector(-1., 0., 0.));
+        let rightwards = Ray::new(Tuple::point(-5., 0., 0.), Tuple::vector(1., 0., 0.));
+
+        assert_eq!(bvh.candidates(&leftwards).first(), Some(&7));
+        assert_eq!(bvh.candidates(&rightwards).first(), Some(&0));
+
+        let unordered = Bvh::new(&objects, BvhOptions { ordered_traversal: false, ..options });
+        assert_eq!(unordered.candidates(&leftwards).first(), Some(&0));
+    }
+
+    #[test]
+    fn refit_follows_moved_objects() {
+        let mut objects = irregular_scene();
+        let mut bvh = Bvh::new(&objects, BvhOptions::default());
+        for (i, o) in objects.iter_mut().enumerate().filter(|(i, _)| i % 10 == 0) {
+            o.set_transform(Matrix::translation(0., 8. + i as f64 / 10., 0.) * o.transformation());
+        }
+        bvh.refit(&objects);
+        let rebuilt = Bvh::new(&objects, BvhOptions::default());
+
+        assert_eq!(bvh.nodes[0].bounds(), rebuilt.nodes[0].bounds());
+        let mut rng = Rng::new(8);
+        for _ in 0..200 {
+            let ray = random_ray(&mut rng);
+            let candidates = bvh.candidates(&ray);
+            assert!(brute_force_hits(&objects, ray).iter().all(|i| candidates.contains(i)));
+        }
+        let moved = objects[10].transformation() * Tuple::point(0., 0., 0.);
+        let towards_moved = Ray::new(moved - Tuple::vector(0., 0., 10.), Tuple::vector(0., 0., 1.));
+        assert!(bvh.candidates(&towards_moved).contains(&10));
+    }
+
+    #[test]
+    #[should_panic]
+    fn refit_needs_same_objects() {
+        let objects = irregular_scene();
+        let mut bvh = Bvh::new(&objects, BvhOptions::default());
+        bvh.refit(&objects[1..]);
+    }
+
+    #[test]
+    fn objects_at_same_place_share_leaf() {
+        let objects: Vec<BoxShape> = (0..10).map(|_| sphere_at(1., 1., 1., 1.)).collect();
+        let bvh = Bvh::new(&objects, BvhOptions::default());
+
+        assert_eq!(bvh.node_count(), 1);
+        assert_eq!(bvh.candidates(&Ray::new(Tuple::point(1., 1., -5.), Tuple::vector(0., 0., 1.))).len(), 10);
+    }
+
+    #[test]
+    fn empty_tree_has_no_candidates() {
+        let bvh = Bvh::new::<BoxShape>(&[], BvhOptions::default());
+
+        assert!(bvh.candidates(&Ray::new(Tuple::point(0., 0., 0.), Tuple::vector(0., 0., 1.))).is_empty());
+        assert_eq!(bvh.expected_cost(), 0.);
+    }
+}
@@ -1,98 +1,748 @@
-use super::canvas::Canvas;
+use super::canvas::{Canvas, UNRENDERED_COLOR};
 use super::tuple::{Tuple, ORIGO};
 use super::ray::Ray;
-use super::matrix::Matrix;
+use super::matrix::{Matrix, InvertibleMatrix, IDENTITY_MATRIX};
 use super::world::World;
+use super::prepared_scene::PreparedScene;
+use super::color::{Color, BLACK};
+use super::sampler::{BoxSampler, CenterSampler, SampleId};
+use super::render_pass::{RenderPass, PassKind};
+use super::projection::{Projection, BoxProjection, PerspectiveProjection, perspective_half_extent};
+use super::logging::PhaseTimer;
+use super::film::FilmResponse;
+use super::exposure::Exposure;
+use super::render_checkpoint::{RenderCheckpoint, Tile};
+use super::error::Error;
+use super::angle::{Angle, rad};
+use super::spectral::{sample_weight, sample_wavelength};
+use log::{debug, warn};
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Pixels where RenderSettings::detect_non_finite found NaN or infinity
+pub const NON_FINITE_COLOR: Color = Color { r: 1., g: 0., b: 1. };
+
+pub const DEFAULT_PROGRESS_ROWS: usize = 32;
+pub const DEFAULT_FIELD_OF_VIEW: Angle = Angle::from_radians(std::f64::consts::FRAC_PI_3);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RenderMode {
+    // Ray tracing as in the book, direct lighting and mirror reflections only
+    Whitted,
+    // Global illumination, needs many samples per pixel to converge
+    PathTraced
+}
 
 
 pub struct Camera {
-    pub hsize: usize,
-    pub vsize: usize,
-    pub field_of_view: f64,
-    pub pixel_size: f64,
-    pub transform: Matrix,
-    half_width: f64,
-    half_height: f64
+    // The size, pixel_size and projection are private so that pixel_size can't get out of step
+    // with the others, see set_size, set_field_of_view and set_projection
+    hsize: usize,
+    vsize: usize,
+    // See update_pixel_size
+    pixel_size: f64,
+    // With its inverse, which every camera ray needs, see set_transform
+    transform: InvertibleMatrix,
+    pub sampler: BoxSampler,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    projection: BoxProjection,
+    pub render_mode: RenderMode,
+    // Each sample is traced at a single wavelength and added to its pixel in the color of that
+    // wavelength, so that dispersive materials split light into its colors. Needs many samples
+    // per pixel to converge, see spectral.rs.
+    pub spectral: bool,
+    // Render progress is logged at debug level every progress_rows rows, 0 turns it off
+    pub progress_rows: usize,
+    // Applied to the final color of each pixel, so the look is baked into the image. Render
+    // passes other than beauty hold data rather than light and are left linear.
+    pub film_response: Option<FilmResponse>,
+    // For scenes lit in physical units, applied before the film response
+    pub exposure: Option<Exposure>,
+    // Together with the pixel and sample index they make the SampleId all randomness of a
+    // sample comes from. Set frame to the frame number of an animation so that the noise
+    // changes between frames, keep it to get the same noise on a still scene.
+    pub seed: u64,
+    pub frame: u64
 }
 
 impl Camera {
-    pub fn new(hsize: usize, vsize: usize, field_of_view: f64, transform: Option<Matrix>) -> Self {
-        let half_view = (field_of_view / 2.).tan();
-        let aspect_ratio = hsize as f64 / vsize as f64;
-        let (half_width, half_height) = if aspect_ratio >= 1. {
-            (half_view, half_view / aspect_ratio)
-        } else {
-            (half_view * aspect_ratio, half_view)
-        };
-        let pixel_size = half_width * 2. / hsize as f64;
-        Self { 
+    // The field of view is an Angle, e g deg(60.) or rad(PI / 3.)
+    pub fn new(hsize: usize, vsize: usize, field_of_view: Angle, transform: Option<Matrix>) -> Self {
+        let field_of_view = field_of_view.radians();
+        let mut camera = Self { 
             hsize, 
             vsize, 
-            field_of_view,
-            pixel_size,
-            transform: transform.unwrap_or_default(), 
-            half_width,
-            half_height }
+            pixel_size: 0.,
+            transform: transform.into(),
+            sampler: CenterSampler::new_boxed(),
+            shutter_open: 0.,
+            shutter_close: 0.,
+            projection: PerspectiveProjection::new_boxed(field_of_view),
+            render_mode: RenderMode::Whitted,
+            spectral: false,
+            progress_rows: DEFAULT_PROGRESS_ROWS,
+            film_response: None,
+            exposure: None,
+            seed: 0,
+            frame: 0 };
+        camera.update_pixel_size();
+        camera
+    }
+
+    pub fn builder(hsize: usize, vsize: usize) -> CameraBuilder {
+        CameraBuilder::new(hsize, vsize)
+    }
+
+    pub fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.vsize
     }
 
+    pub fn pixel_size(&self) -> f64 {
+        self.pixel_size
+    }
+
+    pub fn projection(&self) -> &dyn Projection {
+        &*self.projection
+    }
+
+    // Changes the resolution keeping the view, with pixel_size to match. Panics if either is 0.
+    pub fn set_size(&mut self, hsize: usize, vsize: usize) {
+        self.try_set_size(hsize, vsize).unwrap();
+    }
+
+    pub fn try_set_size(&mut self, hsize: usize, vsize: usize) -> Result<(), Error> {
+        check_size(hsize, vsize)?;
+        self.hsize = hsize;
+        self.vsize = vsize;
+        self.update_pixel_size();
+        Ok(())
+    }
+
+    // Changes the field of view of the camera and its projection, with pixel_size to match.
+    // Panics unless it is above 0 and below the max_field_of_view of the projection.
+    pub fn set_field_of_view(&mut self, field_of_view: Angle) {
+        self.try_set_field_of_view(field_of_view).unwrap();
+    }
+
+    pub fn try_set_field_of_view(&mut self, field_of_view: Angle) -> Result<(), Error> {
+        let field_of_view = field_of_view.radians();
+        check_field_of_view(field_of_view, &*self.projection)?;
+        self.projection = self.projection.with_field_of_view(field_of_view);
+        self.update_pixel_size();
+        Ok(())
+    }
+
+    // Changes how the camera maps the image to directions, with pixel_size to match. The field
+    // of view is that of the new projection.
+    pub fn set_projection(&mut self, projection: BoxProjection) {
+        self.projection = projection;
+        self.update_pixel_size();
+    }
+
+    // In radians, that of the projection. None for projections that have none, like an
+    // equirectangular one.
+    pub fn field_of_view(&self) -> Option<f64> {
+        self.projection.field_of_view()
+    }
+
+    // The size of a pixel on the image plane of a perspective projection with the field of view
+    // of the camera, as in the book. 0 for fields of view a perspective projection can't have.
+    fn update_pixel_size(&mut self) {
+        self.pixel_size = match self.field_of_view() {
+            Some(field_of_view) if field_of_view < std::f64::consts::PI => {
+                let (half_width, _) = perspective_half_extent(self.hsize, self.vsize, field_of_view);
+                half_width * 2. / self.hsize as f64
+            },
+            _ => 0.
+        };
+    }
+
+    pub fn transform(&self) -> Matrix {
+        self.transform.matrix()
+    }
+
+    pub fn inverse_transform(&self) -> Matrix {
+        self.transform.inverse()
+    }
+
+    // Panics if transform is not invertible, leaving the camera unchanged
+    pub fn set_transform(&mut self, transform: Matrix) {
+        self.transform = InvertibleMatrix::new(transform);
+    }
+
+    pub fn try_set_transform(&mut self, transform: Matrix) -> Result<(), Error> {
+        self.transform = InvertibleMatrix::try_new(transform)?;
+        Ok(())
+    }
+
+    // Panics if the pixel is outside of what the projection can see, e g a corner of a fisheye
+    // image, which a perspective projection never has
     pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
-        let xoffset = (px as f64 + 0.5) * self.pixel_size;
-        let yoffset = (py as f64 + 0.5) * self.pixel_size;
-        let world_x = self.half_width - xoffset;
-        let world_y = self.half_height - yoffset;
-        let pixel = self.transform.inverse().unwrap() * Tuple::point(world_x, world_y, -1.);
-        let origin = self.transform.inverse().unwrap() * ORIGO;
-        let direction = (pixel - origin).normalize();
+        self.ray_for_sample(px, py, 0.5, 0.5)
+    }
 
-        Ray::new(origin, direction)
+    // None if the pixel is outside of what the projection can see
+    pub fn try_ray_for_pixel(&self, px: usize, py: usize) -> Option<Ray> {
+        self.try_ray_for_sample(px, py, 0.5, 0.5)
+    }
+
+    pub fn ray_for_sample(&self, px: usize, py: usize, dx: f64, dy: f64) -> Ray {
+        self.try_ray_for_sample(px, py, dx, dy).unwrap_or_else(|| {
+            panic!("({}, {}) in pixel ({}, {}) is outside of what the projection can see", dx, dy, px, py)
+        })
+    }
+
+    pub fn try_ray_for_sample(&self, px: usize, py: usize, dx: f64, dy: f64) -> Option<Ray> {
+        let direction = self.projection.direction(px as f64 + dx, py as f64 + dy, self.hsize, self.vsize)?;
+        let inverse = self.transform.inverse();
+        Some(Ray::new(inverse * ORIGO, (inverse * direction).normalize()))
+    }
+
+    // Where on the image point shows up, in pixels from the top left corner like the arguments
+    // of try_ray_for_sample. None if it is out of view, or if the projection can't be inverted.
+    pub fn pixel_for_point(&self, point: Tuple) -> Option<(f64, f64)> {
+        let direction = self.transform.matrix() * point - ORIGO;
+        self.projection.image_point(direction.normalize(), self.hsize, self.vsize)
+    }
+
+    // A camera with the same view at 1/divisor of the resolution, one sample per pixel and
+    // without path tracing, for quick previews. Spectral cameras preview in RGB, as one
+    // wavelength per pixel would only give colored noise.
+    pub fn preview(&self, divisor: usize) -> Camera {
+        let divisor = divisor.max(1);
+        let mut c = Camera::new(
+            (self.hsize / divisor).max(1), 
+            (self.vsize / divisor).max(1), 
+            DEFAULT_FIELD_OF_VIEW, 
+            Some(self.transform()));
+        c.set_projection(self.projection.clone());
+        c.shutter_open = self.shutter_open;
+        c.shutter_close = self.shutter_close;
+        c.progress_rows = self.progress_rows;
+        c.film_response = self.film_response;
+        c.exposure = self.exposure;
+        c.seed = self.seed;
+        c.frame = self.frame;
+        c
     }
 
     pub fn render(&self, world: World) -> Canvas {
-        let mut image = Canvas::new(self.hsize, self.vsize);
+        self.render_scene(&PreparedScene::new(world))
+    }
+
+    // Rows are rendered on as many threads as there are cores, see sample_rows
+    pub fn render_scene(&self, scene: &PreparedScene) -> Canvas {
+        let timer = PhaseTimer::start("render");
+        let mut image = Canvas::new_filled(self.hsize, self.vsize, UNRENDERED_COLOR);
+        let rows = self.sample_rows(scene.world(), 0..self.hsize, 0..self.vsize, &|rows| self.log_progress(&timer, rows));
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, color) in row.into_iter().enumerate() {
+                image.write_pixel(x, y, color);
+            }
+        }
+        timer.finish(&self.render_details());
+        image
+    }
+
+    // The pixels of tile, in a canvas the size of the tile
+    pub fn render_tile(&self, scene: &PreparedScene, tile: &Tile) -> Canvas {
+        let mut pixels = Canvas::new(tile.width, tile.height);
+        let rows = self.sample_rows(scene.world(), tile.x..tile.x + tile.width, tile.y..tile.y + tile.height, &|_| ());
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, color) in row.into_iter().enumerate() {
+                pixels.write_pixel(x, y, color);
+            }
+        }
+        pixels
+    }
+
+    // The colors of the pixels in columns xs of rows ys, row by row. Threads take the next row
+    // that is left until there are none, and on_row is called with the number of rows done
+    // after each. The samples of a pixel don't depend on which thread takes it, so the image
+    // is the same as rendering one row at a time, which is what happens when detecting non
+    // finite numbers since the count of them can only tell which pixel they came from then.
+    fn sample_rows(&self, world: &World, xs: Range<usize>, ys: Range<usize>, on_row: &(dyn Fn(usize) + Sync)) -> Vec<Vec<Color>> {
+        let threads = if world.settings.checks_non_finite() {
+            1
+        } else {
+            thread::available_parallelism().map_or(1, |n| n.get()).min(ys.len()).max(1)
+        };
+        let next_row = AtomicUsize::new(0);
+        let rows_done = AtomicUsize::new(0);
+        let sample_rows = || {
+            let mut rows = vec![];
+            loop {
+                let i = next_row.fetch_add(1, Ordering::Relaxed);
+                if i >= ys.len() {
+                    return rows;
+                }
+                let y = ys.start + i;
+                rows.push((i, xs.clone().map(|x| self.sample_pixel(world, x, y)).collect::<Vec<Color>>()));
+                on_row(rows_done.fetch_add(1, Ordering::Relaxed) + 1);
+            }
+        };
+        let mut rows: Vec<(usize, Vec<Color>)> = if threads == 1 {
+            sample_rows()
+        } else {
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..threads).map(|_| scope.spawn(sample_rows)).collect();
+                handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+            })
+        };
+        rows.sort_unstable_by_key(|(i, _)| *i);
+        rows.into_iter().map(|(_, row)| row).collect()
+    }
+
+    // Renders the tiles of checkpoint that aren't done yet, giving the same image as
+    // render_scene once all are. on_tile is called after each tile, e g to save the checkpoint
+    // now and then, and an error from it stops the render. Panics if checkpoint is for another
+    // image size.
+    pub fn render_tiles<F>(&self, scene: &PreparedScene, checkpoint: &mut RenderCheckpoint, mut on_tile: F) -> io::Result<()>
+        where F: FnMut(&RenderCheckpoint) -> io::Result<()> {
+        if checkpoint.width() != self.hsize || checkpoint.height() != self.vsize {
+            panic!("Checkpoint is for a {}x{} image, not {}x{}", checkpoint.width(), checkpoint.height(), self.hsize, self.vsize);
+        }
+        let timer = PhaseTimer::start("render_tiles");
+        let remaining = checkpoint.remaining_tiles();
+        for tile in &remaining {
+            let pixels = self.render_tile(scene, tile);
+            checkpoint.complete_tile(tile, &pixels);
+            on_tile(checkpoint)?;
+        }
+        timer.finish(&format!("{} tiles={} resumed_from={}",
+            self.render_details(), checkpoint.tile_count(), checkpoint.tile_count() - remaining.len()));
+        Ok(())
+    }
+
+    // Renders world as a layer for compositing. Pixels where nothing was hit are left transparent,
+    // and the alpha of each pixel is the fraction of its samples that hit an object.
+    pub fn render_layer(&self, world: &World) -> Canvas {
+        let timer = PhaseTimer::start("render_layer");
+        let mut image = Canvas::new_transparent(self.hsize, self.vsize);
         for y in 0..self.vsize {
             for x in 0..self.hsize {
-                let ray = self.ray_for_pixel(x, y);
-                let color = world.color_at(ray);
+                let (color, coverage) = self.sample_layer_pixel(world, x, y);
+                // The color is premultiplied by coverage, the curve applies to the color itself
+                let color = if coverage > 0. { self.develop(color * (1. / coverage)) * coverage } else { color };
                 image.write_pixel(x, y, color);
+                image.write_alpha(x, y, coverage);
             }
+            self.log_progress(&timer, y + 1);
         }
+        timer.finish(&self.render_details());
         image
     }
+
+    // Renders each world as a separate layer and composites them, first world at the bottom
+    pub fn render_composited(&self, worlds: &[&World]) -> Canvas {
+        let layers: Vec<Canvas> = worlds.iter().map(|w| self.render_layer(w)).collect();
+        let layer_refs: Vec<&Canvas> = layers.iter().collect();
+        Canvas::composite(&layer_refs)
+    }
+
+    // Renders one canvas per pass, in the same order as passes. Each camera ray is traced only
+    // once and its result is shared between all passes.
+    pub fn render_passes(&self, world: &World, passes: &[RenderPass]) -> Vec<Canvas> {
+        let timer = PhaseTimer::start("render_passes");
+        let mut images: Vec<Canvas> = passes.iter().map(|_| Canvas::new_filled(self.hsize, self.vsize, UNRENDERED_COLOR)).collect();
+        let samples = self.sampler.samples_per_pixel();
+        let weight = 1. / samples as f64;
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let mut colors = vec![BLACK; passes.len()];
+                for i in 0..samples {
+                    let shading = self.sample_ray(x, y, i)
+                        .and_then(|r| world.shading_components_at(r).map(|sc| sc.weighted(sample_weight(&r))));
+                    for (color, pass) in colors.iter_mut().zip(passes) {
+                        if pass.averages_samples() {
+                            *color += pass.value(shading.as_ref()) * weight;
+                        } else if i == 0 {
+                            *color = pass.value(shading.as_ref());
+                        }
+                    }
+                }
+                for ((image, color), pass) in images.iter_mut().zip(colors).zip(passes) {
+                    image.write_pixel(x, y, if pass.kind == PassKind::Beauty { self.develop(color) } else { color });
+                }
+            }
+            self.log_progress(&timer, y + 1);
+        }
+        timer.finish(&format!("{} passes={}", self.render_details(), passes.len()));
+        images
+    }
+
+    fn render_details(&self) -> String {
+        format!("width={} height={} samples_per_pixel={} mode={:?}",
+            self.hsize, self.vsize, self.sampler.samples_per_pixel(), self.render_mode)
+    }
+
+    // Called each time another row is done, with the number of rows done
+    fn log_progress(&self, timer: &PhaseTimer, rows: usize) {
+        if self.progress_rows > 0 && rows.is_multiple_of(self.progress_rows) && rows < self.vsize {
+            debug!("rendered {} of {} rows in {:.1} s", rows, self.vsize, timer.elapsed().as_secs_f64());
+        }
+    }
+
+    fn sample_layer_pixel(&self, world: &World, px: usize, py: usize) -> (Color, f64) {
+        let samples = self.sampler.samples_per_pixel();
+        let mut color = BLACK;
+        let mut hits = 0;
+        for i in 0..samples {
+            let hit_color = self.sample_ray(px, py, i).and_then(|r| match self.render_mode {
+                RenderMode::Whitted => world.hit_color_at(r),
+                RenderMode::PathTraced => world.hit_color_at_pathtraced(r, &mut self.sample_id(px, py, i).rng())
+            }.map(|c| c * sample_weight(&r)));
+            if let Some(c) = hit_color {
+                color += c;
+                hits += 1;
+            }
+        }
+        let weight = 1. / samples as f64;
+        (color * weight, hits as f64 * weight)
+    }
+
+    pub(crate) fn sample_pixel(&self, world: &World, px: usize, py: usize) -> Color {
+        if !world.settings.checks_non_finite() {
+            return self.develop(self.average_samples(world, px, py));
+        }
+        let found_before = world.non_finite_count();
+        let color = self.average_samples(world, px, py);
+        if world.non_finite_count() > found_before || !color.is_finite() {
+            warn!("Non finite numbers in pixel ({}, {})", px, py);
+            return NON_FINITE_COLOR;
+        }
+        self.develop(color)
+    }
+
+    // Through the film response curve, if there is one
+    pub(crate) fn develop(&self, color: Color) -> Color {
+        let color = match &self.exposure {
+            Some(e) => e.apply(color),
+            None => color
+        };
+        match &self.film_response {
+            Some(f) => f.apply(color),
+            None => color
+        }
+    }
+
+    pub(crate) fn average_samples(&self, world: &World, px: usize, py: usize) -> Color {
+        let samples = self.sampler.samples_per_pixel();
+        let mut color = BLACK;
+        for i in 0..samples {
+            if let Some(r) = self.sample_ray(px, py, i) {
+                color += sample_weight(&r) * match self.render_mode {
+                    RenderMode::Whitted => world.color_at(r),
+                    RenderMode::PathTraced => world.color_at_pathtraced(r, 0, &mut self.sample_id(px, py, i).rng())
+                };
+            }
+        }
+        color * (1. / samples as f64)
+    }
+
+    // What the randomness of sample index of a pixel comes from, for the sampler and the
+    // integrator alike
+    pub fn sample_id(&self, px: usize, py: usize, index: usize) -> SampleId {
+        SampleId { seed: self.seed, frame: self.frame, px, py, index }
+    }
+
+    // The ray for sample index of a pixel, shot at a time within the shutter interval, and at a
+    // wavelength of its own when spectral. What it sees has to be weighted by sample_weight.
+    pub(crate) fn sample_ray(&self, px: usize, py: usize, index: usize) -> Option<Ray> {
+        let id = self.sample_id(px, py, index);
+        let (dx, dy) = self.sampler.sample(id);
+        let shutter = self.sampler.time_sample(id);
+        let time = self.shutter_open + (self.shutter_close - self.shutter_open) * shutter;
+        let ray = self.try_ray_for_sample(px, py, dx, dy)?.with_time(time);
+        if self.spectral {
+            Some(ray.with_wavelength(sample_wavelength(self.sampler.wavelength_sample(id))))
+        } else {
+            Some(ray)
+        }
+    }
+}
+
+// Builds a camera with its settings checked, returning an error rather than a camera that
+// renders nothing or panics later, e g for cameras set up from user input
+pub struct CameraBuilder {
+    hsize: usize,
+    vsize: usize,
+    field_of_view: f64,
+    transform: Matrix,
+    projection: BoxProjection,
+    sampler: BoxSampler,
+    render_mode: RenderMode,
+    seed: u64
+}
+
+impl CameraBuilder {
+    pub fn new(hsize: usize, vsize: usize) -> Self {
+        Self {
+            hsize,
+            vsize,
+            field_of_view: DEFAULT_FIELD_OF_VIEW.radians(),
+            transform: IDENTITY_MATRIX,
+            projection: PerspectiveProjection::new_boxed(DEFAULT_FIELD_OF_VIEW.radians()),
+            sampler: CenterSampler::new_boxed(),
+            render_mode: RenderMode::Whitted,
+            seed: 0
+        }
+    }
+
+    pub fn field_of_view(self, field_of_view: Angle) -> Self {
+        Self { field_of_view: field_of_view.radians(), ..self }
+    }
+
+    pub fn transform(self, transform: Matrix) -> Self {
+        Self { transform, ..self }
+    }
+
+    // Takes the field of view of the builder
+    pub fn projection(self, projection: BoxProjection) -> Self {
+        Self { projection, ..self }
+    }
+
+    pub fn sampler(self, sampler: BoxSampler) -> Self {
+        Self { sampler, ..self }
+    }
+
+    pub fn render_mode(self, render_mode: RenderMode) -> Self {
+        Self { render_mode, ..self }
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    // Fails for a size of 0, a field of view the projection can't show and a transform that
+    // can't be inverted
+    pub fn build(self) -> Result<Camera, Error> {
+        check_size(self.hsize, self.vsize)?;
+        check_field_of_view(self.field_of_view, &*self.projection)?;
+        let mut camera = Camera::new(self.hsize, self.vsize, rad(self.field_of_view), None);
+        camera.set_projection(self.projection.with_field_of_view(self.field_of_view));
+        camera.try_set_transform(self.transform)?;
+        camera.sampler = self.sampler;
+        camera.render_mode = self.render_mode;
+        camera.seed = self.seed;
+        Ok(camera)
+    }
+}
+
+// The cameras of a scene by name, for rendering several views (e g a product from different
+// angles) of the same prepared world. The first camera added is the default.
+#[derive(Default)]
+pub struct CameraSet {
+    cameras: Vec<(String, Camera)>
+}
+
+impl CameraSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A camera with the name of one already in the set takes its place
+    pub fn insert(&mut self, name: &str, camera: Camera) {
+        match self.cameras.iter_mut().find(|(n, _)| n == name) {
+            Some((_, c)) => *c = camera,
+            None => self.cameras.push((name.to_string(), camera))
+        }
+    }
+
+    pub fn with_camera(mut self, name: &str, camera: Camera) -> Self {
+        self.insert(name, camera);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Camera> {
+        self.cameras.iter().find(|(n, _)| n == name).map(|(_, c)| c)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Camera> {
+        self.cameras.iter_mut().find(|(n, _)| n == name).map(|(_, c)| c)
+    }
+
+    pub fn default_name(&self) -> Option<&str> {
+        self.cameras.first().map(|(n, _)| n.as_str())
+    }
+
+    // In the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.cameras.iter().map(|(n, _)| n.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Camera)> {
+        self.cameras.iter().map(|(n, c)| (n.as_str(), c))
+    }
+
+    // The camera called name, e g as given on the command line, failing with the names there
+    // are if there is none
+    pub fn try_take(mut self, name: &str) -> Result<Camera, Error> {
+        match self.cameras.iter().position(|(n, _)| n == name) {
+            Some(i) => Ok(self.cameras.swap_remove(i).1),
+            None => Err(Error::UnknownCamera { name: name.to_string(), known: self.names().map(String::from).collect() })
+        }
+    }
+}
+
+fn check_size(hsize: usize, vsize: usize) -> Result<(), Error> {
+    if hsize == 0 || vsize == 0 {
+        return Err(Error::InvalidCamera("the image has no pixels"));
+    }
+    Ok(())
+}
+
+fn check_field_of_view(field_of_view: f64, projection: &dyn Projection) -> Result<(), Error> {
+    if !(field_of_view > 0. && field_of_view < projection.max_field_of_view() && field_of_view.is_finite()) {
+        return Err(Error::InvalidCamera("the field of view has to be above 0 and below the widest the projection can show"));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI, SQRT_2};
     use crate::approx_eq;
-    use crate::matrix::IDENTITY_MATRIX;
-    use crate::color::Color;
+    use crate::angle::deg;
+    use crate::tuple::Tuple;
+    use crate::sampler::{StratifiedSampler, JitteredSampler};
+    use crate::render_pass::PassKind;
+    use crate::material_palette::MaterialPalette;
+    use crate::color::{RED, WHITE};
+    use crate::light::PointLight;
+    use crate::material::{Material, DEFAULT_SHININESS};
+    use crate::sphere::Sphere;
+    use crate::plane::Plane;
+    use crate::projection::{FisheyeProjection, EquirectangularProjection};
+    use crate::pattern::StripePattern;
+    use crate::spectral::{MAX_WAVELENGTH, MIN_WAVELENGTH, SF11_GLASS};
 
     #[test]
     fn construct_camera() {
-        let c = Camera::new(160, 120, FRAC_PI_2, None);
+        let c = Camera::new(160, 120, rad(FRAC_PI_2), None);
 
-        assert_eq!(c.hsize, 160);
-        assert_eq!(c.vsize, 120);
-        assert_eq!(c.field_of_view, FRAC_PI_2);
-        assert_eq!(c.transform, IDENTITY_MATRIX);
+        assert_eq!(c.hsize(), 160);
+        assert_eq!(c.vsize(), 120);
+        assert_eq!(c.field_of_view(), Some(FRAC_PI_2));
+        assert_eq!(c.transform(), IDENTITY_MATRIX);
+        assert_eq!(c.shutter_open, 0.);
+        assert_eq!(c.shutter_close, 0.);
+        assert_eq!(c.render_mode, RenderMode::Whitted);
     }
 
     #[test]
     fn pixel_size_horizontal_canvas() {
-        let c = Camera::new(200, 125, FRAC_PI_2, None);
-        assert!(approx_eq(c.pixel_size, 0.01));
+        let c = Camera::new(200, 125, rad(FRAC_PI_2), None);
+        assert!(approx_eq(c.pixel_size(), 0.01));
     }
 
     #[test]
     fn pixel_size_vertical_canvas() {
-        let c = Camera::new(125, 200, FRAC_PI_2, None);
-        assert!(approx_eq(c.pixel_size, 0.01));
+        let c = Camera::new(125, 200, rad(FRAC_PI_2), None);
+        assert!(approx_eq(c.pixel_size(), 0.01));
+    }
+
+    #[test]
+    fn camera_builder_checks_settings() {
+        let view = Matrix::view_transform(Tuple::point(0., 0., -5.), ORIGO, Tuple::vector(0., 1., 0.));
+        let c = Camera::builder(200, 125).field_of_view(rad(FRAC_PI_2)).transform(view).seed(7).render_mode(RenderMode::PathTraced).build().unwrap();
+
+        assert_eq!((c.hsize(), c.vsize(), c.field_of_view(), c.seed), (200, 125, Some(FRAC_PI_2), 7));
+        assert_eq!(c.render_mode, RenderMode::PathTraced);
+        assert_eq!(c.transform(), view);
+        assert!(approx_eq(c.pixel_size(), 0.01));
+        assert!(matches!(Camera::builder(0, 10).build(), Err(Error::InvalidCamera(_))));
+        assert!(matches!(Camera::builder(10, 10).field_of_view(rad(PI)).build(), Err(Error::InvalidCamera(_))));
+        assert!(matches!(Camera::builder(10, 10).field_of_view(rad(f64::NAN)).build(), Err(Error::InvalidCamera(_))));
+        assert!(matches!(Camera::builder(10, 10).field_of_view(deg(180.)).build(), Err(Error::InvalidCamera(_))));
+        assert!(matches!(Camera::builder(10, 10).transform(Matrix::scaling(0., 1., 1.)).build(), Err(Error::NotInvertible(_))));
+    }
+
+    #[test]
+    fn cameras_by_name_share_a_scene() {
+        let scene = PreparedScene::new(World::default_world());
+        let front = Matrix::view_transform(Tuple::point(0., 0., -5.), ORIGO, Tuple::vector(0., 1., 0.));
+        let side = Matrix::view_transform(Tuple::point(5., 0., 0.), ORIGO, Tuple::vector(0., 1., 0.));
+        let mut cameras = CameraSet::new()
+            .with_camera("front", Camera::new(11, 11, rad(FRAC_PI_2), Some(front)))
+            .with_camera("side", Camera::new(11, 11, rad(FRAC_PI_2), None))
+            .with_camera("side", Camera::new(11, 11, rad(FRAC_PI_2), Some(side)));
+        cameras.get_mut("front").unwrap().seed = 3;
+
+        assert_eq!(cameras.names().collect::<Vec<&str>>(), vec!["front", "side"]);
+        assert_eq!(cameras.default_name(), Some("front"));
+        assert_eq!(cameras.get("side").unwrap().transform(), side);
+        assert!(cameras.get("top").is_none());
+        let centers: Vec<Color> = cameras.iter().map(|(_, c)| c.render_scene(&scene).pixel_at(5, 5)).collect();
+        assert_eq!(centers[0], Color::new(0.38066, 0.47583, 0.2855));
+        assert_ne!(centers[1], centers[0]);
+        let e = CameraSet::new().with_camera("front", Camera::new(1, 1, rad(FRAC_PI_2), None)).try_take("top").err().unwrap();
+        assert_eq!(e.to_string(), "No camera named top, the cameras are front");
+        assert_eq!(cameras.try_take("front").unwrap().seed, 3);
+    }
+
+    #[test]
+    fn setters_keep_pixel_size_and_projection_in_step() {
+        let mut c = Camera::new(100, 100, rad(FRAC_PI_4), None);
+        c.set_size(200, 125);
+        c.set_field_of_view(rad(FRAC_PI_2));
+
+        assert!(approx_eq(c.pixel_size(), 0.01));
+        assert_eq!(c.ray_for_pixel(10, 20).direction, Camera::new(200, 125, rad(FRAC_PI_2), None).ray_for_pixel(10, 20).direction);
+        assert!(c.try_set_size(0, 10).is_err());
+        assert!(c.try_set_field_of_view(rad(-1.)).is_err());
+        assert_eq!((c.hsize(), c.field_of_view()), (200, Some(FRAC_PI_2)));
+        c.set_field_of_view(deg(45.));
+        assert!(approx_eq(c.field_of_view().unwrap(), FRAC_PI_4));
+    }
+
+    #[test]
+    fn set_projection_updates_pixel_size() {
+        let mut c = Camera::new(200, 125, rad(FRAC_PI_4), None);
+        c.set_projection(PerspectiveProjection::new_boxed(FRAC_PI_2));
+
+        assert!(approx_eq(c.pixel_size(), 0.01));
+        c.set_projection(EquirectangularProjection::new_boxed());
+        assert_eq!((c.field_of_view(), c.pixel_size()), (None, 0.));
+    }
+
+    #[test]
+    fn field_of_view_of_fisheye_follows_camera() {
+        let mut c = Camera::new(101, 101, rad(FRAC_PI_2), None);
+        c.set_projection(FisheyeProjection::new_boxed(FRAC_PI_2));
+        c.set_field_of_view(rad(FRAC_PI_4));
+        let edge = c.ray_for_sample(0, 50, 0., 0.5).direction;
+
+        assert!(approx_eq(edge.angle_between(&Tuple::vector(0., 0., -1.)), FRAC_PI_4 / 2.));
+    }
+
+    #[test]
+    fn field_of_view_is_checked_against_projection() {
+        let mut c = Camera::new(101, 101, rad(FRAC_PI_2), None);
+        assert!(c.try_set_field_of_view(deg(200.)).is_err());
+        c.set_projection(FisheyeProjection::new_boxed(FRAC_PI_2));
+        c.set_field_of_view(deg(200.));
+        let edge = c.ray_for_sample(0, 50, 0., 0.5).direction;
+
+        assert!(approx_eq(edge.angle_between(&Tuple::vector(0., 0., -1.)), deg(100.).radians()));
+        assert!(c.try_set_field_of_view(deg(360.)).is_err());
+        let fisheye = Camera::builder(101, 101).projection(FisheyeProjection::new_boxed(FRAC_PI_2)).field_of_view(deg(200.)).build().unwrap();
+        assert_eq!(fisheye.ray_for_sample(0, 50, 0., 0.5).direction, edge);
+        assert!(Camera::builder(10, 5).projection(EquirectangularProjection::new_boxed()).field_of_view(deg(360.)).build().is_ok());
+        assert!(Camera::builder(10, 5).projection(EquirectangularProjection::new_boxed()).field_of_view(rad(f64::INFINITY)).build().is_err());
     }
 
     #[test]
     fn construct_ray_through_center_of_canvas() {
-        let c = Camera::new(201, 101, FRAC_PI_2, None);
+        let c = Camera::new(201, 101, rad(FRAC_PI_2), None);
         let r = c.ray_for_pixel(100, 50);
 
         assert_eq!(r.origin, ORIGO);
@@ -101,17 +751,44 @@ mod tests {
 
     #[test]
     fn construct_ray_through_corner_of_canvas() {
-        let c = Camera::new(201, 101, FRAC_PI_2, None);
+        let c = Camera::new(201, 101, rad(FRAC_PI_2), None);
         let r = c.ray_for_pixel(0, 0);
 
         assert_eq!(r.origin, ORIGO);
         assert_eq!(r.direction, Tuple::vector(0.66519, 0.33259, -0.66851));
     }
 
+    #[test]
+    fn setting_transform_keeps_inverse_in_sync() {
+        let mut c = Camera::new(201, 101, rad(FRAC_PI_2), None);
+        c.set_transform(Matrix::rotation_y(rad(FRAC_PI_4)) * Matrix::translation(0., -2., 5.));
+        let r = c.ray_for_pixel(100, 50);
+
+        assert_eq!(c.inverse_transform(), c.transform().inverse().unwrap());
+        assert_eq!(r.origin, Tuple::point(0., 2., -5.));
+        assert_eq!(r.direction, Tuple::vector(SQRT_2 / 2., 0., -SQRT_2 / 2.));
+    }
+
+    #[test]
+    #[should_panic]
+    fn camera_transform_must_be_invertible() {
+        Camera::new(10, 10, rad(FRAC_PI_2), None).set_transform(Matrix::scaling(1., 0., 1.));
+    }
+
+    #[test]
+    fn setting_singular_camera_transform_without_panicking() {
+        let mut c = Camera::new(10, 10, rad(FRAC_PI_2), Some(Matrix::translation(0., 0., 5.)));
+
+        assert!(c.try_set_transform(Matrix::scaling(1., 0., 1.)).is_err());
+        assert_eq!(c.transform(), Matrix::translation(0., 0., 5.));
+        c.try_set_transform(Matrix::translation(0., 1., 0.)).unwrap();
+        assert_eq!(c.inverse_transform(), Matrix::translation(0., -1., 0.));
+    }
+
     #[test]
     fn construct_ray_when_camera_transformed() {
-        let t = Matrix::rotation_y(FRAC_PI_4) * Matrix::translation(0., -2., 5.);
-        let c = Camera::new(201, 101, FRAC_PI_2, Some(t));
+        let t = Matrix::rotation_y(rad(FRAC_PI_4)) * Matrix::translation(0., -2., 5.);
+        let c = Camera::new(201, 101, rad(FRAC_PI_2), Some(t));
         let r = c.ray_for_pixel(100, 50);
 
         assert_eq!(r.origin, Tuple::point(0., 2., -5.));
@@ -125,9 +802,505 @@ mod tests {
         let to = ORIGO;
         let up = Tuple::vector(0., 1., 0.);
         let tr = Matrix::view_transform(from, to, up);
-        let c = Camera::new(11, 11, FRAC_PI_2, Some(tr));
+        let c = Camera::new(11, 11, rad(FRAC_PI_2), Some(tr));
 
         let image = c.render(w);
         assert_eq!(image.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ray_for_sample_at_pixel_corner() {
+        let c = Camera::new(201, 101, rad(FRAC_PI_2), None);
+        let r = c.ray_for_sample(100, 50, 0., 0.);
+        let expected = Tuple::vector(0.5 * c.pixel_size(), 0.5 * c.pixel_size(), -1.).normalize();
+
+        assert_eq!(r.direction, expected);
+    }
+
+    #[test]
+    fn camera_defaults_to_one_sample_per_pixel() {
+        let c = Camera::new(160, 120, rad(FRAC_PI_2), None);
+        assert_eq!(c.sampler.samples_per_pixel(), 1);
+    }
+
+    #[test]
+    fn render_with_stratified_sampler_averages_samples() {
+        let from = Tuple::point(0., 0., -5.);
+        let tr = Matrix::view_transform(from, ORIGO, Tuple::vector(0., 1., 0.));
+        let mut c = Camera::new(11, 11, rad(FRAC_PI_2), Some(tr));
+        c.sampler = StratifiedSampler::new_boxed(3);
+        let image = c.render(World::default_world());
+        let mut expected = BLACK;
+        for i in 0..9 {
+            let (dx, dy) = c.sampler.sample(c.sample_id(5, 5, i));
+            expected += World::default_world().color_at(c.ray_for_sample(5, 5, dx, dy));
+        }
+
+        assert_eq!(image.pixel_at(5, 5), expected * (1. / 9.));
+        assert_eq!(image.pixel_at(0, 0), BLACK);
+    }
+
+    #[test]
+    fn swapping_sampler_keeps_flat_regions_unchanged() {
+        let mut c = Camera::new(11, 11, rad(FRAC_PI_2), None);
+        c.sampler = JitteredSampler::new_boxed(4);
+        let image = c.render(World::new(None, vec![]));
+
+        assert_eq!(image.pixel_at(3, 3), BLACK);
+    }
+
+    fn camera_facing_default_world() -> Camera {
+        let tr = Matrix::view_transform(Tuple::point(0., 0., -5.), ORIGO, Tuple::vector(0., 1., 0.));
+        Camera::new(11, 11, rad(FRAC_PI_2), Some(tr))
+    }
+
+    #[test]
+    fn render_layer_leaves_misses_transparent() {
+        let c = camera_facing_default_world();
+        let image = c.render_layer(&World::default_world());
+
+        assert_eq!(image.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
+        assert_eq!(image.alpha_at(5, 5), 1.);
+        assert_eq!(image.alpha_at(0, 0), 0.);
+    }
+
+    #[test]
+    fn render_layer_alpha_is_sample_coverage() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(4);
+        let image = c.render_layer(&World::default_world());
+
+        assert_eq!(image.alpha_at(5, 5), 1.);
+        assert!(image.alpha_at(5, 4) > 0. && image.alpha_at(5, 4) < 1.);
+        assert_eq!(image.alpha_at(5, 2), 0.);
+    }
+
+    #[test]
+    fn path_traced_layer_has_coverage() {
+        let mut c = camera_facing_default_world();
+        c.render_mode = RenderMode::PathTraced;
+        c.sampler = StratifiedSampler::new_boxed(4);
+        let image = c.render_layer(&World::default_world());
+
+        assert_eq!(image.alpha_at(5, 5), 1.);
+        assert!(image.pixel_at(5, 5) != BLACK);
+        assert!(image.alpha_at(5, 4) > 0. && image.alpha_at(5, 4) < 1.);
+        assert_eq!(image.alpha_at(0, 0), 0.);
+    }
+
+    #[test]
+    fn render_composited_puts_later_worlds_on_top() {
+        let c = camera_facing_default_world();
+        let background = World::default_world();
+        let light = Some(PointLight::new(Tuple::point(-10., 10., -10.), WHITE));
+        let m = Material::new(RED, 1., 0., 0., DEFAULT_SHININESS, None);
+        let foreground = World::new(light, vec![Sphere::new_boxed(Some(m), Some(Matrix::scaling(0.2, 0.2, 0.2)))]);
+        let image = c.render_composited(&[&background, &foreground]);
+
+        assert_eq!(image.pixel_at(5, 5), RED);
+        assert_eq!(image.pixel_at(5, 4), background.color_at(c.ray_for_pixel(5, 4)));
+        assert_eq!(image.alpha_at(5, 4), 1.);
+        assert_eq!(image.alpha_at(0, 0), 0.);
+    }
+
+    #[test]
+    fn sample_rays_spread_over_shutter_interval() {
+        let mut c = Camera::new(11, 11, rad(FRAC_PI_2), None);
+        c.sampler = StratifiedSampler::new_boxed(2);
+        c.shutter_open = 0.2;
+        c.shutter_close = 0.6;
+        let times: Vec<f64> = (0..4).map(|i| c.sample_ray(5, 5, i).unwrap().time).collect();
+
+        assert!(times.iter().all(|t| (0.2..0.6).contains(t)));
+        assert!(times[0] < 0.3 && times[3] >= 0.5);
+    }
+
+    #[test]
+    fn motion_blur_smears_moving_object() {
+        let c = camera_facing_default_world();
+        let light = Some(PointLight::new(Tuple::point(-10., 10., -10.), WHITE));
+        let m = Material::new(RED, 1., 0., 0., DEFAULT_SHININESS, None);
+        let s = Sphere::new(Some(m), None).with_motion(Matrix::translation(2., 0., 0.));
+        let w = World::new(light, vec![Box::new(s)]);
+        let mut blurred = camera_facing_default_world();
+        blurred.sampler = StratifiedSampler::new_boxed(4);
+        blurred.shutter_close = 1.;
+
+        let still = c.render(w.clone());
+        assert_eq!(still.pixel_at(5, 5), RED);
+        assert_eq!(still.pixel_at(8, 5), BLACK);
+
+        let image = blurred.render(w);
+        let smeared = image.pixel_at(8, 5);
+        assert!(smeared.r > 0. && smeared.r < 1.);
+    }
+
+    #[test]
+    fn render_passes_in_single_traversal() {
+        let c = camera_facing_default_world();
+        let w = World::default_world();
+        let passes = vec![
+            RenderPass::new("beauty", PassKind::Beauty),
+            RenderPass::new("shadow", PassKind::Shadow),
+            RenderPass::new("outer", PassKind::Mask(vec![0]))];
+        let images = c.render_passes(&w, &passes);
+
+        assert_eq!(images.len(), 3);
+        assert_eq!(images[0].pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
+        assert_eq!(images[1].pixel_at(5, 5), BLACK);
+        assert_eq!(images[2].pixel_at(5, 5), WHITE);
+        assert_eq!(images[2].pixel_at(0, 0), BLACK);
+    }
+
+    #[test]
+    fn beauty_pass_matches_render() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(2);
+        let images = c.render_passes(&World::default_world(), &[RenderPass::new("beauty", PassKind::Beauty)]);
+        let image = c.render(World::default_world());
+
+        for x in 0..11 {
+            assert_eq!(images[0].pixel_at(x, 4), image.pixel_at(x, 4));
+        }
+    }
+
+    #[test]
+    fn film_response_applies_to_light_but_not_data_passes() {
+        let mut c = camera_facing_default_world();
+        let linear = c.render(World::default_world()).pixel_at(5, 5);
+        c.film_response = Some(FilmResponse::filmic());
+        let passes = [RenderPass::new("beauty", PassKind::Beauty), RenderPass::new("shadow", PassKind::Shadow)];
+        let images = c.render_passes(&World::default_world(), &passes);
+
+        assert_eq!(c.render(World::default_world()).pixel_at(5, 5), FilmResponse::filmic().apply(linear));
+        assert_eq!(images[0].pixel_at(5, 5), FilmResponse::filmic().apply(linear));
+        assert_eq!(c.preview(2).film_response, c.film_response);
+        assert_eq!(images[1].pixel_at(5, 5), BLACK);
+    }
+
+    #[test]
+    fn exposure_applies_before_film_response() {
+        let mut c = camera_facing_default_world();
+        let mut w = World::default_world();
+        w.light = Some(PointLight::from_lumens(Tuple::point(-10., 10., -10.), 10000., WHITE));
+        let scene = PreparedScene::new(w);
+        let luminance = c.render_scene(&scene).pixel_at(5, 5);
+        let exposure = Exposure::new(400., 1. / 30., 2.8);
+        c.exposure = Some(exposure);
+        c.film_response = Some(FilmResponse::filmic());
+
+        assert_eq!(c.render_scene(&scene).pixel_at(5, 5), FilmResponse::filmic().apply(exposure.apply(luminance)));
+        assert_eq!(c.preview(2).exposure, c.exposure);
+    }
+
+    #[test]
+    fn household_bulb_at_indoor_exposure() {
+        // Looking straight down at a white floor, 2 m below an 800 lm bulb, at settings for a dim room
+        let view = Matrix::view_transform(Tuple::point(0., 1.5, 0.), ORIGO, Tuple::vector(0., 0., 1.));
+        let mut c = Camera::new(1, 1, rad(FRAC_PI_2), Some(view));
+        c.exposure = Some(Exposure::new(800., 1. / 30., 2.));
+        let bulb = PointLight::from_lumens(Tuple::point(0., 2., 0.), 800., WHITE);
+        let image = c.render(World::new(Some(bulb), vec![Plane::new_boxed(None, None)]));
+        // Ambient, diffuse and specular (seen head on) add up to 1.9 times the light on the floor
+        let expected = 1.9 * 800. / (4. * PI) / (PI * 4.) * c.exposure.unwrap().scale();
+
+        assert!(approx_eq(image.pixel_at(0, 0).r, expected));
+        assert!(expected > 0.3 && expected < 0.8);
+    }
+
+    #[test]
+    fn material_id_pass_is_not_blended() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(4);
+        let w = World::default_world();
+        let palette = MaterialPalette::new(&w);
+        let images = c.render_passes(&w, &[palette.id_pass()]);
+        let ids = [BLACK, MaterialPalette::id_color(1), MaterialPalette::id_color(2)];
+
+        for x in 0..11 {
+            for y in 0..11 {
+                assert!(ids.contains(&images[0].pixel_at(x, y)));
+            }
+        }
+        assert_eq!(images[0].pixel_at(5, 5), MaterialPalette::id_color(1));
+    }
+
+    #[test]
+    fn film_response_on_layer_keeps_coverage() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(4);
+        let linear = c.render_layer(&World::default_world());
+        c.film_response = Some(FilmResponse::soft());
+        let image = c.render_layer(&World::default_world());
+
+        assert_eq!(image.alpha_at(5, 4), linear.alpha_at(5, 4));
+        assert_eq!(image.pixel_at(5, 5), FilmResponse::soft().apply(linear.pixel_at(5, 5)));
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of what the projection can see")]
+    fn ray_for_pixel_outside_of_fisheye_circle_panics() {
+        let mut c = Camera::new(11, 11, rad(FRAC_PI_2), None);
+        c.set_projection(FisheyeProjection::new_boxed(FRAC_PI_2));
+        c.ray_for_pixel(0, 0);
+    }
+
+    #[test]
+    fn field_of_view_is_that_of_projection() {
+        let mut c = Camera::new(20, 10, rad(FRAC_PI_2), None);
+        c.set_projection(EquirectangularProjection::new_boxed());
+        assert_eq!(c.field_of_view(), None);
+        c.set_projection(FisheyeProjection::new_boxed(FRAC_PI_4));
+        assert_eq!(c.field_of_view(), Some(FRAC_PI_4));
+        assert_eq!(Camera::builder(20, 10).projection(FisheyeProjection::new_boxed(FRAC_PI_2)).field_of_view(deg(200.)).build().unwrap().pixel_size, 0.);
+    }
+
+    #[test]
+    fn fisheye_camera_leaves_outside_of_image_circle_black() {
+        let mut c = camera_facing_default_world();
+        c.set_projection(FisheyeProjection::new_boxed(FRAC_PI_2));
+        let image = c.render_layer(&World::default_world());
+
+        assert!(c.try_ray_for_pixel(0, 0).is_none());
+        assert_eq!(image.pixel_at(0, 0), BLACK);
+        assert_eq!(image.alpha_at(0, 0), 0.);
+        assert_eq!(image.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn equirectangular_camera_sees_behind_itself() {
+        let mut c = Camera::new(200, 100, rad(FRAC_PI_2), Some(Matrix::translation(0., 0., 5.)));
+        c.set_projection(EquirectangularProjection::new_boxed());
+        let r = c.ray_for_pixel(0, 49);
+
+        assert_eq!(r.origin, Tuple::point(0., 0., -5.));
+        assert!(r.direction.z > 0.99);
+    }
+
+    #[test]
+    fn render_leaves_no_pixel_unrendered() {
+        let image = camera_facing_default_world().render(World::default_world());
+
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_ne!(image.pixel_at(x, y), UNRENDERED_COLOR);
+            }
+        }
+    }
+
+    #[test]
+    fn pathtraced_render_is_repeatable() {
+        let mut c = camera_facing_default_world();
+        c.render_mode = RenderMode::PathTraced;
+        c.sampler = StratifiedSampler::new_boxed(2);
+        let first = c.render(World::default_world());
+        let second = c.render(World::default_world());
+
+        assert!(first.pixels().zip(second.pixels()).all(|(a, b)| a == b));
+        assert_ne!(first.pixel_at(5, 5), BLACK);
+        assert_eq!(first.pixel_at(0, 0), BLACK);
+    }
+
+    #[test]
+    fn frame_and_seed_change_the_noise() {
+        let mut c = camera_facing_default_world();
+        c.render_mode = RenderMode::PathTraced;
+        c.sampler = JitteredSampler::new_boxed(2);
+        let first = c.render(World::default_world());
+        c.frame = 1;
+        let next_frame = c.render(World::default_world());
+        c.frame = 0;
+        c.seed = 1;
+        let other_seed = c.render(World::default_world());
+
+        assert_ne!(first.pixel_at(5, 5), next_frame.pixel_at(5, 5));
+        assert_ne!(first.pixel_at(5, 5), other_seed.pixel_at(5, 5));
+        assert_eq!(c.preview(1).sample_id(3, 4, 0), c.sample_id(3, 4, 0));
+        assert_eq!(c.sample_id(3, 4, 5), SampleId { seed: 1, frame: 0, px: 3, py: 4, index: 5 });
+    }
+
+    #[test]
+    fn preview_camera_has_same_view_at_lower_resolution() {
+        let mut c = Camera::new(200, 100, rad(FRAC_PI_2), Some(Matrix::translation(0., 0., 5.)));
+        c.sampler = StratifiedSampler::new_boxed(4);
+        c.render_mode = RenderMode::PathTraced;
+        c.set_projection(FisheyeProjection::new_boxed(FRAC_PI_2));
+        let p = c.preview(4);
+
+        assert_eq!((p.hsize(), p.vsize()), (50, 25));
+        assert_eq!(p.transform(), c.transform());
+        assert_eq!(p.sampler.samples_per_pixel(), 1);
+        assert_eq!(p.render_mode, RenderMode::Whitted);
+        assert!(p.try_ray_for_pixel(0, 0).is_none());
+        assert_eq!(p.ray_for_sample(25, 12, 0., 0.5).direction, c.ray_for_sample(100, 50, 0., 0.).direction);
+    }
+
+    #[test]
+    fn preview_and_full_render_share_prepared_scene() {
+        let c = camera_facing_default_world();
+        let scene = PreparedScene::new(World::default_world());
+        let preview = c.preview(2).render_scene(&scene);
+        let image = c.render_scene(&scene);
+
+        assert_eq!((preview.width, preview.height), (5, 5));
+        assert_eq!(image.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
+    }
+
+    // Every stochastic part of a render draws from the SampleId of its sample, so a render is
+    // the same down to the last bit for the same seed, whichever way it is done
+    #[test]
+    fn seeded_renders_are_reproducible() {
+        let mut c = camera_facing_default_world();
+        c.render_mode = RenderMode::PathTraced;
+        c.sampler = StratifiedSampler::new_boxed(2);
+        c.seed = 1234;
+        c.frame = 3;
+        let scene = PreparedScene::new(World::default_world());
+        let image = c.render_scene(&scene);
+        let mut checkpoint = RenderCheckpoint::new(c.hsize(), c.vsize(), 3);
+        c.render_tiles(&scene, &mut checkpoint, |_| Ok(())).unwrap();
+        let mut same_seed = camera_facing_default_world();
+        same_seed.render_mode = RenderMode::PathTraced;
+        same_seed.sampler = StratifiedSampler::new_boxed(2);
+        same_seed.seed = 1234;
+        same_seed.frame = 3;
+
+        assert_eq!(image.diff(&c.render_scene(&scene)).largest_error(), 0.);
+        assert_eq!(image.diff(checkpoint.image()).largest_error(), 0.);
+        assert_eq!(image.diff(&same_seed.render_scene(&scene)).largest_error(), 0.);
+    }
+
+    #[test]
+    fn rows_rendered_in_parallel_match_pixels_sampled_one_by_one() {
+        let mut c = camera_facing_default_world();
+        c.render_mode = RenderMode::PathTraced;
+        c.sampler = StratifiedSampler::new_boxed(2);
+        let scene = PreparedScene::new(World::default_world());
+        let image = c.render_scene(&scene);
+        let tile = c.render_tile(&scene, &Tile { index: 0, x: 3, y: 2, width: 4, height: 5 });
+        let mut one_by_one = Canvas::new(c.hsize(), c.vsize());
+        for (x, y, pixel) in one_by_one.enumerate_pixels_mut() {
+            *pixel = c.sample_pixel(scene.world(), x, y);
+        }
+
+        assert_eq!(image.diff(&one_by_one).largest_error(), 0.);
+        for (x, y, pixel) in tile.enumerate_pixels() {
+            let expected = one_by_one.pixel_at(3 + x, 2 + y);
+            assert_eq!((pixel.r, pixel.g, pixel.b), (expected.r, expected.g, expected.b));
+        }
+    }
+
+    #[test]
+    fn interrupted_tiled_render_resumes_to_the_same_image() {
+        let c = camera_facing_default_world();
+        let scene = PreparedScene::new(World::default_world());
+        let mut checkpoint = RenderCheckpoint::new(c.hsize(), c.vsize(), 4);
+        let mut tiles_left = 3;
+        let stopped = c.render_tiles(&scene, &mut checkpoint, |_| {
+            tiles_left -= 1;
+            if tiles_left == 0 { Err(io::Error::other("stopped")) } else { Ok(()) }
+        });
+
+        assert!(stopped.is_err());
+        assert_eq!(checkpoint.completed_tiles(), 3);
+        let mut rendered = 0;
+        c.render_tiles(&scene, &mut checkpoint, |_| { rendered += 1; Ok(()) }).unwrap();
+
+        assert_eq!(rendered, checkpoint.tile_count() - 3);
+        assert!(checkpoint.is_complete());
+        assert!(checkpoint.into_image().diff(&c.render_scene(&scene)).approx_eq(0.));
+    }
+
+    #[test]
+    #[should_panic]
+    fn tiled_render_needs_checkpoint_of_same_size() {
+        let c = camera_facing_default_world();
+        let mut checkpoint = RenderCheckpoint::new(c.hsize() + 1, c.vsize(), 4);
+        let _ = c.render_tiles(&PreparedScene::new(World::default_world()), &mut checkpoint, |_| Ok(()));
+    }
+
+    #[test]
+    fn pixels_with_non_finite_colors_are_marked() {
+        let mut w = World::default_world();
+        w.light = Some(PointLight::new(Tuple::point(-10., 10., -10.), Color::new(1., f64::INFINITY, 1.)));
+        w.settings.detect_non_finite = true;
+        let from = Tuple::point(0., 0., -5.);
+        let c = Camera::new(11, 11, rad(FRAC_PI_2), Some(Matrix::view_transform(from, ORIGO, Tuple::vector(0., 1., 0.))));
+        let image = c.render(w);
+
+        assert_eq!(image.pixel_at(5, 5), NON_FINITE_COLOR);
+        assert_eq!(image.pixel_at(0, 0), BLACK);
+    }
+
+    #[test]
+    fn spectral_samples_carry_visible_wavelengths() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(2);
+        assert_eq!(c.sample_ray(5, 5, 0).unwrap().wavelength, None);
+        c.spectral = true;
+        let wavelengths: Vec<f64> = (0..4).map(|i| c.sample_ray(5, 5, i).unwrap().wavelength.unwrap()).collect();
+
+        assert!(wavelengths.iter().all(|w| (MIN_WAVELENGTH..MAX_WAVELENGTH).contains(w)));
+        assert!(wavelengths.iter().any(|&w| w != wavelengths[0]));
+        assert!(!c.preview(2).spectral);
+    }
+
+    #[test]
+    fn preview_of_spectral_camera_keeps_white_scene_grey() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(4);
+        c.spectral = true;
+        let white = Material { color: WHITE, ..Material::default() };
+        let world = World::new(World::default_world().light, vec![Sphere::new_boxed(Some(white), None)]);
+        let preview = c.preview(1).render(world);
+
+        for (_, _, pixel) in preview.enumerate_pixels() {
+            assert!(approx_eq(pixel.r, pixel.g) && approx_eq(pixel.g, pixel.b), "{:?} isn't grey", pixel);
+        }
+        assert!(preview.pixel_at(5, 5).r > 0.5);
+    }
+
+    #[test]
+    fn spectral_render_without_dispersion_converges_to_rgb_render() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(8);
+        let rgb = c.render(World::default_world());
+        c.spectral = true;
+        let diff = c.render(World::default_world()).diff(&rgb);
+
+        assert!(diff.mean_error.max_component() < 0.01, "mean error {:?}", diff.mean_error);
+        // On the edge of the highlight a few samples are much brighter than the rest, and
+        // which wavelengths they get makes a difference
+        assert!(diff.approx_eq(0.25), "max error {:?}", diff.max_error);
+    }
+
+    // A ball of flint glass in front of a black and white striped wall
+    fn prism_world(glass: Material) -> World {
+        let stripes = StripePattern::new_boxed(WHITE, BLACK, Some(Matrix::scaling(0.5, 1., 1.)));
+        let wall = Material::new(WHITE, 1., 0., 0., DEFAULT_SHININESS, Some(stripes));
+        let glass = Material { ambient: 0., diffuse: 0., specular: 0., casts_shadow: false, ..glass };
+        World::new(World::default_world().light, vec![
+            Plane::new_boxed(Some(wall), Some(Matrix::translation(0., 0., 3.) * Matrix::rotation_x(rad(PI / 2.)))),
+            Sphere::new_boxed(Some(glass), None)])
+    }
+
+    // The largest difference between red and blue of any pixel
+    fn largest_tint(image: &Canvas) -> f64 {
+        (0..image.height).flat_map(|y| (0..image.width).map(move |x| (x, y)))
+            .map(|(x, y)| (image.pixel_at(x, y).r - image.pixel_at(x, y).b).abs())
+            .fold(0., f64::max)
+    }
+
+    #[test]
+    fn dispersive_glass_fringes_stripes_with_color() {
+        let mut c = camera_facing_default_world();
+        c.sampler = StratifiedSampler::new_boxed(8);
+        let flint = Material::default().with_transparency(1.).with_dispersion(SF11_GLASS);
+        let rgb = c.render(prism_world(flint.clone()));
+        c.spectral = true;
+        let spectral = c.render(prism_world(flint));
+
+        assert_eq!(largest_tint(&rgb), 0.);
+        assert!(largest_tint(&spectral) > 0.2, "largest tint {}", largest_tint(&spectral));
+    }
+}
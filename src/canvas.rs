@@ -1,34 +1,316 @@
-use std::io::Result;
+use std::fmt;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
 use png::HasParameters;
 use std::fs::File;
 
 use super::color::*;
+use super::error;
+use super::film::FilmResponse;
+
+// Stands out against anything a scene would normally render, so pixels that haven't been
+// rendered yet are easy to spot
+pub const UNRENDERED_COLOR: Color = Color { r: 1., g: 0., b: 1. };
+
+// The thresholds of the pixels of each 4x4 block in dither, ordered so that pixels next to each
+// other are far apart
+const BAYER_MATRIX: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PixelOutOfBounds {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl fmt::Display for PixelOutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pixel ({}, {}) is outside of {}x{} canvas", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl std::error::Error for PixelOutOfBounds {}
+
+// Bits per channel in a saved PNG. 16 bits avoids the banding 8 bits gives in smooth gradients
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen
+}
+
+// How far a canvas is from another one, e g a render from a golden image. Errors are the
+// absolute differences of each channel, with max_error and mean_error over all pixels. A
+// channel that isn't finite in either canvas, unless both are the same infinity, has an
+// infinite error, so that a broken render never passes for a good one.
+pub struct DiffReport {
+    pub max_error: Color,
+    pub mean_error: Color,
+    // Black where the canvases are equal, through red and yellow to white for the largest
+    // finite error, so that where they differ shows up however small the differences are.
+    // Infinite errors are white too.
+    pub heatmap: Canvas
+}
+
+impl DiffReport {
+    // Whether no channel of any pixel differs by more than tolerance
+    pub fn approx_eq(&self, tolerance: f64) -> bool {
+        self.largest_error() <= tolerance
+    }
+
+    pub fn largest_error(&self) -> f64 {
+        self.max_error.max_component()
+    }
+}
 
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
-    canvas: Vec<Vec<Color>>
+    // Row by row from the top left corner, pixel (x, y) is at y * width + x
+    canvas: Vec<Color>,
+    alpha: Vec<f64>
 }
 
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Canvas {
-        let black_row = vec![BLACK; width];
+        Canvas::new_with_alpha(width, height, BLACK, 1.)
+    }
+
+    pub fn new_filled(width: usize, height: usize, color: Color) -> Canvas {
+        Canvas::new_with_alpha(width, height, color, 1.)
+    }
+
+    // A fully transparent canvas, used as the starting point for layers that are composited later
+    pub fn new_transparent(width: usize, height: usize) -> Canvas {
+        Canvas::new_with_alpha(width, height, BLACK, 0.)
+    }
+
+    fn new_with_alpha(width: usize, height: usize, color: Color, alpha: f64) -> Canvas {
         Canvas { 
             width, 
             height,
-            canvas: vec![black_row; height]
+            canvas: vec![color; width * height],
+            alpha: vec![alpha; width * height]
+        }
+    }
+
+    // Sets every pixel to color, fully opaque
+    pub fn fill(&mut self, color: Color) {
+        self.canvas.iter_mut().for_each(|pixel| *pixel = color);
+        self.alpha.iter_mut().for_each(|alpha| *alpha = 1.);
+    }
+
+    // Back to opaque black, as from Canvas::new
+    pub fn clear(&mut self) {
+        self.fill(BLACK);
+    }
+
+    // Sets the pixels of the rectangle with its top left corner at (x, y) to color, fully
+    // opaque. The parts outside of the canvas are left out.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        for row in y..(y + height).min(self.height) {
+            for column in x..(x + width).min(self.width) {
+                let i = self.index(column, row);
+                self.canvas[i] = color;
+                self.alpha[i] = 1.;
+            }
+        }
+    }
+
+    // Copies all of other, colors and alpha, with its top left corner at (x, y), replacing what
+    // was there, e g to put a rendered tile in place. Use composite_over to blend instead. The
+    // parts outside of the canvas are left out.
+    pub fn blit(&mut self, other: &Canvas, x: usize, y: usize) {
+        let columns = other.width.min(self.width.saturating_sub(x));
+        if columns == 0 {
+            return;
+        }
+        for row in 0..other.height.min(self.height.saturating_sub(y)) {
+            let to = self.index(x, y + row);
+            let from = other.index(0, row);
+            self.canvas[to..to + columns].copy_from_slice(&other.canvas[from..from + columns]);
+            self.alpha[to..to + columns].copy_from_slice(&other.alpha[from..from + columns]);
+        }
+    }
+
+    // The check on x is needed since a too large x would otherwise silently end up on the next row
+    fn checked_index(&self, x: usize, y: usize) -> std::result::Result<usize, PixelOutOfBounds> {
+        if x >= self.width || y >= self.height {
+            return Err(PixelOutOfBounds { x, y, width: self.width, height: self.height });
+        }
+        Ok(y * self.width + x)
+    }
+
+    // Panics if (x, y) is outside the canvas
+    fn index(&self, x: usize, y: usize) -> usize {
+        match self.checked_index(x, y) {
+            Ok(i) => i,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    // Coordinates may be negative here, which is convenient when plotting e g a trajectory
+    fn clipped_index(&self, x: isize, y: isize) -> Option<usize> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.checked_index(x as usize, y as usize).ok()
+    }
+
+    pub fn try_pixel_at(&self, x: usize, y: usize) -> std::result::Result<Color, PixelOutOfBounds> {
+        self.checked_index(x, y).map(|i| self.canvas[i])
+    }
+
+    pub fn try_write_pixel(&mut self, x: usize, y: usize, c: Color) -> std::result::Result<(), PixelOutOfBounds> {
+        let i = self.checked_index(x, y)?;
+        self.canvas[i] = c;
+        Ok(())
+    }
+
+    // None outside of the canvas
+    pub fn pixel_at_clipped(&self, x: isize, y: isize) -> Option<Color> {
+        self.clipped_index(x, y).map(|i| self.canvas[i])
+    }
+
+    // Writes outside of the canvas are ignored
+    pub fn write_pixel_clipped(&mut self, x: isize, y: isize, c: Color) {
+        if let Some(i) = self.clipped_index(x, y) {
+            self.canvas[i] = c;
         }
     }
 
     pub fn pixel_at(&self, x: usize, y: usize) -> Color {
-        self.canvas[y][x]
+        self.canvas[self.index(x, y)]
     }
 
     pub fn write_pixel(&mut self, x: usize, y: usize, c: Color) {
-        self.canvas[y][x] = c;
+        let i = self.index(x, y);
+        self.canvas[i] = c;
+    }
+
+    // All pixels, row by row from the top left corner, without copying
+    pub fn as_slice(&self) -> &[Color] {
+        &self.canvas
     }
 
-    fn clamp_to_byte(color_component: f64) -> u8 {
+    // Rows from top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = &[Color]> + '_ {
+        self.canvas.chunks(self.width.max(1))
+    }
+
+    // All pixels, row by row from the top left corner
+    pub fn pixels(&self) -> impl Iterator<Item = &Color> + '_ {
+        self.canvas.iter()
+    }
+
+    // All pixels in the same order as pixels(), with their x and y coordinates
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (usize, usize, &Color)> + '_ {
+        let width = self.width;
+        self.canvas.iter().enumerate().map(move |(i, pixel)| (i % width, i / width, pixel))
+    }
+
+    pub fn enumerate_pixels_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut Color)> + '_ {
+        let width = self.width;
+        self.canvas.iter_mut().enumerate().map(move |(i, pixel)| (i % width, i / width, pixel))
+    }
+
+    pub fn alpha_at(&self, x: usize, y: usize) -> f64 {
+        self.alpha[self.index(x, y)]
+    }
+
+    pub fn write_alpha(&mut self, x: usize, y: usize, alpha: f64) {
+        let i = self.index(x, y);
+        self.alpha[i] = alpha;
+    }
+
+    // Porter-Duff 'over' with top placed on top of this canvas. Colors are premultiplied by alpha.
+    pub fn composite_over(&mut self, top: &Canvas) {
+        if self.width != top.width || self.height != top.height {
+            panic!("Can only composite canvases of the same size");
+        }
+        for i in 0..self.canvas.len() {
+            let top_alpha = top.alpha[i];
+            self.canvas[i] = top.canvas[i] + self.canvas[i] * (1. - top_alpha);
+            self.alpha[i] = top_alpha + self.alpha[i] * (1. - top_alpha);
+        }
+    }
+
+    // Panics if the canvases aren't the same size
+    pub fn diff(&self, other: &Canvas) -> DiffReport {
+        if self.width != other.width || self.height != other.height {
+            panic!("Can only diff canvases of the same size, {}x{} and {}x{}", self.width, self.height, other.width, other.height);
+        }
+        // f64::max would drop NaN, so errors are never NaN
+        let error = |a: f64, b: f64| {
+            let e = (a - b).abs();
+            if a == b { 0. } else if e.is_nan() { f64::INFINITY } else { e }
+        };
+        let errors: Vec<Color> = self.canvas.iter().zip(&other.canvas)
+            .map(|(a, b)| Color::new(error(a.r, b.r), error(a.g, b.g), error(a.b, b.b)))
+            .collect();
+        let max_error = errors.iter().fold(BLACK, |m, e| Color::new(m.r.max(e.r), m.g.max(e.g), m.b.max(e.b)));
+        let sum = errors.iter().fold(BLACK, |sum, e| sum + *e);
+        let mean_error = if errors.is_empty() { BLACK } else { sum * (1. / errors.len() as f64) };
+        let largest = errors.iter().map(|e| e.max_component()).filter(|e| e.is_finite()).fold(0., f64::max);
+        let mut heatmap = Canvas::new(self.width, self.height);
+        for (pixel, e) in heatmap.canvas.iter_mut().zip(&errors) {
+            let t = if e.max_component().is_finite() { 3. * e.max_component() / largest } else { 3. };
+            if t > 0. {
+                *pixel = Color::new(t.min(1.), (t - 1.).clamp(0., 1.), (t - 2.).clamp(0., 1.));
+            }
+        }
+        DiffReport { max_error, mean_error, heatmap }
+    }
+
+    // Layers are given bottom first, i e the last layer ends up on top
+    pub fn composite(layers: &[&Canvas]) -> Canvas {
+        let (first, rest) = match layers.split_first() {
+            Some(split) => split,
+            None => panic!("Need at least one layer to composite")
+        };
+        let mut result = Canvas::new_transparent(first.width, first.height);
+        result.composite_over(first);
+        for layer in rest {
+            result.composite_over(layer);
+        }
+        result
+    }
+
+    // Colors are premultiplied by alpha, so they are mapped without it. Call before encode_srgb,
+    // which expects values in [0, 1].
+    pub fn tonemap(&mut self, response: FilmResponse) {
+        self.map_colors(|c| response.apply(c));
+    }
+
+    // Stores the linear colors with the sRGB curve, as the last step before saving
+    pub fn encode_srgb(&mut self) {
+        self.map_colors(|c| Color::new(srgb_encode(c.r), srgb_encode(c.g), srgb_encode(c.b)));
+    }
+
+    // Ordered dithering for 8 bit output, after encode_srgb as the last step before saving.
+    // Each pixel is nudged by up to half a level by its place in a 4x4 Bayer matrix, so that a
+    // smooth gradient becomes a fine pattern of neighbouring levels instead of visible bands.
+    pub fn dither(&mut self) {
+        let width = self.width;
+        for (i, (color, alpha)) in self.canvas.iter_mut().zip(&self.alpha).enumerate() {
+            if *alpha > 0. {
+                let threshold = (BAYER_MATRIX[i / width % 4][i % width % 4] as f64 + 0.5) / 16.;
+                let offset = (threshold - 0.5) / 256.;
+                *color = (*color * (1. / alpha) + Color::new(offset, offset, offset)) * *alpha;
+            }
+        }
+    }
+
+    fn map_colors<F: Fn(Color) -> Color>(&mut self, f: F) {
+        for (color, alpha) in self.canvas.iter_mut().zip(&self.alpha) {
+            if *alpha > 0. {
+                *color = f(*color * (1. / alpha)) * *alpha;
+            }
+        }
+    }
+
+    pub fn clamp_to_byte(color_component: f64) -> u8 {
         if color_component < 0.0 {
             0u8
         } else if color_component >= 1.0 {
@@ -38,32 +320,267 @@ impl Canvas {
         }
     }
 
+    pub fn clamp_to_u16(color_component: f64) -> u16 {
+        if color_component < 0.0 {
+            0u16
+        } else if color_component >= 1.0 {
+            65535u16
+        } else {
+            (color_component * 65536.) as u16
+        }
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.alpha.iter().all(|a| *a >= 1.)
+    }
+
+    // Samples for a PNG, with alpha if the canvas has any transparency. PNG alpha isn't
+    // premultiplied, so colors are divided by it. 16 bit samples are big endian, as in PNG.
+    fn to_png_bytes(&self, bit_depth: BitDepth) -> Vec<u8> {
+        let with_alpha = !self.is_opaque();
+        let channels = if with_alpha { 4 } else { 3 };
+        let sample_size = if bit_depth == BitDepth::Sixteen { 2 } else { 1 };
+        let mut bytes = Vec::with_capacity(self.width * self.height * channels * sample_size);
+        for (color, alpha) in self.canvas.iter().zip(&self.alpha) {
+            let color = if with_alpha && *alpha > 0. { *color * (1. / alpha) } else { *color };
+            let components = [color.r, color.g, color.b, *alpha];
+            for component in &components[..channels] {
+                match bit_depth {
+                    BitDepth::Eight => bytes.push(Canvas::clamp_to_byte(*component)),
+                    BitDepth::Sixteen => bytes.extend_from_slice(&Canvas::clamp_to_u16(*component).to_be_bytes())
+                }
+            }
+        }
+        bytes
+    }
+
     fn to_rgb_bytes(&self) -> Vec<u8> {
         const BYTES_PER_PIXEL: usize = 3;
-        let mut bytes = vec![0u8; self.width * self.height * BYTES_PER_PIXEL];
-        let mut index = 0;
-        for row in &self.canvas {
-            for color in row {
-                bytes[index] = Canvas::clamp_to_byte(color.r);
-                bytes[index + 1] = Canvas::clamp_to_byte(color.g);
-                bytes[index + 2] = Canvas::clamp_to_byte(color.b);
-                index += 3;
-            }
+        let mut bytes = Vec::with_capacity(self.width * self.height * BYTES_PER_PIXEL);
+        for color in self.pixels() {
+            bytes.extend_from_slice(&[
+                Canvas::clamp_to_byte(color.r),
+                Canvas::clamp_to_byte(color.g),
+                Canvas::clamp_to_byte(color.b)]);
         }
         bytes
     }
 
-    pub fn save(&self, file_name: &str) -> Result<()> {
+    pub fn save(&self, file_name: &str) -> std::result::Result<(), error::Error> {
+        self.save_with_bit_depth(file_name, BitDepth::Eight)
+    }
+
+    // Fails without creating the file if the canvas is empty
+    pub fn save_with_bit_depth(&self, file_name: &str, bit_depth: BitDepth) -> std::result::Result<(), error::Error> {
+        self.check_not_empty()?;
         let file = File::create(file_name)?;
-        let w = &mut std::io::BufWriter::new(file);
+        self.write_png_with_bit_depth(std::io::BufWriter::new(file), bit_depth)
+    }
 
+    // Writes the PNG to any target, e g a buffer in memory or stdout, rather than a file
+    pub fn write_png<W: Write>(&self, w: W) -> std::result::Result<(), error::Error> {
+        self.write_png_with_bit_depth(w, BitDepth::Eight)
+    }
+
+    pub fn write_png_with_bit_depth<W: Write>(&self, w: W, bit_depth: BitDepth) -> std::result::Result<(), error::Error> {
+        self.check_not_empty()?;
         let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
-        encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+        let png_depth = match bit_depth {
+            BitDepth::Eight => png::BitDepth::Eight,
+            BitDepth::Sixteen => png::BitDepth::Sixteen
+        };
+        let color_type = if self.is_opaque() { png::ColorType::RGB } else { png::ColorType::RGBA };
+        encoder.set(color_type).set(png_depth);
         let mut writer = encoder.write_header()?;
 
-        writer.write_image_data(&self.to_rgb_bytes())?; // Save
+        writer.write_image_data(&self.to_png_bytes(bit_depth))?; // Save
         Ok(())
     }
+
+    fn check_not_empty(&self) -> std::result::Result<(), error::Error> {
+        if self.width == 0 || self.height == 0 {
+            return Err(error::Error::EmptyImage { width: self.width, height: self.height });
+        }
+        Ok(())
+    }
+
+    // Plain (P3) PPM as in the book: a header, then the red, green and blue value of each pixel in
+    // 0..255, with every row starting on a new line and no line longer than 70 characters.
+    // Being text, it is easy to compare in tests.
+    pub fn to_ppm(&self) -> String {
+        const MAX_LINE_LENGTH: usize = 70;
+        let mut ppm = format!("P3\n{} {}\n255\n", self.width, self.height);
+        let bytes = self.to_rgb_bytes();
+        for row in bytes.chunks(self.width * 3) {
+            let mut line = String::new();
+            for value in row {
+                let value = value.to_string();
+                if !line.is_empty() && line.len() + 1 + value.len() > MAX_LINE_LENGTH {
+                    ppm.push_str(&line);
+                    ppm.push('\n');
+                    line.clear();
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(&value);
+            }
+            ppm.push_str(&line);
+            ppm.push('\n');
+        }
+        ppm
+    }
+
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_ppm())
+    }
+
+    // Radiance RGBE (.hdr): each pixel is three 8 bit mantissas sharing an 8 bit exponent, so
+    // colors keep their full range (brighter than white, or very dark) for tone mapping or other
+    // processing afterwards. Negative and non finite components become 0. Scanlines are stored
+    // without run length encoding, which all readers accept.
+    pub fn to_hdr(&self) -> Vec<u8> {
+        let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height, self.width).into_bytes();
+        bytes.reserve(self.width * self.height * 4);
+        for color in self.pixels() {
+            bytes.extend_from_slice(&Canvas::to_rgbe(*color));
+        }
+        bytes
+    }
+
+    pub fn save_hdr<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_hdr())
+    }
+
+    fn to_rgbe(color: Color) -> [u8; 4] {
+        let Color { r, g, b } = color.sanitize();
+        let v = r.max(g).max(b);
+        if v < 1e-32 {
+            return [0, 0, 0, 0];
+        }
+        // v = m * 2^exponent with m in [0.5, 1)
+        let mut exponent = v.log2().floor() as i32 + 1;
+        if v / 2f64.powi(exponent) >= 1. {
+            exponent += 1;
+        }
+        let exponent = exponent.clamp(-128, 127);
+        let scale = 256. / 2f64.powi(exponent);
+        let mantissa = |c: f64| (c * scale).min(255.) as u8;
+        [mantissa(r), mantissa(g), mantissa(b), (exponent + 128) as u8]
+    }
+
+    // Reads a JPEG if the file name ends with .jpg or .jpeg, otherwise a PNG
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Canvas> {
+        if is_jpeg(path.as_ref()) { Canvas::load_jpeg(path) } else { Canvas::load_png(path) }
+    }
+
+    // Like load, for an image file that has already been read, e g by a FileResolver. name is
+    // only used to tell JPEG from PNG.
+    pub fn decode<P: AsRef<Path>>(name: P, bytes: &[u8]) -> Result<Canvas> {
+        if is_jpeg(name.as_ref()) { Canvas::read_jpeg(bytes) } else { Canvas::read_png(bytes) }
+    }
+
+    // Reads a grayscale or RGB JPEG image. Images in CMYK are not supported.
+    pub fn load_jpeg<P: AsRef<Path>>(path: P) -> Result<Canvas> {
+        Canvas::read_jpeg(std::io::BufReader::new(File::open(path)?))
+    }
+
+    fn read_jpeg<R: Read>(reader: R) -> Result<Canvas> {
+        let invalid = |e: jpeg_decoder::Error| Error::new(ErrorKind::InvalidData, e.to_string());
+        let mut decoder = jpeg_decoder::Decoder::new(reader);
+        let bytes = decoder.decode().map_err(invalid)?;
+        let info = decoder.info().ok_or_else(|| Error::new(ErrorKind::InvalidData, "JPEG without image information"))?;
+        let (width, height) = (info.width as usize, info.height as usize);
+        let unit: Box<dyn Fn(usize) -> Color> = match info.pixel_format {
+            jpeg_decoder::PixelFormat::L8 => Box::new(|i| {
+                let v = bytes[i] as f64 / 255.;
+                Color::new(v, v, v)
+            }),
+            jpeg_decoder::PixelFormat::L16 => Box::new(|i| {
+                let v = u16::from_ne_bytes([bytes[2 * i], bytes[2 * i + 1]]) as f64 / 65535.;
+                Color::new(v, v, v)
+            }),
+            jpeg_decoder::PixelFormat::RGB24 => Box::new(|i| {
+                let p = &bytes[3 * i..3 * i + 3];
+                Color::new(p[0] as f64 / 255., p[1] as f64 / 255., p[2] as f64 / 255.)
+            }),
+            jpeg_decoder::PixelFormat::CMYK32 => return Err(Error::new(ErrorKind::InvalidData, "CMYK JPEG is not supported"))
+        };
+        let mut canvas = Canvas::new(width, height);
+        for (i, (_, _, color)) in canvas.enumerate_pixels_mut().enumerate() {
+            *color = unit(i);
+        }
+        Ok(canvas)
+    }
+
+    // Reads an 8 or 16 bit PNG image. The alpha channel, if any, is kept with the colors
+    // premultiplied by it, like the rest of the canvas.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Canvas> {
+        Canvas::read_png(File::open(path)?)
+    }
+
+    fn read_png<R: Read>(reader: R) -> Result<Canvas> {
+        let mut decoder = png::Decoder::new(reader);
+        // Keep 16 bit samples instead of stripping them to 8 bits
+        decoder.set(png::Transformations::EXPAND);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut bytes = vec![0u8; info.buffer_size()];
+        reader.next_frame(&mut bytes)?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::RGB => 3,
+            png::ColorType::RGBA => 4,
+            png::ColorType::Indexed => return Err(Error::new(ErrorKind::InvalidData, "Unexpanded indexed PNG"))
+        };
+        // The reported bit depth is always 8 once expanded, but the rows are still 16 bit samples
+        // for a 16 bit image
+        let sample_size = info.line_size / (info.width as usize * channels);
+        let (width, height) = (info.width as usize, info.height as usize);
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            let row = &bytes[y * info.line_size..];
+            for x in 0..width {
+                let pixel_size = channels * sample_size;
+                let pixel = &row[x * pixel_size..(x + 1) * pixel_size];
+                let unit = |i: usize| if sample_size == 2 {
+                    u16::from_be_bytes([pixel[2 * i], pixel[2 * i + 1]]) as f64 / 65535.
+                } else {
+                    pixel[i] as f64 / 255.
+                };
+                let (color, alpha) = match channels {
+                    1 => (Color::new(unit(0), unit(0), unit(0)), 1.),
+                    2 => (Color::new(unit(0), unit(0), unit(0)), unit(1)),
+                    3 => (Color::new(unit(0), unit(1), unit(2)), 1.),
+                    _ => (Color::new(unit(0), unit(1), unit(2)), unit(3))
+                };
+                canvas.write_pixel(x, y, color * alpha);
+                canvas.write_alpha(x, y, alpha);
+            }
+        }
+        Ok(canvas)
+    }
+}
+
+fn is_jpeg(path: &Path) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    matches!(extension.as_deref(), Some("jpg") | Some("jpeg"))
+}
+
+// canvas[(x, y)], panics outside of the canvas like pixel_at
+impl std::ops::Index<(usize, usize)> for Canvas {
+    type Output = Color;
+    fn index(&self, (x, y): (usize, usize)) -> &Color {
+        &self.canvas[Canvas::index(self, x, y)]
+    }
+}
+
+impl std::ops::IndexMut<(usize, usize)> for Canvas {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Color {
+        let i = Canvas::index(self, x, y);
+        &mut self.canvas[i]
+    }
 }
 
 #[cfg(test)]
@@ -77,14 +594,311 @@ mod tests {
 
         assert_eq!(c.width, 10);
         assert_eq!(c.height, 20);
+        assert_eq!(c.alpha_at(9, 19), 1.);
+
+        assert_eq!(c.canvas.len(), 200);
+        for color in c.canvas {
+            assert_eq!(color, BLACK);
+        }
+    }
+
+    #[test]
+    fn creating_filled_canvas() {
+        let c = Canvas::new_filled(4, 3, RED);
 
-        for row in c.canvas {
-            for color in row {
-                assert_eq!(color, BLACK);
+        for y in 0..3 {
+            for x in 0..4 {
+                assert_eq!(c.pixel_at(x, y), RED);
+                assert_eq!(c.alpha_at(x, y), 1.);
             }
         }
     }
 
+    #[test]
+    fn filling_canvas_with_opaque_color() {
+        let mut c = Canvas::new_transparent(3, 2);
+        c.write_pixel(1, 1, RED);
+        c.fill(UNRENDERED_COLOR);
+
+        for y in 0..2 {
+            for x in 0..3 {
+                assert_eq!(c.pixel_at(x, y), UNRENDERED_COLOR);
+                assert_eq!(c.alpha_at(x, y), 1.);
+            }
+        }
+    }
+
+    #[test]
+    fn clearing_canvas_makes_it_like_new() {
+        let mut c = Canvas::new_transparent(3, 2);
+        c.write_pixel(1, 1, RED);
+        c.clear();
+
+        assert_eq!(c.as_slice(), Canvas::new(3, 2).as_slice());
+        assert!(c.is_opaque());
+    }
+
+    #[test]
+    fn filling_rectangle_clips_to_canvas() {
+        let mut c = Canvas::new_transparent(4, 3);
+        c.fill_rect(1, 1, 2, 1, RED);
+        c.fill_rect(3, 2, 5, 5, GREEN);
+
+        assert_eq!(c.pixel_at(0, 1), BLACK);
+        assert_eq!((c.pixel_at(1, 1), c.pixel_at(2, 1)), (RED, RED));
+        assert_eq!(c.alpha_at(2, 1), 1.);
+        assert_eq!(c.pixel_at(3, 1), BLACK);
+        assert_eq!(c.alpha_at(3, 1), 0.);
+        assert_eq!(c.pixel_at(3, 2), GREEN);
+    }
+
+    #[test]
+    fn blitting_copies_colors_and_alpha() {
+        let mut tile = Canvas::new_filled(2, 2, RED);
+        tile.write_alpha(1, 1, 0.5);
+        let mut c = Canvas::new_filled(5, 4, WHITE);
+        c.blit(&tile, 1, 2);
+
+        assert_eq!(c.pixel_at(0, 2), WHITE);
+        assert_eq!((c.pixel_at(1, 2), c.pixel_at(2, 3)), (RED, RED));
+        assert_eq!(c.alpha_at(2, 3), 0.5);
+        assert_eq!(c.pixel_at(3, 3), WHITE);
+        assert_eq!(c.pixel_at(1, 1), WHITE);
+    }
+
+    #[test]
+    fn blitting_clips_to_canvas() {
+        let mut c = Canvas::new(3, 3);
+        c.blit(&Canvas::new_filled(4, 4, BLUE), 2, 1);
+        c.blit(&Canvas::new_filled(2, 2, RED), 5, 5);
+
+        assert_eq!(c.pixel_at(1, 1), BLACK);
+        assert_eq!((c.pixel_at(2, 1), c.pixel_at(2, 2)), (BLUE, BLUE));
+        assert_eq!(c.pixel_at(2, 0), BLACK);
+    }
+
+    #[test]
+    fn blitting_right_of_canvas_changes_nothing() {
+        let mut c = Canvas::new(3, 3);
+        c.blit(&Canvas::new_filled(2, 2, RED), 3, 0);
+        c.blit(&Canvas::new_filled(2, 2, RED), 7, 1);
+
+        assert!(c.pixels().all(|&p| p == BLACK));
+    }
+
+    #[test]
+    fn iterating_rows_and_pixels() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(2, 0, RED);
+        c.write_pixel(0, 1, WHITE);
+        let rows: Vec<&[Color]> = c.rows().collect();
+        let pixels: Vec<Color> = c.pixels().copied().collect();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], &[BLACK, BLACK, RED][..]);
+        assert_eq!(rows[1], &[WHITE, BLACK, BLACK][..]);
+        assert_eq!(pixels, vec![BLACK, BLACK, RED, WHITE, BLACK, BLACK]);
+    }
+
+    #[test]
+    fn modifying_enumerated_pixels() {
+        let mut c = Canvas::new(3, 2);
+        for (x, y, pixel) in c.enumerate_pixels_mut() {
+            *pixel = Color::new(x as f64, y as f64, 0.);
+        }
+
+        assert_eq!(c.pixel_at(2, 0), Color::new(2., 0., 0.));
+        assert_eq!(c.pixel_at(1, 1), Color::new(1., 1., 0.));
+        assert_eq!(c.pixels().count(), 6);
+    }
+
+    #[test]
+    fn enumerating_pixels() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(1, 1, RED);
+        let red: Vec<(usize, usize)> = c.enumerate_pixels().filter(|(_, _, p)| **p == RED).map(|(x, y, _)| (x, y)).collect();
+
+        assert_eq!(red, vec![(1, 1)]);
+        assert_eq!(c.enumerate_pixels().last().map(|(x, y, _)| (x, y)), Some((2, 1)));
+    }
+
+    #[test]
+    fn indexing_pixels() {
+        let mut c = Canvas::new(3, 2);
+        c[(2, 1)] = RED;
+        c[(0, 1)] = c[(2, 1)] * 0.5;
+
+        assert_eq!(c.pixel_at(2, 1), RED);
+        assert_eq!(c[(0, 1)], Color::new(0.5, 0., 0.));
+        assert_eq!(c[(1, 0)], BLACK);
+    }
+
+    #[test]
+    #[should_panic]
+    fn indexing_outside_canvas_panics() {
+        let c = Canvas::new(3, 2);
+        let _ = c[(3, 0)];
+    }
+
+    #[test]
+    fn diff_of_equal_canvases() {
+        let c = Canvas::new_filled(3, 2, RED);
+        let report = c.diff(&c);
+
+        assert_eq!(report.max_error, BLACK);
+        assert!(report.approx_eq(0.));
+        assert!(report.heatmap.pixels().all(|p| *p == BLACK));
+    }
+
+    #[test]
+    fn diff_reports_max_and_mean_error() {
+        let a = Canvas::new_filled(2, 2, Color::new(0.5, 0.5, 0.5));
+        let mut b = Canvas::new_filled(2, 2, Color::new(0.5, 0.5, 0.5));
+        b[(1, 0)] = Color::new(0.9, 0.5, 0.4);
+        b[(0, 1)] = Color::new(0.6, 0.5, 0.5);
+        let report = a.diff(&b);
+
+        assert_eq!(report.max_error, Color::new(0.4, 0., 0.1));
+        assert_eq!(report.mean_error, Color::new(0.125, 0., 0.025));
+        assert!(crate::approx_eq(report.largest_error(), 0.4));
+        assert!(report.approx_eq(0.4 + crate::EPSILON));
+        assert!(!report.approx_eq(0.3));
+    }
+
+    #[test]
+    fn diff_heatmap_is_relative_to_largest_error() {
+        let a = Canvas::new(3, 1);
+        let mut b = Canvas::new(3, 1);
+        b[(1, 0)] = Color::new(0., 0.2, 0.);
+        b[(2, 0)] = Color::new(0., 0., 0.6);
+        let heatmap = a.diff(&b).heatmap;
+
+        assert_eq!(heatmap[(0, 0)], BLACK);
+        assert_eq!(heatmap[(1, 0)], RED);
+        assert_eq!(heatmap[(2, 0)], WHITE);
+    }
+
+    #[test]
+    fn diff_of_non_finite_pixels_is_infinite() {
+        let a = Canvas::new_filled(3, 1, RED);
+        let mut b = Canvas::new_filled(3, 1, RED);
+        b[(0, 0)] = Color::new(f64::NAN, 0., 0.);
+        b[(1, 0)] = Color::new(1., 0., -f64::INFINITY);
+        let report = a.diff(&b);
+
+        assert_eq!((report.max_error.r, report.max_error.g, report.max_error.b), (f64::INFINITY, 0., f64::INFINITY));
+        assert!(!report.approx_eq(1e9));
+        assert!(report.mean_error.r.is_infinite());
+        assert_eq!((report.heatmap[(0, 0)], report.heatmap[(1, 0)], report.heatmap[(2, 0)]), (WHITE, WHITE, BLACK));
+        assert!(Canvas::new_filled(1, 1, Color::new(f64::INFINITY, 0., 0.)).diff(&Canvas::new_filled(1, 1, Color::new(f64::INFINITY, 0., 0.))).approx_eq(0.));
+        assert!(!Canvas::new_filled(1, 1, Color::new(f64::NAN, 0., 0.)).diff(&Canvas::new_filled(1, 1, Color::new(f64::NAN, 0., 0.))).approx_eq(0.));
+    }
+
+    #[test]
+    #[should_panic]
+    fn diff_of_canvases_of_different_sizes_panics() {
+        Canvas::new(3, 2).diff(&Canvas::new(2, 3));
+    }
+
+    #[test]
+    fn canvas_as_slice_is_row_major() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(1, 1, RED);
+
+        assert_eq!(c.as_slice().len(), 6);
+        assert_eq!(c.as_slice()[4], RED);
+    }
+
+    #[test]
+    fn checked_pixel_access() {
+        let mut c = Canvas::new(3, 2);
+
+        assert_eq!(c.try_write_pixel(2, 1, RED), Ok(()));
+        assert_eq!(c.try_pixel_at(2, 1), Ok(RED));
+        let e = PixelOutOfBounds { x: 3, y: 0, width: 3, height: 2 };
+        assert_eq!(c.try_write_pixel(3, 0, RED), Err(e));
+        assert_eq!(c.try_pixel_at(3, 0), Err(e));
+        assert_eq!(c.try_pixel_at(0, 2).unwrap_err().to_string(), "Pixel (0, 2) is outside of 3x2 canvas");
+    }
+
+    #[test]
+    fn clipped_pixel_access_ignores_outside_coordinates() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel_clipped(1, 0, RED);
+        c.write_pixel_clipped(-1, 0, WHITE);
+        c.write_pixel_clipped(3, 1, WHITE);
+        c.write_pixel_clipped(0, 2, WHITE);
+
+        assert_eq!(c.pixel_at_clipped(1, 0), Some(RED));
+        assert_eq!(c.pixel_at_clipped(0, -1), None);
+        assert_eq!(c.pixel_at_clipped(3, 0), None);
+        assert!(c.pixels().all(|&p| p == BLACK || p == RED));
+    }
+
+    #[should_panic]
+    #[test]
+    fn writing_pixel_beyond_row_end_panics() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(3, 0, RED);
+    }
+
+    #[test]
+    fn transparent_canvas_has_zero_alpha() {
+        let c = Canvas::new_transparent(10, 20);
+
+        assert_eq!(c.pixel_at(3, 4), BLACK);
+        assert_eq!(c.alpha_at(3, 4), 0.);
+    }
+
+    #[test]
+    fn compositing_opaque_layer_replaces_pixel() {
+        let mut bottom = Canvas::new(2, 1);
+        bottom.write_pixel(0, 0, RED);
+        bottom.write_pixel(1, 0, RED);
+        let mut top = Canvas::new_transparent(2, 1);
+        top.write_pixel(0, 0, GREEN);
+        top.write_alpha(0, 0, 1.);
+        bottom.composite_over(&top);
+
+        assert_eq!(bottom.pixel_at(0, 0), GREEN);
+        assert_eq!(bottom.pixel_at(1, 0), RED);
+        assert_eq!(bottom.alpha_at(1, 0), 1.);
+    }
+
+    #[test]
+    fn compositing_partially_covered_pixel_blends() {
+        let mut bottom = Canvas::new(1, 1);
+        bottom.write_pixel(0, 0, RED);
+        let mut top = Canvas::new_transparent(1, 1);
+        top.write_pixel(0, 0, GREEN * 0.25);
+        top.write_alpha(0, 0, 0.25);
+        bottom.composite_over(&top);
+
+        assert_eq!(bottom.pixel_at(0, 0), Color::new(0.75, 0.25, 0.));
+        assert_eq!(bottom.alpha_at(0, 0), 1.);
+    }
+
+    #[test]
+    fn composite_stacks_layers_bottom_first() {
+        let mut background = Canvas::new(1, 1);
+        background.write_pixel(0, 0, BLUE);
+        let mut middle = Canvas::new_transparent(1, 1);
+        middle.write_pixel(0, 0, RED * 0.5);
+        middle.write_alpha(0, 0, 0.5);
+        let top = Canvas::new_transparent(1, 1);
+        let result = Canvas::composite(&[&background, &middle, &top]);
+
+        assert_eq!(result.pixel_at(0, 0), Color::new(0.5, 0., 0.5));
+        assert_eq!(result.alpha_at(0, 0), 1.);
+    }
+
+    #[should_panic]
+    #[test]
+    fn compositing_canvases_of_different_size() {
+        let mut bottom = Canvas::new(2, 2);
+        bottom.composite_over(&Canvas::new(1, 2));
+    }
+
     #[test]
     fn writing_pixel_to_canvas() {
         let mut c = Canvas::new(10, 20);
@@ -114,9 +928,305 @@ mod tests {
         let rgb_bytes = c.to_rgb_bytes();
 
         assert_eq!(255u8, rgb_bytes[0]);                                        // clamp the 1.5 r value to 255
-        assert_eq!(128u8, rgb_bytes[(2 + 1 * WIDTH) * BYTES_PER_PIXEL + 1]);     // the .5 g value should be converted to 128 
-        assert_eq!(0u8, rgb_bytes[(4 + 2 * WIDTH) * BYTES_PER_PIXEL + 0]);     // clamp the -.5 r value to 0
+        assert_eq!(128u8, rgb_bytes[(2 + WIDTH) * BYTES_PER_PIXEL + 1]);     // the .5 g value should be converted to 128 
+        assert_eq!(0u8, rgb_bytes[(4 + 2 * WIDTH) * BYTES_PER_PIXEL]);     // clamp the -.5 r value to 0
         assert_eq!(255u8, rgb_bytes[(4 + 2 * WIDTH) * BYTES_PER_PIXEL + 2]);     // the 1.0 b value should be 255
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn load_saved_canvas() {
+        let path = std::env::temp_dir().join(format!("raytracer_load_saved_canvas_{}.png", std::process::id()));
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(0, 0, RED);
+        c.write_pixel(2, 1, WHITE);
+        c.write_pixel(1, 0, Color::new(0.5, 0., 0.));
+        c.save(path.to_str().unwrap()).unwrap();
+        let loaded = Canvas::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width, loaded.height), (3, 2));
+        assert_eq!(loaded.pixel_at(0, 0), RED);
+        assert_eq!(loaded.pixel_at(2, 1), WHITE);
+        assert_eq!(loaded.pixel_at(1, 1), BLACK);
+        assert_eq!(loaded.pixel_at(1, 0), Color::new(128. / 255., 0., 0.));
+        assert_eq!(loaded.alpha_at(1, 0), 1.);
+    }
+
+    #[test]
+    fn sixteen_bit_canvas_keeps_fine_steps() {
+        let path = std::env::temp_dir().join(format!("raytracer_sixteen_bit_canvas_{}.png", std::process::id()));
+        let mut c = Canvas::new(3, 1);
+        c.write_pixel(0, 0, Color::new(0.5, 0.501, 2.));
+        c.write_pixel(1, 0, Color::new(0.502, -1., 1.));
+        c.save_with_bit_depth(path.to_str().unwrap(), BitDepth::Sixteen).unwrap();
+        let loaded = Canvas::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Steps of 0.001 are lost at 8 bits but kept at 16
+        assert!(loaded.pixel_at(0, 0).g > loaded.pixel_at(0, 0).r);
+        assert!(loaded.pixel_at(1, 0).r > loaded.pixel_at(0, 0).g);
+        assert_eq!(loaded.pixel_at(0, 0), Color::new(0.5, 0.501, 1.));
+        assert_eq!(loaded.pixel_at(1, 0), Color::new(0.502, 0., 1.));
+        assert_eq!(loaded.pixel_at(2, 0), BLACK);
+    }
+
+    #[test]
+    fn transparent_canvas_is_saved_with_alpha() {
+        let path = std::env::temp_dir().join(format!("raytracer_transparent_canvas_{}.png", std::process::id()));
+        let mut c = Canvas::new_transparent(3, 1);
+        c.write_pixel(0, 0, RED);
+        c.write_alpha(0, 0, 1.);
+        // Half covered, premultiplied
+        c.write_pixel(1, 0, Color::new(0., 0.25, 0.));
+        c.write_alpha(1, 0, 0.5);
+        assert!(!c.is_opaque());
+        c.save_with_bit_depth(path.to_str().unwrap(), BitDepth::Sixteen).unwrap();
+        let loaded = Canvas::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.pixel_at(0, 0), RED);
+        assert_eq!(loaded.alpha_at(0, 0), 1.);
+        assert_eq!(loaded.pixel_at(1, 0), Color::new(0., 0.25, 0.));
+        assert!(crate::approx_eq(loaded.alpha_at(1, 0), 0.5));
+        assert_eq!(loaded.alpha_at(2, 0), 0.);
+    }
+
+    #[test]
+    fn opaque_canvas_is_saved_without_alpha() {
+        let c = Canvas::new_filled(2, 1, WHITE);
+
+        assert!(c.is_opaque());
+        assert_eq!(c.to_png_bytes(BitDepth::Eight), c.to_rgb_bytes());
+    }
+
+    #[test]
+    fn tone_mapping_keeps_highlights_apart() {
+        let mut c = Canvas::new_filled(2, 1, Color::new(2., 4., 0.5));
+        c.write_pixel(1, 0, Color::new(8., 8., 8.));
+        c.tonemap(FilmResponse::Reinhard { white: f64::INFINITY });
+
+        assert_eq!(c.pixel_at(0, 0), Color::new(2. / 3., 0.8, 1. / 3.));
+        assert!(Canvas::clamp_to_byte(c.pixel_at(1, 0).r) > Canvas::clamp_to_byte(c.pixel_at(0, 0).g));
+    }
+
+    #[test]
+    fn tone_mapping_and_srgb_respect_alpha() {
+        let mut c = Canvas::new_transparent(2, 1);
+        c.write_pixel(0, 0, Color::new(0.5, 0.5, 0.5));
+        c.write_alpha(0, 0, 0.5);
+        c.tonemap(FilmResponse::Reinhard { white: f64::INFINITY });
+
+        assert_eq!(c.pixel_at(0, 0), Color::new(0.25, 0.25, 0.25));
+        c.encode_srgb();
+        assert_eq!(c.pixel_at(0, 0), Color::new(srgb_encode(0.5) * 0.5, srgb_encode(0.5) * 0.5, srgb_encode(0.5) * 0.5));
+        assert_eq!(c.pixel_at(1, 0), BLACK);
+    }
+
+    fn bytes_of_red(c: &Canvas) -> Vec<u8> {
+        c.pixels().map(|p| Canvas::clamp_to_byte(p.r)).collect()
+    }
+
+    #[test]
+    fn dither_mixes_neighbouring_levels_in_proportion() {
+        let mut between = Canvas::new_filled(4, 4, Color::new(100.75 / 256., 0., 0.));
+        let mut middle = Canvas::new_filled(4, 4, Color::new(100.5 / 256., 0., 0.));
+        assert!(bytes_of_red(&between).iter().all(|&b| b == 100));
+        between.dither();
+        middle.dither();
+        let bytes = bytes_of_red(&between);
+
+        assert_eq!(bytes.iter().filter(|&&b| b == 101).count(), 4);
+        assert_eq!(bytes.iter().filter(|&&b| b == 100).count(), 12);
+        // Not two next to each other
+        assert!(bytes.windows(2).all(|w| w != [101, 101]));
+        assert!(bytes_of_red(&middle).iter().all(|&b| b == 100));
+    }
+
+    #[test]
+    fn dither_leaves_transparent_pixels_alone() {
+        let mut c = Canvas::new_transparent(2, 1);
+        c.write_pixel(1, 0, Color::new(0.5, 0.5, 0.5));
+        c.write_alpha(1, 0, 0.5);
+        c.dither();
+
+        assert_eq!(c.pixel_at(0, 0), BLACK);
+        assert!((c.pixel_at(1, 0).r - 0.5).abs() < 0.5 / 256.);
+    }
+
+    #[test]
+    fn write_png_to_memory() {
+        let mut c = Canvas::new(4, 3);
+        c.write_pixel(1, 2, RED);
+        let mut buffer = vec![];
+        c.write_png(&mut buffer).unwrap();
+        let (info, mut reader) = png::Decoder::new(buffer.as_slice()).read_info().unwrap();
+        let mut bytes = vec![0u8; info.buffer_size()];
+        reader.next_frame(&mut bytes).unwrap();
+
+        assert_eq!(&buffer[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!((info.width, info.height), (4, 3));
+        assert_eq!(&bytes[(2 * 4 + 1) * 3..(2 * 4 + 2) * 3], &[255, 0, 0]);
+    }
+
+    #[test]
+    fn saving_empty_canvas_fails() {
+        let path = std::env::temp_dir().join(format!("raytracer_empty_{}.png", std::process::id()));
+
+        assert!(matches!(Canvas::new(0, 3).write_png(&mut vec![]), Err(error::Error::EmptyImage { width: 0, height: 3 })));
+        assert!(Canvas::new(4, 0).save(path.to_str().unwrap()).is_err());
+        assert!(!path.exists());
+        assert!(matches!(Canvas::new(2, 2).save("/nonexistent/directory/image.png"), Err(error::Error::Io(_))));
+    }
+
+    #[test]
+    fn clamping_to_sixteen_bits() {
+        assert_eq!(Canvas::clamp_to_u16(-0.5), 0);
+        assert_eq!(Canvas::clamp_to_u16(0.5), 32768);
+        assert_eq!(Canvas::clamp_to_u16(1.5), 65535);
+    }
+
+    #[test]
+    fn ppm_header() {
+        let ppm = Canvas::new(5, 3).to_ppm();
+
+        assert_eq!(ppm.lines().take(3).collect::<Vec<&str>>(), vec!["P3", "5 3", "255"]);
+    }
+
+    #[test]
+    fn ppm_pixel_data() {
+        let mut c = Canvas::new(5, 3);
+        c.write_pixel(0, 0, Color::new(1.5, 0., 0.));
+        c.write_pixel(2, 1, Color::new(0., 0.5, 0.));
+        c.write_pixel(4, 2, Color::new(-0.5, 0., 1.));
+        let ppm = c.to_ppm();
+
+        assert_eq!(ppm.lines().skip(3).collect::<Vec<&str>>(), vec![
+            "255 0 0 0 0 0 0 0 0 0 0 0 0 0 0",
+            "0 0 0 0 0 0 0 128 0 0 0 0 0 0 0",
+            "0 0 0 0 0 0 0 0 0 0 0 0 0 0 255"]);
+    }
+
+    #[test]
+    fn ppm_splits_long_lines() {
+        let c = Canvas::new_filled(10, 2, Color::new(1., 0.8, 0.6));
+        let ppm = c.to_ppm();
+
+        assert_eq!(ppm.lines().skip(3).collect::<Vec<&str>>(), vec![
+            "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204",
+            "153 255 204 153 255 204 153 255 204 153 255 204 153",
+            "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204",
+            "153 255 204 153 255 204 153 255 204 153 255 204 153"]);
+    }
+
+    #[test]
+    fn ppm_ends_with_newline() {
+        assert!(Canvas::new(5, 3).to_ppm().ends_with('\n'));
+    }
+
+    #[test]
+    fn save_ppm_writes_text() {
+        let path = std::env::temp_dir().join(format!("raytracer_save_ppm_{}.ppm", std::process::id()));
+        let c = Canvas::new_filled(2, 1, RED);
+        c.save_ppm(&path).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), c.to_ppm());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn from_rgbe(rgbe: &[u8]) -> Color {
+        if rgbe[3] == 0 {
+            return BLACK;
+        }
+        let f = 2f64.powi(rgbe[3] as i32 - 128 - 8);
+        Color::new((rgbe[0] as f64 + 0.5) * f, (rgbe[1] as f64 + 0.5) * f, (rgbe[2] as f64 + 0.5) * f)
+    }
+
+    #[test]
+    fn hdr_keeps_colors_beyond_white() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(0, 0, Color::new(12.5, 3., 0.25));
+        c.write_pixel(1, 0, Color::new(0.001, 0.002, 0.0005));
+        c.write_pixel(2, 1, Color::new(-1., f64::NAN, 1.));
+        let hdr = c.to_hdr();
+        let header = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+        let pixels = &hdr[header.len()..];
+
+        assert!(hdr.starts_with(header.as_bytes()));
+        assert_eq!(pixels.len(), 6 * 4);
+        for (i, expected) in [Color::new(12.5, 3., 0.25), Color::new(0.001, 0.002, 0.0005), BLACK].iter().enumerate() {
+            let decoded = from_rgbe(&pixels[i * 4..i * 4 + 4]);
+            let largest = expected.max_component();
+            assert!((decoded.r - expected.r).abs() <= largest / 128., "{:?} should be {:?}", decoded, expected);
+            assert!((decoded.g - expected.g).abs() <= largest / 128.);
+            assert!((decoded.b - expected.b).abs() <= largest / 128.);
+        }
+        assert_eq!(&pixels[20..24], &[0, 0, 128, 129]);
+    }
+
+    #[test]
+    fn rgbe_exponent_at_powers_of_two() {
+        assert_eq!(Canvas::to_rgbe(Color::new(1., 0., 0.)), [128, 0, 0, 129]);
+        assert_eq!(Canvas::to_rgbe(Color::new(0.5, 0.25, 0.)), [128, 64, 0, 128]);
+        assert_eq!(Canvas::to_rgbe(BLACK), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn save_hdr_writes_file() {
+        let path = std::env::temp_dir().join(format!("raytracer_save_hdr_{}.hdr", std::process::id()));
+        let c = Canvas::new_filled(2, 2, Color::new(4., 2., 1.));
+        c.save_hdr(&path).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), c.to_hdr());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn save_jpeg(path: &Path, width: u16, height: u16, rgb: &[u8], color_type: jpeg_encoder::ColorType) {
+        jpeg_encoder::Encoder::new_file(path, 100).unwrap().encode(rgb, width, height, color_type).unwrap();
+    }
+
+    #[test]
+    fn load_rgb_jpeg() {
+        let path = std::env::temp_dir().join(format!("raytracer_load_rgb_jpeg_{}.JPG", std::process::id()));
+        let mut rgb = vec![];
+        for i in 0..16 * 8 {
+            rgb.extend_from_slice(if i % 16 < 8 { &[255, 0, 0] } else { &[0, 0, 255] });
+        }
+        save_jpeg(&path, 16, 8, &rgb, jpeg_encoder::ColorType::Rgb);
+        let loaded = Canvas::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width, loaded.height), (16, 8));
+        // Lossy, and the colors are stored at lower resolution
+        let near = |a: Color, b: Color| (a.r - b.r).abs() < 0.05 && (a.g - b.g).abs() < 0.05 && (a.b - b.b).abs() < 0.05;
+        assert!(near(loaded.pixel_at(2, 3), RED), "{:?}", loaded.pixel_at(2, 3));
+        assert!(near(loaded.pixel_at(13, 4), BLUE), "{:?}", loaded.pixel_at(13, 4));
+        assert_eq!(loaded.alpha_at(0, 0), 1.);
+    }
+
+    #[test]
+    fn load_grayscale_jpeg() {
+        let path = std::env::temp_dir().join(format!("raytracer_load_grayscale_jpeg_{}.jpeg", std::process::id()));
+        save_jpeg(&path, 8, 8, &[128; 64], jpeg_encoder::ColorType::Luma);
+        let loaded = Canvas::load_jpeg(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let c = loaded.pixel_at(4, 4);
+        assert!((c.r - 128. / 255.).abs() < 0.01);
+        assert_eq!((c.r, c.g), (c.g, c.b));
+    }
+
+    #[test]
+    fn load_invalid_jpeg_is_error() {
+        let path = std::env::temp_dir().join(format!("raytracer_load_invalid_jpeg_{}.jpg", std::process::id()));
+        std::fs::write(&path, b"not a jpeg").unwrap();
+        let result = Canvas::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn load_missing_file_is_error() {
+        assert!(Canvas::load("no_such_image.png").is_err());
+    }
+}
@@ -14,6 +14,9 @@ pub const RED: Color = Color { r: 1., g: 0., b: 0. };
 pub const GREEN: Color = Color { r: 0., g: 1., b: 0. };
 pub const BLUE: Color = Color { r: 0., g: 0., b: 1. };
 
+pub const MIN_KELVIN: f64 = 1000.;
+pub const MAX_KELVIN: f64 = 40000.;
+
 impl PartialEq for Color {
     fn eq(&self, other: &Self) -> bool {
         super::approx_eq(self.r, other.r) &&
@@ -66,15 +69,220 @@ impl ops::Mul<Color> for Color {
     }
 }
 
+forward_ref_binop! { impl Add, add for Color, Color }
+forward_ref_binop! { impl Sub, sub for Color, Color }
+forward_ref_binop! { impl Mul, mul for Color, f64 }
+forward_ref_binop! { impl Mul, mul for Color, Color }
+assign_op! { impl AddAssign, add_assign from Add, add for Color, Color }
+assign_op! { impl SubAssign, sub_assign from Sub, sub for Color, Color }
+assign_op! { impl MulAssign, mul_assign from Mul, mul for Color, f64 }
+assign_op! { impl MulAssign, mul_assign from Mul, mul for Color, Color }
+
 impl Color {
     pub fn new(r: f64, g: f64, b: f64) -> Color {
         Color {r, g, b}
     }
+
+    // False for NaN and infinite components, which come from divisions by zero and the like
+    // and would spread through filtering and averaging, see RenderSettings::checks_non_finite
+    pub fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+
+    // Each component clamped to [min, max]
+    pub fn clamp(&self, min: f64, max: f64) -> Color {
+        Color::new(self.r.clamp(min, max), self.g.clamp(min, max), self.b.clamp(min, max))
+    }
+
+    // The displayable range, which cuts highlights off and shifts their hue towards white. See
+    // scale_to_max for keeping the hue, and FilmResponse for rolling highlights off.
+    pub fn clamp01(&self) -> Color {
+        self.clamp(0., 1.)
+    }
+
+    // Scaled down so that no component is above max, keeping the hue, e g to limit fireflies
+    // without tinting them. Colors already within max are left as they are.
+    pub fn scale_to_max(&self, max: f64) -> Color {
+        let largest = self.max_component();
+        if largest > max { *self * (max / largest) } else { *self }
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    pub fn min_component(&self) -> f64 {
+        self.r.min(self.g).min(self.b)
+    }
+
+    // NaN, infinite and negative components set to 0, for storing colors where those can't be
+    // represented, e g in an HDR image
+    pub fn sanitize(&self) -> Color {
+        let component = |c: f64| if c.is_finite() { c.max(0.) } else { 0. };
+        Color::new(component(self.r), component(self.g), component(self.b))
+    }
+
+    // Like + and * followed by clamp01, for combining displayable colors, e g overlays on a
+    // rendered image
+    pub fn saturating_add(&self, other: &Color) -> Color {
+        (*self + *other).clamp01()
+    }
+
+    pub fn saturating_mul(&self, factor: f64) -> Color {
+        (*self * factor).clamp01()
+    }
+
+    // t = 0 gives self and t = 1 gives other
+    pub fn lerp(&self, other: &Color, t: f64) -> Color {
+        *self + (*other - *self) * t
+    }
+
+    // The brightness as the eye sees it, with the Rec. 709 weights for linear sRGB
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    // Components from 0 to 255 as fractions, as they are stored without --srgb. Colors picked
+    // for sRGB output need srgb_decode on each component as well.
+    pub fn from_u8(r: u8, g: u8, b: u8) -> Color {
+        Color::new(r as f64 / 255., g as f64 / 255., b as f64 / 255.)
+    }
+
+    // "#rrggbb" or the short "#rgb", with or without the #, like in CSS, see from_u8
+    pub fn from_hex(hex: &str) -> Result<Color, String> {
+        let digits = hex.trim().trim_start_matches('#');
+        let invalid = || format!("Invalid hex color '{}'", hex);
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let component = |i: usize, len: usize| {
+            let v = u8::from_str_radix(&digits[i * len..(i + 1) * len], 16).unwrap();
+            if len == 1 { v * 17 } else { v }
+        };
+        match digits.len() {
+            3 => Ok(Color::from_u8(component(0, 1), component(1, 1), component(2, 1))),
+            6 => Ok(Color::from_u8(component(0, 2), component(1, 2), component(2, 2))),
+            _ => Err(invalid())
+        }
+    }
+
+    // Hue in degrees [0, 360), saturation and value as fractions
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Color {
+        let h = hue.rem_euclid(360.) / 60.;
+        let chroma = value * saturation;
+        let x = chroma * (1. - (h % 2. - 1.).abs());
+        let (r, g, b) = match h as usize {
+            0 => (chroma, x, 0.),
+            1 => (x, chroma, 0.),
+            2 => (0., chroma, x),
+            3 => (0., x, chroma),
+            4 => (x, 0., chroma),
+            _ => (chroma, 0., x)
+        };
+        let m = value - chroma;
+        Color::new(r + m, g + m, b + m)
+    }
+
+    // The color of a black body at temperature (in Kelvin, clamped to MIN_KELVIN..MAX_KELVIN), as
+    // linear sRGB scaled so that the largest component is 1. Candles are around 1900K, light bulbs
+    // 2700K and daylight 6500K, which is close to white. Found by integrating Planck's law over
+    // the visible spectrum, weighted by the CIE 1931 color matching functions.
+    pub fn from_kelvin(temperature: f64) -> Color {
+        let temperature = temperature.clamp(MIN_KELVIN, MAX_KELVIN);
+        let (mut x, mut y, mut z) = (0., 0., 0.);
+        for nm in (380..=780).step_by(5) {
+            let wavelength = nm as f64;
+            let radiance = planck(wavelength * 1e-9, temperature);
+            let (cx, cy, cz) = cie_matching(wavelength);
+            x += radiance * cx;
+            y += radiance * cy;
+            z += radiance * cz;
+        }
+        let c = xyz_to_linear_srgb(x, y, z).clamp(0., f64::INFINITY);
+        c * (1. / c.r.max(c.g).max(c.b))
+    }
+
+    pub fn to_hsv(&self) -> (f64, f64, f64) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let chroma = max - min;
+        let hue = if chroma == 0. {
+            0.
+        } else if max == self.r {
+            60. * ((self.g - self.b) / chroma).rem_euclid(6.)
+        } else if max == self.g {
+            60. * ((self.b - self.r) / chroma + 2.)
+        } else {
+            60. * ((self.r - self.g) / chroma + 4.)
+        };
+        let saturation = if max == 0. { 0. } else { chroma / max };
+        (hue, saturation, max)
+    }
+
+    // Hue in degrees [0, 360), saturation and lightness as fractions
+    pub fn from_hsl(hue: f64, saturation: f64, lightness: f64) -> Color {
+        let value = lightness + saturation * lightness.min(1. - lightness);
+        let hsv_saturation = if value == 0. { 0. } else { 2. * (1. - lightness / value) };
+        Color::from_hsv(hue, hsv_saturation, value)
+    }
+
+    pub fn to_hsl(&self) -> (f64, f64, f64) {
+        let (hue, hsv_saturation, value) = self.to_hsv();
+        let lightness = value * (1. - hsv_saturation / 2.);
+        let saturation = if lightness == 0. || lightness == 1. { 0. } else { (value - lightness) / lightness.min(1. - lightness) };
+        (hue, saturation, lightness)
+    }
+}
+
+// Spectral radiance of a black body, without the constant factor
+fn planck(wavelength: f64, temperature: f64) -> f64 {
+    const SECOND_RADIATION_CONSTANT: f64 = 1.4388e-2;
+    1. / (wavelength.powi(5) * ((SECOND_RADIATION_CONSTANT / (wavelength * temperature)).exp() - 1.))
+}
+
+// CIE XYZ to linear sRGB, with components outside of [0, 1] for colors outside of the gamut
+pub(crate) fn xyz_to_linear_srgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z)
+}
+
+// The CIE 1931 color matching functions at wavelength in nm, as the sums of piecewise Gaussians
+// fitted by Wyman, Sloan and Shirley (2013)
+pub(crate) fn cie_matching(wavelength: f64) -> (f64, f64, f64) {
+    let g = |mean: f64, below: f64, above: f64| {
+        let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    (1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+     0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+     1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8))
+}
+
+// The sRGB transfer curve, for storing linear values in [0, 1] the way image viewers expect
+pub fn srgb_encode(value: f64) -> f64 {
+    let v = value.clamp(0., 1.);
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_decode(value: f64) -> f64 {
+    let v = value.clamp(0., 1.);
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::approx_eq;
 
     #[test]
     fn colors_are_rgb_tuples()
@@ -133,4 +341,141 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn clamping_interpolating_and_luminance()
+    {
+        let c = Color::new(-0.5, 0.4, 1.7);
+
+        assert_eq!(c.clamp(0., 1.), Color::new(0., 0.4, 1.));
+        assert_eq!(BLACK.lerp(&c, 0.5), Color::new(-0.25, 0.2, 0.85));
+        assert_eq!(c.lerp(&WHITE, 1.), WHITE);
+        assert!(crate::approx_eq(WHITE.luminance(), 1.));
+        assert!(GREEN.luminance() > RED.luminance() && RED.luminance() > BLUE.luminance());
+    }
+
+    #[test]
+    fn range_helpers()
+    {
+        let c = Color::new(-0.5, 0.4, 1.6);
+
+        assert_eq!(c.clamp01(), Color::new(0., 0.4, 1.));
+        assert_eq!((c.max_component(), c.min_component()), (1.6, -0.5));
+        assert_eq!(Color::new(0.8, 0.4, 2.).scale_to_max(1.), Color::new(0.4, 0.2, 1.));
+        assert_eq!(c.scale_to_max(2.), c);
+        assert_eq!(Color::new(f64::NAN, f64::INFINITY, -1.).sanitize(), BLACK);
+        assert_eq!(c.sanitize(), Color::new(0., 0.4, 1.6));
+        assert_eq!(Color::new(0.5, 0.8, 0.).saturating_add(&Color::new(0.25, 0.5, -1.)), Color::new(0.75, 1., 0.));
+        assert_eq!(c.saturating_mul(0.5), Color::new(0., 0.2, 0.8));
+    }
+
+    #[test]
+    fn non_finite_colors()
+    {
+        assert!(Color::new(0.5, 1e300, 0.).is_finite());
+        assert!(!Color::new(f64::NAN, 0., 0.).is_finite());
+        assert!(!Color::new(0., 0., f64::INFINITY).is_finite());
+    }
+
+    #[test]
+    fn hsv_round_trip()
+    {
+        let c = Color::new(0.2, 0.6, 0.4);
+        let (h, s, v) = c.to_hsv();
+
+        assert!((h - 150.).abs() < 1e-9);
+        assert!((s - 2. / 3.).abs() < 1e-9);
+        assert_eq!(v, 0.6);
+        assert_eq!(Color::from_hsv(h, s, v), c);
+    }
+
+    #[test]
+    fn hsv_primaries()
+    {
+        assert_eq!(Color::from_hsv(0., 1., 1.), RED);
+        assert_eq!(Color::from_hsv(120., 1., 1.), GREEN);
+        assert_eq!(Color::from_hsv(-120., 1., 1.), BLUE);
+        assert_eq!(Color::from_hsv(42., 0., 0.5), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn hsl_round_trip()
+    {
+        let c = Color::new(0.2, 0.6, 0.4);
+        let (h, s, l) = c.to_hsl();
+
+        assert!((h - 150.).abs() < 1e-9);
+        assert!((s - 0.5).abs() < 1e-9);
+        assert!((l - 0.4).abs() < 1e-9);
+        assert_eq!(Color::from_hsl(h, s, l), c);
+        assert_eq!(Color::from_hsl(240., 1., 0.5), BLUE);
+        assert_eq!(Color::from_hsl(0., 1., 1.), WHITE);
+        assert_eq!(WHITE.to_hsl(), (0., 0., 1.));
+        assert_eq!(BLACK.to_hsl(), (0., 0., 0.));
+    }
+
+    #[test]
+    fn colors_from_bytes_and_hex_codes()
+    {
+        let amber = Color::new(1., 0.8, 0.);
+
+        assert_eq!(Color::from_u8(255, 204, 0), amber);
+        assert_eq!(Color::from_hex("#ffcc00"), Ok(amber));
+        assert_eq!(Color::from_hex("FFCC00"), Ok(amber));
+        assert_eq!(Color::from_hex("#fc0"), Ok(amber));
+        assert!(Color::from_hex("#ffcc0").is_err());
+        assert!(Color::from_hex("#ffcc0g").is_err());
+        assert!(Color::from_hex("#+fcc0").is_err());
+    }
+
+    #[test]
+    fn colors_of_black_bodies()
+    {
+        let candle = Color::from_kelvin(1900.);
+        let bulb = Color::from_kelvin(2700.);
+        let daylight = Color::from_kelvin(6500.);
+        let sky = Color::from_kelvin(10000.);
+
+        assert!([candle, bulb, daylight].iter().all(|c| crate::approx_eq(c.r, 1.)));
+        assert!(candle.g < bulb.g && bulb.b < 0.15 && bulb.g > bulb.b);
+        assert!(daylight.g > 0.9 && daylight.b > 0.9);
+        assert!(crate::approx_eq(sky.b, 1.) && sky.r < sky.g);
+    }
+
+    #[test]
+    fn kelvin_is_clamped()
+    {
+        assert_eq!(Color::from_kelvin(0.), Color::from_kelvin(MIN_KELVIN));
+        assert_eq!(Color::from_kelvin(1e9), Color::from_kelvin(MAX_KELVIN));
+        assert!(Color::from_kelvin(f64::INFINITY).is_finite());
+    }
+
+    #[test]
+    #[allow(clippy::op_ref)]
+    fn operators_on_references_and_compound_assignment()
+    {
+        let a = Color::new(0.9, 0.6, 0.75);
+        let b = Color::new(0.5, 0.25, 0.5);
+        assert_eq!(a + b, &a + &b);
+        assert_eq!(a - b, a - &b);
+        assert_eq!(a * b, &a * b);
+        assert_eq!(a * 2., &a * 2.);
+
+        let mut c = a;
+        c += b;
+        c -= &b;
+        c *= b;
+        c *= 2.;
+        assert_eq!(a * b * 2., c);
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        assert_eq!(srgb_encode(0.), 0.);
+        assert!(approx_eq(srgb_encode(1.), 1.));
+        assert!(approx_eq(srgb_encode(0.18), 0.461356));
+        for i in 0..=10 {
+            let v = i as f64 / 10.;
+            assert!(approx_eq(srgb_decode(srgb_encode(v)), v));
+        }
+    }
+}
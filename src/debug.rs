@@ -0,0 +1,192 @@
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use super::bounding_box::BoundingBox;
+use super::color::Color;
+use super::intersection::{Intersection, Intersections};
+use super::material::Material;
+use super::matrix::{Matrix, InvertibleMatrix};
+use super::pattern::{BoxPattern, Pattern};
+use super::ray::Ray;
+use super::shape::{BoxShape, Shape};
+use super::tuple::Tuple;
+use super::error::Error;
+
+// A shape for testing code that works with shapes. It remembers the object space rays it is
+// intersected with, hits at configurable distances along them, and has the object space point
+// as its normal (the normal of a unit sphere).
+#[derive(Debug, Clone)]
+pub struct DebugShape {
+    transform: InvertibleMatrix,
+    material: Material,
+    hits: Vec<f64>,
+    // Shared by all clones, so that a test can keep a clone of a shape it has put in a world
+    rays: Arc<Mutex<Vec<Ray>>>
+}
+
+impl PartialEq for DebugShape {
+    fn eq(&self, other: &Self) -> bool {
+        self.transform == other.transform &&
+        self.material == other.material &&
+        self.hits == other.hits
+    }
+}
+
+impl Shape for DebugShape {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn box_clone(&self) -> BoxShape {
+        Box::new((*self).clone())
+    }
+
+    fn inner_intersect(&self, object_ray: Ray) -> Intersections<'_> {
+        self.rays.lock().unwrap().push(object_ray);
+        Intersections::new(self.hits.iter().map(|t| Intersection::new(*t, self)).collect())
+    }
+
+    fn inner_normal_at(&self, object_point: Tuple) -> Tuple {
+        Tuple::vector(object_point.x, object_point.y, object_point.z)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform.matrix()
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.transform.inverse()
+    }
+
+    fn normal_transformation(&self) -> Matrix {
+        self.transform.normal_matrix()
+    }
+
+    fn try_set_transform(&mut self, transform: Matrix) -> Result<(), Error> {
+        self.transform = InvertibleMatrix::try_new(transform)?;
+        Ok(())
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Tuple::point(-1., -1., -1.), Tuple::point(1., 1., 1.))
+    }
+}
+
+impl DebugShape {
+    pub fn new(material: Option<Material>, transform: Option<Matrix>) -> Self {
+        Self {
+            transform: InvertibleMatrix::from(transform),
+            material: material.unwrap_or_default(),
+            hits: vec![],
+            rays: Arc::new(Mutex::new(vec![]))
+        }
+    }
+
+    pub fn new_boxed(material: Option<Material>, transform: Option<Matrix>) -> BoxShape {
+        Box::new(DebugShape::new(material, transform))
+    }
+
+    // Every ray hits the shape at these distances, none by default
+    pub fn with_hits(self, hits: &[f64]) -> Self {
+        Self { hits: hits.to_vec(), ..self }
+    }
+
+    // The object space rays received so far, by this shape and its clones, oldest first
+    pub fn received_rays(&self) -> Vec<Ray> {
+        self.rays.lock().unwrap().clone()
+    }
+
+    pub fn last_ray(&self) -> Option<Ray> {
+        self.rays.lock().unwrap().last().copied()
+    }
+}
+
+// A pattern whose color is the pattern space point, so tests can see which point was looked up
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugPattern {
+    transform: InvertibleMatrix
+}
+
+impl DebugPattern {
+    pub fn new(transform: Option<Matrix>) -> Self {
+        Self { transform: InvertibleMatrix::from(transform) }
+    }
+
+    pub fn new_boxed(transform: Option<Matrix>) -> BoxPattern {
+        Box::new(DebugPattern::new(transform))
+    }
+}
+
+impl Pattern for DebugPattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new((*self).clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform.matrix()
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.transform.inverse()
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        Color::new(pattern_point.x, pattern_point.y, pattern_point.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_shape_records_rays_of_all_clones() {
+        let s = DebugShape::new(None, Some(Matrix::translation(1., 0., 0.)));
+        let copy = s.box_clone();
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        s.intersect(r);
+        copy.intersect(r);
+
+        assert_eq!(s.received_rays().len(), 2);
+        assert_eq!(s.last_ray().unwrap().origin, Tuple::point(-1., 0., -5.));
+    }
+
+    #[test]
+    fn debug_shape_hits_at_configured_distances() {
+        let s = DebugShape::new(None, None).with_hits(&[4., -1.]);
+        let xs = s.intersect(Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)));
+
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, -1.);
+        assert_eq!(xs.hit().unwrap().t, 4.);
+        assert!(*xs[1].object == *s.box_clone());
+    }
+
+    #[test]
+    fn debug_pattern_shows_pattern_point() {
+        let p = DebugPattern::new(Some(Matrix::scaling(2., 2., 2.)));
+
+        assert_eq!(p.inner_pattern_at(Tuple::point(1., 2., 3.)), Color::new(1., 2., 3.));
+        assert_eq!(p.inverse_transformation(), Matrix::scaling(0.5, 0.5, 0.5));
+    }
+}
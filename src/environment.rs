@@ -0,0 +1,67 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use super::canvas::Canvas;
+use super::color::Color;
+use super::tuple::Tuple;
+
+// What is seen in the directions where a ray hits nothing
+#[derive(Clone)]
+pub enum Environment {
+    // Blends from horizon at and below the horizon to zenith straight up
+    SkyGradient { horizon: Color, zenith: Color },
+    // A full 360 degree image with the same layout as EquirectangularProjection, so a scene
+    // rendered with that projection (and an untransformed camera) can be used directly
+    Equirectangular(Arc<Canvas>)
+}
+
+impl Environment {
+    pub fn color_in_direction(&self, direction: Tuple) -> Color {
+        let d = direction.normalize();
+        match self {
+            Environment::SkyGradient { horizon, zenith } => {
+                let t = d.y.max(0.);
+                *horizon + (*zenith - *horizon) * t
+            },
+            Environment::Equirectangular(image) => {
+                let longitude = d.x.atan2(-d.z);
+                let latitude = d.y.clamp(-1., 1.).asin();
+                let u = 0.5 - longitude / (2. * PI);
+                let v = 0.5 - latitude / PI;
+                let x = ((u * image.width as f64) as usize).min(image.width - 1);
+                let y = ((v * image.height as f64) as usize).min(image.height - 1);
+                image.pixel_at(x, y)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{BLACK, WHITE, RED, GREEN, BLUE};
+
+    #[test]
+    fn sky_gradient_blends_towards_zenith() {
+        let sky = Environment::SkyGradient { horizon: WHITE, zenith: BLUE };
+
+        assert_eq!(sky.color_in_direction(Tuple::vector(0., 1., 0.)), BLUE);
+        assert_eq!(sky.color_in_direction(Tuple::vector(1., 0., 0.)), WHITE);
+        assert_eq!(sky.color_in_direction(Tuple::vector(0., -1., 0.)), WHITE);
+        assert_eq!(sky.color_in_direction(Tuple::vector(0., 1., -1.)), Color::new(0.29289, 0.29289, 1.));
+    }
+
+    #[test]
+    fn equirectangular_map_looks_up_by_direction() {
+        let mut image = Canvas::new(4, 2);
+        image.write_pixel(2, 0, RED);
+        image.write_pixel(1, 1, GREEN);
+        image.write_pixel(0, 1, BLUE);
+        let env = Environment::Equirectangular(Arc::new(image));
+
+        assert_eq!(env.color_in_direction(Tuple::vector(0., 0.5, -1.)), RED);
+        assert_eq!(env.color_in_direction(Tuple::vector(1., -0.5, -1.)), GREEN);
+        assert_eq!(env.color_in_direction(Tuple::vector(0.1, -0.5, 1.)), BLUE);
+        assert_eq!(env.color_in_direction(Tuple::vector(0., 0.5, 1.)), BLACK);
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::io;
+
+use super::matrix::Matrix;
+
+// What can go wrong when building or saving a scene, for the fallible (try_) versions of the
+// constructors and setters that otherwise panic
+#[derive(Debug)]
+pub enum Error {
+    // The origin of a ray isn't a point, or its direction isn't a vector
+    InvalidRay(&'static str),
+    // A camera without pixels, or with a field of view it can't have
+    InvalidCamera(&'static str),
+    // A view transform that can't tell which way the camera is looking or which way is up
+    InvalidView(&'static str),
+    // A camera asked for by a name that none of the cameras of the scene has
+    UnknownCamera { name: String, known: Vec<String> },
+    IndexOutOfBounds { row: usize, col: usize, size: usize },
+    // A transformation that can't be inverted, like a scaling by 0
+    NotInvertible(Box<Matrix>),
+    // Transformations that a shape can't move between, since the ones in between can't be
+    // inverted or the decomposition can't represent them
+    InvalidMotion(&'static str),
+    // A shape that can't be transformed after it has been made, by the name of its type
+    FixedTransform(&'static str),
+    // An image without pixels, which PNG can't store
+    EmptyImage { width: usize, height: usize },
+    Io(io::Error)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidRay(reason) => write!(f, "Invalid ray, {}", reason),
+            Error::InvalidCamera(reason) => write!(f, "Invalid camera, {}", reason),
+            Error::InvalidView(reason) => write!(f, "Invalid view transform, {}", reason),
+            Error::UnknownCamera { name, known } => write!(f, "No camera named {}, the cameras are {}", name, known.join(", ")),
+            Error::IndexOutOfBounds { row, col, size } => write!(f, "({}, {}) is outside of a {}x{} matrix", row, col, size, size),
+            Error::NotInvertible(m) => write!(f, "Transformation is not invertible {:?}", m),
+            Error::InvalidMotion(reason) => write!(f, "Invalid motion, {}", reason),
+            Error::FixedTransform(shape) => write!(f, "A {} can't be transformed after it has been made", shape),
+            Error::EmptyImage { width, height } => write!(f, "Can't save an image of {}x{} pixels", width, height),
+            Error::Io(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_are_the_source() {
+        let e = Error::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+
+        assert_eq!(e.to_string(), "no such file");
+        assert!(std::error::Error::source(&e).is_some());
+        assert!(std::error::Error::source(&Error::InvalidRay("direction should be a vector")).is_none());
+    }
+}
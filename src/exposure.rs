@@ -0,0 +1,68 @@
+use super::color::Color;
+
+// ISO 12232 saturation based sensitivity: the luminance that just saturates the sensor is
+// 78 / (S q) with q = 0.65, 1.2 times the luminance 2^EV100 that EV100 is defined by
+const SATURATION_FACTOR: f64 = 1.2;
+
+// The settings of a physical camera, for scenes lit in physical units (see
+// PointLight::from_lumens), where the color that comes out of a surface is its luminance in
+// cd/m². Scales colors so that the luminance that would saturate a sensor of this sensitivity
+// becomes 1, so a scene that looks right with the settings a photographer would use on the real
+// one looks right here too. Scenes without physical lights are already in [0, 1] and should be
+// rendered without an exposure.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Exposure {
+    pub iso: f64,
+    // Seconds
+    pub shutter_time: f64,
+    // The f-number, focal length divided by the diameter of the aperture
+    pub aperture: f64
+}
+
+impl Exposure {
+    pub fn new(iso: f64, shutter_time: f64, aperture: f64) -> Self {
+        Self { iso, shutter_time, aperture }
+    }
+
+    // Sunny 16: f/16 with a shutter time of 1 / ISO for a sunlit scene
+    pub fn sunny_16(iso: f64) -> Self {
+        Exposure::new(iso, 1. / iso, 16.)
+    }
+
+    // The exposure value at ISO 100, higher for brighter scenes
+    pub fn ev100(&self) -> f64 {
+        (self.aperture * self.aperture / self.shutter_time * 100. / self.iso).log2()
+    }
+
+    pub fn scale(&self) -> f64 {
+        1. / (SATURATION_FACTOR * self.ev100().exp2())
+    }
+
+    pub fn apply(&self, luminance: Color) -> Color {
+        luminance * self.scale()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq;
+
+    #[test]
+    fn exposure_value() {
+        assert!(approx_eq(Exposure::new(100., 1., 1.).ev100(), 0.));
+        assert!(approx_eq(Exposure::new(100., 1. / 125., 16.).ev100(), (256. * 125f64).log2()));
+        // Doubling the sensitivity or halving the shutter time needs a stop less light
+        assert!(approx_eq(Exposure::new(200., 1., 1.).ev100(), -1.));
+        assert!(approx_eq(Exposure::new(100., 2., 1.).ev100(), -1.));
+    }
+
+    #[test]
+    fn saturating_luminance_becomes_white() {
+        let e = Exposure::new(400., 1. / 60., 2.8);
+        let saturating = SATURATION_FACTOR * e.ev100().exp2();
+
+        assert_eq!(e.apply(Color::new(saturating, saturating / 2., 0.)), Color::new(1., 0.5, 0.));
+        assert!(approx_eq(Exposure::sunny_16(100.).ev100(), (256f64 * 100.).log2()));
+    }
+}
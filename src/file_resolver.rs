@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Component, Path, PathBuf};
+
+// Finds and reads the files a scene refers to (textures, environment maps, meshes), so loaders
+// don't have to touch the file system themselves. Embedders (tests, WASM) can hand out assets
+// kept in memory instead.
+pub trait FileResolver: Send + Sync {
+    // Where name, as written in the scene, refers to. Also identifies the file when caching it.
+    fn resolve(&self, name: &str) -> PathBuf;
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    fn load(&self, name: &str) -> Result<Vec<u8>> {
+        self.read(&self.resolve(name))
+    }
+}
+
+// Reads from the file system, with relative names taken as relative to base, normally the
+// directory of the scene file, rather than to the current directory
+#[derive(Debug, Clone, Default)]
+pub struct FsResolver {
+    pub base: PathBuf
+}
+
+impl FsResolver {
+    pub fn new<P: AsRef<Path>>(base: P) -> Self {
+        Self { base: base.as_ref().to_path_buf() }
+    }
+
+    // Resolves names relative to the directory scene_file is in
+    pub fn relative_to<P: AsRef<Path>>(scene_file: P) -> Self {
+        Self::new(scene_file.as_ref().parent().unwrap_or_else(|| Path::new("")))
+    }
+}
+
+impl FileResolver for FsResolver {
+    // Canonicalized when the file exists, so that different ways of naming the same file give
+    // the same path
+    fn resolve(&self, name: &str) -> PathBuf {
+        let path = self.base.join(name);
+        fs::canonicalize(&path).unwrap_or(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        fs::read(path)
+    }
+}
+
+// Files kept in memory, under names relative to an imaginary scene directory. "." and ".."
+// are resolved, so "textures/../wood.png" is the same file as "wood.png".
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    files: HashMap<PathBuf, Vec<u8>>
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, contents: Vec<u8>) {
+        self.files.insert(normalize(Path::new(name)), contents);
+    }
+
+    pub fn with_file(mut self, name: &str, contents: Vec<u8>) -> Self {
+        self.insert(name, contents);
+        self
+    }
+}
+
+impl FileResolver for MemoryResolver {
+    fn resolve(&self, name: &str) -> PathBuf {
+        normalize(Path::new(name))
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.files.get(path).cloned()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} is not among the files in memory", path.display())))
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => (),
+            Component::ParentDir => { normalized.pop(); },
+            c => normalized.push(c)
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fs_names_are_relative_to_scene_file() {
+        let dir = std::env::temp_dir().join(format!("raytracer_resolver_{}", std::process::id()));
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        fs::write(dir.join("textures").join("wood.txt"), b"wood").unwrap();
+        let resolver = FsResolver::relative_to(dir.join("scene.yaml"));
+        let wood = resolver.load("textures/wood.txt");
+        let same = resolver.resolve("textures/../textures/wood.txt") == resolver.resolve("textures/wood.txt");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(wood.unwrap(), b"wood");
+        assert!(same);
+        assert!(resolver.load("textures/missing.txt").is_err());
+    }
+
+    #[test]
+    fn memory_files() {
+        let resolver = MemoryResolver::new().with_file("textures/wood.png", vec![1, 2, 3]);
+
+        assert_eq!(resolver.load("textures/wood.png").unwrap(), vec![1, 2, 3]);
+        assert_eq!(resolver.load("./textures/../textures/wood.png").unwrap(), vec![1, 2, 3]);
+        assert_eq!(resolver.load("wood.png").unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
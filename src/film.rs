@@ -0,0 +1,251 @@
+use std::str::FromStr;
+
+use super::color::Color;
+
+// A film response curve, turning the light arriving at a pixel (linear, and unbounded since
+// highlights can be brighter than white) into a value in [0, 1], so that bright highlights
+// roll off instead of clipping. Without one, values are just clipped at 1. Applied to each
+// channel on its own, which desaturates highlights towards white the way film does.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilmResponse {
+    // An S curve with a look, see FilmicCurve
+    Filmic(FilmicCurve),
+    // 1 - e^(-exposure x), like film exposed for that long. Never quite reaches white.
+    Exposure(f64),
+    // x (1 + x / white²) / (1 + x), Reinhard's operator where white is the lowest value that
+    // becomes white. An infinite white gives the simple x / (1 + x).
+    Reinhard { white: f64 },
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces
+}
+
+// The curve is an S: a toe that darkens the shadows slightly, a straight middle and a shoulder
+// that rolls highlights off gently towards white.
+//
+// The shape is John Hable's filmic curve, as used in Uncharted 2:
+//   f(x) = ((x (A x + C B) + D E) / (x (A x + B) + D F)) - E / F
+// normalized so that white_point maps to 1.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FilmicCurve {
+    // A
+    pub shoulder_strength: f64,
+    // B
+    pub linear_strength: f64,
+    // C
+    pub linear_angle: f64,
+    // D
+    pub toe_strength: f64,
+    // E
+    pub toe_numerator: f64,
+    // F
+    pub toe_denominator: f64,
+    // The lowest exposed light that becomes white
+    pub white_point: f64,
+    // Light is multiplied by this before the curve
+    pub exposure: f64
+}
+
+impl FilmResponse {
+    // A neutral filmic look, bright midtones with a long roll off
+    pub fn filmic() -> Self {
+        FilmResponse::Filmic(FilmicCurve {
+            shoulder_strength: 0.15,
+            linear_strength: 0.5,
+            linear_angle: 0.1,
+            toe_strength: 0.2,
+            toe_numerator: 0.02,
+            toe_denominator: 0.3,
+            white_point: 11.2,
+            exposure: 2.
+        })
+    }
+
+    // Deeper shadows and a steeper middle, like slide film
+    pub fn high_contrast() -> Self {
+        FilmResponse::Filmic(FilmicCurve {
+            shoulder_strength: 0.22,
+            linear_strength: 0.3,
+            linear_angle: 0.1,
+            toe_strength: 0.2,
+            toe_numerator: 0.01,
+            toe_denominator: 0.3,
+            white_point: 8.,
+            exposure: 2.
+        })
+    }
+
+    // A flat middle and lifted shadows, like negative film scanned without much contrast
+    pub fn soft() -> Self {
+        FilmResponse::Filmic(FilmicCurve {
+            shoulder_strength: 0.1,
+            linear_strength: 0.6,
+            linear_angle: 0.2,
+            toe_strength: 0.2,
+            toe_numerator: 0.03,
+            toe_denominator: 0.3,
+            white_point: 16.,
+            exposure: 2.
+        })
+    }
+
+    pub fn apply_value(&self, value: f64) -> f64 {
+        let x = value.max(0.);
+        let mapped = match *self {
+            FilmResponse::Filmic(curve) => curve.apply_value(x),
+            FilmResponse::Exposure(exposure) => 1. - (-x * exposure).exp(),
+            FilmResponse::Reinhard { white } => x * (1. + x / (white * white)) / (1. + x),
+            FilmResponse::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+        };
+        mapped.min(1.)
+    }
+
+    pub fn apply(&self, c: Color) -> Color {
+        Color::new(self.apply_value(c.r), self.apply_value(c.g), self.apply_value(c.b))
+    }
+}
+
+impl FilmicCurve {
+    fn curve(&self, x: f64) -> f64 {
+        let a = self.shoulder_strength;
+        let b = self.linear_strength;
+        let c = self.linear_angle;
+        let d = self.toe_strength;
+        let e = self.toe_numerator;
+        let f = self.toe_denominator;
+        (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+    }
+
+    pub fn apply_value(&self, value: f64) -> f64 {
+        let x = (value * self.exposure).max(0.);
+        (self.curve(x) / self.curve(self.white_point)).min(1.)
+    }
+}
+
+// The filmic presets by name, filmic, high-contrast or soft, and the other curves as
+// "reinhard", "reinhard:<white>", "aces" or "exposure:<exposure>"
+impl FromStr for FilmResponse {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let parameter = match parts.next().map(|p| p.trim().parse::<f64>()) {
+            None => None,
+            Some(Ok(p)) if p > 0. => Some(p),
+            Some(_) => return Err(format!("Invalid film response parameter in '{}'", s))
+        };
+        match (name, parameter) {
+            ("filmic", None) => Ok(FilmResponse::filmic()),
+            ("high-contrast", None) => Ok(FilmResponse::high_contrast()),
+            ("soft", None) => Ok(FilmResponse::soft()),
+            ("reinhard", None) => Ok(FilmResponse::Reinhard { white: f64::INFINITY }),
+            ("reinhard", Some(white)) => Ok(FilmResponse::Reinhard { white }),
+            ("aces", None) => Ok(FilmResponse::Aces),
+            ("exposure", Some(exposure)) => Ok(FilmResponse::Exposure(exposure)),
+            _ => Err(format!("Unknown film response '{}'", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq;
+    use crate::color::{BLACK, WHITE};
+
+    fn presets() -> Vec<FilmicCurve> {
+        [FilmResponse::filmic(), FilmResponse::high_contrast(), FilmResponse::soft()].iter()
+            .map(|f| match f {
+                FilmResponse::Filmic(curve) => *curve,
+                _ => unreachable!()
+            })
+            .collect()
+    }
+
+    fn operators() -> Vec<FilmResponse> {
+        vec![FilmResponse::Exposure(1.), FilmResponse::Reinhard { white: f64::INFINITY }, FilmResponse::Reinhard { white: 4. }, FilmResponse::Aces]
+    }
+
+    fn slope(f: &FilmResponse, value: f64) -> f64 {
+        (f.apply_value(value + 0.001) - f.apply_value(value)) / 0.001
+    }
+
+    #[test]
+    fn black_stays_black_and_white_point_is_white() {
+        for curve in presets() {
+            let f = FilmResponse::Filmic(curve);
+            assert_eq!(f.apply(BLACK), BLACK);
+            assert!(approx_eq(f.apply_value(curve.white_point / curve.exposure), 1.));
+            assert_eq!(f.apply_value(100.), 1.);
+        }
+    }
+
+    #[test]
+    fn curves_rise_in_an_s() {
+        for curve in presets() {
+            let f = FilmResponse::Filmic(curve);
+            let brightest = curve.white_point / curve.exposure;
+            let values: Vec<f64> = (0..=100).map(|i| f.apply_value(i as f64 / 100. * brightest)).collect();
+            assert!(values.windows(2).all(|w| w[1] > w[0]));
+            // Highlights are compressed compared to the middle
+            assert!(slope(&f, 3.) < slope(&f, 0.2));
+        }
+    }
+
+    #[test]
+    fn highlights_roll_off_instead_of_clipping() {
+        let f = FilmResponse::filmic();
+
+        assert!(f.apply_value(1.) < f.apply_value(2.));
+        assert!(f.apply(WHITE).r < 1.);
+    }
+
+    #[test]
+    fn high_contrast_is_steeper_than_soft() {
+        assert!(slope(&FilmResponse::high_contrast(), 0.18) > slope(&FilmResponse::soft(), 0.18));
+        assert!(FilmResponse::high_contrast().apply_value(0.02) < FilmResponse::soft().apply_value(0.02));
+    }
+
+    #[test]
+    fn presets_by_name() {
+        assert_eq!("filmic".parse(), Ok(FilmResponse::filmic()));
+        assert_eq!(" high-contrast".parse(), Ok(FilmResponse::high_contrast()));
+        assert_eq!("soft".parse(), Ok(FilmResponse::soft()));
+        assert_eq!("sepia".parse::<FilmResponse>(), Err("Unknown film response 'sepia'".to_string()));
+    }
+
+    #[test]
+    fn operators_keep_black_and_compress_highlights() {
+        for op in operators() {
+            assert!(approx_eq(op.apply(BLACK).r, 0.));
+            let values: Vec<f64> = (0..40).map(|i| op.apply_value(i as f64 * 0.1)).collect();
+            assert!(values.windows(2).all(|w| w[1] >= w[0]), "{:?} should never get darker", op);
+            assert!(op.apply_value(1000.) <= 1.);
+            assert!(op.apply_value(3.) - op.apply_value(2.) < op.apply_value(0.2) - op.apply_value(0.1) + 0.1);
+        }
+    }
+
+    #[test]
+    fn reinhard_white_point_is_white() {
+        assert_eq!(FilmResponse::Reinhard { white: 4. }.apply_value(4.), 1.);
+        assert_eq!(FilmResponse::Reinhard { white: f64::INFINITY }.apply_value(1.), 0.5);
+    }
+
+    #[test]
+    fn exposure_and_aces_values() {
+        assert!(approx_eq(FilmResponse::Exposure(2.).apply_value(0.5), 1. - (-1f64).exp()));
+        assert!(approx_eq(FilmResponse::Aces.apply_value(1.), 2.54 / 3.16));
+        assert_eq!(FilmResponse::Aces.apply_value(100.), 1.);
+    }
+
+    #[test]
+    fn operators_by_name() {
+        assert_eq!("reinhard".parse(), Ok(FilmResponse::Reinhard { white: f64::INFINITY }));
+        assert_eq!("reinhard:4".parse(), Ok(FilmResponse::Reinhard { white: 4. }));
+        assert_eq!(" aces".parse(), Ok(FilmResponse::Aces));
+        assert_eq!("exposure:1.5".parse(), Ok(FilmResponse::Exposure(1.5)));
+        assert_eq!("exposure".parse::<FilmResponse>(), Err("Unknown film response 'exposure'".to_string()));
+        assert_eq!("reinhard:-1".parse::<FilmResponse>(), Err("Invalid film response parameter in 'reinhard:-1'".to_string()));
+        assert_eq!("soft:2".parse::<FilmResponse>(), Err("Unknown film response 'soft:2'".to_string()));
+    }
+}
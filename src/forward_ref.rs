@@ -0,0 +1,57 @@
+// Operators on references for the small Copy types, which are implemented by value. Lets
+// &a + &b, a * &b and so on work without dereferencing at the call site.
+
+// &a op b, a op &b and &a op &b, given a op b
+macro_rules! forward_ref_binop {
+    (impl $imp:ident, $method:ident for $t:ty, $u:ty) => {
+        impl core::ops::$imp<$u> for &$t {
+            type Output = <$t as core::ops::$imp<$u>>::Output;
+            fn $method(self, rhs: $u) -> Self::Output {
+                core::ops::$imp::$method(*self, rhs)
+            }
+        }
+
+        impl core::ops::$imp<&$u> for $t {
+            type Output = <$t as core::ops::$imp<$u>>::Output;
+            fn $method(self, rhs: &$u) -> Self::Output {
+                core::ops::$imp::$method(self, *rhs)
+            }
+        }
+
+        impl core::ops::$imp<&$u> for &$t {
+            type Output = <$t as core::ops::$imp<$u>>::Output;
+            fn $method(self, rhs: &$u) -> Self::Output {
+                core::ops::$imp::$method(*self, *rhs)
+            }
+        }
+    };
+}
+
+// -&a, given -a
+macro_rules! forward_ref_unop {
+    (impl $imp:ident, $method:ident for $t:ty) => {
+        impl core::ops::$imp for &$t {
+            type Output = <$t as core::ops::$imp>::Output;
+            fn $method(self) -> Self::Output {
+                core::ops::$imp::$method(*self)
+            }
+        }
+    };
+}
+
+// a op= b and a op= &b, given a op b
+macro_rules! assign_op {
+    (impl $imp:ident, $method:ident from $op:ident, $op_method:ident for $t:ty, $u:ty) => {
+        impl core::ops::$imp<$u> for $t {
+            fn $method(&mut self, rhs: $u) {
+                *self = core::ops::$op::$op_method(*self, rhs);
+            }
+        }
+
+        impl core::ops::$imp<&$u> for $t {
+            fn $method(&mut self, rhs: &$u) {
+                *self = core::ops::$op::$op_method(*self, *rhs);
+            }
+        }
+    };
+}
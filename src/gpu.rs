@@ -0,0 +1,451 @@
+use std::borrow::Cow;
+use std::sync::mpsc;
+
+use bytemuck::{Pod, Zeroable};
+use log::{debug, warn};
+use wgpu::util::DeviceExt;
+
+use super::camera::{Camera, RenderMode};
+use super::canvas::Canvas;
+use super::color::{Color, BLACK};
+use super::logging::PhaseTimer;
+use super::material::Material;
+use super::matrix::Matrix;
+use super::plane::Plane;
+use super::polygon::Polygon;
+use super::prepared_scene::PreparedScene;
+use super::shape::SharedShape;
+use super::sphere::Sphere;
+use super::world::World;
+
+const SHADER: &str = include_str!("gpu.wgsl");
+const WORKGROUP_SIZE: u32 = 64;
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+const SPHERE: u32 = 0;
+const PLANE: u32 = 1;
+const POLYGON: u32 = 2;
+
+// Bits of GpuObject::shadows
+const CASTS_SHADOW: u32 = 1;
+const RECEIVES_SHADOW: u32 = 2;
+
+// The structs below are laid out like their counterparts in gpu.wgsl
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Params {
+    light_position: [f32; 4],
+    light_intensity: [f32; 4],
+    object_count: u32,
+    ray_count: u32,
+    threads_per_row: u32,
+    _padding: u32
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct CameraRay {
+    origin: [f32; 4],
+    direction: [f32; 4]
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct GpuObject {
+    // Column by column, like WGSL matrices
+    inverse: [[f32; 4]; 4],
+    normal: [[f32; 4]; 4],
+    color: [f32; 4],
+    lighting: [f32; 4],
+    emission: [f32; 4],
+    kind: u32,
+    first_triangle: u32,
+    triangle_count: u32,
+    shadows: u32
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct Triangle {
+    ab: [f32; 4],
+    c: [f32; 4]
+}
+
+// An experimental renderer that intersects and shades the camera rays on the gpu, through wgpu
+// compute shaders, as a start for rendering partly on the gpu and partly on the cpu. Handles
+// spheres, planes and polygons with Phong shading and shadows from the point light, one sample
+// per pixel. Surfaces with patterns or reflections are shaded on the cpu, and frames the gpu
+// can't do at all (see gpu_support) are rendered by Camera::render_scene. Colors are computed in
+// single precision, so they differ from a cpu render in the last few bits.
+pub struct GpuRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline
+}
+
+impl GpuRenderer {
+    // None if there is no gpu that wgpu can use
+    pub fn new() -> Option<GpuRenderer> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        let descriptor = wgpu::DeviceDescriptor {
+            label: Some("raytracer"),
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults()
+        };
+        let (device, queue) = match pollster::block_on(adapter.request_device(&descriptor, None)) {
+            Ok(d) => d,
+            Err(e) => {
+                warn!("Can't use gpu {}: {}", adapter.get_info().name, e);
+                return None;
+            }
+        };
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gpu.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER))
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("primary rays"),
+            layout: None,
+            module: &module,
+            entry_point: "main"
+        });
+        debug!("rendering with gpu {}", adapter.get_info().name);
+        Some(GpuRenderer { device, queue, pipeline })
+    }
+
+    pub fn render(&self, camera: &Camera, scene: &PreparedScene) -> Canvas {
+        let world = scene.world();
+        if let Err(reason) = gpu_support(camera, world) {
+            debug!("rendering on the cpu, the gpu can't do {}", reason);
+            return camera.render_scene(scene);
+        }
+        let timer = PhaseTimer::start("render_gpu");
+        let (objects, triangles) = pack_objects(world.objects(), world);
+        let rays: Vec<CameraRay> = (0..camera.vsize()).flat_map(|y| (0..camera.hsize()).map(move |x| (x, y)))
+            .map(|(x, y)| pack_ray(camera, x, y))
+            .collect();
+        let output = match self.trace(world, &objects, &triangles, &rays) {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("Rendering on the cpu after gpu failure: {}", e);
+                return camera.render_scene(scene);
+            }
+        };
+        let cpu_shaded: Vec<bool> = world.objects().iter().map(|o| needs_cpu_shading(o, world)).collect();
+        let mut cpu_pixels = 0;
+        let mut image = Canvas::new(camera.hsize(), camera.vsize());
+        for ((x, y, pixel), result) in image.enumerate_pixels_mut().zip(&output) {
+            *pixel = if result[3] < 0. {
+                let background = camera.sample_ray(x, y, 0).map_or(BLACK, |r| world.background_color(r));
+                camera.develop(background)
+            } else if cpu_shaded[result[3] as usize] {
+                cpu_pixels += 1;
+                camera.sample_pixel(world, x, y)
+            } else {
+                camera.develop(Color::new(result[0] as f64, result[1] as f64, result[2] as f64))
+            };
+        }
+        timer.finish(&format!("width={} height={} objects={} cpu_pixels={}", camera.hsize(), camera.vsize(), objects.len(), cpu_pixels));
+        image
+    }
+
+    // Color and object index of the hit for each ray, see gpu.wgsl
+    fn trace(&self, world: &World, objects: &[GpuObject], triangles: &[Triangle], rays: &[CameraRay]) -> Result<Vec<[f32; 4]>, String> {
+        let light = world.light.unwrap();
+        let groups = (rays.len() as u32).div_ceil(WORKGROUP_SIZE);
+        let (groups_x, groups_y) = (groups.clamp(1, MAX_WORKGROUPS_PER_DIMENSION), groups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION).max(1));
+        let params = Params {
+            light_position: [light.position.x as f32, light.position.y as f32, light.position.z as f32, 1.],
+            light_intensity: [light.intensity.r as f32, light.intensity.g as f32, light.intensity.b as f32, 0.],
+            object_count: objects.len() as u32,
+            ray_count: rays.len() as u32,
+            threads_per_row: groups_x * WORKGROUP_SIZE,
+            _padding: 0
+        };
+        let output_size = (rays.len().max(1) * std::mem::size_of::<[f32; 4]>()) as u64;
+        let uniform = self.buffer("params", bytemuck::bytes_of(&params), wgpu::BufferUsages::UNIFORM);
+        let rays = self.buffer("rays", &storage_bytes(rays), wgpu::BufferUsages::STORAGE);
+        let objects = self.buffer("objects", &storage_bytes(objects), wgpu::BufferUsages::STORAGE);
+        let triangles = self.buffer("triangles", &storage_bytes(triangles), wgpu::BufferUsages::STORAGE);
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size: output_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: output_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: uniform.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: rays.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: objects.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: triangles.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: output.as_entire_binding() }
+            ]
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, output_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+        let results = bytemuck::cast_slice::<u8, [f32; 4]>(&slice.get_mapped_range())[..params.ray_count as usize].to_vec();
+        readback.unmap();
+        Ok(results)
+    }
+
+    fn buffer(&self, label: &str, contents: &[u8], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents, usage })
+    }
+}
+
+// Why the gpu can't render a frame, if it can't
+pub fn gpu_support(camera: &Camera, world: &World) -> Result<(), String> {
+    if camera.render_mode != RenderMode::Whitted {
+        return Err("path tracing".to_string());
+    }
+    if camera.sampler.samples_per_pixel() != 1 {
+        return Err("more than one sample per pixel".to_string());
+    }
+    if camera.spectral {
+        return Err("spectral rendering".to_string());
+    }
+    match world.light {
+        None => return Err("worlds without a light".to_string()),
+        Some(l) if l.physical => return Err("physical light units".to_string()),
+        _ => ()
+    }
+    if world.environment.is_some() && world.environment_lighting {
+        return Err("environment lighting".to_string());
+    }
+    if world.ambient_light != BLACK {
+        return Err("ambient light".to_string());
+    }
+    if world.settings.checks_non_finite() {
+        return Err("detecting non finite colors".to_string());
+    }
+    for o in world.objects() {
+        if object_kind(o).is_none() {
+            return Err(format!("{} objects", o.type_name()));
+        }
+        if o.transformation_at(0.) != o.transformation_at(1.) {
+            return Err("moving objects".to_string());
+        }
+        // The shader doesn't refract, and takes every object in the way of the light to block
+        // all of it
+        if world.settings.resolve_material(o.material()).transparency > 0. {
+            return Err("transparent objects".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn object_kind(object: &SharedShape) -> Option<u32> {
+    let any = object.as_any();
+    if any.is::<Sphere>() {
+        Some(SPHERE)
+    } else if any.is::<Plane>() {
+        Some(PLANE)
+    } else if any.is::<Polygon>() {
+        Some(POLYGON)
+    } else {
+        None
+    }
+}
+
+// Whether the shader can't shade the object, which it still intersects
+fn needs_cpu_shading(object: &SharedShape, world: &World) -> bool {
+    let material = world.settings.resolve_material(object.material());
+    material.pattern.is_some() || material.is_reflective()
+}
+
+fn pack_objects(objects: &[SharedShape], world: &World) -> (Vec<GpuObject>, Vec<Triangle>) {
+    let mut packed = vec![];
+    let mut triangles = vec![];
+    for o in objects {
+        let material = world.settings.resolve_material(o.material());
+        let first_triangle = triangles.len() as u32;
+        if let Some(p) = o.as_any().downcast_ref::<Polygon>() {
+            triangles.extend(p.triangles().iter().map(|[a, b, c]| Triangle {
+                ab: [a.0 as f32, a.1 as f32, b.0 as f32, b.1 as f32],
+                c: [c.0 as f32, c.1 as f32, 0., 0.]
+            }));
+        }
+        let emission = material.emission();
+        packed.push(GpuObject {
+            inverse: columns(&o.inverse_transformation()),
+            normal: columns(&o.normal_transformation()),
+            color: [material.color.r as f32, material.color.g as f32, material.color.b as f32, material.ambient as f32],
+            lighting: [material.diffuse as f32, material.specular as f32, material.shininess as f32, 0.],
+            emission: [emission.r as f32, emission.g as f32, emission.b as f32, 0.],
+            kind: object_kind(o).unwrap(),
+            first_triangle,
+            triangle_count: triangles.len() as u32 - first_triangle,
+            shadows: shadow_flags(o.material())
+        });
+    }
+    (packed, triangles)
+}
+
+fn shadow_flags(material: &Material) -> u32 {
+    let flag = |set: bool, bit: u32| if set { bit } else { 0 };
+    flag(material.casts_shadow, CASTS_SHADOW) | flag(material.receives_shadow, RECEIVES_SHADOW)
+}
+
+// The ray render_scene would shoot for the pixel, direction.w is 0 if there is none
+fn pack_ray(camera: &Camera, x: usize, y: usize) -> CameraRay {
+    match camera.sample_ray(x, y, 0) {
+        Some(r) => CameraRay {
+            origin: [r.origin.x as f32, r.origin.y as f32, r.origin.z as f32, 1.],
+            direction: [r.direction.x as f32, r.direction.y as f32, r.direction.z as f32, 1.]
+        },
+        None => CameraRay::zeroed()
+    }
+}
+
+fn columns(m: &Matrix) -> [[f32; 4]; 4] {
+    let mut columns = [[0.; 4]; 4];
+    for (c, column) in columns.iter_mut().enumerate() {
+        for (r, value) in column.iter_mut().enumerate() {
+            *value = m[r][c] as f32;
+        }
+    }
+    columns
+}
+
+// wgpu doesn't allow empty buffers to be bound, so empty arrays get one unused element
+fn storage_bytes<T: Pod + Zeroable>(items: &[T]) -> Vec<u8> {
+    if items.is_empty() {
+        bytemuck::bytes_of(&T::zeroed()).to_vec()
+    } else {
+        bytemuck::cast_slice(items).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::FRAC_PI_3;
+    use crate::color::{WHITE, RED};
+    use crate::debug::DebugShape;
+    use crate::environment::Environment;
+    use crate::material::Material;
+    use crate::pattern::StripePattern;
+    use crate::sampler::StratifiedSampler;
+    use crate::shape::Shape;
+    use crate::tuple::Tuple;
+    use crate::world::ObjectId;
+
+    fn camera() -> Camera {
+        let view = Matrix::view_transform(Tuple::point(0., 1.5, -5.), Tuple::point(0., 0.5, 0.), Tuple::vector(0., 1., 0.));
+        Camera::new(32, 24, rad(FRAC_PI_3), Some(view))
+    }
+
+    fn scene() -> World {
+        let mut w = World::default_world();
+        w.add_object(Plane::new_boxed(None, Some(Matrix::translation(0., -1., 0.))));
+        let square = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)];
+        w.add_object(Polygon::new_boxed(&square, &[], None, Some(Matrix::translation(2., 0., 0.) * Matrix::rotation_x(rad(-1.)))));
+        w
+    }
+
+    #[test]
+    fn frames_the_gpu_can_render() {
+        let c = camera();
+        let mut w = scene();
+        assert_eq!(gpu_support(&c, &w), Ok(()));
+
+        w.environment = Some(Environment::SkyGradient { horizon: WHITE, zenith: BLACK });
+        assert_eq!(gpu_support(&c, &w), Ok(()));
+        w.environment_lighting = true;
+        assert_eq!(gpu_support(&c, &w), Err("environment lighting".to_string()));
+
+        let mut w = scene();
+        w.add_object(DebugShape::new_boxed(None, None));
+        assert_eq!(gpu_support(&c, &w), Err("DebugShape objects".to_string()));
+
+        let mut w = scene();
+        w.add_object(Sphere::new(None, None).with_motion(Matrix::translation(1., 0., 0.)).box_clone());
+        assert_eq!(gpu_support(&c, &w), Err("moving objects".to_string()));
+
+        let mut w = scene();
+        w.object_mut(ObjectId::from_number(1)).unwrap().set_material(Material { transparency: 0.5, ..Material::default() });
+        assert_eq!(gpu_support(&c, &w), Err("transparent objects".to_string()));
+        w.object_mut(ObjectId::from_number(1)).unwrap().set_material(Material { transparency: 0.5, casts_shadow: false, ..Material::default() });
+        assert_eq!(gpu_support(&c, &w), Err("transparent objects".to_string()));
+
+        let mut c = camera();
+        c.sampler = StratifiedSampler::new_boxed(2);
+        assert!(gpu_support(&c, &scene()).is_err());
+
+        let mut c = camera();
+        c.spectral = true;
+        assert_eq!(gpu_support(&c, &scene()), Err("spectral rendering".to_string()));
+    }
+
+    #[test]
+    fn objects_are_packed_for_the_shader() {
+        let w = scene();
+        let (objects, triangles) = pack_objects(w.objects(), &w);
+
+        assert_eq!(objects.iter().map(|o| o.kind).collect::<Vec<u32>>(), vec![SPHERE, SPHERE, PLANE, POLYGON]);
+        assert_eq!(objects[1].inverse[3], [0., 0., 0., 1.]);
+        assert_eq!(objects[2].inverse[3], [0., 1., 0., 1.]);
+        assert_eq!((objects[3].first_triangle, objects[3].triangle_count), (0, 2));
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(objects[0].color, [0.8, 1., 0.6, 0.1]);
+    }
+
+    #[test]
+    fn patterned_and_reflective_surfaces_are_shaded_on_the_cpu() {
+        let mut w = scene();
+        w.object_mut(ObjectId::from_number(0)).unwrap().set_material(Material { reflective: Some(WHITE), ..Material::default() });
+        w.object_mut(ObjectId::from_number(1)).unwrap().set_material(Material::new(WHITE, 0.1, 0.9, 0.9, 200., Some(StripePattern::new_boxed(WHITE, BLACK, None))));
+
+        assert_eq!(w.objects().iter().map(|o| needs_cpu_shading(o, &w)).collect::<Vec<bool>>(), vec![true, true, false, false]);
+        w.settings.disable_reflections = true;
+        assert!(!needs_cpu_shading(&w.objects()[0], &w));
+    }
+
+    // Only runs where wgpu finds an adapter, a software one will do
+    #[test]
+    fn gpu_render_matches_cpu_render() {
+        let renderer = match GpuRenderer::new() {
+            Some(r) => r,
+            None => return
+        };
+        let c = camera();
+        let mut w = scene();
+        w.object_mut(ObjectId::from_number(1)).unwrap().set_material(Material { reflective: Some(Color::new(0.5, 0.5, 0.5)), ..Material::default() });
+        w.environment = Some(Environment::SkyGradient { horizon: Color::new(0.9, 0.9, 1.), zenith: Color::new(0.3, 0.5, 1.) });
+        let scene = PreparedScene::new(w);
+
+        assert!(renderer.render(&c, &scene).diff(&c.render_scene(&scene)).approx_eq(1e-3));
+
+        let mut w = scene.world;
+        w.add_object(Sphere::new_boxed(Some(Material { color: RED, transparency: 0.5, ..Material::default() }), Some(Matrix::translation(-1., 1., -1.))));
+        let scene = PreparedScene::new(w);
+
+        assert!(renderer.render(&c, &scene).diff(&c.render_scene(&scene)).approx_eq(1e-3));
+    }
+}
@@ -0,0 +1,184 @@
+// Primary rays against spheres, planes and polygons with Phong shading and shadows from the point
+// light, one invocation per pixel. Mirrors Shape::intersect, Material::lighting and
+// World::is_shadowed in single precision. See gpu.rs for the layout of the buffers.
+
+struct Params {
+    light_position: vec4<f32>,
+    light_intensity: vec4<f32>,
+    object_count: u32,
+    ray_count: u32,
+    threads_per_row: u32,
+    _padding: u32,
+}
+
+// direction.w is 0 for pixels without a ray
+struct CameraRay {
+    origin: vec4<f32>,
+    direction: vec4<f32>,
+}
+
+struct Object {
+    inverse: mat4x4<f32>,
+    normal: mat4x4<f32>,
+    // rgb and ambient
+    color: vec4<f32>,
+    // diffuse, specular and shininess
+    lighting: vec4<f32>,
+    emission: vec4<f32>,
+    kind: u32,
+    first_triangle: u32,
+    triangle_count: u32,
+    // CASTS_SHADOW and RECEIVES_SHADOW bits
+    shadows: u32,
+}
+
+// Corners (x, z) in the plane of a polygon, a and b in ab and c in c.xy
+struct Triangle {
+    ab: vec4<f32>,
+    c: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> rays: array<CameraRay>;
+@group(0) @binding(2) var<storage, read> objects: array<Object>;
+@group(0) @binding(3) var<storage, read> triangles: array<Triangle>;
+// rgb and the index of the object hit, -1 for a miss
+@group(0) @binding(4) var<storage, read_write> output: array<vec4<f32>>;
+
+const SPHERE: u32 = 0u;
+const PLANE: u32 = 1u;
+const POLYGON: u32 = 2u;
+const CASTS_SHADOW: u32 = 1u;
+const RECEIVES_SHADOW: u32 = 2u;
+const EPSILON: f32 = 0.00001;
+// Single precision needs a larger offset than the 1e-5 the cpu uses to keep clear of acne
+const OVER_POINT_EPSILON: f32 = 0.0001;
+const NO_HIT: f32 = 3.0e38;
+
+fn cross2(o: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x);
+}
+
+fn polygon_contains(o: Object, p: vec2<f32>) -> bool {
+    for (var i = 0u; i < o.triangle_count; i = i + 1u) {
+        let t = triangles[o.first_triangle + i];
+        if (cross2(t.ab.xy, t.ab.zw, p) >= -EPSILON && cross2(t.ab.zw, t.c.xy, p) >= -EPSILON && cross2(t.c.xy, t.ab.xy, p) >= -EPSILON) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// The closest distance t >= 0 at which the ray hits object i, NO_HIT if it doesn't
+fn hit_distance(i: u32, origin: vec4<f32>, direction: vec4<f32>) -> f32 {
+    let o = objects[i];
+    let ro = o.inverse * origin;
+    let rd = o.inverse * direction;
+    if (o.kind == SPHERE) {
+        let a = dot(rd.xyz, rd.xyz);
+        let b = 2.0 * dot(rd.xyz, ro.xyz);
+        let c = dot(ro.xyz, ro.xyz) - 1.0;
+        let discriminant = b * b - 4.0 * a * c;
+        if (discriminant < 0.0) {
+            return NO_HIT;
+        }
+        let t1 = (-b - sqrt(discriminant)) / (2.0 * a);
+        let t2 = (-b + sqrt(discriminant)) / (2.0 * a);
+        if (t1 >= 0.0) {
+            return t1;
+        }
+        if (t2 >= 0.0) {
+            return t2;
+        }
+        return NO_HIT;
+    }
+    if (abs(rd.y) < EPSILON * length(rd.xyz)) {
+        return NO_HIT;
+    }
+    let t = -ro.y / rd.y;
+    if (t < 0.0) {
+        return NO_HIT;
+    }
+    if (o.kind == POLYGON && !polygon_contains(o, (ro + rd * t).xz)) {
+        return NO_HIT;
+    }
+    return t;
+}
+
+fn is_shadowed(point: vec3<f32>) -> bool {
+    let v = params.light_position.xyz - point;
+    let distance = length(v);
+    let origin = vec4<f32>(point, 1.0);
+    let direction = vec4<f32>(v / distance, 0.0);
+    for (var i = 0u; i < params.object_count; i = i + 1u) {
+        if ((objects[i].shadows & CASTS_SHADOW) != 0u && hit_distance(i, origin, direction) < distance) {
+            return true;
+        }
+    }
+    return false;
+}
+
+fn shade(i: u32, origin: vec4<f32>, direction: vec4<f32>, t: f32) -> vec3<f32> {
+    let o = objects[i];
+    let point = origin + direction * t;
+    let eyev = -normalize(direction.xyz);
+    var object_normal = vec4<f32>(0.0, 1.0, 0.0, 0.0);
+    if (o.kind == SPHERE) {
+        object_normal = vec4<f32>((o.inverse * point).xyz, 0.0);
+    }
+    var normal = normalize((o.normal * object_normal).xyz);
+    if (dot(normal, eyev) < 0.0) {
+        normal = -normal;
+    }
+    let magnitude = max(max(abs(origin.x), abs(origin.y)), abs(origin.z)) + length(direction.xyz * t);
+    let over_point = point.xyz + normal * max(OVER_POINT_EPSILON * magnitude, 1e-6);
+
+    let effective_color = o.color.rgb * params.light_intensity.rgb;
+    let ambient = effective_color * o.color.w + o.emission.rgb;
+    let lit = o.lighting.x != 0.0 || o.lighting.y != 0.0;
+    let receives_shadow = (o.shadows & RECEIVES_SHADOW) != 0u;
+    if (!lit || (receives_shadow && is_shadowed(over_point))) {
+        return ambient;
+    }
+    let lightv = normalize(params.light_position.xyz - point.xyz);
+    let light_dot_normal = dot(lightv, normal);
+    if (light_dot_normal < 0.0) {
+        return ambient;
+    }
+    let diffuse = effective_color * o.lighting.x * light_dot_normal;
+    let reflect_dot_eye = dot(reflect(-lightv, normal), eyev);
+    var specular = vec3<f32>(0.0, 0.0, 0.0);
+    if (reflect_dot_eye > 0.0) {
+        specular = params.light_intensity.rgb * o.lighting.y * pow(reflect_dot_eye, o.lighting.z);
+    }
+    return ambient + diffuse + specular;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x + id.y * params.threads_per_row;
+    if (index >= params.ray_count) {
+        return;
+    }
+    let ray = rays[index];
+    if (ray.direction.w == 0.0) {
+        output[index] = vec4<f32>(0.0, 0.0, 0.0, -1.0);
+        return;
+    }
+    let origin = vec4<f32>(ray.origin.xyz, 1.0);
+    let direction = vec4<f32>(ray.direction.xyz, 0.0);
+    var nearest = NO_HIT;
+    var hit = -1;
+    for (var i = 0u; i < params.object_count; i = i + 1u) {
+        let t = hit_distance(i, origin, direction);
+        if (t < nearest) {
+            nearest = t;
+            hit = i32(i);
+        }
+    }
+    if (hit < 0) {
+        output[index] = vec4<f32>(0.0, 0.0, 0.0, -1.0);
+        return;
+    }
+    output[index] = vec4<f32>(shade(u32(hit), origin, direction, nearest), f32(hit));
+}
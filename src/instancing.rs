@@ -0,0 +1,114 @@
+use super::color::Color;
+use super::matrix::Matrix;
+use super::rng::Rng;
+use super::shape::BoxShape;
+
+// How much scattered copies of a shape may differ from each other, so that e g rocks or leaves
+// don't look like identical clones. Each amount is the largest change in either direction, and
+// the actual change is picked at random per copy.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Variation {
+    // Hue shift in degrees
+    pub hue: f64,
+    // Change of brightness, as a fraction of the original
+    pub value: f64,
+    // Change of uniform size, as a fraction of the original
+    pub scale: f64
+}
+
+impl Variation {
+    pub fn new(hue: f64, value: f64, scale: f64) -> Self {
+        Self { hue, value, scale }
+    }
+
+    // A copy of base placed with placement, i e transformed by placement * base transformation.
+    // Only the base color of the material varies, patterns are left as they are. Three numbers
+    // are always drawn from rng, so turning one kind of variation on or off does not change
+    // the others.
+    pub fn apply(&self, base: &BoxShape, placement: Matrix, rng: &mut Rng) -> BoxShape {
+        let hue_shift = self.hue * signed_unit(rng);
+        let value_factor = 1. + self.value * signed_unit(rng);
+        let scale_factor = (1. + self.scale * signed_unit(rng)).max(0.);
+
+        let mut instance = base.clone();
+        let mut material = base.material().clone();
+        let (h, s, v) = material.color.to_hsv();
+        material.color = Color::from_hsv(h + hue_shift, s, v * value_factor);
+        instance.set_material(material);
+        let size = Matrix::scaling(scale_factor, scale_factor, scale_factor);
+        instance.set_transform(placement * size * base.transformation());
+        instance
+    }
+}
+
+// One copy of base per placement, varied by variation. The same seed gives the same copies.
+pub fn scatter(base: &BoxShape, placements: &[Matrix], variation: &Variation, seed: u64) -> Vec<BoxShape> {
+    let mut rng = Rng::new(seed);
+    placements.iter().map(|p| variation.apply(base, *p, &mut rng)).collect()
+}
+
+// Uniform in [-1, 1)
+fn signed_unit(rng: &mut Rng) -> f64 {
+    rng.next_f64() * 2. - 1.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::tuple::Tuple;
+
+    fn base() -> BoxShape {
+        let m = Material { color: Color::new(0.2, 0.6, 0.4), ..Material::default() };
+        Sphere::new_boxed(Some(m), Some(Matrix::scaling(2., 2., 2.)))
+    }
+
+    fn placements() -> Vec<Matrix> {
+        (0..10).map(|i| Matrix::translation(i as f64 * 5., 0., 0.)).collect()
+    }
+
+    #[test]
+    fn no_variation_gives_placed_clones() {
+        let copies = scatter(&base(), &placements(), &Variation::default(), 1);
+
+        assert_eq!(copies.len(), 10);
+        assert_eq!(copies[3].material(), base().material());
+        assert_eq!(copies[3].transformation(), Matrix::translation(15., 0., 0.) * Matrix::scaling(2., 2., 2.));
+    }
+
+    #[test]
+    fn variation_stays_within_limits() {
+        let variation = Variation::new(20., 0.25, 0.5);
+        let (h, s, v) = base().material().color.to_hsv();
+        for copy in scatter(&base(), &placements(), &variation, 7) {
+            let (ch, cs, cv) = copy.material().color.to_hsv();
+            let scale = copy.transformation().decompose().scale.x;
+
+            assert!((ch - h).abs() <= 20. + 1e-9);
+            assert!((cs - s).abs() < 1e-9);
+            assert!(cv >= v * 0.75 - 1e-9 && cv <= v * 1.25 + 1e-9);
+            assert!((1. ..=3.).contains(&scale));
+        }
+    }
+
+    #[test]
+    fn copies_differ_but_seed_repeats() {
+        let variation = Variation::new(20., 0.25, 0.5);
+        let a = scatter(&base(), &placements(), &variation, 7);
+        let b = scatter(&base(), &placements(), &variation, 7);
+        let c = scatter(&base(), &placements(), &variation, 8);
+
+        assert!(a == b);
+        assert!(a != c);
+        assert_ne!(a[0].material().color, a[1].material().color);
+    }
+
+    #[test]
+    fn scale_variation_is_around_placement_origin() {
+        let variation = Variation::new(0., 0., 0.5);
+        let copy = scatter(&base(), &[Matrix::translation(5., 0., 0.)], &variation, 3).remove(0);
+
+        assert_eq!(copy.transformation().decompose().translation, Tuple::vector(5., 0., 0.));
+    }
+}
@@ -1,99 +1,383 @@
 use core::ops;
-use super::EPSILON;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use super::scaled_epsilon;
 use super::shape::*;
 use super::ray::Ray;
 use super::precomputed_data::PrecomputedData;
+use super::material::DEFAULT_REFRACTIVE_INDEX;
+use super::world::ObjectId;
+
+// Which of the triangles making up a shape was hit, and where on it. u and v are the
+// barycentric weights of its second and third corner, so the hit is at
+// (1 - u - v) * first + u * second + v * third. Kept in 12 bytes, since every intersection has
+// room for one; f32 is plenty for looking up colors and texture coordinates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PrimitiveHit {
+    face_index: u32,
+    u: f32,
+    v: f32
+}
+
+impl PrimitiveHit {
+    pub fn new(face_index: usize, u: f64, v: f64) -> Self {
+        let face_index = u32::try_from(face_index).expect("a shape should have fewer than 2^32 triangles");
+        Self { face_index, u: u as f32, v: v as f32 }
+    }
 
-#[derive(Debug, Clone)]
-pub struct Intersection {
+    pub fn face_index(&self) -> usize {
+        self.face_index as usize
+    }
+
+    pub fn u(&self) -> f64 {
+        self.u as f64
+    }
+
+    pub fn v(&self) -> f64 {
+        self.v as f64
+    }
+}
+
+// Borrows the shape that was hit, so that intersections cost no allocation of their own
+#[derive(Debug, Clone, Copy)]
+pub struct Intersection<'a> {
     pub t: f64,
-    pub object: BoxShape
+    pub object: &'a dyn Shape,
+    // Only for shapes made of triangles
+    pub primitive: Option<PrimitiveHit>,
+    // Set by World::intersect, None for intersections with shapes on their own
+    pub object_id: Option<ObjectId>
 }
 
-impl PartialEq for Intersection {
+impl PartialEq for Intersection<'_> {
     fn eq(&self, other: &Intersection) -> bool {
         self.t == other.t &&
-        &self.object == &other.object
+        self.object == other.object &&
+        self.primitive == other.primitive
     }
 }
 
-impl Intersection {
-    pub fn new(t: f64, object: BoxShape) -> Self {
-        Intersection { t, object }
+impl<'a> Intersection<'a> {
+    pub fn new(t: f64, object: &'a dyn Shape) -> Self {
+        Intersection { t, object, primitive: None, object_id: None }
+    }
+
+    pub fn with_primitive(self, primitive: PrimitiveHit) -> Self {
+        Self { primitive: Some(primitive), ..self }
     }
 
-    pub fn prepare_computations(&self, ray: Ray) -> PrecomputedData {
+    pub fn prepare_computations(&self, ray: Ray) -> PrecomputedData<'a> {
         let point = ray.position(self.t);
-        let eyev = -ray.direction;
-        let mut normalv = self.object.normal_at(point);
+        // Lighting assumes unit vectors, and the caller's ray may not have a unit direction
+        let eyev = -ray.direction.normalize();
+        let mut normalv = self.object.normal_at_time(point, ray.time);
         let inside = if normalv.dot(&eyev) < 0. {
             normalv = -normalv;
             true
         } else {
             false
         };
-        let over_point = point + normalv * EPSILON;
-
-        PrecomputedData::new(
-            self.t,
-            self.object.clone(),
+        // The accuracy of point depends on how far the ray travelled and where it started, so
+        // the offset has to follow the scale of the scene rather than being a fixed distance
+        let origin = ray.origin;
+        let magnitude = origin.x.abs().max(origin.y.abs()).max(origin.z.abs()) + (ray.direction * self.t).magnitude();
+        let offset = normalv * scaled_epsilon(magnitude);
+        let reflectv = ray.direction.reflect(normalv).normalize();
+        // Without the other intersections along the ray there is nothing to tell what surrounds
+        // the object, so it is taken to be vacuum
+        let n = self.object.material().refractive_index_at(ray.wavelength);
+        let (n1, n2) = if inside { (n, DEFAULT_REFRACTIVE_INDEX) } else { (DEFAULT_REFRACTIVE_INDEX, n) };
+
+        PrecomputedData {
+            t: self.t,
+            time: ray.time,
+            wavelength: ray.wavelength,
+            object: self.object,
             point,
             eyev,
             normalv,
             inside,
-            over_point
-        )
+            over_point: point + offset,
+            under_point: point - offset,
+            reflectv,
+            n1,
+            n2,
+            primitive: self.primitive
+        }
+    }
+
+    // Like prepare_computations, with n1 and n2 from the objects the ray is inside of at the
+    // hit according to xs, so that transparent objects can be nested in or touch each other. xs
+    // has to be all the intersections along ray, with self among them. The indices are those of
+    // the materials of the objects, before any RenderSettings overrides.
+    pub fn prepare_computations_with(&self, ray: Ray, xs: &Intersections<'a>) -> PrecomputedData<'a> {
+        let mut comps = self.prepare_computations(ray);
+        if let Some((n1, n2)) = self.refractive_indices(xs, ray.wavelength) {
+            comps.n1 = n1;
+            comps.n2 = n2;
+        }
+        comps
     }
+
+    // Walks xs up to self keeping track of the objects the ray is inside of, the last of which
+    // it is in at each point. None if self isn't in xs.
+    fn refractive_indices(&self, xs: &Intersections<'a>, wavelength: Option<f64>) -> Option<(f64, f64)> {
+        let mut containers: Vec<&dyn Shape> = vec![];
+        let innermost = |containers: &[&dyn Shape]| containers.last()
+            .map_or(DEFAULT_REFRACTIVE_INDEX, |o| o.material().refractive_index_at(wavelength));
+        for i in xs.iter() {
+            let is_hit = i.t == self.t && std::ptr::addr_eq(i.object, self.object);
+            let n1 = innermost(&containers);
+            match containers.iter().position(|o| std::ptr::addr_eq(*o, i.object)) {
+                Some(p) => { containers.remove(p); },
+                None => containers.push(i.object)
+            }
+            if is_hit {
+                return Some((n1, innermost(&containers)));
+            }
+        }
+        None
+    }
+}
+
+// Most rays hit no more than a few objects, so up to INLINE_CAPACITY intersections are kept
+// in place instead of in a separately allocated vector
+const INLINE_CAPACITY: usize = 4;
+// Lists up to this long are sorted by insertion, which is faster than a general sort for them
+const INSERTION_SORT_MAX: usize = 16;
+
+#[derive(Debug)]
+enum Storage<'a> {
+    // The slots from len on hold copies of earlier intersections, for lack of anything else to
+    // fill them with, so a list starts out as an empty vector, which doesn't allocate either
+    Inline([Intersection<'a>; INLINE_CAPACITY], usize),
+    Heap(Vec<Intersection<'a>>)
 }
 
 #[derive(Debug)]
-pub struct Intersections {
-    inner: Vec<Intersection>,
-    current_hit: Option<Intersection>
+pub struct Intersections<'a> {
+    storage: Storage<'a>,
+    current_hit: Option<Intersection<'a>>
 }
 
-impl ops::Index<usize> for Intersections {
-    type Output = Intersection;
+impl<'a> ops::Index<usize> for Intersections<'a> {
+    type Output = Intersection<'a>;
     fn index(&self, i: usize) -> &Self::Output {
-        &self.inner[i]
+        &self.as_slice()[i]
     }
 }
 
-impl Intersections {
+impl<'a> Intersections<'a> {
+
+    // NaN distances are sorted last and are never the hit
+    pub fn new(range: Vec<Intersection<'a>>) -> Intersections<'a> {
+        let storage = match range.first() {
+            Some(first) if range.len() <= INLINE_CAPACITY => {
+                let mut items = [*first; INLINE_CAPACITY];
+                items[..range.len()].copy_from_slice(&range);
+                Storage::Inline(items, range.len())
+            },
+            _ => Storage::Heap(range)
+        };
+        Intersections::sorted(storage)
+    }
+
+    pub fn empty() -> Intersections<'a> {
+        Intersections { storage: Storage::Heap(Vec::new()), current_hit: None }
+    }
+
+    // Room for capacity intersections without allocating again as they are added by extend
+    pub fn with_capacity(capacity: usize) -> Intersections<'a> {
+        if capacity <= INLINE_CAPACITY {
+            Intersections::empty()
+        } else {
+            Intersections { storage: Storage::Heap(Vec::with_capacity(capacity)), current_hit: None }
+        }
+    }
+
+    // Removes all intersections but keeps any allocated room, for reusing the list for another ray
+    pub fn clear(&mut self) {
+        match &mut self.storage {
+            Storage::Inline(_, len) => *len = 0,
+            Storage::Heap(items) => items.clear()
+        }
+        self.current_hit = None;
+    }
 
-    pub fn new(range: Vec<Intersection>) -> Intersections {
-        let mut xs = Intersections { inner: range, current_hit: None };
-        xs.inner.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
-        for i in xs.inner.iter() {
-            if i.t >= 0. { 
-                xs.current_hit = Some(i.clone());
-                break;
-            };
+    // Like new, without allocating for up to INLINE_CAPACITY intersections
+    pub fn from_array<const N: usize>(range: [Intersection<'a>; N]) -> Intersections<'a> {
+        let mut xs = Intersections::empty();
+        for i in IntoIterator::into_iter(range) {
+            xs.push(i);
         }
+        Intersections::sorted(xs.storage)
+    }
+
+    fn sorted(storage: Storage<'a>) -> Intersections<'a> {
+        let mut xs = Intersections { storage, current_hit: None };
+        xs.sort();
+        let hit = xs.hits().next().copied();
+        xs.current_hit = hit;
         xs
     }
 
-    pub fn extend(&mut self, range: Intersections) {
-        self.inner.extend(range.inner);
-        match range.current_hit {
-            Some(range_hit) =>
-                match &self.current_hit {
-                    None => self.current_hit = Some(range_hit.clone()),
-                    Some(i) => if i.t > range_hit.t { self.current_hit = Some(range_hit.clone());}
-                }
-            _ => ()
+    // Both lists are sorted already, so range is merged in rather than everything sorted again
+    pub fn extend(&mut self, range: Intersections<'a>) {
+        if let Some(range_hit) = range.current_hit {
+            match &self.current_hit {
+                None => self.current_hit = Some(range_hit),
+                Some(i) => if i.t > range_hit.t { self.current_hit = Some(range_hit); }
+            }
+        }
+        let n = self.len();
+        for i in range.iter() {
+            self.push(*i);
         }
-        self.inner.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        merge_back(self.as_mut_slice(), n, range.as_slice());
     }
 
     pub fn len(&self) -> usize {
-        self.inner.len()
+        self.as_slice().len()
     }
 
-    pub fn hit(&self) -> Option<&Intersection> {
-        match &self.current_hit {
-            None => None,
-            Some(i) => Some(i).clone()
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // In order of distance
+    pub fn as_slice(&self) -> &[Intersection<'a>] {
+        match &self.storage {
+            Storage::Inline(items, len) => &items[..*len],
+            Storage::Heap(items) => items
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Intersection<'a>> {
+        self.as_slice().iter()
+    }
+
+    // The intersections in front of the ray origin, nearest first, so the first is the hit
+    pub fn hits(&self) -> impl Iterator<Item = &Intersection<'a>> + '_ {
+        self.iter().filter(|i| i.t >= 0.)
+    }
+
+    pub fn hit(&self) -> Option<&Intersection<'a>> {
+        self.current_hit.as_ref()
+    }
+
+    // Marks all intersections as being with the object of a world with the given id
+    pub(crate) fn set_object_id(&mut self, id: ObjectId) {
+        for i in self.as_mut_slice() {
+            i.object_id = Some(id);
+        }
+        if let Some(hit) = &mut self.current_hit {
+            hit.object_id = Some(id);
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [Intersection<'a>] {
+        match &mut self.storage {
+            Storage::Inline(items, len) => &mut items[..*len],
+            Storage::Heap(items) => items
+        }
+    }
+
+    // Unsorted, moving to the heap once the inline storage is full
+    fn push(&mut self, i: Intersection<'a>) {
+        match &mut self.storage {
+            Storage::Inline(items, len) if *len < INLINE_CAPACITY => {
+                items[*len] = i;
+                *len += 1;
+            },
+            Storage::Inline(items, len) => {
+                let mut heap: Vec<Intersection<'a>> = Vec::with_capacity(2 * INLINE_CAPACITY);
+                heap.extend_from_slice(&items[..*len]);
+                heap.push(i);
+                self.storage = Storage::Heap(heap);
+            },
+            Storage::Heap(items) if items.capacity() == 0 => self.storage = Storage::Inline([i; INLINE_CAPACITY], 1),
+            Storage::Heap(items) => items.push(i)
+        }
+    }
+
+    fn sort(&mut self) {
+        let items = self.as_mut_slice();
+        if items.len() <= INSERTION_SORT_MAX {
+            insertion_sort(items, |i| i.t);
+        } else {
+            items.sort_by(|a, b| compare_t(a.t, b.t));
+        }
+    }
+}
+
+// Owns the intersections, so that they can outlive the list. Goes by index since the
+// intersections are Copy, which works the same for both kinds of storage.
+pub struct IntoIter<'a> {
+    xs: Intersections<'a>,
+    next: usize
+}
+
+impl<'a> Iterator for IntoIter<'a> {
+    type Item = Intersection<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let i = self.xs.as_slice().get(self.next).copied();
+        self.next += 1;
+        i
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.xs.len().saturating_sub(self.next);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for IntoIter<'_> {}
+
+impl<'a> IntoIterator for Intersections<'a> {
+    type Item = Intersection<'a>;
+    type IntoIter = IntoIter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { xs: self, next: 0 }
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b Intersections<'a> {
+    type Item = &'b Intersection<'a>;
+    type IntoIter = std::slice::Iter<'b, Intersection<'a>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Merges the sorted tail into the sorted items[..n], where the caller has made room for it
+// after them. Works from the back so that nothing has to be moved twice. Items of the first
+// part come first among equals.
+fn merge_back<'a>(items: &mut [Intersection<'a>], n: usize, tail: &[Intersection<'a>]) {
+    let (mut i, mut j) = (n, tail.len());
+    while j > 0 {
+        if i > 0 && compare_t(items[i - 1].t, tail[j - 1].t) == Ordering::Greater {
+            items[i + j - 1] = items[i - 1];
+            i -= 1;
+        } else {
+            items[i + j - 1] = tail[j - 1];
+            j -= 1;
+        }
+    }
+}
+
+// Orders by distance with NaN last, whatever its sign. total_cmp alone would put NaN with the
+// sign bit set, as e g 0. / 0. gives on x86, first.
+fn compare_t(a: f64, b: f64) -> Ordering {
+    a.is_nan().cmp(&b.is_nan()).then(a.total_cmp(&b))
+}
+
+// Stable, like sort_by, and NaN sorts last
+fn insertion_sort<T, F: Fn(&T) -> f64>(items: &mut [T], t: F) {
+    for i in 1..items.len() {
+        let mut j = i;
+        while j > 0 && compare_t(t(&items[j - 1]), t(&items[j])) == Ordering::Greater {
+            items.swap(j - 1, j);
+            j -= 1;
         }
     }
 }
@@ -101,24 +385,63 @@ impl Intersections {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::matrix::Matrix;
+    use crate::material::Material;
+    use crate::matrix::{Matrix, IDENTITY_MATRIX};
     use crate::tuple::Tuple;
     use crate::sphere::Sphere;
+    use crate::plane::Plane;
+    use crate::spectral::{REFERENCE_WAVELENGTH, SF11_GLASS};
+    use crate::EPSILON;
+
+    #[test]
+    fn nan_intersections_are_sorted_last_and_never_hit() {
+        let s = Sphere::default_boxed();
+        let xs = Intersections::new(vec![Intersection::new(f64::NAN, &*s), Intersection::new(2., &*s)]);
+        let nan_only = Intersections::new(vec![Intersection::new(f64::NAN, &*s)]);
+
+        assert_eq!(xs[0].t, 2.);
+        assert!(xs[1].t.is_nan());
+        assert_eq!(xs.hit().unwrap().t, 2.);
+        assert!(nan_only.hit().is_none());
+    }
+
+    #[test]
+    fn negative_nan_intersections_are_sorted_last_too() {
+        let s = Sphere::default_boxed();
+        let few = Intersections::new(vec![Intersection::new(-f64::NAN, &*s), Intersection::new(-1., &*s), Intersection::new(2., &*s)]);
+        let many = Intersections::new((0..20).map(|i| Intersection::new(if i % 2 == 0 { -f64::NAN } else { i as f64 }, &*s)).collect());
+        let mut merged = Intersections::from_array([Intersection::new(1., &*s), Intersection::new(-f64::NAN, &*s)]);
+        merged.extend(Intersections::from_array([Intersection::new(-f64::NAN, &*s), Intersection::new(3., &*s)]));
+
+        assert_eq!((few[0].t, few[1].t), (-1., 2.));
+        assert!(few[2].t.is_nan());
+        assert_eq!(few.hit().unwrap().t, 2.);
+        assert_eq!(many.iter().take(10).map(|i| i.t).collect::<Vec<f64>>(), (0..10).map(|i| (2 * i + 1) as f64).collect::<Vec<f64>>());
+        assert!(many.iter().skip(10).all(|i| i.t.is_nan()));
+        assert_eq!((merged[0].t, merged[1].t), (1., 3.));
+        assert!(merged[2].t.is_nan() && merged[3].t.is_nan());
+    }
+
+    #[test]
+    fn intersections_stay_small() {
+        assert_eq!(std::mem::size_of::<PrimitiveHit>(), 12);
+        assert!(std::mem::size_of::<Intersection>() <= 48);
+    }
 
     #[test]
     fn intersection_encapsulates_t_and_object() {
         let s = Sphere::default_boxed();
-        let i = Intersection::new(3.5, s.clone());
+        let i = Intersection::new(3.5, &*s);
 
         assert_eq!(i.t, 3.5);
-        assert_eq!(&i.object, &s);
+        assert_eq!(i.object, &*s);
     }
 
     #[test]
     fn aggregate_intersections() {
         let s = Sphere::default_boxed();
-        let i1 = Intersection::new(1., s.clone());
-        let i2 = Intersection::new(2., s);
+        let i1 = Intersection::new(1., &*s);
+        let i2 = Intersection::new(2., &*s);
         let xs = Intersections::new(vec![i1, i2]);
 
         assert_eq!(2, xs.len());
@@ -129,10 +452,10 @@ mod tests {
     #[test]
     fn aggregate_intersections_with_add() {
         let s = Sphere::default_boxed();
-        let i1 = Intersection::new(1., s.clone());
-        let i2 = Intersection::new(2., s.clone());
-        let i3 = Intersection::new(3., s.clone());
-        let i4 = Intersection::new(4., s);
+        let i1 = Intersection::new(1., &*s);
+        let i2 = Intersection::new(2., &*s);
+        let i3 = Intersection::new(3., &*s);
+        let i4 = Intersection::new(4., &*s);
         let xs = Intersections::new(vec![i1, i2, i3, i4]);
 
         assert_eq!(4, xs.len());
@@ -149,16 +472,16 @@ mod tests {
         let xs = s.intersect(r);
 
         assert_eq!(2, xs.len());
-        assert_eq!(&xs[0].object, &s);
-        assert_eq!(&xs[1].object, &s);
+        assert_eq!(xs[0].object, &*s);
+        assert_eq!(xs[1].object, &*s);
     }
 
     #[test]
     fn hit_all_intersections_positive_t() {
         let s = Sphere::default_boxed();
-        let i1 = Intersection::new(1., s.clone());
-        let i2 = Intersection::new(2., s);
-        let xs = Intersections::new(vec![i2, i1.clone()]);
+        let i1 = Intersection::new(1., &*s);
+        let i2 = Intersection::new(2., &*s);
+        let xs = Intersections::new(vec![i2, i1]);
         let i = xs.hit().unwrap();
 
         assert_eq!(*i, i1);
@@ -167,9 +490,9 @@ mod tests {
     #[test]
     fn hit_some_intersections_negative_t() {
         let s = Sphere::default_boxed();
-        let i1 = Intersection::new(-1., s.clone());
-        let i2 = Intersection::new(1., s);
-        let xs = Intersections::new(vec![i2.clone(), i1]);
+        let i1 = Intersection::new(-1., &*s);
+        let i2 = Intersection::new(1., &*s);
+        let xs = Intersections::new(vec![i2, i1]);
         let i = xs.hit().unwrap();
 
         assert_eq!(*i, i2);
@@ -178,8 +501,8 @@ mod tests {
     #[test]
     fn hit_all_intersections_negative_t() {
         let s = Sphere::default_boxed();
-        let i1 = Intersection::new(-2., s.clone());
-        let i2 = Intersection::new(-1., s);
+        let i1 = Intersection::new(-2., &*s);
+        let i2 = Intersection::new(-1., &*s);
         let xs = Intersections::new(vec![i2, i1]);
         let i = xs.hit();
 
@@ -189,11 +512,11 @@ mod tests {
     #[test]
     fn hit_lowest_non_negative_intersection() {
         let s = Sphere::default_boxed();
-        let i1 = Intersection::new(5., s.clone());
-        let i2 = Intersection::new(7., s.clone());
-        let i3 = Intersection::new(-3., s.clone());
-        let i4 = Intersection::new(2., s);
-        let xs = Intersections::new(vec![i1, i2, i3, i4.clone()]);
+        let i1 = Intersection::new(5., &*s);
+        let i2 = Intersection::new(7., &*s);
+        let i3 = Intersection::new(-3., &*s);
+        let i4 = Intersection::new(2., &*s);
+        let xs = Intersections::new(vec![i1, i2, i3, i4]);
         let i = xs.hit().unwrap();
 
         assert_eq!(*i, i4);
@@ -202,17 +525,17 @@ mod tests {
     #[test]
     fn extend_intersections_gets_union() {
         let s1 = Sphere::default_boxed();
-        let i1 = Intersection::new(5., s1.clone());
-        let i2 = Intersection::new(7., s1.clone());
-        let i3 = Intersection::new(-3., s1.clone());
-        let i4 = Intersection::new(2., s1);
+        let i1 = Intersection::new(5., &*s1);
+        let i2 = Intersection::new(7., &*s1);
+        let i3 = Intersection::new(-3., &*s1);
+        let i4 = Intersection::new(2., &*s1);
         let mut xs1 = Intersections::new(vec![i1, i2, i3, i4]);
 
         let s2 = Sphere::default_boxed();
-        let i5 = Intersection::new(-1., s2.clone());
-        let i6 = Intersection::new(1., s2.clone());
-        let i7 = Intersection::new(2., s2);
-        let xs2 = Intersections::new(vec![i5, i6.clone(), i7]);
+        let i5 = Intersection::new(-1., &*s2);
+        let i6 = Intersection::new(1., &*s2);
+        let i7 = Intersection::new(2., &*s2);
+        let xs2 = Intersections::new(vec![i5, i6, i7]);
 
         xs1.extend(xs2);    // xs2 is moved
 
@@ -220,23 +543,138 @@ mod tests {
         assert_eq!(*xs1.hit().unwrap(), i6);
     }
 
+    #[test]
+    fn many_intersections_move_to_heap_and_stay_sorted() {
+        let s = Sphere::default_boxed();
+        let mut xs = Intersections::from_array([Intersection::new(3., &*s), Intersection::new(-1., &*s)]);
+        for n in 0..30 {
+            let t = ((n * 7) % 30) as f64 - 5.;
+            xs.extend(Intersections::from_array([Intersection::new(t, &*s)]));
+        }
+
+        assert_eq!(xs.len(), 32);
+        assert!(xs.iter().zip(xs.iter().skip(1)).all(|(a, b)| a.t <= b.t));
+        assert_eq!(xs.hit().unwrap().t, 0.);
+        assert_eq!(xs[31].t, 24.);
+    }
+
+    #[test]
+    fn extend_merges_keeping_earlier_first_among_equals() {
+        let (a, b) = (Sphere::default_boxed(), Sphere::default_boxed());
+        let mut xs = Intersections::with_capacity(8);
+        xs.extend(Intersections::new(vec![Intersection::new(1., &*a), Intersection::new(4., &*a), Intersection::new(6., &*a)]));
+        xs.extend(Intersections::new([0., 4., 5., 9., f64::NAN].iter().map(|t| Intersection::new(*t, &*b)).collect()));
+
+        assert_eq!(xs.iter().map(|i| i.t).take(6).collect::<Vec<f64>>(), vec![0., 1., 4., 4., 5., 6.]);
+        assert!(std::ptr::addr_eq(xs[2].object, &*a) && std::ptr::addr_eq(xs[3].object, &*b));
+        assert_eq!(xs[6].t, 9.);
+        assert!(xs[7].t.is_nan());
+        assert_eq!(xs.hit().unwrap().t, 0.);
+
+        xs.clear();
+        assert!(xs.is_empty() && xs.hit().is_none());
+        xs.extend(Intersections::from_array([Intersection::new(-2., &*a), Intersection::new(3., &*a)]));
+        xs.extend(Intersections::from_array([Intersection::new(2., &*b)]));
+        assert_eq!(xs.iter().map(|i| i.t).collect::<Vec<f64>>(), vec![-2., 2., 3.]);
+        assert_eq!(xs.hit().unwrap().t, 2.);
+    }
+
+    #[test]
+    fn iterate_and_slice_intersections() {
+        let s = Sphere::default_boxed();
+        for ts in [vec![2., -1., 3.], vec![5., -2., 1., -4., 0., 7.]] {
+            let xs = Intersections::new(ts.iter().map(|t| Intersection::new(*t, &*s)).collect());
+            let mut sorted = ts.clone();
+            sorted.sort_by(|a, b| a.total_cmp(b));
+            let by_ref: Vec<f64> = (&xs).into_iter().map(|i| i.t).collect();
+            let hits: Vec<f64> = xs.hits().map(|i| i.t).collect();
+
+            assert_eq!(xs.as_slice().iter().map(|i| i.t).collect::<Vec<f64>>(), sorted);
+            assert_eq!(by_ref, sorted);
+            assert_eq!(hits, sorted.iter().copied().filter(|t| *t >= 0.).collect::<Vec<f64>>());
+            assert_eq!(xs.hit(), xs.hits().next());
+            let owned = xs.into_iter();
+            assert_eq!(owned.len(), ts.len());
+            assert_eq!(owned.map(|i| i.t).collect::<Vec<f64>>(), sorted);
+        }
+        assert!(Intersections::empty().as_slice().is_empty());
+        assert_eq!(Intersections::new(vec![Intersection::new(f64::NAN, &*s)]).hits().count(), 0);
+    }
+
+    #[test]
+    fn inline_and_heap_intersections_sort_the_same() {
+        let s = Sphere::default_boxed();
+        let ts = [4., f64::NAN, -2., 1., 1., 0.5];
+        let few = Intersections::new(ts[..4].iter().map(|t| Intersection::new(*t, &*s)).collect());
+        let many = Intersections::new(ts.iter().map(|t| Intersection::new(*t, &*s)).collect());
+
+        assert_eq!(few.iter().map(|i| i.t).take(3).collect::<Vec<f64>>(), vec![-2., 1., 4.]);
+        assert!(few[3].t.is_nan());
+        assert_eq!(many.iter().map(|i| i.t).take(5).collect::<Vec<f64>>(), vec![-2., 0.5, 1., 1., 4.]);
+        assert!(many[5].t.is_nan());
+        assert_eq!(many.hit().unwrap().t, 0.5);
+        assert!(Intersections::empty().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_beyond_inline_intersections_panics() {
+        let s = Sphere::new(None, None);
+        let xs = Intersections::from_array([Intersection::new(1., &s)]);
+        let _ = &xs[1];
+    }
+
+    #[test]
+    fn intersections_refer_to_the_shape_itself() {
+        let s = Sphere::new(None, None);
+        let xs = s.intersect(Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)));
+        let comps = xs.hit().unwrap().prepare_computations(Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)));
+
+        assert!(std::ptr::addr_eq(xs[0].object, &s));
+        assert!(std::ptr::addr_eq(comps.object, &s));
+    }
+
     #[test]
     fn precompute_state_of_intersection() {
         let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
         let shape = Sphere::default_boxed();
-        let i = Intersection::new(4., shape);
+        let i = Intersection::new(4., &*shape);
         let comps = i.prepare_computations(r);
 
         assert_eq!(comps.t, i.t);
         assert_eq!(comps.point, Tuple::point(0., 0., -1.));
         assert_eq!(comps.eyev, Tuple::vector(0., 0., -1.));
+        assert_eq!(comps.time, 0.);
+    }
+
+    #[test]
+    fn precompute_with_non_unit_ray_direction() {
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 2.));
+        let shape = Sphere::new(None, None);
+        let i = Intersection::new(2., &shape);
+        let comps = i.prepare_computations(r);
+
+        assert_eq!(comps.point, Tuple::point(0., 0., -1.));
+        assert_eq!(comps.eyev, Tuple::vector(0., 0., -1.));
+        assert_eq!(comps.reflectv, Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn precompute_state_of_moving_object() {
+        let r = Ray::new(Tuple::point(2., 0., -5.), Tuple::vector(0., 0., 1.)).with_time(1.);
+        let shape = Sphere::new(None, None).with_motion(Matrix::translation(2., 0., 0.));
+        let i = Intersection::new(4., &shape);
+        let comps = i.prepare_computations(r);
+
+        assert_eq!(comps.time, 1.);
+        assert_eq!(comps.normalv, Tuple::vector(0., 0., -1.));
     }
 
     #[test]
     fn hit_when_intersection_on_outside() {
         let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
         let shape = Sphere::default_boxed();
-        let i = Intersection::new(4., shape);
+        let i = Intersection::new(4., &*shape);
         let comps = i.prepare_computations(r);
 
         assert!(!comps.inside);
@@ -246,7 +684,7 @@ mod tests {
     fn hit_when_intersection_on_inside() {
         let r = Ray::new(Tuple::point(0., 0., 0.), Tuple::vector(0., 0., 1.));
         let shape = Sphere::default_boxed();
-        let i = Intersection::new(1., shape);
+        let i = Intersection::new(1., &*shape);
         let comps = i.prepare_computations(r);
 
         assert_eq!(comps.point, Tuple::point(0., 0., 1.));
@@ -260,9 +698,125 @@ mod tests {
         let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
         let transform = Matrix::translation(0., 0., 1.);
         let shape = Sphere::new_boxed(None, Some(transform));
-        let i = Intersection::new(5., shape);
+        let i = Intersection::new(5., &*shape);
         let comps = i.prepare_computations(r);
         assert!(comps.over_point.z < - EPSILON / 2.);
         assert!(comps.point.z > comps.over_point.z);
     }
+
+    #[test]
+    fn under_point_is_offset_below_surface() {
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let shape = glass_sphere(Matrix::translation(0., 0., 1.), 1.5);
+        let i = Intersection::new(5., &*shape);
+        let comps = i.prepare_computations_with(r, &Intersections::from_array([i]));
+
+        assert!(comps.under_point.z > EPSILON / 2.);
+        assert!(comps.point.z < comps.under_point.z);
+    }
+
+    fn glass_sphere(transform: Matrix, refractive_index: f64) -> BoxShape {
+        let glass = Material::default().with_transparency(1.).with_refractive_index(refractive_index);
+        Sphere::new_boxed(Some(glass), Some(transform))
+    }
+
+    #[test]
+    fn n1_and_n2_at_intersections_of_nested_objects() {
+        let a = glass_sphere(Matrix::scaling(2., 2., 2.), 1.5);
+        let b = glass_sphere(Matrix::translation(0., 0., -0.25), 2.);
+        let c = glass_sphere(Matrix::translation(0., 0., 0.25), 2.5);
+        let r = Ray::new(Tuple::point(0., 0., -4.), Tuple::vector(0., 0., 1.));
+        let xs = Intersections::new(vec![
+            Intersection::new(2., &*a), Intersection::new(2.75, &*b), Intersection::new(3.25, &*c),
+            Intersection::new(4.75, &*b), Intersection::new(5.25, &*c), Intersection::new(6., &*a)]);
+        let expected = [(1., 1.5), (1.5, 2.), (2., 2.5), (2.5, 2.5), (2.5, 1.5), (1.5, 1.)];
+
+        for (i, &(n1, n2)) in expected.iter().enumerate() {
+            let comps = xs[i].prepare_computations_with(r, &xs);
+            assert_eq!((comps.n1, comps.n2), (n1, n2), "intersection {}", i);
+        }
+    }
+
+    #[test]
+    fn n1_and_n2_without_other_intersections() {
+        let s = glass_sphere(IDENTITY_MATRIX, 1.5);
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let entering = Intersection::new(4., &*s).prepare_computations(r);
+        let leaving = Intersection::new(6., &*s).prepare_computations(r);
+        let not_in_xs = Intersection::new(4., &*s).prepare_computations_with(r, &Intersections::empty());
+
+        assert_eq!((entering.n1, entering.n2), (1., 1.5));
+        assert_eq!((leaving.n1, leaving.n2), (1.5, 1.));
+        assert_eq!((not_in_xs.n1, not_in_xs.n2), (1., 1.5));
+    }
+
+    #[test]
+    fn n1_and_n2_at_wavelength_of_ray() {
+        let glass = Material::default().with_transparency(1.).with_dispersion(SF11_GLASS);
+        let s = Sphere::new_boxed(Some(glass), None);
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let xs = Intersections::new(vec![Intersection::new(4., &*s), Intersection::new(6., &*s)]);
+        let blue = xs[0].prepare_computations_with(r.with_wavelength(450.), &xs);
+        let red = xs[1].prepare_computations_with(r.with_wavelength(650.), &xs);
+        let rgb = xs[0].prepare_computations_with(r, &xs);
+
+        assert_eq!((blue.n1, blue.n2), (1., SF11_GLASS.at(450.)));
+        assert_eq!((red.n1, red.n2), (SF11_GLASS.at(650.), 1.));
+        assert_eq!(rgb.n2, SF11_GLASS.at(REFERENCE_WAVELENGTH));
+        assert_eq!(blue.wavelength, Some(450.));
+    }
+
+    #[test]
+    fn refracted_ray_bends_towards_normal() {
+        let s = glass_sphere(IDENTITY_MATRIX, 1.5);
+        let pv = 2.0f64.sqrt() / 2.;
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)).with_time(0.5);
+        let straight = Intersection::new(4., &*s).prepare_computations(r).refracted_ray().unwrap();
+        // Hits the sphere 45 degrees from the normal
+        let slanted = Ray::new(Tuple::point(pv, 0., -5.), Tuple::vector(0., 0., 1.));
+        let bent = Intersection::new(5. - pv, &*s).prepare_computations(slanted).refracted_ray().unwrap();
+        let sin_t = (bent.direction.normalize() - Tuple::vector(-pv, 0., pv)).magnitude();
+
+        assert_eq!(straight.direction, Tuple::vector(0., 0., 1.));
+        assert_eq!(straight.time, 0.5);
+        assert!(straight.origin.z > -1.);
+        // Snell's law: sin 45° = 1.5 sin θt, measured as the chord from the normal
+        let theta = (pv / 1.5).asin();
+        assert!((sin_t - 2. * (theta / 2.).sin()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn total_internal_reflection_refracts_nothing() {
+        let s = glass_sphere(IDENTITY_MATRIX, 1.5);
+        let pv = 2.0f64.sqrt() / 2.;
+        let r = Ray::new(Tuple::point(0., 0., pv), Tuple::vector(0., 1., 0.));
+        let xs = Intersections::new(vec![Intersection::new(-pv, &*s), Intersection::new(pv, &*s)]);
+        let comps = xs[1].prepare_computations_with(r, &xs);
+
+        assert!(comps.refracted_ray().is_none());
+    }
+
+    #[test]
+    fn precompute_reflection_vector() {
+        let shape = Plane::new_boxed(None, None);
+        let pv = 2.0f64.sqrt() / 2.0;
+        let r = Ray::new(Tuple::point(0., 1., -1.), Tuple::vector(0., -pv, pv));
+        let i = Intersection::new(2.0f64.sqrt(), &*shape);
+        let comps = i.prepare_computations(r);
+
+        assert_eq!(comps.reflectv, Tuple::vector(0., pv, pv));
+    }
+
+    #[test]
+    fn offset_of_point_follows_scene_scale() {
+        for &(k, expected) in &[(1e-4, EPSILON * 9e-3), (1., EPSILON), (1e6, EPSILON * 9e3)] {
+            let r = Ray::new(Tuple::point(0., 0., -5. * k), Tuple::vector(0., 0., 1.));
+            let shape = Sphere::new_boxed(None, Some(Matrix::scaling(k, k, k)));
+            let i = Intersection::new(4. * k, &*shape);
+            let comps = i.prepare_computations(r);
+            let offset = comps.point.z - comps.over_point.z;
+
+            assert!((offset - expected).abs() < expected * 1e-3, "scale {}", k);
+        }
+    }
 }
@@ -1,22 +1,100 @@
+#[macro_use]
+mod forward_ref;
+
+pub mod error;
 pub mod tuple;
+pub mod angle;
 pub mod color;
+pub mod spectral;
 pub mod canvas;
 pub mod matrix;
 pub mod transform;
+pub mod quaternion;
+pub mod bounding_box;
+pub mod bvh;
 pub mod ray;
 pub mod shape;
 pub mod sphere;
 pub mod plane;
+pub mod polygon;
 pub mod intersection;
 pub mod light;
 pub mod material;
 pub mod pattern;
+pub mod noise;
+pub mod pattern_definition;
+pub mod texture_map;
 pub mod world;
+pub mod prepared_scene;
 pub mod precomputed_data;
 pub mod camera;
+pub mod render_checkpoint;
+pub mod wavefront;
+pub mod temporal;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "render-examples")]
+pub mod thumbnail;
+pub mod projection;
+pub mod reference_geometry;
+pub mod file_resolver;
+pub mod resource_manager;
+pub mod scene_watcher;
+pub mod sampler;
+pub mod rng;
+pub mod sampling;
+pub mod render_pass;
+pub mod material_palette;
+pub mod render_settings;
+pub mod environment;
+pub mod film;
+pub mod exposure;
+pub mod logging;
+pub mod scene_summary;
+pub mod scene_lint;
+pub mod instancing;
+pub mod point_cloud;
+pub mod tessellation;
+pub mod obj_export;
+pub mod debug;
+pub mod reference;
+
+pub use error::Error;
 
+// EPSILON is an absolute tolerance, which is only meaningful for scenes measured in units
+// close to 1. Offsets that push secondary rays off a surface should use scaled_epsilon instead.
 pub const EPSILON: f64 = 0.00001;
+const MIN_SCALED_EPSILON: f64 = 1e-12;
+// Magnitudes in this range get EPSILON itself, so that scenes in units close to 1 render as in
+// the book. Outside of it the offset grows or shrinks in proportion.
+const UNIT_SCALE_MIN: f64 = 0.1;
+const UNIT_SCALE_MAX: f64 = 1000.;
+
+pub fn scaled_epsilon(magnitude: f64) -> f64 {
+    let magnitude = magnitude.abs();
+    let scale = if magnitude < UNIT_SCALE_MIN { magnitude / UNIT_SCALE_MIN } else { (magnitude / UNIT_SCALE_MAX).max(1.) };
+    (EPSILON * scale).max(MIN_SCALED_EPSILON)
+}
 
 pub fn approx_eq(a: f64, b: f64) -> bool {
     (a - b).abs() < EPSILON
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_epsilon_follows_magnitude() {
+        assert_eq!(scaled_epsilon(1.), EPSILON);
+        assert_eq!(scaled_epsilon(0.1), EPSILON);
+        assert_eq!(scaled_epsilon(1000.), EPSILON);
+        assert_eq!(scaled_epsilon(1e6), EPSILON * 1e3);
+        assert_eq!(scaled_epsilon(-1e-4), EPSILON * 1e-3);
+    }
+
+    #[test]
+    fn scaled_epsilon_never_reaches_zero() {
+        assert!(scaled_epsilon(0.) > 0.);
+    }
+}
@@ -1,15 +1,77 @@
+use std::f64::consts::PI;
+
 use super::color::Color;
 use super::tuple::Tuple;
 
+pub const WARM_WHITE_KELVIN: f64 = 2700.;
+pub const COOL_WHITE_KELVIN: f64 = 6500.;
+
+/// A light to the right of a sphere, which lights its right side and leaves only the ambient light on
+/// the left:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::color::WHITE;
+/// use raytracer::light::PointLight;
+/// use raytracer::sphere::Sphere;
+/// use raytracer::thumbnail::render_thumbnail;
+/// use raytracer::tuple::Tuple;
+/// use raytracer::world::World;
+///
+/// let light = PointLight::new(Tuple::point(10., 0., 0.), WHITE);
+/// let image = render_thumbnail(World::new(Some(light), vec![Sphere::new_boxed(None, None)]));
+/// assert!(image.pixel_at(20, 16).r > 0.5);
+/// assert!(image.pixel_at(13, 16).r < 0.15);
+/// # }
+/// ```
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct PointLight {
     pub position: Tuple,
-    pub intensity: Color
+    pub intensity: Color,
+    // A physical light has its intensity in candela and falls off with the square of the
+    // distance, see intensity_at. Other lights reach everything with the same intensity, like in
+    // the book, which is in [0, 1] for a scene that needs no exposure.
+    pub physical: bool
 }
 
 impl PointLight {
     pub fn new(position: Tuple, intensity: Color) -> PointLight {
-        PointLight { position, intensity }
+        PointLight { position, intensity, physical: false }
+    }
+
+    // A physical light of candela in the color tint, WHITE for a neutral light
+    pub fn from_candela(position: Tuple, candela: f64, tint: Color) -> PointLight {
+        PointLight { position, intensity: tint * candela, physical: true }
+    }
+
+    // A physical light shining lumens equally in all directions, like the rating of a bulb
+    pub fn from_lumens(position: Tuple, lumens: f64, tint: Color) -> PointLight {
+        PointLight::from_candela(position, lumens / (4. * PI), tint)
+    }
+
+    // The light reaching point. For physical lights it is the illuminance (lux) divided by pi, so
+    // that a white diffuse surface facing the light shades to its luminance in cd/m², which is
+    // what Exposure expects.
+    pub fn intensity_at(&self, point: Tuple) -> Color {
+        if !self.physical {
+            return self.intensity;
+        }
+        let v = self.position - point;
+        self.intensity * (1. / (PI * v.dot(&v)))
+    }
+
+    pub fn from_kelvin(position: Tuple, temperature: f64) -> PointLight {
+        PointLight::new(position, Color::from_kelvin(temperature))
+    }
+
+    // Like an incandescent bulb
+    pub fn warm(position: Tuple) -> PointLight {
+        PointLight::from_kelvin(position, WARM_WHITE_KELVIN)
+    }
+
+    // Like daylight
+    pub fn cool(position: Tuple) -> PointLight {
+        PointLight::from_kelvin(position, COOL_WHITE_KELVIN)
     }
 }
 
@@ -28,4 +90,27 @@ mod tests {
         assert_eq!(light.intensity, intensity);
     }
 
+    #[test]
+    fn warm_and_cool_lights() {
+        let position = Tuple::point(1., 2., 3.);
+        let warm = PointLight::warm(position);
+        let cool = PointLight::cool(position);
+
+        assert_eq!(warm.position, position);
+        assert_eq!(warm.intensity, Color::from_kelvin(2700.));
+        assert_eq!(cool.intensity, PointLight::from_kelvin(position, 6500.).intensity);
+        assert!(warm.intensity.b < cool.intensity.b);
+    }
+
+    #[test]
+    fn physical_lights_fall_off_with_distance() {
+        let light = PointLight::from_lumens(Tuple::point(0., 2., 0.), 800., WHITE);
+        let candela = 800. / (4. * PI);
+
+        assert!(light.physical && !PointLight::new(Tuple::point(0., 2., 0.), WHITE).physical);
+        assert_eq!(light.intensity, WHITE * candela);
+        assert_eq!(light.intensity_at(Tuple::point(0., 0., 0.)), WHITE * (candela / (4. * PI)));
+        assert_eq!(light.intensity_at(Tuple::point(0., -2., 0.)), WHITE * (candela / (16. * PI)));
+        assert_eq!(PointLight::new(Tuple::point(0., 2., 0.), WHITE).intensity_at(Tuple::point(0., -2., 0.)), WHITE);
+    }
 }
\ No newline at end of file
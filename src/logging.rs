@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+
+// Diagnostics go through the log facade, so an application embedding the renderer decides
+// where they end up and at which level. Nothing is logged unless a logger is installed.
+//
+// Levels used:
+// - error: just before panicking on invalid input, e g a singular transformation
+// - warn: something is wrong but rendering continues, e g non finite numbers
+// - info: one line per phase (preparing a scene, rendering, loading a resource) with timing
+// - debug: progress within a phase, once per chunk of rows
+//
+// Phase lines have the form "phase=<name> elapsed_ms=<ms> <key>=<value>...", so they can be
+// filtered and parsed.
+
+// Measures one phase of the work and logs it at info level when finished
+pub struct PhaseTimer {
+    phase: &'static str,
+    start: Instant
+}
+
+impl PhaseTimer {
+    pub fn start(phase: &'static str) -> Self {
+        Self { phase, start: Instant::now() }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // details are key=value pairs separated by spaces
+    pub fn finish(self, details: &str) -> Duration {
+        let elapsed = self.elapsed();
+        info!("{}", phase_line(self.phase, elapsed, details));
+        elapsed
+    }
+}
+
+fn phase_line(phase: &str, elapsed: Duration, details: &str) -> String {
+    let mut line = format!("phase={} elapsed_ms={:.3}", phase, elapsed.as_secs_f64() * 1000.);
+    if !details.is_empty() {
+        line.push(' ');
+        line.push_str(details);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_line_is_key_value_pairs() {
+        assert_eq!(phase_line("render", Duration::from_micros(1500), "width=4 height=3"), "phase=render elapsed_ms=1.500 width=4 height=3");
+        assert_eq!(phase_line("load", Duration::from_millis(2), ""), "phase=load elapsed_ms=2.000");
+    }
+
+    #[test]
+    fn timer_measures_elapsed_time() {
+        let timer = PhaseTimer::start("test");
+        std::thread::sleep(Duration::from_millis(2));
+
+        assert!(timer.finish("") >= Duration::from_millis(2));
+    }
+}
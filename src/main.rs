@@ -6,11 +6,31 @@ use raytracer::ray::*;
 use raytracer::shape::*;
 use raytracer::sphere::*;
 use raytracer::matrix::*;
+use raytracer::angle::*;
 use raytracer::light::*;
 use raytracer::material::*;
 use raytracer::world::*;
 use raytracer::camera::*;
 use raytracer::pattern::*;
+use raytracer::render_pass::*;
+use raytracer::projection::*;
+use raytracer::sampler::*;
+use raytracer::environment::*;
+use raytracer::prepared_scene::*;
+use raytracer::scene_summary::*;
+use raytracer::scene_lint::LintReport;
+use raytracer::material_palette::MaterialPalette;
+use raytracer::film::FilmResponse;
+use raytracer::point_cloud;
+use raytracer::obj_export::{save_obj, ObjExportOptions};
+use raytracer::render_checkpoint::{RenderCheckpoint, DEFAULT_TILE_SIZE};
+use raytracer::resource_manager::ResourceManager;
+use raytracer::scene_watcher::{SceneWatcher, DEFAULT_POLL_INTERVAL};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::cell::Cell;
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 fn canvas_to_file(filename: &str)
 {
@@ -41,9 +61,8 @@ fn circle_shadow(filename: &str)
             let position = Tuple::point(world_x, world_y, wall_z);
             let r = Ray::new(ray_origin, (position - ray_origin).normalize());
             let xs = shape.intersect(r);
-            match xs.hit() {
-                Some(_i) => canvas.write_pixel(x, y, color),
-                None => ()
+            if xs.hit().is_some() {
+                canvas.write_pixel(x, y, color);
             }
         }
     }
@@ -60,9 +79,9 @@ fn rendered_sphere(filename: &str)
     let half = WALL_SIZE as f64 / 2.0;
 
     let mut canvas = Canvas::new(CANVAS_PIXELS, CANVAS_PIXELS);
-    let mut m = Material::default();
-    m.color = Color::new(1., 0.2, 1.);
-    m.pattern = Some(StripePattern::new_boxed(GREEN, RED, Some(Matrix::scaling(0.1, 0.1, 0.1))));
+    let m = Material::default()
+        .with_color(Color::new(1., 0.2, 1.))
+        .with_pattern(Some(StripePattern::new_boxed(GREEN, RED, Some(Matrix::scaling(0.1, 0.1, 0.1)))));
     let tr = Matrix::shearing(1., 0., 0.5, 0., 0., 0.) * Matrix::scaling(1., 0.5, 1.);
     let shape = Sphere::new(Some(m), Some(tr));
     let light_position = Tuple::point(-10., 10., -10.);
@@ -76,65 +95,487 @@ fn rendered_sphere(filename: &str)
             let position = Tuple::point(world_x, world_y, wall_z);
             let r = Ray::new(ray_origin, (position - ray_origin).normalize());
             let xs = shape.intersect(r);
-            match xs.hit() {
-                Some(h) => {
-                    let point = r.position(h.t);
-                    let normal = h.object.normal_at(point);
-                    let eye = - r.direction;
-                    let color = h.object.material().lighting(&*h.object, &light, point, eye, normal, false);
-                    canvas.write_pixel(x, y, color);
-                },
-                _ => ()
+            if let Some(h) = xs.hit() {
+                let point = r.position(h.t);
+                let normal = h.object.normal_at(point);
+                let eye = - r.direction;
+                let color = h.object.material().lighting(h.object, &light, point, eye, normal, false);
+                canvas.write_pixel(x, y, color);
             }
         }
     }
     canvas.save(filename).unwrap();
 }
 
-fn camera_render_world(filename: &str) {
-    let floor_material = Material::new(Color::new(1., 0.9, 0.9), DEFAULT_AMBIENT, DEFAULT_DIFFUSE, 0., DEFAULT_SHININESS, None);
+fn three_spheres_world() -> World {
+    let floor_material = Material::default().with_color(Color::new(1., 0.9, 0.9)).with_specular(0.);
     let floor_transform = Matrix::scaling(10., 0.01, 10.);
     let floor = Sphere::new_boxed(Some(floor_material.clone()), Some(floor_transform));
 
     let left_wall_transform = 
         Matrix::translation(0., 0., 5.) * 
-        Matrix::rotation_y(-FRAC_PI_4) * 
-        Matrix::rotation_x(FRAC_PI_2) * 
+        Matrix::rotation_y(rad(-FRAC_PI_4)) * 
+        Matrix::rotation_x(rad(FRAC_PI_2)) * 
         Matrix::scaling(10., 0.01, 10.);
     let left_wall = Sphere::new_boxed(Some(floor_material.clone()), Some(left_wall_transform));
 
     let right_wall_transform = 
         Matrix::translation(0., 0., 5.) *
-        Matrix::rotation_y(FRAC_PI_4) *
-        Matrix::rotation_x(FRAC_PI_2) *
+        Matrix::rotation_y(rad(FRAC_PI_4)) *
+        Matrix::rotation_x(rad(FRAC_PI_2)) *
         Matrix::scaling(10., 0.01, 10.);
     let right_wall = Sphere::new_boxed(Some(floor_material), Some(right_wall_transform));
 
+    let sphere_material = Material::default().with_diffuse(0.7).with_specular(0.3);
     let middle_transform = Matrix::translation(-0.5, 1., 0.5);
-    let middle_material = Material::new(Color::new(0.1, 1., 0.5), DEFAULT_AMBIENT, 0.7, 0.3, DEFAULT_SHININESS, None);
+    let middle_material = sphere_material.clone().with_color(Color::new(0.1, 1., 0.5));
     let middle = Sphere::new_boxed(Some(middle_material), Some(middle_transform));
 
     let right_transform = Matrix::translation(1.5, 0.5, -0.5) * Matrix::scaling(0.5, 0.5, 0.5);
-    let right_material = Material::new(Color::new(0.5, 1., 0.1), DEFAULT_AMBIENT, 0.7, 0.3, DEFAULT_SHININESS, None);
+    let right_material = sphere_material.clone().with_color(Color::new(0.5, 1., 0.1));
     let right = Sphere::new_boxed(Some(right_material), Some(right_transform));
 
     let left_transform = Matrix::translation(-1.5, 0.33, -0.75) * Matrix::scaling(0.33, 0.33, 0.33);
-    let left_material = Material::new(Color::new(1., 0.8, 0.1), DEFAULT_AMBIENT, 0.7, 0.3, DEFAULT_SHININESS, None);
+    let left_material = sphere_material.with_color(Color::new(1., 0.8, 0.1));
     let left = Sphere::new_boxed(Some(left_material), Some(left_transform));
 
     let light = Some(PointLight::new(Tuple::point(-10., 10., -10.), WHITE));
-    let world = World::new(light, vec![floor, left_wall, right_wall, middle, right, left]);
-    let view_transform = Matrix::view_transform(Tuple::point(0., 1.5, -5.), Tuple::point(0., 1., 0.), Tuple::vector(0., 1., 0.));
-    let camera = Camera::new(700, 500, FRAC_PI_3, Some(view_transform));
-    let canvas = camera.render(world);
+    World::new(light, vec![floor, left_wall, right_wall, middle, right, left])
+}
 
-    canvas.save(filename).unwrap();
+// Picked by --camera, front by default
+fn three_spheres_cameras() -> CameraSet {
+    let view = |from: Tuple, up: Tuple| Some(Matrix::view_transform(from, Tuple::point(0., 1., 0.), up));
+    CameraSet::new()
+        .with_camera("front", Camera::new(700, 500, rad(FRAC_PI_3), view(Tuple::point(0., 1.5, -5.), Tuple::vector(0., 1., 0.))))
+        .with_camera("left", Camera::new(700, 500, rad(FRAC_PI_3), view(Tuple::point(-4., 2., -4.), Tuple::vector(0., 1., 0.))))
+        .with_camera("top", Camera::new(500, 500, rad(FRAC_PI_2), view(Tuple::point(0., 6., -0.5), Tuple::vector(0., 0., 1.))))
+}
+
+// How the three spheres renders are written: sRGB encoded, dithered, then quantized
+#[derive(Clone, Copy)]
+struct OutputSettings {
+    bit_depth: BitDepth,
+    srgb: bool,
+    // Only makes a difference with 8 bits
+    dither: bool
+}
+
+impl OutputSettings {
+    fn save(&self, mut canvas: Canvas, filename: &str) {
+        if self.srgb {
+            canvas.encode_srgb();
+        }
+        if self.dither && self.bit_depth == BitDepth::Eight {
+            canvas.dither();
+        }
+        canvas.save_with_bit_depth(filename, self.bit_depth).unwrap();
+    }
+}
+
+// How the three spheres are rendered to a file, from the command line
+struct RenderOptions<'a> {
+    // Of the camera, used in file names and kept in checkpoints
+    camera_name: &'a str,
+    preview: bool,
+    film: Option<FilmResponse>,
+    output: OutputSettings,
+    checkpoint: Option<&'a str>,
+    watchdog: Watchdog
+}
+
+// Saved at most this often when rendering with a checkpoint, since each save writes the whole image
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+// Like timeout(1) and a shell killed by SIGINT
+const TIME_LIMIT_EXIT_CODE: i32 = 124;
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// Set by the Ctrl-C handler, renders stop after the tile they are on
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Decides when a render has to stop before it is done, either because of Ctrl-C or because it
+// has taken longer than the time limit. The time limit is per render, counted from the last
+// restart, so that with --watch every render after a change gets the whole time limit rather
+// than stopping at once when the first render has used it up.
+struct Watchdog {
+    started: Cell<Instant>,
+    time_limit: Option<Duration>
+}
+
+impl Watchdog {
+    fn new(time_limit: Option<Duration>) -> Self {
+        // The first Ctrl-C lets the render save what it has, the second quits right away
+        let handler = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+            eprintln!("Stopping after the current tile, press Ctrl-C again to quit right away");
+        });
+        if let Err(e) = handler {
+            eprintln!("Can't handle Ctrl-C: {}", e);
+        }
+        Self { started: Cell::new(Instant::now()), time_limit }
+    }
+
+    // Called when a render starts
+    fn restart(&self) {
+        self.started.set(Instant::now());
+    }
+
+    fn stop_reason(&self) -> Option<&'static str> {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            Some("interrupted")
+        } else if self.time_limit.is_some_and(|limit| self.started.get().elapsed() >= limit) {
+            Some("time limit reached")
+        } else {
+            None
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        if INTERRUPTED.load(Ordering::SeqCst) { INTERRUPTED_EXIT_CODE } else { TIME_LIMIT_EXIT_CODE }
+    }
+}
+
+// Renders tile by tile, saving the progress to checkpoint_file now and then and when the watchdog
+// stops the render. With resume, and the file there from an earlier render of the same size that
+// was stopped, the render continues from it. A checkpoint for another camera is left alone and
+// the program exits, rather than mixing two views in one image. The file is removed once the
+// render is done, so the returned checkpoint is only incomplete if the render was stopped.
+fn render_with_checkpoint(camera: &Camera, scene: &PreparedScene, checkpoint_file: &str, resume: bool, options: &RenderOptions) -> RenderCheckpoint {
+    let (camera_name, watchdog) = (options.camera_name, &options.watchdog);
+    let new_checkpoint = || RenderCheckpoint::new(camera.hsize(), camera.vsize(), DEFAULT_TILE_SIZE).with_camera(camera_name);
+    let mut checkpoint = match RenderCheckpoint::load(checkpoint_file) {
+        _ if !resume => new_checkpoint(),
+        // Checkpoints saved before they knew their camera can't be told apart
+        Ok(c) if !c.camera().is_empty() && c.camera() != camera_name => {
+            eprintln!("{} is for camera {}, render with --camera {} to continue it", checkpoint_file, c.camera(), c.camera());
+            std::process::exit(1);
+        },
+        Ok(c) if c.width() == camera.hsize() && c.height() == camera.vsize() => {
+            eprintln!("Resuming from {} with {} of {} tiles done", checkpoint_file, c.completed_tiles(), c.tile_count());
+            c
+        },
+        Ok(_) => {
+            eprintln!("{} is for another image size, starting over", checkpoint_file);
+            new_checkpoint()
+        },
+        Err(e) => {
+            if e.kind() != ErrorKind::NotFound {
+                eprintln!("Can't resume from {}: {}, starting over", checkpoint_file, e);
+            }
+            new_checkpoint()
+        }
+    };
+    let mut last_save = Instant::now();
+    let result = camera.render_tiles(scene, &mut checkpoint, |c| {
+        if c.is_complete() {
+            return Ok(());
+        }
+        if let Some(reason) = watchdog.stop_reason() {
+            c.save(checkpoint_file)?;
+            return Err(io::Error::new(ErrorKind::Interrupted, reason));
+        }
+        if last_save.elapsed() >= CHECKPOINT_INTERVAL {
+            c.save(checkpoint_file)?;
+            last_save = Instant::now();
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => { let _ = std::fs::remove_file(checkpoint_file); },
+        Err(e) if e.kind() == ErrorKind::Interrupted => {
+            eprintln!("Render stopped ({}) with {} of {} tiles done, saved to {}", e, checkpoint.completed_tiles(), checkpoint.tile_count(), checkpoint_file);
+        },
+        Err(e) => panic!("Can't save {}: {}", checkpoint_file, e)
+    }
+    checkpoint
+}
+
+// filename is without extension, the camera name and .png are added to it, so that the views
+// of a scene don't overwrite each other. With preview, a quick image at a quarter of the
+// resolution and without reflections is written dithered to preview_<filename> before the full
+// render starts, since its few pixels make banding in gradients stand out. The render is done
+// in tiles so that it can be stopped by the watchdog, which saves what is done to
+// partial_<filename> and the progress to the checkpoint file, <filename>.checkpoint if none is
+// given, and exits. A render with a checkpoint file given continues from it, see
+// render_with_checkpoint.
+fn render_to_file(camera: &Camera, scene: &mut PreparedScene, filename: &str, options: &RenderOptions) {
+    options.watchdog.restart();
+    let filename = &format!("{}_{}.png", filename, options.camera_name);
+    let output = &options.output;
+    if options.preview {
+        let settings = scene.world.settings;
+        scene.world.settings.disable_reflections = true;
+        let preview_output = OutputSettings { dither: true, ..*output };
+        preview_output.save(camera.preview(4).render_scene(scene), &format!("preview_{}", filename));
+        scene.world.settings = settings;
+    }
+    let checkpoint_file = options.checkpoint.map_or_else(|| format!("{}.checkpoint", filename), String::from);
+    let rendered = render_with_checkpoint(camera, scene, &checkpoint_file, options.checkpoint.is_some(), options);
+    if rendered.is_complete() {
+        output.save(rendered.into_image(), filename);
+        return;
+    }
+    output.save(rendered.into_image(), &format!("partial_{}", filename));
+    eprintln!("Run again with --checkpoint {} to continue", checkpoint_file);
+    std::process::exit(options.watchdog.exit_code());
+}
+
+fn camera_render_world(mut camera: Camera, world: World, filename: &str, options: &RenderOptions) {
+    let mut scene = PreparedScene::new(world);
+    camera.film_response = options.film;
+
+    render_to_file(&camera, &mut scene, filename, options);
+}
+
+// The three spheres in front of the equirectangular image in environment_file, if one is given.
+// The image is loaded through resources, which keeps it for the next time the scene is built.
+fn three_spheres_scene(environment_file: Option<&str>, resources: &mut ResourceManager) -> io::Result<World> {
+    let mut world = three_spheres_world();
+    if let Some(file) = environment_file {
+        world.environment = Some(Environment::Equirectangular(resources.load_image(file)?));
+    }
+    Ok(world)
+}
+
+// Renders the three spheres, then again each time one of the files of the scene changes, until
+// Ctrl-C. Changed files are forgotten by the resource manager so that they are loaded again,
+// while the others are reused from the last render.
+fn watch_and_render(mut camera: Camera, environment_file: Option<&str>, options: &RenderOptions) {
+    camera.film_response = options.film;
+    let mut resources = ResourceManager::new();
+    let mut watcher = SceneWatcher::default();
+    // Watched from the start, so that a file that can't be loaded yet is rendered once it can
+    if let Some(file) = environment_file {
+        watcher.watch(resources.resolver().resolve(file));
+    }
+    loop {
+        match three_spheres_scene(environment_file, &mut resources) {
+            Ok(world) => render_to_file(&camera, &mut PreparedScene::new(world), "three_spheres", options),
+            Err(e) => eprintln!("Can't load the scene: {}", e)
+        }
+        watcher.watch_resources(&resources);
+        if watcher.files().next().is_none() {
+            eprintln!("The scene has no files to watch, give it one with --environment <file>");
+            return;
+        }
+        eprintln!("Watching {} file(s) for changes, press Ctrl-C to stop", watcher.files().count());
+        let changed = watcher.wait_for_change(DEFAULT_POLL_INTERVAL, || INTERRUPTED.load(Ordering::SeqCst));
+        if changed.is_empty() {
+            return;
+        }
+        for path in &changed {
+            eprintln!("{} changed, rendering again", path.display());
+            resources.forget(path);
+        }
+    }
+}
+
+// A 360 degree view of the scene from just in front of the spheres, usable as an environment map
+fn environment_map(filename: &str) {
+    let view_transform = Matrix::view_transform(Tuple::point(0., 1., -2.5), Tuple::point(0., 1., 0.), Tuple::vector(0., 1., 0.));
+    let mut camera = Camera::new(800, 400, rad(FRAC_PI_3), Some(view_transform));
+    camera.set_projection(EquirectangularProjection::new_boxed());
+
+    camera.render(three_spheres_world()).save(filename).unwrap();
+}
+
+// Global illumination under a sky, 64 samples per pixel
+fn camera_render_pathtraced(mut camera: Camera, filename: &str, options: &RenderOptions) {
+    camera.render_mode = RenderMode::PathTraced;
+    camera.film_response = options.film;
+    camera.sampler = StratifiedSampler::new_boxed(8);
+    let mut world = three_spheres_world();
+    world.environment = Some(Environment::SkyGradient { horizon: Color::new(0.9, 0.9, 1.), zenith: Color::new(0.3, 0.5, 1.) });
+
+    render_to_file(&camera, &mut PreparedScene::new(world), filename, options);
+}
+
+// Writes one file per pass, named <prefix>_<pass name>.png
+fn camera_render_passes(camera: &Camera, prefix: &str, passes: &[RenderPass]) {
+    let canvases = camera.render_passes(&three_spheres_world(), passes);
+
+    for (pass, canvas) in passes.iter().zip(canvases) {
+        canvas.save(&format!("{}_{}.png", prefix, pass.name)).unwrap();
+    }
+}
+
+// Writes a mask per material, <prefix>_material_<id>.png, an id map, <prefix>_material_id.png,
+// and the palette of the id map, <prefix>_materials.json
+fn camera_render_material_masks(camera: &Camera, prefix: &str) {
+    let world = three_spheres_world();
+    let palette = MaterialPalette::new(&world);
+    let mut passes = palette.mask_passes();
+    passes.push(palette.id_pass());
+    camera_render_passes(camera, prefix, &passes);
+    std::fs::write(format!("{}_materials.json", prefix), palette.to_json()).unwrap();
+}
+
+// Render passes are given as a comma separated list of pass names, e g --passes beauty,shadow
+// (mask passes use ; between object ids, e g mask:3;4;5)
+fn parse_passes(spec: &str) -> Result<Vec<RenderPass>, String> {
+    spec.split(',').map(|p| p.replace(';', ",").parse()).collect()
+}
+
+// Writes renderer diagnostics to stderr
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        eprintln!("[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+// --log <level> sets the most detailed level logged (off, error, warn, info, debug or trace),
+// the default is warn
+fn init_logging(args: &[String]) {
+    let level = match args.iter().position(|a| a == "--log").and_then(|i| args.get(i + 1)) {
+        Some(l) => l.parse().unwrap_or_else(|_| { eprintln!("unknown log level {}", l); LevelFilter::Warn }),
+        None => LevelFilter::Warn
+    };
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(level);
 }
 
 fn main()
 {
+    let args: Vec<String> = std::env::args().collect();
+    init_logging(&args);
+    let preview = args.iter().any(|a| a == "--preview");
+    // --16-bit saves the three spheres renders with 16 bits per channel and --srgb stores them
+    // with the sRGB curve
+    let output = OutputSettings {
+        bit_depth: if args.iter().any(|a| a == "--16-bit") { BitDepth::Sixteen } else { BitDepth::Eight },
+        srgb: args.iter().any(|a| a == "--srgb"),
+        dither: false
+    };
+    // --film <filmic|high-contrast|soft|reinhard[:white]|aces|exposure:<exposure>> renders the
+    // three spheres through a film response curve
+    let film = match args.iter().position(|a| a == "--film").map(|i| args.get(i + 1).map(|f| f.parse())) {
+        None => None,
+        Some(Some(Ok(f))) => Some(f),
+        Some(Some(Err(e))) => { eprintln!("{}", e); return; },
+        Some(None) => { eprintln!("--film needs a film response"); return; }
+    };
+    // --camera <front|left|top> picks the view of the three spheres
+    let camera_name = match args.iter().position(|a| a == "--camera").map(|i| args.get(i + 1)) {
+        None => "front",
+        Some(Some(name)) => name.as_str(),
+        Some(None) => { eprintln!("--camera needs a camera name"); return; }
+    };
+    let camera = match three_spheres_cameras().try_take(camera_name) {
+        Ok(c) => c,
+        Err(e) => { eprintln!("{}", e); return; }
+    };
+    let prefix = format!("three_spheres_{}", camera_name);
+    if let Some(i) = args.iter().position(|a| a == "--passes") {
+        match args.get(i + 1).map(|spec| parse_passes(spec)) {
+            Some(Ok(passes)) => camera_render_passes(&camera, &prefix, &passes),
+            Some(Err(e)) => eprintln!("{}", e),
+            None => eprintln!("--passes needs a list of render passes")
+        }
+        return;
+    }
+    if args.iter().any(|a| a == "--material-masks") {
+        camera_render_material_masks(&camera, &prefix);
+        return;
+    }
+    // --describe prints the three spheres scene as Markdown, --describe json as JSON
+    if let Some(i) = args.iter().position(|a| a == "--describe") {
+        let summary = SceneSummary::new(&three_spheres_world(), Some(&camera));
+        match args.get(i + 1).map(|f| f.as_str()) {
+            Some("json") => println!("{}", summary.to_json()),
+            _ => print!("{}", summary.to_markdown())
+        }
+        return;
+    }
+    // --lint checks the three spheres scene for mistakes, --lint json reports them as JSON. Exits
+    // with status 1 if any of them is an error.
+    if let Some(i) = args.iter().position(|a| a == "--lint") {
+        let report = LintReport::new(&three_spheres_world(), &camera);
+        match args.get(i + 1).map(|f| f.as_str()) {
+            Some("json") => println!("{}", report.to_json()),
+            _ => print!("{}", report.to_text())
+        }
+        if report.has_errors() {
+            std::process::exit(1);
+        }
+        return;
+    }
+    // --point-cloud <file> writes what the camera sees as a .ply or .xyz point cloud
+    if let Some(i) = args.iter().position(|a| a == "--point-cloud") {
+        match args.get(i + 1) {
+            Some(file) => {
+                let points = point_cloud::capture(&camera, &three_spheres_world());
+                point_cloud::save(&points, file).unwrap();
+            },
+            None => eprintln!("--point-cloud needs a file name")
+        }
+        return;
+    }
+    // --obj <file> exports the three spheres scene as triangles
+    if let Some(i) = args.iter().position(|a| a == "--obj") {
+        match args.get(i + 1) {
+            Some(file) => { save_obj(&three_spheres_world(), &ObjExportOptions::default(), file).unwrap(); },
+            None => eprintln!("--obj needs a file name")
+        }
+        return;
+    }
+    // --environment-map <file> writes a 360 degree view of the three spheres
+    if let Some(i) = args.iter().position(|a| a == "--environment-map") {
+        match args.get(i + 1) {
+            Some(file) => environment_map(file),
+            None => eprintln!("--environment-map needs a file name")
+        }
+        return;
+    }
+    // --checkpoint <file> renders the three spheres in tiles, saving the progress to file so that
+    // a stopped render continues where it was when run again
+    let checkpoint = match args.iter().position(|a| a == "--checkpoint").map(|i| args.get(i + 1)) {
+        None => None,
+        Some(Some(file)) => Some(file.as_str()),
+        Some(None) => { eprintln!("--checkpoint needs a file name"); return; }
+    };
+    // --time-limit <seconds> stops the three spheres render after that many seconds, like Ctrl-C
+    // does, saving what is done and the progress so that it can be continued. With --watch each
+    // render after a change gets that long again.
+    let time_limit = match args.iter().position(|a| a == "--time-limit").map(|i| args.get(i + 1).map(|t| t.parse::<f64>())) {
+        None => None,
+        Some(Some(Ok(seconds))) if seconds.is_finite() && seconds >= 0. => Some(Duration::from_secs_f64(seconds)),
+        Some(_) => { eprintln!("--time-limit needs a number of seconds"); return; }
+    };
+    // --environment <file> puts the three spheres in front of an equirectangular image, e g one
+    // written by --environment-map
+    let environment_file = match args.iter().position(|a| a == "--environment").map(|i| args.get(i + 1)) {
+        None => None,
+        Some(Some(file)) => Some(file.as_str()),
+        Some(None) => { eprintln!("--environment needs a file name"); return; }
+    };
+    let options = RenderOptions { camera_name, preview, film, output, checkpoint, watchdog: Watchdog::new(time_limit) };
+    // --watch renders the three spheres again whenever the files they are made from change
+    if args.iter().any(|a| a == "--watch") {
+        watch_and_render(camera, environment_file, &options);
+        return;
+    }
+    if args.iter().any(|a| a == "--pathtraced") {
+        camera_render_pathtraced(camera, "three_spheres_pathtraced", &options);
+        return;
+    }
     canvas_to_file("black.png");
     circle_shadow("shadow.png");
     rendered_sphere("sphere.png");
-    camera_render_world("three_spheres.png");
+    let world = match three_spheres_scene(environment_file, &mut ResourceManager::new()) {
+        Ok(w) => w,
+        Err(e) => { eprintln!("Can't load the scene: {}", e); return; }
+    };
+    camera_render_world(camera, world, "three_spheres", &options);
 }
@@ -3,6 +3,10 @@ use super::tuple::Tuple;
 use super::light::PointLight;
 use super::pattern::BoxPattern;
 use super::shape::Shape;
+use super::intersection::PrimitiveHit;
+use super::precomputed_data::PrecomputedData;
+use super::spectral::{CauchyIor, REFERENCE_WAVELENGTH};
+use super::EPSILON;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Material {
@@ -11,20 +15,52 @@ pub struct Material {
     pub diffuse: f64,
     pub specular: f64,
     pub shininess: f64,
-    pub pattern: Option<BoxPattern>
+    pub pattern: Option<BoxPattern>,
+    pub reflective: Option<Color>,
+    // Light given off by the surface itself, independent of any light source
+    pub emissive: Option<Color>,
+    // How much light passes through, from 0 for opaque to 1 for clear glass
+    pub transparency: f64,
+    // How much light bends entering the material, 1 for vacuum (and close enough for air),
+    // 1.33 for water and about 1.5 for glass
+    pub refractive_index: f64,
+    // How the refractive index changes with the wavelength, for rays of a single wavelength.
+    // Rays without one use refractive_index.
+    pub dispersion: Option<CauchyIor>,
+    // Whether the object blocks light from reaching other surfaces, and whether light can be
+    // blocked from reaching it, e g a fake floor that only should show the surroundings.
+    // Render overrides leave these alone, they belong to the scene rather than the look.
+    pub casts_shadow: bool,
+    pub receives_shadow: bool
 }
 
 pub const DEFAULT_AMBIENT: f64 = 0.1;
 pub const DEFAULT_DIFFUSE: f64 = 0.9;
 pub const DEFAULT_SPECULAR: f64 = 0.9;
 pub const DEFAULT_SHININESS: f64 = 200.0;
+pub const DEFAULT_REFRACTIVE_INDEX: f64 = 1.0;
 pub const DEFAULT_MATERIAL: Material = Material {
     color: WHITE, 
     ambient: DEFAULT_AMBIENT, 
     diffuse: DEFAULT_DIFFUSE, 
     specular: DEFAULT_SPECULAR, 
     shininess: DEFAULT_SHININESS,
-    pattern: None };
+    pattern: None,
+    reflective: None,
+    emissive: None,
+    transparency: 0.,
+    refractive_index: DEFAULT_REFRACTIVE_INDEX,
+    dispersion: None,
+    casts_shadow: true,
+    receives_shadow: true };
+
+// What shading a material needs, so that work that can't change its color is skipped
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadingFlags {
+    // Has diffuse or specular light from the light source, and so can be shadowed
+    pub lit: bool,
+    pub reflective: bool
+}
 
 impl Default for Material {
     fn default() -> Self {
@@ -34,17 +70,158 @@ impl Default for Material {
 
 impl Material {
     pub fn new(color: Color, ambient: f64, diffuse: f64, specular: f64, shininess: f64, pattern: Option<BoxPattern>) -> Material {
-        Material { color, ambient, diffuse, specular, shininess, pattern }
+        Material { color, ambient, diffuse, specular, shininess, pattern, reflective: None, emissive: None, transparency: 0., refractive_index: DEFAULT_REFRACTIVE_INDEX,
+            dispersion: None, casts_shadow: true, receives_shadow: true }
     }
 
-    pub fn lighting(&self, object: &dyn Shape, light: &PointLight, point: Tuple, eyev: Tuple, normalv: Tuple, in_shadow: bool) -> Color {
-        let color = match &self.pattern {
-            Some(p) => p.pattern_at_shape(object, point),
+    // Builders for families of related materials: a base material, cloned and with the fields
+    // that differ overridden, e g base.clone().with_color(RED).with_reflective(Some(WHITE))
+    pub fn with_color(self, color: Color) -> Self {
+        Material { color, ..self }
+    }
+
+    pub fn with_ambient(self, ambient: f64) -> Self {
+        Material { ambient, ..self }
+    }
+
+    pub fn with_diffuse(self, diffuse: f64) -> Self {
+        Material { diffuse, ..self }
+    }
+
+    pub fn with_specular(self, specular: f64) -> Self {
+        Material { specular, ..self }
+    }
+
+    pub fn with_shininess(self, shininess: f64) -> Self {
+        Material { shininess, ..self }
+    }
+
+    pub fn with_pattern(self, pattern: Option<BoxPattern>) -> Self {
+        Material { pattern, ..self }
+    }
+
+    pub fn with_reflective(self, reflective: Option<Color>) -> Self {
+        Material { reflective, ..self }
+    }
+
+    pub fn with_emissive(self, emissive: Option<Color>) -> Self {
+        Material { emissive, ..self }
+    }
+
+    pub fn with_transparency(self, transparency: f64) -> Self {
+        Material { transparency, ..self }
+    }
+
+    pub fn with_refractive_index(self, refractive_index: f64) -> Self {
+        Material { refractive_index, ..self }
+    }
+
+    // With refractive_index set to that at REFERENCE_WAVELENGTH
+    pub fn with_dispersion(self, ior: CauchyIor) -> Self {
+        Material { refractive_index: ior.at(REFERENCE_WAVELENGTH), dispersion: Some(ior), ..self }
+    }
+
+    pub fn with_casts_shadow(self, casts_shadow: bool) -> Self {
+        Material { casts_shadow, ..self }
+    }
+
+    pub fn with_receives_shadow(self, receives_shadow: bool) -> Self {
+        Material { receives_shadow, ..self }
+    }
+
+    pub fn is_reflective(&self) -> bool {
+        match self.reflective {
+            None => false,
+            Some(r) => r != BLACK
+        }
+    }
+
+    // For a ray of wavelength, or of all of RGB with None
+    pub fn refractive_index_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(ior), Some(w)) => ior.at(w),
+            _ => self.refractive_index
+        }
+    }
+
+    pub fn shading_flags(&self) -> ShadingFlags {
+        ShadingFlags { lit: self.diffuse != 0. || self.specular != 0., reflective: self.is_reflective() }
+    }
+
+    pub fn emission(&self) -> Color {
+        self.emissive.unwrap_or(BLACK)
+    }
+
+    // The largest fraction of arriving light, per channel, that the surface sends back: the
+    // ambient and diffuse terms plus mirror reflection. Phong highlights are left out since
+    // they cover a tiny solid angle. The colors of a pattern aren't known here, so a patterned
+    // surface is taken to be white.
+    pub fn reflectance(&self) -> Color {
+        let color = if self.pattern.is_some() { WHITE } else { self.color };
+        color * (self.ambient + self.diffuse) + self.reflective.unwrap_or(BLACK)
+    }
+
+    // Whether the surface gives back no more light than it receives. Surfaces that give back
+    // more make path traced images blow up, since light gains energy at every bounce.
+    pub fn is_energy_conserving(&self) -> bool {
+        self.reflectance().max_component() <= 1. + EPSILON
+    }
+
+    // A copy with ambient, diffuse and reflective scaled down just enough to conserve energy,
+    // keeping their proportions and the color
+    pub fn normalize(&self) -> Material {
+        let largest = self.reflectance().max_component();
+        if largest <= 1. {
+            return self.clone();
+        }
+        let scale = 1. / largest;
+        Material {
+            ambient: self.ambient * scale,
+            diffuse: self.diffuse * scale,
+            reflective: self.reflective.map(|c| c * scale),
+            ..self.clone()
+        }
+    }
+
+    // The color of the surface at point, from the pattern if there is one
+    pub fn color_at(&self, object: &dyn Shape, point: Tuple) -> Color {
+        self.color_at_hit(object, point, None)
+    }
+
+    // Like color_at, for a pattern that may want to know which triangle of object was hit
+    pub fn color_at_hit(&self, object: &dyn Shape, point: Tuple, primitive: Option<&PrimitiveHit>) -> Color {
+        match &self.pattern {
+            Some(p) => p.pattern_at_hit(object, point, primitive),
             None => self.color
-        };
-        let effective_color = color * light.intensity;
-        let lightv = (light.position - point).normalize();
+        }
+    }
+
+    pub fn lighting(&self, object: &dyn Shape, light: &PointLight, point: Tuple, eyev: Tuple, normalv: Tuple, in_shadow: bool) -> Color {
+        let light_transmission = if in_shadow { BLACK } else { WHITE };
+        self.lighting_with_transmission(object, light, point, eyev, normalv, light_transmission)
+    }
+
+    // Like lighting, with the part of the light, per channel, that reaches point past the
+    // objects in the way, e g tinted by colored glass. BLACK is full shadow and WHITE none.
+    pub fn lighting_with_transmission(&self, object: &dyn Shape, light: &PointLight, point: Tuple, eyev: Tuple, normalv: Tuple, light_transmission: Color) -> Color {
+        self.shade(self.color_at(object, point), light, point, eyev, normalv, light_transmission)
+    }
+
+    // Like lighting_with_transmission, at the hit comps was prepared for, so that the pattern
+    // gets to know which triangle was hit
+    pub fn lighting_at(&self, comps: &PrecomputedData, light: &PointLight, light_transmission: Color) -> Color {
+        let color = self.color_at_hit(comps.object, comps.point, comps.primitive.as_ref());
+        self.shade(color, light, comps.point, comps.eyev, comps.normalv, light_transmission)
+    }
+
+    fn shade(&self, color: Color, light: &PointLight, point: Tuple, eyev: Tuple, normalv: Tuple, light_transmission: Color) -> Color {
+        let intensity = light.intensity_at(point);
+        let effective_color = color * intensity;
         let ambient = effective_color * self.ambient;
+        if light_transmission == BLACK || !self.shading_flags().lit {
+            return ambient + self.emission();
+        }
+        let lightv = (light.position - point).normalize();
         let light_dot_normal = lightv.dot(&normalv);
         let (diffuse, specular) = 
             if light_dot_normal < 0.0 {
@@ -59,11 +236,11 @@ impl Material {
                     }
                     else {
                         let factor = reflect_dot_eye.powf(self.shininess);
-                        light.intensity * self.specular * factor
+                        intensity * self.specular * factor
                     }
                 )
             };
-        ambient + if in_shadow { BLACK } else { diffuse + specular }
+        ambient + self.emission() + (diffuse + specular) * light_transmission
     }
 }
 
@@ -73,6 +250,7 @@ mod tests {
     use crate::tuple::ORIGO;
     use crate::sphere::Sphere;
     use crate::pattern::StripePattern;
+    use crate::spectral::SF11_GLASS;
 
     #[test]
     fn default_material() {
@@ -81,6 +259,113 @@ mod tests {
         assert_eq!(m.diffuse, 0.9);
         assert_eq!(m.specular, 0.9);
         assert_eq!(m.shininess, 200.);
+        assert_eq!(m.reflective, None);
+        assert_eq!(m.emissive, None);
+        assert_eq!(m.transparency, 0.);
+        assert_eq!(m.refractive_index, 1.);
+        assert!(m.casts_shadow && m.receives_shadow);
+        assert_eq!(m, DEFAULT_MATERIAL);
+    }
+
+    #[test]
+    fn materials_derived_from_a_base() {
+        let base = Material::default().with_ambient(0.2).with_diffuse(0.7).with_specular(0.3).with_shininess(50.);
+        let red_mirror = base.clone().with_color(Color::new(1., 0., 0.)).with_reflective(Some(Color::new(0.1, 0.1, 0.1)));
+        let lamp = base.clone().with_emissive(Some(WHITE)).with_pattern(Some(StripePattern::new_boxed(WHITE, BLACK, None)));
+
+        assert_eq!(red_mirror, Material {
+            color: Color::new(1., 0., 0.),
+            reflective: Some(Color::new(0.1, 0.1, 0.1)),
+            ..Material::new(WHITE, 0.2, 0.7, 0.3, 50., None) });
+        assert_eq!(lamp.emission(), WHITE);
+        assert!(lamp.pattern.is_some());
+        assert_eq!((lamp.ambient, lamp.color), (base.ambient, base.color));
+    }
+
+    #[test]
+    fn default_material_conserves_energy() {
+        assert_eq!(Material::default().reflectance(), WHITE);
+        assert!(Material::default().is_energy_conserving());
+    }
+
+    #[test]
+    fn reflective_material_can_give_back_too_much() {
+        let m = Material { reflective: Some(Color::new(0.5, 0.5, 0.5)), ..Material::new(Color::new(1., 0.2, 0.2), 0.1, 0.9, 0.9, 200., None) };
+
+        assert_eq!(m.reflectance(), Color::new(1.5, 0.7, 0.7));
+        assert!(!m.is_energy_conserving());
+        let n = m.normalize();
+        assert!(n.is_energy_conserving());
+        assert_eq!(n.reflectance(), Color::new(1., 0.7 / 1.5, 0.7 / 1.5));
+        assert_eq!(n.color, m.color);
+        assert_eq!(n.specular, m.specular);
+    }
+
+    #[test]
+    fn normalizing_conserving_material_changes_nothing() {
+        let m = Material::new(Color::new(0.5, 0.5, 0.5), 0.2, 0.8, 0.9, 200., None);
+
+        assert_eq!(m.normalize(), m);
+    }
+
+    #[test]
+    fn patterned_material_is_taken_to_be_white() {
+        let m = Material::new(BLACK, 0.1, 0.9, 0.9, 200., Some(StripePattern::new_boxed(WHITE, BLACK, None)));
+
+        assert_eq!(m.reflectance(), WHITE);
+    }
+
+    #[test]
+    fn dispersive_material_bends_each_wavelength_differently() {
+        let glass = Material::default().with_transparency(1.).with_dispersion(SF11_GLASS);
+
+        assert_eq!(glass.refractive_index, SF11_GLASS.at(REFERENCE_WAVELENGTH));
+        assert_eq!(glass.refractive_index_at(None), glass.refractive_index);
+        assert_eq!(glass.refractive_index_at(Some(450.)), SF11_GLASS.at(450.));
+        assert!(glass.refractive_index_at(Some(450.)) > glass.refractive_index_at(Some(650.)));
+        assert_eq!(Material::default().with_refractive_index(1.5).refractive_index_at(Some(450.)), 1.5);
+    }
+
+    #[test]
+    fn shading_flags_of_materials() {
+        let mirror = Material { reflective: Some(WHITE), ..Material::new(BLACK, 0., 0., 0., 200., None) };
+        let unlit = Material::new(WHITE, 1., 0., 0., 200., None);
+
+        assert_eq!(Material::default().shading_flags(), ShadingFlags { lit: true, reflective: false });
+        assert_eq!(mirror.shading_flags(), ShadingFlags { lit: false, reflective: true });
+        assert_eq!(unlit.shading_flags(), ShadingFlags { lit: false, reflective: false });
+    }
+
+    #[test]
+    fn unlit_surface_ignores_the_light() {
+        let object = Sphere::new(None, None);
+        let m = Material::new(Color::new(0.5, 0.5, 0.5), 1., 0., 0., 200., None);
+        let light = PointLight::new(Tuple::point(0., 0., -10.), WHITE);
+        let lit = m.lighting(&object, &light, ORIGO, Tuple::vector(0., 0., -1.), Tuple::vector(0., 0., -1.), false);
+        let shadowed = m.lighting(&object, &light, ORIGO, Tuple::vector(0., 0., -1.), Tuple::vector(0., 0., -1.), true);
+
+        assert_eq!(lit, Color::new(0.5, 0.5, 0.5));
+        assert_eq!(lit, shadowed);
+    }
+
+    #[test]
+    fn emissive_surface_glows_in_shadow() {
+        let object = Sphere::new(None, None);
+        let m = Material { emissive: Some(Color::new(0.5, 0.25, 0.)), ..Material::default() };
+        let eyev = Tuple::vector(0., 0., -1.);
+        let normalv = Tuple::vector(0., 0., -1.);
+        let light = PointLight::new(Tuple::point(0., 0., -10.), WHITE);
+        let result = m.lighting(&object, &light, ORIGO, eyev, normalv, true);
+
+        assert_eq!(result, Color::new(0.6, 0.35, 0.1));
+    }
+
+    #[test]
+    fn black_reflectance_is_not_reflective() {
+        let m = Material { reflective: Some(BLACK), ..Material::default() };
+        assert!(!m.is_reflective());
+        let m = Material { reflective: Some(Color::new(1., 0.8, 0.3)), ..Material::default() };
+        assert!(m.is_reflective());
     }
 
     #[test]
@@ -150,6 +435,19 @@ mod tests {
         assert_eq!(result, Color::new(0.1, 0.1, 0.1));
     }
 
+    #[test]
+    fn lighting_with_part_of_light_transmitted() {
+        let m = Material::default();
+        let object = Sphere::default();
+        let light = PointLight::new(Tuple::point(0., 0., -10.), WHITE);
+        let (eyev, normalv) = (Tuple::vector(0., 0., -1.), Tuple::vector(0., 0., -1.));
+        let lit = m.lighting(&object, &light, ORIGO, eyev, normalv, false);
+        let shadowed = m.lighting(&object, &light, ORIGO, eyev, normalv, true);
+        let tinted = m.lighting_with_transmission(&object, &light, ORIGO, eyev, normalv, Color::new(1., 0.5, 0.));
+
+        assert_eq!(tinted, Color::new(lit.r, (lit.g + shadowed.g) / 2., shadowed.b));
+    }
+
     #[test]
     fn lighing_with_surface_in_shadow() {
         let object = Sphere::new(None, None);
@@ -0,0 +1,134 @@
+use super::color::Color;
+use super::material::Material;
+use super::render_pass::{RenderPass, PassKind};
+use super::scene_summary::{json_string, material_json};
+use super::world::World;
+
+// The distinct materials of a world, for grading all surfaces sharing a material (all glass, say)
+// together in post. Materials have no names of their own, so objects with equal materials share
+// an entry, and entries are numbered from 1 in the order their first object appears. 0 is left
+// for pixels without any object.
+pub struct MaterialPalette {
+    pub entries: Vec<MaterialEntry>
+}
+
+pub struct MaterialEntry {
+    pub id: usize,
+    pub material: Material,
+    // The ObjectId numbers of the objects
+    pub objects: Vec<usize>
+}
+
+impl MaterialEntry {
+    pub fn name(&self) -> String {
+        format!("material_{}", self.id)
+    }
+}
+
+impl MaterialPalette {
+    pub fn new(world: &World) -> Self {
+        let mut entries: Vec<MaterialEntry> = vec![];
+        for (i, o) in world.ids().iter().map(|id| id.number()).zip(world.objects()) {
+            match entries.iter_mut().find(|e| e.material == *o.material()) {
+                Some(e) => e.objects.push(i),
+                None => entries.push(MaterialEntry { id: entries.len() + 1, material: o.material().clone(), objects: vec![i] })
+            }
+        }
+        Self { entries }
+    }
+
+    // The material id of each object, by ObjectId number
+    pub fn object_ids(&self) -> Vec<usize> {
+        let count = self.entries.iter().flat_map(|e| e.objects.iter()).max().map_or(0, |i| i + 1);
+        let mut ids = vec![0; count];
+        for e in &self.entries {
+            for &i in &e.objects {
+                ids[i] = e.id;
+            }
+        }
+        ids
+    }
+
+    // A single image with the material id of the visible surface packed in each pixel, see
+    // MaterialPalette::id_color
+    pub fn id_pass(&self) -> RenderPass {
+        RenderPass::new("material_id", PassKind::MaterialId(self.object_ids()))
+    }
+
+    // One grayscale mask per material, named after it
+    pub fn mask_passes(&self) -> Vec<RenderPass> {
+        self.entries.iter().map(|e| RenderPass::new(&e.name(), PassKind::Mask(e.objects.clone()))).collect()
+    }
+
+    // The id split into bytes, lowest in red, so that it survives being saved as an 8 bit image
+    pub fn id_color(id: usize) -> Color {
+        let byte = |shift: usize| ((id >> shift) & 0xff) as f64 / 255.;
+        Color::new(byte(0), byte(8), byte(16))
+    }
+
+    // The palette for an id map: the id and color of each material and the objects using it
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self.entries.iter().map(|e| {
+            let objects: Vec<String> = e.objects.iter().map(|i| i.to_string()).collect();
+            format!(
+                "{{\"id\": {}, \"name\": {}, \"id_color\": [{}, {}, {}], \"objects\": [{}], \"material\": {}}}",
+                e.id, json_string(&e.name()), e.id & 0xff, (e.id >> 8) & 0xff, (e.id >> 16) & 0xff,
+                objects.join(", "), material_json(&e.material))
+        }).collect();
+        format!("{{\"materials\": [{}]}}", entries.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::Canvas;
+    use crate::color::{BLACK, WHITE};
+    use crate::sphere::Sphere;
+
+    fn world_with_shared_material() -> World {
+        let mut w = World::default_world();
+        let inner = w.objects()[1].material().clone();
+        w.add_object(Sphere::new_boxed(Some(inner), None));
+        w
+    }
+
+    #[test]
+    fn equal_materials_share_an_entry() {
+        let palette = MaterialPalette::new(&world_with_shared_material());
+
+        assert_eq!(palette.entries.len(), 2);
+        assert_eq!(palette.entries[0].id, 1);
+        assert_eq!(palette.entries[0].objects, vec![0]);
+        assert_eq!(palette.entries[1].objects, vec![1, 2]);
+        assert_eq!(palette.object_ids(), vec![1, 2, 2]);
+    }
+
+    #[test]
+    fn one_mask_pass_per_material() {
+        let passes = MaterialPalette::new(&world_with_shared_material()).mask_passes();
+
+        assert_eq!(passes, vec![
+            RenderPass::new("material_1", PassKind::Mask(vec![0])),
+            RenderPass::new("material_2", PassKind::Mask(vec![1, 2]))]);
+    }
+
+    #[test]
+    fn id_colors_survive_eight_bits() {
+        for &id in &[0, 1, 255, 256, 70000] {
+            let c = MaterialPalette::id_color(id);
+            let bytes = [Canvas::clamp_to_byte(c.r), Canvas::clamp_to_byte(c.g), Canvas::clamp_to_byte(c.b)];
+            assert_eq!(bytes[0] as usize | (bytes[1] as usize) << 8 | (bytes[2] as usize) << 16, id);
+        }
+        assert_eq!(MaterialPalette::id_color(0), BLACK);
+        assert_eq!(MaterialPalette::id_color(0xffffff), WHITE);
+    }
+
+    #[test]
+    fn palette_as_json() {
+        let json = MaterialPalette::new(&world_with_shared_material()).to_json();
+
+        assert!(json.starts_with("{\"materials\": [{\"id\": 1, \"name\": \"material_1\", \"id_color\": [1, 0, 0], \"objects\": [0], \"material\": {\"color\": [0.8, 1, 0.6]"));
+        assert!(json.contains("{\"id\": 2, \"name\": \"material_2\", \"id_color\": [2, 0, 0], \"objects\": [1, 2]"));
+    }
+}
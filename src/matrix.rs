@@ -1,78 +1,122 @@
 use core::ops;
+use std::sync::Arc;
+use log::error;
+use super::error::Error;
 use super::tuple::Tuple;
 
+// A square matrix with N rows and columns, indexed m[row][col]. Transformations are 4x4, which
+// is what Matrix without a size means; the smaller sizes are the submatrices that determinants
+// are expanded into, so using a matrix of the wrong size is a compile error rather than a panic.
 #[derive(Debug, Copy, Clone)]
-pub struct Row {
-    inner: [f64; 4],
-    size: usize
+pub struct Matrix<const N: usize = 4> {
+    inner: [[f64; N]; N]
 }
 
-impl ops::Index<usize> for Row {
-    type Output = f64;
-    fn index(&self, col: usize) -> &Self::Output {
-        if col >= self.size { panic!("Index out-of-bounds") }
-        &self.inner[col]
+pub type Matrix2 = Matrix<2>;
+pub type Matrix3 = Matrix<3>;
+pub type Matrix4 = Matrix<4>;
+
+pub const IDENTITY_MATRIX: Matrix = Matrix {
+    inner: [
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.]] };
+
+// The products work on references, since a matrix is large enough that the copies for the by
+// value forms show up in hot loops
+fn product<const N: usize>(a: &Matrix<N>, b: &Matrix<N>) -> Matrix<N> {
+    let mut m = Matrix::new_empty();
+    for row in 0..N {
+        for col in 0..N {
+            m.inner[row][col] = (0..N).map(|i| a.inner[row][i] * b.inner[i][col]).sum();
+        }
     }
+    m
 }
 
-impl PartialEq for Row {
-    fn eq(&self, other: &Self) -> bool {
-        (0..self.size).all(|col| super::approx_eq(self[col], other[col]))
+fn transform(m: &Matrix, t: &Tuple) -> Tuple {
+    Tuple::new(m.tuple(0).dot(t), m.tuple(1).dot(t), m.tuple(2).dot(t), m.tuple(3).dot(t))
+}
+
+impl<const N: usize> ops::Mul<Matrix<N>> for Matrix<N> {
+    type Output = Matrix<N>;
+    fn mul(self, rhs: Matrix<N>) -> Matrix<N> {
+        product(&self, &rhs)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct Matrix {
-    inner: [Row; 4],
-    pub size: usize
+impl<const N: usize> ops::Mul<&Matrix<N>> for Matrix<N> {
+    type Output = Matrix<N>;
+    fn mul(self, rhs: &Matrix<N>) -> Matrix<N> {
+        product(&self, rhs)
+    }
 }
 
-pub const IDENTITY_MATRIX: Matrix = Matrix { 
-            inner: [
-                Row { inner: [1., 0., 0., 0.], size: 4 }, 
-                Row { inner: [0., 1., 0., 0.], size: 4 },
-                Row { inner: [0., 0., 1., 0.], size: 4 },
-                Row { inner: [0., 0., 0., 1.], size: 4 } ], 
-            size: 4 };
-
-impl ops::Mul<Matrix> for Matrix {
-    type Output = Matrix;
-    fn mul(self, rhs: Matrix) -> Matrix {
-        let mut m = self.empty();
-        let size = self.size;
-        for row in 0..size {
-            for col in 0..size {
-                let a = (0..size).map(|i| self[row][i] * rhs[i][col]).sum();
-                m.set(row, col, a);
-            }
-        }
-        m
+impl<const N: usize> ops::Mul<Matrix<N>> for &Matrix<N> {
+    type Output = Matrix<N>;
+    fn mul(self, rhs: Matrix<N>) -> Matrix<N> {
+        product(self, &rhs)
+    }
+}
+
+impl<const N: usize> ops::Mul<&Matrix<N>> for &Matrix<N> {
+    type Output = Matrix<N>;
+    fn mul(self, rhs: &Matrix<N>) -> Matrix<N> {
+        product(self, rhs)
+    }
+}
+
+impl<const N: usize> ops::MulAssign<Matrix<N>> for Matrix<N> {
+    fn mul_assign(&mut self, rhs: Matrix<N>) {
+        *self = product(self, &rhs);
+    }
+}
+
+impl<const N: usize> ops::MulAssign<&Matrix<N>> for Matrix<N> {
+    fn mul_assign(&mut self, rhs: &Matrix<N>) {
+        *self = product(self, rhs);
     }
 }
 
 impl ops::Mul<Tuple> for Matrix {
     type Output = Tuple;
     fn mul(self, rhs: Tuple) -> Tuple {
-        Tuple::new(
-            self.tuple(0).dot(&rhs),
-            self.tuple(1).dot(&rhs),
-            self.tuple(2).dot(&rhs),
-            self.tuple(3).dot(&rhs))
+        transform(&self, &rhs)
+    }
+}
+
+impl ops::Mul<&Tuple> for Matrix {
+    type Output = Tuple;
+    fn mul(self, rhs: &Tuple) -> Tuple {
+        transform(&self, rhs)
+    }
+}
+
+impl ops::Mul<Tuple> for &Matrix {
+    type Output = Tuple;
+    fn mul(self, rhs: Tuple) -> Tuple {
+        transform(self, &rhs)
+    }
+}
+
+impl ops::Mul<&Tuple> for &Matrix {
+    type Output = Tuple;
+    fn mul(self, rhs: &Tuple) -> Tuple {
+        transform(self, rhs)
     }
 }
 
-impl ops::Index<usize> for Matrix {
-    type Output = Row;
+impl<const N: usize> ops::Index<usize> for Matrix<N> {
+    type Output = [f64; N];
     fn index(&self, row: usize) -> &Self::Output {
-        if row >= self.size { panic!("Index out-of-bounds") }
         &self.inner[row]
     }
 }
 
-impl PartialEq for Matrix {
+impl<const N: usize> PartialEq for Matrix<N> {
     fn eq(&self, other: &Self) -> bool {
-        self.size == other.size &&
-        (0..self.size).all(|row| self[row] == other[row])
+        self.inner.iter().flatten().zip(other.inner.iter().flatten()).all(|(&a, &b)| super::approx_eq(a, b))
     }
 }
 
@@ -80,75 +124,97 @@ impl Default for Matrix {
     fn default() -> Self { IDENTITY_MATRIX }
 }
 
-impl Matrix {
-    const EMPTY_ROW:  [f64; 4] = [0., 0., 0., 0.];
-    const EMPTY_ROW3: [f64; 3] = [0., 0., 0.];
-    const EMPTY_ROW2: [f64; 2] = [0., 0.];
+impl<const N: usize> Matrix<N> {
+    pub fn from_rows(rows: [[f64; N]; N]) -> Self {
+        Self { inner: rows }
+    }
 
-    pub fn new(row0: [f64; 4], row1: [f64; 4], row2 : [f64; 4], row3 : [f64; 4]) -> Self
-    {
-        Self { 
-            inner: [ 
-                Row { inner: row0, size: 4 }, 
-                Row { inner: row1, size: 4 }, 
-                Row { inner: row2, size: 4 }, 
-                Row { inner: row3, size: 4 }], 
-            size: 4}
+    pub fn new_empty() -> Self {
+        Self { inner: [[0.; N]; N] }
     }
 
-    pub fn new3(row0: [f64; 3], row1: [f64; 3], row2 : [f64; 3]) -> Self
-    {
-        Self { 
-            inner: [ 
-                Matrix::coerce_array3(row0), 
-                Matrix::coerce_array3(row1), 
-                Matrix::coerce_array3(row2), 
-                Matrix::coerce_array3(Matrix::EMPTY_ROW3)], 
-            size: 3}
+    pub fn set(&mut self, row: usize, col: usize, value: f64) {
+        self.inner[row][col] = value;
     }
 
-    pub fn new2(row0: [f64; 2], row1: [f64; 2]) -> Self
-    {
-        Self { 
-            inner: [ 
-                Matrix::coerce_array2(row0), 
-                Matrix::coerce_array2(row1), 
-                Matrix::coerce_array2(Matrix::EMPTY_ROW2), 
-                Matrix::coerce_array2(Matrix::EMPTY_ROW2)], 
-            size: 2 }
+    pub fn transpose(&self) -> Self {
+        let mut m = Self::new_empty();
+        for row in 0..N {
+            for col in 0..N {
+                m.inner[col][row] = self.inner[row][col];
+            }
+        }
+        m
     }
 
-    pub fn new_empty4() -> Self {
-        Matrix::new(Matrix::EMPTY_ROW, Matrix::EMPTY_ROW, Matrix::EMPTY_ROW, Matrix::EMPTY_ROW)
+    // Element-wise interpolation, t = 0 gives self and t = 1 gives other
+    pub fn lerp(&self, other: &Self, t: f64) -> Self {
+        let mut m = Self::new_empty();
+        for row in 0..N {
+            for col in 0..N {
+                m.inner[row][col] = self.inner[row][col] + (other.inner[row][col] - self.inner[row][col]) * t;
+            }
+        }
+        m
     }
 
-    pub fn new_empty3() -> Self {
-        Matrix::new3(Matrix::EMPTY_ROW3, Matrix::EMPTY_ROW3, Matrix::EMPTY_ROW3)
+    // Like m[row][col], but an error instead of a panic outside of the matrix
+    pub fn get(&self, row: usize, col: usize) -> Result<f64, Error> {
+        if row >= N || col >= N {
+            return Err(Error::IndexOutOfBounds { row, col, size: N });
+        }
+        Ok(self.inner[row][col])
     }
 
-    pub fn new_empty2() -> Self {
-        Matrix::new2(Matrix::EMPTY_ROW2, Matrix::EMPTY_ROW2)
+    // Without row and col, with the rows and columns after them moved up and left. M is always
+    // N - 1, which can't be written as a const generic expression yet.
+    fn without<const M: usize>(&self, row: usize, col: usize) -> Matrix<M> {
+        let mut m = Matrix::new_empty();
+        for (r_new, r) in (0..N).filter(|&r| r != row).enumerate() {
+            for (c_new, c) in (0..N).filter(|&c| c != col).enumerate() {
+                m.inner[r_new][c_new] = self.inner[r][c];
+            }
+        }
+        m
     }
+}
 
-    fn coerce_array2(arr: [f64; 2]) -> Row {
-        Row { inner: [arr[0], arr[1], 0., 0.], size: 2 }
+impl Matrix2 {
+    pub fn new2(row0: [f64; 2], row1: [f64; 2]) -> Self {
+        Matrix::from_rows([row0, row1])
     }
 
-    fn coerce_array3(arr: [f64; 3]) -> Row {
-        Row { inner: [arr[0], arr[1], arr[2], 0.], size: 3 }
+    pub fn determinant(&self) -> f64 {
+        self[0][0] * self[1][1] - self[0][1] * self[1][0]
     }
+}
 
-    fn empty(&self) -> Self {
-        match self.size {
-            2 => Matrix::new_empty2(),
-            3 => Matrix::new_empty3(),
-            4 => Matrix::new_empty4(),
-            _ => { panic!("bad dimension") }
-        }
+impl Matrix3 {
+    pub fn new3(row0: [f64; 3], row1: [f64; 3], row2: [f64; 3]) -> Self {
+        Matrix::from_rows([row0, row1, row2])
     }
 
-    pub fn set(&mut self, row: usize, col: usize, value: f64) {
-        self.inner[row].inner[col] = value;
+    pub fn determinant(&self) -> f64 {
+        (0..3).map(|col| self[0][col] * self.cofactor(0, col)).sum()
+    }
+
+    pub fn submatrix(&self, row: usize, col: usize) -> Matrix2 {
+        self.without(row, col)
+    }
+
+    pub fn minor(&self, row: usize, col: usize) -> f64 {
+        self.submatrix(row, col).determinant()
+    }
+
+    pub fn cofactor(&self, row: usize, col: usize) -> f64 {
+        let minor = self.minor(row, col);
+        if (row + col) & 1 == 1 { -minor } else { minor }
+    }
+}
+
+impl Matrix4 {
+    pub fn new(row0: [f64; 4], row1: [f64; 4], row2: [f64; 4], row3: [f64; 4]) -> Self {
+        Matrix::from_rows([row0, row1, row2, row3])
     }
 
     fn tuple(&self, row: usize) -> Tuple {
@@ -156,78 +222,140 @@ impl Matrix {
         Tuple::new(r[0], r[1], r[2], r[3])
     }
 
-    pub fn transpose(&self) -> Self {
-        let mut m = self.empty();
-        let size = self.size;
-        for row in 0..size {
-            for col in 0..size {
-                m.set(col, row, self[row][col]);
-            }
-        }
-        m
+    pub fn determinant(&self) -> f64 {
+        let (s, c) = self.laplace_minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
     }
 
-    fn determinant(&self) -> f64 {
-        let size = self.size;
-        match size {
-            2 => self[0][0] * self[1][1] - self[0][1] * self[1][0],
-            3..=4 => {
-                let r = &self[0].inner;
-                let mut col = 0;
-                r.iter().map(|c| { let v = c * self.cofactor(0, col); col += 1; v } ).sum()
-            }
-            _ => { panic!("Invalid matrix size, only 2x2, 3x3 and 4x4 supported") }
-        }
+    // The determinants of the 2x2 submatrices of the top two rows (s) and of the bottom two rows
+    // (c), from which the determinant and adjugate of a 4x4 matrix follow by Laplace expansion
+    // along the top two rows, without computing each cofactor separately
+    fn laplace_minors(&self) -> ([f64; 6], [f64; 6]) {
+        let m = |row: usize, col: usize| self.inner[row][col];
+        let pair = |r0: usize, r1: usize, c0: usize, c1: usize| m(r0, c0) * m(r1, c1) - m(r1, c0) * m(r0, c1);
+        ([pair(0, 1, 0, 1), pair(0, 1, 0, 2), pair(0, 1, 0, 3), pair(0, 1, 1, 2), pair(0, 1, 1, 3), pair(0, 1, 2, 3)],
+         [pair(2, 3, 0, 1), pair(2, 3, 0, 2), pair(2, 3, 0, 3), pair(2, 3, 1, 2), pair(2, 3, 1, 3), pair(2, 3, 2, 3)])
     }
 
-    fn submatrix(&self, row: usize, col: usize) -> Self {
-        let size = self.size;
-        let mut m = match size {
-            4 => Matrix::new_empty3(),
-            3 => Matrix::new_empty2(),
-            _ => { panic!("Invalid matrix size, only 3x3 and 4x4 supported") }
-        };
-        let mut r_new = 0;
-        
-        for r in 0..size {
-            if r == row { continue; }
-            let mut c_new = 0;
-            for c in 0..size {
-                if c == col { continue; }
-                m.set(r_new, c_new, self[r][c]);
-                c_new += 1;
-            }
-            r_new += 1;
-        }
-        m
+    pub fn submatrix(&self, row: usize, col: usize) -> Matrix3 {
+        self.without(row, col)
     }
 
-    fn minor(&self, row: usize, col: usize) -> f64 {
+    pub fn minor(&self, row: usize, col: usize) -> f64 {
         self.submatrix(row, col).determinant()
     }
 
-    fn cofactor(&self, row: usize, col: usize) -> f64 {
+    pub fn cofactor(&self, row: usize, col: usize) -> f64 {
         let minor = self.minor(row, col);
         if (row + col) & 1 == 1 { -minor } else { minor }
     }
 
+    pub fn try_inverse(&self) -> Result<Matrix, Error> {
+        self.inverse().ok_or_else(|| Error::NotInvertible(Box::new(*self)))
+    }
+
     pub fn inverse(&self) -> Option<Matrix> {
-        let det = self.determinant();
-        if det == 0.0 { return Option::None; }
-        let size = self.size;
-        let mut inverse = self.empty();
-        for row in 0..size {
-            for col in 0..size {
-                inverse.set(col, row, self.cofactor(row, col) / det);
-            }
+        let (s, c) = self.laplace_minors();
+        let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if det == 0.0 { return None; }
+        let m = |row: usize, col: usize| self.inner[row][col] / det;
+        let mut inverse = Matrix::new(
+            [m(1, 1) * c[5] - m(1, 2) * c[4] + m(1, 3) * c[3],
+             -m(0, 1) * c[5] + m(0, 2) * c[4] - m(0, 3) * c[3],
+             m(3, 1) * s[5] - m(3, 2) * s[4] + m(3, 3) * s[3],
+             -m(2, 1) * s[5] + m(2, 2) * s[4] - m(2, 3) * s[3]],
+            [-m(1, 0) * c[5] + m(1, 2) * c[2] - m(1, 3) * c[1],
+             m(0, 0) * c[5] - m(0, 2) * c[2] + m(0, 3) * c[1],
+             -m(3, 0) * s[5] + m(3, 2) * s[2] - m(3, 3) * s[1],
+             m(2, 0) * s[5] - m(2, 2) * s[2] + m(2, 3) * s[1]],
+            [m(1, 0) * c[4] - m(1, 1) * c[2] + m(1, 3) * c[0],
+             -m(0, 0) * c[4] + m(0, 1) * c[2] - m(0, 3) * c[0],
+             m(3, 0) * s[4] - m(3, 1) * s[2] + m(3, 3) * s[0],
+             -m(2, 0) * s[4] + m(2, 1) * s[2] - m(2, 3) * s[0]],
+            [-m(1, 0) * c[3] + m(1, 1) * c[1] - m(1, 2) * c[0],
+             m(0, 0) * c[3] - m(0, 1) * c[1] + m(0, 2) * c[0],
+             -m(3, 0) * s[3] + m(3, 1) * s[1] - m(3, 2) * s[0],
+             m(2, 0) * s[3] - m(2, 1) * s[1] + m(2, 2) * s[0]]);
+        // The inverse of an affine matrix is affine, but rounding can leave the bottom row a bit
+        // off, and the inverse would no longer map points to points
+        if self.inner[3] == [0., 0., 0., 1.] {
+            inverse.inner[3] = [0., 0., 0., 1.];
         }
-        Option::Some(inverse)
+        Some(inverse)
+    }
+}
+
+#[derive(Debug)]
+struct CachedMatrices {
+    matrix: Matrix,
+    inverse: Matrix,
+    normal_matrix: Matrix
+}
+
+// A transformation with its inverse and the transposed inverse used for normals, computed once
+// when the transformation is set. The matrices are shared, so cloning a shape does not copy them.
+#[derive(Debug, Clone)]
+pub struct InvertibleMatrix {
+    cached: Arc<CachedMatrices>
+}
+
+impl InvertibleMatrix {
+    // Panics if matrix is not invertible
+    pub fn new(matrix: Matrix) -> Self {
+        InvertibleMatrix::try_new(matrix).unwrap_or_else(|_| {
+            error!("singular transformation {:?}", matrix);
+            panic!("transformation should be invertible");
+        })
+    }
+
+    pub fn try_new(matrix: Matrix) -> Result<Self, Error> {
+        let inverse = matrix.try_inverse()?;
+        let normal_matrix = inverse.transpose();
+        Ok(Self { cached: Arc::new(CachedMatrices { matrix, inverse, normal_matrix }) })
+    }
+
+    pub fn matrix(&self) -> Matrix {
+        self.cached.matrix
+    }
+
+    pub fn inverse(&self) -> Matrix {
+        self.cached.inverse
+    }
+
+    pub fn normal_matrix(&self) -> Matrix {
+        self.cached.normal_matrix
+    }
+}
+
+impl From<Option<Matrix>> for InvertibleMatrix {
+    fn from(matrix: Option<Matrix>) -> Self {
+        match matrix {
+            None => Self::default(),
+            Some(m) => Self::new(m)
+        }
+    }
+}
+
+impl Default for InvertibleMatrix {
+    fn default() -> Self {
+        Self { cached: Arc::new(CachedMatrices {
+            matrix: IDENTITY_MATRIX,
+            inverse: IDENTITY_MATRIX,
+            normal_matrix: IDENTITY_MATRIX }) }
+    }
+}
+
+impl PartialEq for InvertibleMatrix {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cached, &other.cached) || self.cached.matrix == other.cached.matrix
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::PI;
 
     #[test]
     fn construct_4x4_matrix()
@@ -249,7 +377,7 @@ mod tests {
     #[test]
     fn construct_3x3_matrix()
     {
-        let m: Matrix = Matrix::new3(
+        let m: Matrix3 = Matrix::new3(
             [-3., 5., 0.],
             [1., -2., -7.],
             [0., 1., 1.]);
@@ -261,7 +389,7 @@ mod tests {
     #[test]
     fn construct_2x2_matrix()
     {
-        let m: Matrix = Matrix::new2(
+        let m: Matrix2 = Matrix::new2(
             [-3., 5.],
             [1., -2.]);
         assert_eq!(-3., m[0][0]);
@@ -363,6 +491,30 @@ mod tests {
         assert_eq!(a, a * IDENTITY_MATRIX);
     }
 
+    #[test]
+    #[allow(clippy::op_ref)]
+    fn multiplying_references()
+    {
+        let a = Matrix::translation(1., 2., 3.);
+        let b = Matrix::scaling(2., 2., 2.);
+        let p = Tuple::point(1., 1., 1.);
+        let expected = a * b;
+
+        assert_eq!(expected, &a * &b);
+        assert_eq!(expected, &a * b);
+        assert_eq!(expected, a * &b);
+        assert_eq!(expected * p, &expected * &p);
+        assert_eq!(expected * p, &expected * p);
+        assert_eq!(expected * p, expected * &p);
+
+        let mut m = a;
+        m *= &b;
+        assert_eq!(expected, m);
+        let mut m = a;
+        m *= b;
+        assert_eq!(expected, m);
+    }
+
     #[test]
     fn transpose_matrix()
     {
@@ -417,16 +569,6 @@ mod tests {
         assert_eq!(expected, a.submatrix(2, 1));
     }
 
-    #[test]
-    #[should_panic]
-    fn submatrix_of_2x2_matrix_shold_panic()
-    {
-        let a = Matrix::new2(
-            [-6., 2.], 
-            [-7., 1.]);
-        a.submatrix(1, 1);
-    }
-
     #[test]
     fn minor_of_3x3_matrix()
     {
@@ -481,6 +623,36 @@ mod tests {
         assert_eq!(-4071., a.determinant());
     }
 
+    #[test]
+    fn closed_form_inverse_matches_cofactors() {
+        let matrices = [
+            Matrix::rotation_y(rad(0.3)) * Matrix::translation(1., 2., 3.) * Matrix::scaling(1., 2., 0.5) * Matrix::rotation_x(rad(0.2)),
+            Matrix::shearing(1., 0., 0.5, 0., 0., 2.) * Matrix::translation(-4., 0., 7.),
+            Matrix::new([-2., -8., 3., 5.], [-3., 1., 7., 3.], [1., 2., -9., 6.], [-6., 7., 7., -9.])];
+        for m in matrices {
+            let det: f64 = (0..4).map(|col| m[0][col] * m.cofactor(0, col)).sum();
+            let mut expected = Matrix::new_empty();
+            for row in 0..4 {
+                for col in 0..4 {
+                    expected.set(col, row, m.cofactor(row, col) / det);
+                }
+            }
+
+            assert!((m.determinant() - det).abs() < 1e-9 * det.abs());
+            assert_eq!(m.inverse().unwrap(), expected);
+            assert_eq!(m * m.inverse().unwrap(), IDENTITY_MATRIX);
+        }
+    }
+
+    #[test]
+    fn inverse_of_affine_matrix_maps_points_to_points() {
+        let view = Matrix::view_transform(Tuple::point(0., 1., -5.), Tuple::point(0., 0.5, 0.), Tuple::vector(0., 1., 0.));
+        let inverse = view.inverse().unwrap();
+
+        assert_eq!(inverse[3], [0., 0., 0., 1.]);
+        assert!((inverse * Tuple::point(0., 0., 0.)).is_point());
+    }
+
     #[test]
     fn matrix_is_invertible()
     {
@@ -576,4 +748,72 @@ mod tests {
         let c = a * b;
         assert_eq!(a, c * b.inverse().unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn interpolate_between_matrices() {
+        let a = Matrix::new(
+            [1., 2., 3., 4.],
+            [5., 6., 7., 8.],
+            [9., 8., 7., 6.],
+            [5., 4., 3., 2.]);
+        let b = Matrix::new(
+            [3., 2., 1., 0.],
+            [5., 6., 7., 8.],
+            [1., 0., -1., -2.],
+            [5., 4., 3., 2.]);
+        let expected = Matrix::new(
+            [2., 2., 2., 2.],
+            [5., 6., 7., 8.],
+            [5., 4., 3., 2.],
+            [5., 4., 3., 2.]);
+
+        assert_eq!(a.lerp(&b, 0.), a);
+        assert_eq!(a.lerp(&b, 1.), b);
+        assert_eq!(a.lerp(&b, 0.5), expected);
+    }
+
+    #[test]
+    fn invertible_matrix_caches_inverse_and_normal_matrix() {
+        let m = Matrix::scaling(1., 0.5, 1.) * Matrix::rotation_z(rad(PI / 5.));
+        let im = InvertibleMatrix::new(m);
+
+        assert_eq!(im.matrix(), m);
+        assert_eq!(im.inverse(), m.inverse().unwrap());
+        assert_eq!(im.normal_matrix(), m.inverse().unwrap().transpose());
+    }
+
+    #[test]
+    fn clones_of_invertible_matrix_share_cache() {
+        let im = InvertibleMatrix::new(Matrix::translation(1., 2., 3.));
+        let clone = im.clone();
+
+        assert!(Arc::ptr_eq(&im.cached, &clone.cached));
+        assert_eq!(im, clone);
+        assert_eq!(im, InvertibleMatrix::new(Matrix::translation(1., 2., 3.)));
+        assert_eq!(InvertibleMatrix::from(None).matrix(), IDENTITY_MATRIX);
+    }
+
+    #[should_panic]
+    #[test]
+    fn invertible_matrix_rejects_singular_matrix() {
+        InvertibleMatrix::new(Matrix::scaling(0., 1., 1.));
+    }
+
+    #[test]
+    fn singular_matrix_without_panicking() {
+        let singular = Matrix::scaling(0., 1., 1.);
+
+        assert!(matches!(InvertibleMatrix::try_new(singular), Err(Error::NotInvertible(m)) if *m == singular));
+        assert!(matches!(singular.try_inverse(), Err(Error::NotInvertible(_))));
+        assert_eq!(InvertibleMatrix::try_new(Matrix::scaling(2., 1., 1.)).unwrap().inverse(), Matrix::scaling(0.5, 1., 1.));
+    }
+
+    #[test]
+    fn getting_elements_without_panicking() {
+        let m = Matrix::new3([1., 2., 3.], [4., 5., 6.], [7., 8., 9.]);
+
+        assert_eq!(m.get(1, 2).unwrap(), 6.);
+        assert!(matches!(m.get(3, 0), Err(Error::IndexOutOfBounds { row: 3, col: 0, size: 3 })));
+        assert!(matches!(m.get(0, 3), Err(Error::IndexOutOfBounds { .. })));
+    }
+}
@@ -0,0 +1,125 @@
+use super::tuple::Tuple;
+
+// Ken Perlin's improved noise (2002): smooth, repeatable pseudo random values in about [-1, 1]
+// that vary continuously through space, for procedural textures like marble and wood.
+
+// Perlin's reference permutation of 0..=255, used as a hash of the lattice coordinates
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225,
+    140, 36, 103, 30, 69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148,
+    247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219, 203, 117, 35, 11, 32,
+    57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122,
+    60, 211, 133, 230, 220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54,
+    65, 25, 63, 161, 1, 216, 80, 73, 209, 76, 132, 187, 208, 89, 18, 169,
+    200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173, 186, 3, 64,
+    52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212,
+    207, 206, 59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213,
+    119, 248, 152, 2, 44, 154, 163, 70, 221, 153, 101, 155, 167, 43, 172, 9,
+    129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232, 178, 185, 112, 104,
+    218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162, 241,
+    81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157,
+    184, 84, 204, 176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93,
+    222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn hash(i: usize) -> usize {
+    PERMUTATION[i & 255] as usize
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// Dot product of (x, y, z) with one of 12 gradient directions picked by the hash
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+// Zero at every integer lattice point, repeats every 256 units
+pub fn perlin_noise(point: Tuple) -> f64 {
+    let (fx, fy, fz) = (point.x.floor(), point.y.floor(), point.z.floor());
+    let (xi, yi, zi) = ((fx as i64 & 255) as usize, (fy as i64 & 255) as usize, (fz as i64 & 255) as usize);
+    let (x, y, z) = (point.x - fx, point.y - fy, point.z - fz);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let a = hash(xi) + yi;
+    let aa = hash(a) + zi;
+    let ab = hash(a + 1) + zi;
+    let b = hash(xi + 1) + yi;
+    let ba = hash(b) + zi;
+    let bb = hash(b + 1) + zi;
+
+    lerp(w,
+        lerp(v,
+            lerp(u, grad(hash(aa), x, y, z), grad(hash(ba), x - 1., y, z)),
+            lerp(u, grad(hash(ab), x, y - 1., z), grad(hash(bb), x - 1., y - 1., z))),
+        lerp(v,
+            lerp(u, grad(hash(aa + 1), x, y, z - 1.), grad(hash(ba + 1), x - 1., y, z - 1.)),
+            lerp(u, grad(hash(ab + 1), x, y - 1., z - 1.), grad(hash(bb + 1), x - 1., y - 1., z - 1.))))
+}
+
+// Sum of octaves of noise, each at twice the frequency and half the amplitude of the one
+// before, which gives detail at several scales. Scaled to stay within about [-1, 1].
+pub fn fractal_noise(point: Tuple, octaves: usize) -> f64 {
+    let mut total = 0.;
+    let mut amplitude = 1.;
+    let mut frequency = 1.;
+    let mut max = 0.;
+    for _ in 0..octaves.max(1) {
+        total += perlin_noise(point * frequency) * amplitude;
+        max += amplitude;
+        amplitude /= 2.;
+        frequency *= 2.;
+    }
+    total / max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq;
+
+    #[test]
+    fn noise_is_zero_at_lattice_points() {
+        assert_eq!(perlin_noise(Tuple::point(0., 0., 0.)), 0.);
+        assert_eq!(perlin_noise(Tuple::point(3., -7., 12.)), 0.);
+    }
+
+    #[test]
+    fn noise_matches_reference_implementation() {
+        assert!(approx_eq(perlin_noise(Tuple::point(3.25, 42.7, -7.1)), -0.0824649858140607));
+        assert!(approx_eq(perlin_noise(Tuple::point(0.5, 0.5, 0.5)), -0.25));
+    }
+
+    #[test]
+    fn noise_is_continuous_and_bounded() {
+        let mut previous = perlin_noise(Tuple::point(0., 0.3, 0.7));
+        for i in 1..2000 {
+            let n = perlin_noise(Tuple::point(i as f64 * 0.005, 0.3, 0.7));
+            assert!(n.abs() <= 1.);
+            assert!((n - previous).abs() < 0.05);
+            previous = n;
+        }
+    }
+
+    #[test]
+    fn noise_repeats_every_256_units() {
+        let p = Tuple::point(1.3, -2.7, 5.1);
+        assert!(approx_eq(perlin_noise(p), perlin_noise(p + Tuple::vector(256., 0., -256.))));
+    }
+
+    #[test]
+    fn fractal_noise_with_one_octave_is_plain_noise() {
+        let p = Tuple::point(1.3, -2.7, 5.1);
+        assert_eq!(fractal_noise(p, 1), perlin_noise(p));
+        assert!(fractal_noise(p, 4).abs() <= 1.);
+    }
+}
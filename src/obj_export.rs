@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+use log::warn;
+
+use super::tessellation::{largest_scale, TessellationQuality};
+use super::world::World;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjExportOptions {
+    pub quality: TessellationQuality,
+    // Overrides quality for single objects, by index into World::objects
+    pub object_quality: HashMap<usize, TessellationQuality>
+}
+
+impl ObjExportOptions {
+    pub fn quality_for(&self, object_index: usize) -> TessellationQuality {
+        self.object_quality.get(&object_index).copied().unwrap_or(self.quality)
+    }
+}
+
+// Wavefront OBJ with one object per shape, named by type and index like in SceneSummary,
+// positioned as at the start of the exposure. Shapes that can't be tessellated (planes) are
+// left out with a warning. Returns the number of objects written.
+pub fn write_obj<W: Write>(world: &World, options: &ObjExportOptions, out: &mut W) -> Result<usize> {
+    let mut written = 0;
+    let mut vertex_count = 0;
+    for (i, o) in world.objects().iter().enumerate() {
+        let transform = o.transformation_at(0.);
+        let quality = options.quality_for(i).in_object_space(largest_scale(&transform));
+        let mesh = match o.tessellate(&quality).map(|m| m.transform(&transform)) {
+            Some(Ok(m)) => m,
+            Some(Err(e)) => {
+                warn!("{} {} can't be exported: {}", o.type_name(), i, e);
+                continue;
+            }
+            None => {
+                warn!("{} {} can't be exported as a mesh", o.type_name(), i);
+                continue;
+            }
+        };
+        writeln!(out, "o {}_{}", o.type_name(), i)?;
+        for v in &mesh.vertices {
+            writeln!(out, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        for n in &mesh.normals {
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        // Indices start at 1 and count all vertices in the file so far. Each vertex has the
+        // normal with the same index.
+        for face in &mesh.faces {
+            let [a, b, c] = face.map(|v| v + vertex_count + 1);
+            writeln!(out, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+        vertex_count += mesh.vertices.len();
+        written += 1;
+    }
+    Ok(written)
+}
+
+pub fn save_obj(world: &World, options: &ObjExportOptions, file_name: &str) -> Result<usize> {
+    let mut out = BufWriter::new(File::create(file_name)?);
+    let written = write_obj(world, options, &mut out)?;
+    out.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::plane::Plane;
+    use crate::sphere::Sphere;
+    use crate::tuple::Tuple;
+
+    fn export(world: &World, options: &ObjExportOptions) -> (usize, String) {
+        let mut out = vec![];
+        let written = write_obj(world, options, &mut out).unwrap();
+        (written, String::from_utf8(out).unwrap())
+    }
+
+    fn vertices(obj: &str) -> Vec<Tuple> {
+        obj.lines().filter(|l| l.starts_with("v ")).map(|l| {
+            let c: Vec<f64> = l[2..].split(' ').map(|n| n.parse().unwrap()).collect();
+            Tuple::point(c[0], c[1], c[2])
+        }).collect()
+    }
+
+    #[test]
+    fn planes_are_left_out() {
+        let mut w = World::default_world();
+        w.add_object(Plane::new_boxed(None, None));
+        let (written, obj) = export(&w, &ObjExportOptions::default());
+
+        assert_eq!(written, 2);
+        assert!(obj.contains("o Sphere_0\n"));
+        assert!(obj.contains("o Sphere_1\n"));
+        assert!(!obj.contains("Plane"));
+    }
+
+    #[test]
+    fn vertex_indices_continue_between_objects() {
+        let (_, obj) = export(&World::default_world(), &ObjExportOptions::default());
+        let first_object_vertices = obj.split("o Sphere_1").next().unwrap().lines().filter(|l| l.starts_with("v ")).count();
+        let second_object = obj.split("o Sphere_1").nth(1).unwrap();
+        let first_face = second_object.lines().find(|l| l.starts_with("f ")).unwrap();
+        let index: usize = first_face[2..].split("//").next().unwrap().parse().unwrap();
+
+        assert!(index > first_object_vertices);
+    }
+
+    #[test]
+    fn larger_objects_get_more_triangles() {
+        let small = World::new(None, vec![Sphere::new_boxed(None, None)]);
+        let large = World::new(None, vec![Sphere::new_boxed(None, Some(Matrix::scaling(100., 100., 100.)))]);
+        let options = ObjExportOptions::default();
+
+        assert!(vertices(&export(&large, &options).1).len() > vertices(&export(&small, &options).1).len());
+    }
+
+    #[test]
+    fn exported_sphere_is_within_tolerance() {
+        let center = Tuple::point(1., 2., 3.);
+        let w = World::new(None, vec![Sphere::new_boxed(None, Some(Matrix::translation(1., 2., 3.) * Matrix::scaling(5., 5., 5.)))]);
+        let options = ObjExportOptions { quality: TessellationQuality { segments_per_revolution: 8, max_chord_error: 0.01 }, ..Default::default() };
+        let (_, obj) = export(&w, &options);
+        let points = vertices(&obj);
+        let faces: Vec<Vec<usize>> = obj.lines().filter(|l| l.starts_with("f ")).map(|l|
+            l[2..].split(' ').map(|v| v.split("//").next().unwrap().parse::<usize>().unwrap() - 1).collect()).collect();
+
+        for f in faces {
+            let (a, b, c) = (points[f[0]] - center, points[f[1]] - center, points[f[2]] - center);
+            assert!(5. - ((a + b + c) / 3.).magnitude() <= 0.01);
+        }
+    }
+
+    #[test]
+    fn quality_per_object() {
+        let w = World::new(None, vec![Sphere::new_boxed(None, None), Sphere::new_boxed(None, None)]);
+        let mut options = ObjExportOptions::default();
+        options.object_quality.insert(1, TessellationQuality { segments_per_revolution: 200, max_chord_error: 0.1 });
+        let (_, obj) = export(&w, &options);
+        let counts: Vec<usize> = obj.split("o ").skip(1).map(|o| o.lines().filter(|l| l.starts_with("v ")).count()).collect();
+
+        assert_eq!(options.quality_for(0), TessellationQuality::default());
+        assert!(counts[1] > counts[0]);
+    }
+}
@@ -1,12 +1,14 @@
 use std::fmt;
 use std::any::Any;
 
-use super::color::Color;
+use super::color::{Color, BLACK};
 use super::tuple::Tuple;
-use super::matrix::Matrix;
+use super::matrix::{Matrix, IDENTITY_MATRIX};
 use super::shape::{Shape, inverse_transform_parameter};
+use super::intersection::PrimitiveHit;
+use super::noise::fractal_noise;
 
-pub trait Pattern: Any + fmt::Debug {
+pub trait Pattern: Any + fmt::Debug + Send + Sync {
     fn box_clone(&self) -> BoxPattern;
     fn box_eq(&self, other: &dyn Any) -> bool;
     fn as_any(&self) -> &dyn Any;
@@ -18,6 +20,11 @@ pub trait Pattern: Any + fmt::Debug {
         let pattern_point = self.inverse_transformation() * object_point;
         self.inner_pattern_at(pattern_point)
     }
+    // For patterns that depend on which triangle of a shape was hit, if it is made of them.
+    // Others go by the point alone.
+    fn pattern_at_hit(&self, object: &dyn Shape, world_point: Tuple, _primitive: Option<&PrimitiveHit>) -> Color {
+        self.pattern_at_shape(object, world_point)
+    }
 }
 
 pub type BoxPattern = Box<dyn Pattern>;
@@ -34,6 +41,27 @@ impl PartialEq for BoxPattern {
     }
 }
 
+/// Stripes one unit wide across the x axis, on a floor in a thumbnail:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::color::{Color, BLUE, WHITE};
+/// use raytracer::material::Material;
+/// use raytracer::matrix::Matrix;
+/// use raytracer::pattern::StripePattern;
+/// use raytracer::plane::Plane;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let pattern = StripePattern::new_boxed(WHITE, BLUE, None);
+/// let material = Material { pattern: Some(pattern), ..Material::default() };
+/// let floor = Plane::new_boxed(Some(material), Some(Matrix::translation(0., -1., 0.)));
+/// let image = render_thumbnail(thumbnail_world(vec![floor]));
+/// let is_white = |c: Color| c.r > 0.5 && c.b > 0.5;
+/// let is_blue = |c: Color| c.r < 0.1 && c.b > 0.5;
+/// assert!(is_white(image.pixel_at(0, 30)));
+/// assert!(is_blue(image.pixel_at(8, 30)));
+/// # }
+/// ```
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct StripePattern {
     a: Color,
@@ -59,7 +87,7 @@ impl StripePattern {
 
 impl Pattern for StripePattern {
     fn box_clone(&self) -> BoxPattern {
-        Box::new((*self).clone())
+        Box::new(*self)
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -67,7 +95,7 @@ impl Pattern for StripePattern {
     }
 
     fn box_eq(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| self == a)
+        other.downcast_ref::<Self>() == Some(self)
     }
 
     fn transformation(&self) -> Matrix {
@@ -93,6 +121,25 @@ impl Pattern for StripePattern {
     }
 }
 
+/// A gradient stretched over a unit sphere, red at the left edge and blue at the right:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::color::{BLUE, RED};
+/// use raytracer::material::Material;
+/// use raytracer::matrix::Matrix;
+/// use raytracer::pattern::GradientPattern;
+/// use raytracer::sphere::Sphere;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let stretched = Matrix::translation(-1., 0., 0.) * Matrix::scaling(2., 1., 1.);
+/// let material = Material { pattern: Some(GradientPattern::new_boxed(RED, BLUE, Some(stretched))), ..Material::default() };
+/// let image = render_thumbnail(thumbnail_world(vec![Sphere::new_boxed(Some(material), None)]));
+/// let (left, right) = (image.pixel_at(13, 16), image.pixel_at(19, 16));
+/// assert!(left.r > left.b);
+/// assert!(right.b > right.r);
+/// # }
+/// ```
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct GradientPattern {
     a: Color,
@@ -118,7 +165,7 @@ impl GradientPattern {
 
 impl Pattern for GradientPattern {
     fn box_clone(&self) -> BoxPattern {
-        Box::new((*self).clone())
+        Box::new(*self)
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -126,7 +173,7 @@ impl Pattern for GradientPattern {
     }
 
     fn box_eq(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| self == a)
+        other.downcast_ref::<Self>() == Some(self)
     }
 
     fn transformation(&self) -> Matrix {
@@ -138,64 +185,486 @@ impl Pattern for GradientPattern {
     }
 
     fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
-        let distance = self.b - self.a;
-        let fraction = pattern_point.x.fract();
-        self.a + distance * fraction
+        self.a.lerp(&self.b, pattern_point.x.fract())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::color::{BLACK, WHITE};
-    use crate::sphere::Sphere;
-    use crate::matrix::IDENTITY_MATRIX;
-    use crate::EPSILON;
+// Concentric rings around the y axis, alternating at every unit of distance from it
+/// Rings one unit wide around the y axis, on a floor in a thumbnail:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::color::{Color, BLUE, WHITE};
+/// use raytracer::material::Material;
+/// use raytracer::matrix::Matrix;
+/// use raytracer::pattern::RingPattern;
+/// use raytracer::plane::Plane;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let pattern = RingPattern::new_boxed(WHITE, BLUE, None);
+/// let material = Material { pattern: Some(pattern), ..Material::default() };
+/// let floor = Plane::new_boxed(Some(material), Some(Matrix::translation(0., -1., 0.)));
+/// let image = render_thumbnail(thumbnail_world(vec![floor]));
+/// let is_white = |c: Color| c.r > 0.5 && c.b > 0.5;
+/// let is_blue = |c: Color| c.r < 0.1 && c.b > 0.5;
+/// assert!(is_white(image.pixel_at(16, 30)));
+/// assert!(is_blue(image.pixel_at(0, 30)));
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RingPattern {
+    a: Color,
+    b: Color,
+    transform: Matrix,
+    inverse_transform: Matrix
+}
 
-    #[derive(Debug, Clone, PartialEq)]
-    struct TestPattern {
-        transform: Matrix,
-        inverse_transform: Matrix
+impl RingPattern {
+    pub fn new(a: Color, b: Color, transform: Option<Matrix>) -> Self {
+        Self { 
+            a, 
+            b, 
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
+        }
     }
 
-    impl TestPattern {
-        fn new(transform: Option<Matrix>) -> Self {
-            TestPattern {
-                transform: transform.unwrap_or_default(),
-                inverse_transform: inverse_transform_parameter(transform)
-            }
+    pub fn new_boxed(a: Color, b: Color, transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(a, b, transform))
+    }
+}
+
+impl Pattern for RingPattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(*self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        let distance = (pattern_point.x * pattern_point.x + pattern_point.z * pattern_point.z).sqrt();
+        if distance.floor() as i64 % 2 == 0 { self.a } else { self.b }
+    }
+}
+
+/// Unit cubes of alternating colors, seen as squares on a floor in a thumbnail:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::color::{Color, BLUE, WHITE};
+/// use raytracer::material::Material;
+/// use raytracer::matrix::Matrix;
+/// use raytracer::pattern::CheckerPattern;
+/// use raytracer::plane::Plane;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let pattern = CheckerPattern::new_boxed(WHITE, BLUE, None);
+/// let material = Material { pattern: Some(pattern), ..Material::default() };
+/// let floor = Plane::new_boxed(Some(material), Some(Matrix::translation(0., -1., 0.)));
+/// let image = render_thumbnail(thumbnail_world(vec![floor]));
+/// let is_white = |c: Color| c.r > 0.5 && c.b > 0.5;
+/// let is_blue = |c: Color| c.r < 0.1 && c.b > 0.5;
+/// assert!(is_white(image.pixel_at(8, 30)));
+/// assert!(is_blue(image.pixel_at(0, 30)));
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CheckerPattern {
+    a: Color,
+    b: Color,
+    transform: Matrix,
+    inverse_transform: Matrix
+}
+
+impl CheckerPattern {
+    pub fn new(a: Color, b: Color, transform: Option<Matrix>) -> Self {
+        Self { 
+            a, 
+            b, 
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
         }
+    }
+
+    pub fn new_boxed(a: Color, b: Color, transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(a, b, transform))
+    }
+}
+
+impl Pattern for CheckerPattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(*self)
+    }
 
-        fn new_boxed(transform: Option<Matrix>) -> BoxPattern {
-            Box::new(TestPattern::new(transform))
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        let sum = pattern_point.x.floor() + pattern_point.y.floor() + pattern_point.z.floor();
+        if sum.rem_euclid(2.) == 0. { self.a } else { self.b }
+    }
+}
+
+// Moves the point looked up in another pattern by noise, up to scale units in each direction,
+// which makes e g wavy stripes or marble out of straight stripes. The noise is sampled in the
+// space of this pattern's transform, so scaling it changes the size of the waves.
+#[derive(Debug, Clone)]
+pub struct PerturbedPattern {
+    inner: BoxPattern,
+    scale: f64,
+    octaves: usize,
+    transform: Matrix,
+    inverse_transform: Matrix
+}
+
+impl PartialEq for PerturbedPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.eq(&other.inner) &&
+        self.scale == other.scale &&
+        self.octaves == other.octaves &&
+        self.transform == other.transform
+    }
+}
+
+// Offsets so that the noise moving x, y and z is taken from unrelated parts of the noise field
+const PERTURB_OFFSET_Y: f64 = 31.416;
+const PERTURB_OFFSET_Z: f64 = 67.123;
+
+impl PerturbedPattern {
+    pub fn new(inner: BoxPattern, scale: f64, octaves: usize, transform: Option<Matrix>) -> Self {
+        Self { 
+            inner, 
+            scale, 
+            octaves,
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
         }
     }
 
-    impl Pattern for TestPattern {
-        fn box_clone(&self) -> BoxPattern {
-            Box::new((*self).clone())
+    pub fn new_boxed(inner: BoxPattern, scale: f64, octaves: usize, transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(inner, scale, octaves, transform))
+    }
+}
+
+impl Pattern for PerturbedPattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        let p = pattern_point;
+        let offset = Tuple::vector(
+            fractal_noise(p, self.octaves),
+            fractal_noise(p + Tuple::vector(PERTURB_OFFSET_Y, PERTURB_OFFSET_Y, PERTURB_OFFSET_Y), self.octaves),
+            fractal_noise(p + Tuple::vector(PERTURB_OFFSET_Z, PERTURB_OFFSET_Z, PERTURB_OFFSET_Z), self.octaves));
+        // Back to the space of the shape, then into the space of the inner pattern
+        let object_point = self.transform * (p + offset * self.scale);
+        self.inner.inner_pattern_at(self.inner.inverse_transformation() * object_point)
+    }
+}
+
+// Lines of width line_width along the x and z axes at every integer coordinate, as used for a
+// reference grid on the ground
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GridPattern {
+    line: Color,
+    background: Color,
+    line_width: f64,
+    transform: Matrix,
+    inverse_transform: Matrix
+}
+
+impl GridPattern {
+    pub fn new(line: Color, background: Color, line_width: f64, transform: Option<Matrix>) -> Self {
+        Self { 
+            line, 
+            background, 
+            line_width,
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
+        }
+    }
+
+    pub fn new_boxed(line: Color, background: Color, line_width: f64, transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(line, background, line_width, transform))
+    }
+}
+
+impl Pattern for GridPattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(*self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        let half_width = self.line_width / 2.;
+        let on_line = |v: f64| (v - v.round()).abs() <= half_width;
+        if on_line(pattern_point.x) || on_line(pattern_point.z) {
+            self.line
+        } else {
+            self.background
         }
+    }
+}
+
+// Whether (x, z) is in the joints between width by depth blocks laid in rows along x, each row
+// shifted by row_offset times width from the one before it. The joints are joint_width wide and
+// centered on the edges of the blocks.
+fn in_joint(x: f64, z: f64, width: f64, depth: f64, joint_width: f64, row_offset: f64) -> bool {
+    let half_joint = joint_width / 2.;
+    let near_edge = |v: f64, size: f64| {
+        let v = v.rem_euclid(size);
+        v < half_joint || v > size - half_joint
+    };
+    let row = (z / depth).floor();
+    near_edge(z, depth) || near_edge(x - row * row_offset * width, width)
+}
+
+// Bricks width long along x and depth deep along z, with rows shifted by row_offset of a brick
+// (0.5 for the usual running bond) and mortar_width of mortar between them. Laid on the ground
+// like GridPattern, rotate_x(pi / 2) stands them up for a wall.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BrickPattern {
+    brick: Color,
+    mortar: Color,
+    width: f64,
+    depth: f64,
+    mortar_width: f64,
+    row_offset: f64,
+    transform: Matrix,
+    inverse_transform: Matrix
+}
 
-        fn as_any(&self) -> &dyn Any {
-            self
+impl BrickPattern {
+    pub fn new(brick: Color, mortar: Color, width: f64, depth: f64, mortar_width: f64, row_offset: f64, transform: Option<Matrix>) -> Self {
+        Self {
+            brick,
+            mortar,
+            width,
+            depth,
+            mortar_width,
+            row_offset,
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
         }
+    }
 
-        fn box_eq(&self, other: &dyn Any) -> bool {
-            other.downcast_ref::<Self>().map_or(false, |a| self == a)
+    pub fn new_boxed(brick: Color, mortar: Color, width: f64, depth: f64, mortar_width: f64, row_offset: f64, transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(brick, mortar, width, depth, mortar_width, row_offset, transform))
+    }
+}
+
+impl Pattern for BrickPattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(*self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        if in_joint(pattern_point.x, pattern_point.z, self.width, self.depth, self.mortar_width, self.row_offset) {
+            self.mortar
+        } else {
+            self.brick
         }
+    }
+}
+
+// Square tiles size wide on the ground, with grout_width of grout between them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TilePattern {
+    tile: Color,
+    grout: Color,
+    size: f64,
+    grout_width: f64,
+    transform: Matrix,
+    inverse_transform: Matrix
+}
 
-        fn transformation(&self) -> Matrix {
-            self.transform
+impl TilePattern {
+    pub fn new(tile: Color, grout: Color, size: f64, grout_width: f64, transform: Option<Matrix>) -> Self {
+        Self {
+            tile,
+            grout,
+            size,
+            grout_width,
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
         }
+    }
+
+    pub fn new_boxed(tile: Color, grout: Color, size: f64, grout_width: f64, transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(tile, grout, size, grout_width, transform))
+    }
+}
+
+impl Pattern for TilePattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(*self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 
-        fn inverse_transformation(&self) -> Matrix {
-            self.inverse_transform
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        if in_joint(pattern_point.x, pattern_point.z, self.size, self.size, self.grout_width, 0.) {
+            self.grout
+        } else {
+            self.tile
         }
+    }
+}
+
+// A color per triangle of the shape, in turn, for telling its triangles apart. Points not known
+// to be on a triangle get the first color, and no colors at all gives black.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FacePattern {
+    colors: Vec<Color>
+}
+
+impl FacePattern {
+    pub fn new(colors: Vec<Color>) -> Self {
+        Self { colors }
+    }
 
-        fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
-            Color::new(pattern_point.x, pattern_point.y, pattern_point.z)
+    pub fn new_boxed(colors: Vec<Color>) -> BoxPattern {
+        Box::new(Self::new(colors))
+    }
+
+    fn color(&self, face_index: usize) -> Color {
+        if self.colors.is_empty() {
+            BLACK
+        } else {
+            self.colors[face_index % self.colors.len()]
         }
     }
+}
+
+impl Pattern for FacePattern {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        IDENTITY_MATRIX
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        IDENTITY_MATRIX
+    }
+
+    fn inner_pattern_at(&self, _pattern_point: Tuple) -> Color {
+        self.color(0)
+    }
+
+    fn pattern_at_hit(&self, _object: &dyn Shape, _world_point: Tuple, primitive: Option<&PrimitiveHit>) -> Color {
+        self.color(primitive.map_or(0, |p| p.face_index()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{RED, WHITE};
+    use crate::sphere::Sphere;
+    use crate::EPSILON;
+    use crate::debug::DebugPattern;
+    use crate::polygon::Polygon;
+    use crate::ray::Ray;
+    use crate::tuple::ORIGO;
 
     #[test]
     fn create_stripe_pattern() {
@@ -258,20 +727,20 @@ mod tests {
 
     #[test]
     fn default_pattern_transformation() {
-        let tp = TestPattern::new_boxed(None);
+        let tp = DebugPattern::new_boxed(None);
         assert_eq!(tp.transformation(), IDENTITY_MATRIX);
     }
 
     #[test]
     fn assign_transformation_to_pattern() {
-        let tp = TestPattern::new_boxed(Some(Matrix::translation(1., 2., 3.)));
+        let tp = DebugPattern::new_boxed(Some(Matrix::translation(1., 2., 3.)));
         assert_eq!(tp.transformation(), Matrix::translation(1., 2., 3.));
     }
 
     #[test]
     fn pattern_with_object_transformation() {
         let shape = Sphere::new_boxed(None, Some(Matrix::scaling(2., 2., 2.)));
-        let tp = TestPattern::new_boxed(None);
+        let tp = DebugPattern::new_boxed(None);
         let c = tp.pattern_at_shape(&*shape, Tuple::point(2., 3., 4.));
         assert_eq!(c, Color::new(1., 1.5, 2.));
     }
@@ -279,7 +748,7 @@ mod tests {
     #[test]
     fn pattern_with_pattern_transformation() {
         let shape = Sphere::new_boxed(None, None);
-        let tp = TestPattern::new_boxed(Some(Matrix::scaling(2., 2., 2.)));
+        let tp = DebugPattern::new_boxed(Some(Matrix::scaling(2., 2., 2.)));
         let c = tp.pattern_at_shape(&*shape, Tuple::point(2., 3., 4.));
         assert_eq!(c, Color::new(1., 1.5, 2.));
     }
@@ -287,7 +756,7 @@ mod tests {
     #[test]
     fn pattern_with_object_and_pattern_transformation() {
         let shape = Sphere::new_boxed(None, Some(Matrix::scaling(2., 2., 2.)));
-        let tp = TestPattern::new_boxed(Some(Matrix::translation(0.5, 1., 1.5)));
+        let tp = DebugPattern::new_boxed(Some(Matrix::translation(0.5, 1., 1.5)));
         let c = tp.pattern_at_shape(&*shape, Tuple::point(2.5, 3., 3.5));
         assert_eq!(c, Color::new(0.75, 0.5, 0.25));
     }
@@ -301,4 +770,133 @@ mod tests {
         assert_eq!(pattern.inner_pattern_at(Tuple::point(0.75, 0., 0.)), Color::new(0.25, 0.25, 0.25));
         assert_eq!(pattern.inner_pattern_at(Tuple::point(1. - EPSILON, 0., 0.)), BLACK);
     }
+
+    #[test]
+    fn grid_has_lines_at_integer_x_and_z() {
+        let pattern = GridPattern::new(WHITE, BLACK, 0.1, None);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 0.5)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0.5, 0., -2.02)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(-0.97, 5., 0.5)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0.5, 0., 0.5)), BLACK);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(1.1, 0., -0.8)), BLACK);
+    }
+
+    #[test]
+    fn bricks_in_running_bond() {
+        let pattern = BrickPattern::new(RED, WHITE, 2., 1., 0.1, 0.5, None);
+        let at = |x: f64, z: f64| pattern.inner_pattern_at(Tuple::point(x, 3., z));
+
+        assert_eq!(at(1., 0.5), RED);
+        assert_eq!(at(1.97, 0.5), WHITE);
+        assert_eq!(at(1., 0.98), WHITE);
+        assert_eq!(at(1., -0.03), WHITE);
+        // The next row is shifted by half a brick, so its joints are halfway along the bricks
+        assert_eq!(at(1., 1.5), WHITE);
+        assert_eq!(at(1.97, 1.5), RED);
+        assert_eq!(at(-1., -0.5), WHITE);
+    }
+
+    #[test]
+    fn tiles_have_grout_on_every_edge() {
+        let pattern = TilePattern::new(WHITE, BLACK, 0.5, 0.05, None);
+        let at = |x: f64, z: f64| pattern.inner_pattern_at(Tuple::point(x, 0., z));
+
+        assert_eq!(at(0.25, 0.25), WHITE);
+        assert_eq!(at(-0.25, 0.75), WHITE);
+        assert_eq!(at(0.49, 0.25), BLACK);
+        assert_eq!(at(0.25, -0.51), BLACK);
+        assert_eq!(at(1.01, 1.24), BLACK);
+    }
+
+    #[test]
+    fn checkers_repeat_in_x() {
+        let pattern = CheckerPattern::new(WHITE, BLACK, None);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 0.)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0.99, 0., 0.)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(1.01, 0., 0.)), BLACK);
+    }
+
+    #[test]
+    fn checkers_repeat_in_y() {
+        let pattern = CheckerPattern::new(WHITE, BLACK, None);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 0.)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0.99, 0.)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 1.01, 0.)), BLACK);
+    }
+
+    #[test]
+    fn checkers_repeat_in_z() {
+        let pattern = CheckerPattern::new(WHITE, BLACK, None);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 0.)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 0.99)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 1.01)), BLACK);
+    }
+
+    #[test]
+    fn checkers_alternate_across_negative_coordinates() {
+        let pattern = CheckerPattern::new(WHITE, BLACK, None);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(-0.5, 0., 0.)), BLACK);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(-0.5, -0.5, 0.)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(-1.5, 0.5, 2.5)), WHITE);
+    }
+
+    #[test]
+    fn perturbed_pattern_without_scale_is_inner_pattern() {
+        let stripes = StripePattern::new_boxed(WHITE, BLACK, Some(Matrix::scaling(0.5, 1., 1.)));
+        let pattern = PerturbedPattern::new(stripes.clone(), 0., 3, Some(Matrix::scaling(2., 2., 2.)));
+        let shape = Sphere::new(None, None);
+
+        for x in [0.1, 0.3, 0.6, 0.8, -0.4] {
+            let p = Tuple::point(x, 0.2, 0.);
+            assert_eq!(pattern.pattern_at_shape(&shape, p), stripes.pattern_at_shape(&shape, p));
+        }
+    }
+
+    #[test]
+    fn perturbed_pattern_moves_lookup_point() {
+        let pattern = PerturbedPattern::new(DebugPattern::new_boxed(None), 0.5, 1, None);
+        let p = Tuple::point(0.3, 0.6, 0.2);
+        let c = pattern.inner_pattern_at(p);
+        let moved = Tuple::vector(c.r - p.x, c.g - p.y, c.b - p.z);
+
+        assert_ne!(c, Color::new(p.x, p.y, p.z));
+        assert!(moved.magnitude() <= 0.5 * 3f64.sqrt());
+    }
+
+    #[test]
+    fn perturbed_pattern_is_repeatable() {
+        let pattern = PerturbedPattern::new_boxed(StripePattern::new_boxed(WHITE, BLACK, None), 0.3, 4, None);
+        let copy = pattern.clone();
+        let p = Tuple::point(1.7, -0.4, 2.2);
+
+        assert_eq!(pattern.inner_pattern_at(p), copy.inner_pattern_at(p));
+        assert!(pattern == copy);
+    }
+
+    #[test]
+    fn ring_extends_in_x_and_z() {
+        let pattern = RingPattern::new(WHITE, BLACK, None);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 0.)), WHITE);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(1., 0., 0.)), BLACK);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 0., 1.)), BLACK);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0.708, 0., 0.708)), BLACK);
+        assert_eq!(pattern.inner_pattern_at(Tuple::point(0., 5., 2.5)), WHITE);
+    }
+
+    #[test]
+    fn face_pattern_colors_each_triangle() {
+        let square = Polygon::new(&[(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)], &[], None, None);
+        let pattern = FacePattern::new(vec![RED, WHITE]);
+        let color_below = |x: f64, z: f64| {
+            let r = Ray::new(Tuple::point(x, 1., z), Tuple::vector(0., -1., 0.));
+            let comps = square.intersect(r)[0].prepare_computations(r);
+            pattern.pattern_at_hit(comps.object, comps.point, comps.primitive.as_ref())
+        };
+
+        let corners: Vec<Color> = [(-0.9, -0.9), (0.9, -0.9), (0.9, 0.9), (-0.9, 0.9)].iter().map(|&(x, z)| color_below(x, z)).collect();
+
+        assert!(corners.contains(&RED) && corners.contains(&WHITE));
+        assert_eq!(pattern.pattern_at_shape(&square, Tuple::point(0.9, 0., -0.9)), RED);
+        assert_eq!(FacePattern::new(vec![]).pattern_at_hit(&square, ORIGO, None), BLACK);
+    }
 }
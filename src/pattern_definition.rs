@@ -0,0 +1,305 @@
+use super::color::{Color, BLACK, WHITE, RED, GREEN, BLUE};
+use super::matrix::{Matrix, IDENTITY_MATRIX};
+use super::angle::rad;
+use super::pattern::{BoxPattern, StripePattern, GradientPattern, RingPattern, CheckerPattern, GridPattern, PerturbedPattern, BrickPattern, TilePattern};
+
+// Patterns written as text, for scene descriptions. A pattern is its kind, followed by its
+// arguments and then any number of transformations, applied in the order they are written:
+//
+//   checkers(white, [0.2, 0.2, 0.2], scale(0.5, 0.5, 0.5), rotate_y(0.785))
+//   perturbed(stripes(red, white, scale(0.1, 1, 1)), 0.2, 3)
+//
+// Kinds: stripes(a, b), gradient(a, b), rings(a, b), checkers(a, b),
+// grid(line, background, line_width), bricks(brick, mortar, width, depth, mortar_width,
+// row_offset), tiles(tile, grout, size, grout_width) and perturbed(pattern, scale, octaves),
+// which nests another pattern. Colors are [r, g, b], kelvin(temperature) for the color of a black body, or one of
+// white, black, red, green and blue.
+// Transformations: translate(x, y, z), scale(x, y, z), rotate_x(radians), rotate_y(radians),
+// rotate_z(radians) and shear(xy, xz, yx, yz, zx, zy).
+pub fn parse_pattern(text: &str) -> Result<BoxPattern, String> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens, position: 0 };
+    let expr = parser.expr()?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("Unexpected '{}' after pattern", parser.tokens[parser.position]));
+    }
+    pattern(&expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Number(f64),
+    Symbol(char)
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Name(n) => write!(f, "{}", n),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Symbol(c) => write!(f, "{}", c)
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if "()[],".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' ||
+                ((chars[i] == '-' || chars[i] == '+') && matches!(chars[i - 1], 'e' | 'E'))) {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(number.parse().map_err(|_| format!("Invalid number '{}'", number))?));
+        } else {
+            return Err(format!("Unexpected character '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Name(String),
+    Call(String, Vec<Expr>),
+    List(Vec<Expr>)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_symbol(&self, symbol: char) -> bool {
+        self.tokens.get(self.position) == Some(&Token::Symbol(symbol))
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) => {
+                if self.peek_symbol('(') {
+                    self.position += 1;
+                    Ok(Expr::Call(name, self.list(')')?))
+                } else {
+                    Ok(Expr::Name(name))
+                }
+            },
+            Some(Token::Symbol('[')) => Ok(Expr::List(self.list(']')?)),
+            Some(t) => Err(format!("Unexpected '{}'", t)),
+            None => Err("Unexpected end of pattern".to_string())
+        }
+    }
+
+    // Comma separated expressions up to and including end
+    fn list(&mut self, end: char) -> Result<Vec<Expr>, String> {
+        let mut items = vec![];
+        if self.peek_symbol(end) {
+            self.position += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(self.expr()?);
+            match self.next() {
+                Some(Token::Symbol(',')) => continue,
+                Some(Token::Symbol(c)) if c == end => return Ok(items),
+                Some(t) => return Err(format!("Expected ',' or '{}' but found '{}'", end, t)),
+                None => return Err(format!("Missing '{}'", end))
+            }
+        }
+    }
+}
+
+fn number(expr: &Expr) -> Result<f64, String> {
+    match expr {
+        Expr::Number(n) => Ok(*n),
+        _ => Err(format!("Expected a number but found {:?}", expr))
+    }
+}
+
+fn numbers(name: &str, args: &[Expr], count: usize) -> Result<Vec<f64>, String> {
+    if args.len() != count {
+        return Err(format!("{} takes {} numbers", name, count));
+    }
+    args.iter().map(number).collect()
+}
+
+fn color(expr: &Expr) -> Result<Color, String> {
+    match expr {
+        Expr::List(items) => {
+            let c = numbers("A color", items, 3)?;
+            Ok(Color::new(c[0], c[1], c[2]))
+        },
+        Expr::Call(name, args) if name == "kelvin" => Ok(Color::from_kelvin(numbers(name, args, 1)?[0])),
+        Expr::Name(name) => match name.as_str() {
+            "white" => Ok(WHITE),
+            "black" => Ok(BLACK),
+            "red" => Ok(RED),
+            "green" => Ok(GREEN),
+            "blue" => Ok(BLUE),
+            _ => Err(format!("Unknown color '{}'", name))
+        },
+        _ => Err(format!("Expected a color but found {:?}", expr))
+    }
+}
+
+fn transformation(expr: &Expr) -> Result<Matrix, String> {
+    let (name, args) = match expr {
+        Expr::Call(name, args) => (name.as_str(), args),
+        _ => return Err(format!("Expected a transformation but found {:?}", expr))
+    };
+    Ok(match name {
+        "translate" => { let v = numbers(name, args, 3)?; Matrix::translation(v[0], v[1], v[2]) },
+        "scale" => { let v = numbers(name, args, 3)?; Matrix::scaling(v[0], v[1], v[2]) },
+        "rotate_x" => Matrix::rotation_x(rad(numbers(name, args, 1)?[0])),
+        "rotate_y" => Matrix::rotation_y(rad(numbers(name, args, 1)?[0])),
+        "rotate_z" => Matrix::rotation_z(rad(numbers(name, args, 1)?[0])),
+        "shear" => { let v = numbers(name, args, 6)?; Matrix::shearing(v[0], v[1], v[2], v[3], v[4], v[5]) },
+        _ => return Err(format!("Unknown transformation '{}'", name))
+    })
+}
+
+// None if there are no transformations
+fn transformations(pattern: &str, exprs: &[Expr]) -> Result<Option<Matrix>, String> {
+    if exprs.is_empty() {
+        return Ok(None);
+    }
+    let mut m = IDENTITY_MATRIX;
+    for e in exprs {
+        m = transformation(e)? * m;
+    }
+    match m.inverse() {
+        Some(_) => Ok(Some(m)),
+        None => Err(format!("Singular transformation for {}", pattern))
+    }
+}
+
+fn pattern(expr: &Expr) -> Result<BoxPattern, String> {
+    let (name, args) = match expr {
+        Expr::Call(name, args) => (name.as_str(), args.as_slice()),
+        _ => return Err(format!("Expected a pattern but found {:?}", expr))
+    };
+    let arguments = match name {
+        "stripes" | "gradient" | "rings" | "checkers" => 2,
+        "grid" | "perturbed" => 3,
+        "tiles" => 4,
+        "bricks" => 6,
+        _ => return Err(format!("Unknown pattern '{}'", name))
+    };
+    if args.len() < arguments {
+        return Err(format!("{} needs {} arguments", name, arguments));
+    }
+    let transform = transformations(name, &args[arguments..])?;
+    Ok(match name {
+        "stripes" => StripePattern::new_boxed(color(&args[0])?, color(&args[1])?, transform),
+        "gradient" => GradientPattern::new_boxed(color(&args[0])?, color(&args[1])?, transform),
+        "rings" => RingPattern::new_boxed(color(&args[0])?, color(&args[1])?, transform),
+        "checkers" => CheckerPattern::new_boxed(color(&args[0])?, color(&args[1])?, transform),
+        "grid" => GridPattern::new_boxed(color(&args[0])?, color(&args[1])?, number(&args[2])?, transform),
+        "bricks" => BrickPattern::new_boxed(color(&args[0])?, color(&args[1])?, number(&args[2])?, number(&args[3])?,
+            number(&args[4])?, number(&args[5])?, transform),
+        "tiles" => TilePattern::new_boxed(color(&args[0])?, color(&args[1])?, number(&args[2])?, number(&args[3])?, transform),
+        _ => {
+            let octaves = number(&args[2])?;
+            if octaves < 1. || octaves.fract() != 0. {
+                return Err(format!("Invalid number of octaves {}", octaves));
+            }
+            PerturbedPattern::new_boxed(pattern(&args[0])?, number(&args[1])?, octaves as usize, transform)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn parse_simple_pattern() {
+        let p = parse_pattern("stripes(white, [0, 0, 0])").unwrap();
+
+        assert!(p == StripePattern::new_boxed(WHITE, BLACK, None));
+    }
+
+    #[test]
+    fn transformations_apply_in_written_order() {
+        let p = parse_pattern("checkers(red, blue, scale(2, 2, 2), translate(1, 0, -1e-1), rotate_y(1.5707963267948966))").unwrap();
+        let expected = Matrix::rotation_y(rad(FRAC_PI_2)) * Matrix::translation(1., 0., -0.1) * Matrix::scaling(2., 2., 2.);
+
+        assert!(p == CheckerPattern::new_boxed(RED, BLUE, Some(expected)));
+    }
+
+    #[test]
+    fn parse_nested_pattern() {
+        let text = "perturbed(rings([0.5, 0.25, 0], green, scale(0.1, 0.1, 0.1)), 0.2, 3, scale(2, 2, 2))";
+        let p = parse_pattern(text).unwrap();
+        let inner = RingPattern::new_boxed(Color::new(0.5, 0.25, 0.), GREEN, Some(Matrix::scaling(0.1, 0.1, 0.1)));
+
+        assert!(p == PerturbedPattern::new_boxed(inner, 0.2, 3, Some(Matrix::scaling(2., 2., 2.))));
+    }
+
+    #[test]
+    fn parse_grid_and_gradient() {
+        assert!(parse_pattern("grid(white, black, 0.05)").unwrap() == GridPattern::new_boxed(WHITE, BLACK, 0.05, None));
+        assert!(parse_pattern(" gradient ( red , blue ) ").unwrap() == GradientPattern::new_boxed(RED, BLUE, None));
+    }
+
+    #[test]
+    fn parse_bricks_and_tiles() {
+        let bricks = parse_pattern("bricks(red, white, 2, 1, 0.1, 0.5, rotate_x(1.5707963267948966))").unwrap();
+        let tiles = parse_pattern("tiles(white, black, 0.5, 0.05)").unwrap();
+
+        assert!(bricks == BrickPattern::new_boxed(RED, WHITE, 2., 1., 0.1, 0.5, Some(Matrix::rotation_x(rad(FRAC_PI_2)))));
+        assert!(tiles == TilePattern::new_boxed(WHITE, BLACK, 0.5, 0.05, None));
+        assert!(parse_pattern("bricks(red, white, 2)").is_err());
+    }
+
+    #[test]
+    fn parse_color_temperature() {
+        let expected = StripePattern::new_boxed(Color::from_kelvin(2700.), Color::from_kelvin(6500.), None);
+
+        assert!(parse_pattern("stripes(kelvin(2700), kelvin(6.5e3))").unwrap() == expected);
+    }
+
+    #[test]
+    fn errors_describe_problem() {
+        let error = |text: &str| parse_pattern(text).err().unwrap();
+
+        assert_eq!(error("spots(red, blue)"), "Unknown pattern 'spots'");
+        assert_eq!(error("stripes(red)"), "stripes needs 2 arguments");
+        assert_eq!(error("stripes(red, pink)"), "Unknown color 'pink'");
+        assert_eq!(error("stripes(red, [1, 0])"), "A color takes 3 numbers");
+        assert_eq!(error("stripes(red, kelvin())"), "kelvin takes 1 numbers");
+        assert_eq!(error("stripes(red, blue, scale(1, 0, 1))"), "Singular transformation for stripes");
+        assert_eq!(error("stripes(red, blue, twist(1))"), "Unknown transformation 'twist'");
+        assert_eq!(error("stripes(red, blue"), "Missing ')'");
+        assert_eq!(error("stripes(red, blue) x"), "Unexpected 'x' after pattern");
+        assert_eq!(error("perturbed(stripes(red, blue), 0.1, 0)"), "Invalid number of octaves 0");
+        assert_eq!(error("stripes(red; blue)"), "Unexpected character ';'");
+    }
+}
@@ -1,15 +1,35 @@
 use super::intersection::{Intersection, Intersections};
 use super::material::Material;
-use super::matrix::Matrix;
+use super::matrix::{Matrix, InvertibleMatrix};
 use super::ray::Ray;
-use super::shape::{inverse_transform_parameter, BoxShape, Shape};
+use super::transform::Motion;
+use super::error::Error;
+use super::shape::{transform_at_time, inverse_transform_at_time, normal_transform_at_time, replace_transform, BoxShape, Shape};
 use super::tuple::{Tuple, VECTOR_Y_UP};
+use super::bounding_box::BoundingBox;
+use super::EPSILON;
 use std::any::Any;
 
+/// A floor one unit below the origin, filling the lower half of a thumbnail up to the horizon:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::color::BLACK;
+/// use raytracer::matrix::Matrix;
+/// use raytracer::plane::Plane;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let floor = Plane::new_boxed(None, Some(Matrix::translation(0., -1., 0.)));
+/// let image = render_thumbnail(thumbnail_world(vec![floor]));
+/// let near = image.pixel_at(16, 31);
+/// assert!(near.r > 0.5 && near.r == near.g && near.g == near.b);
+/// assert_eq!(image.pixel_at(16, 0), BLACK);
+/// # }
+/// ```
 #[derive(Debug, Clone, PartialEq)]
 pub struct Plane {
-    inverse_transform: Matrix,
-    transform: Matrix,
+    transform: InvertibleMatrix,
+    motion: Option<Motion>,
     material: Material,
 }
 
@@ -19,20 +39,22 @@ impl Shape for Plane {
     }
 
     fn box_eq(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| self == a)
+        other.downcast_ref::<Self>() == Some(self)
     }
 
     fn as_any(&self) -> &dyn Any {
         self
     }
 
-    fn inner_intersect(&self, object_ray: Ray) -> Intersections {
-        if super::approx_eq(0., object_ray.direction.y) {
-            return Intersections::new(vec![]);
+    fn inner_intersect(&self, object_ray: Ray) -> Intersections<'_> {
+        // Relative to the length of the direction, since the object space direction shrinks
+        // or grows with the scaling of the plane
+        if object_ray.direction.y.abs() < EPSILON * object_ray.direction.magnitude() {
+            return Intersections::empty();
         }
         let t = -object_ray.origin.y / object_ray.direction.y;
 
-        Intersections::new(vec![Intersection::new(t, Box::new(self.clone()))])
+        Intersections::from_array([Intersection::new(t, self)])
     }
 
     fn inner_normal_at(&self, _object_point: Tuple) -> Tuple {
@@ -44,22 +66,72 @@ impl Shape for Plane {
     }
 
     fn transformation(&self) -> Matrix {
-        self.transform
+        self.transform.matrix()
     }
 
     fn inverse_transformation(&self) -> Matrix {
-        self.inverse_transform
+        self.transform.inverse()
+    }
+
+    fn normal_transformation(&self) -> Matrix {
+        self.transform.normal_matrix()
+    }
+
+    // A shape in motion keeps the end of it
+    fn try_set_transform(&mut self, transform: Matrix) -> Result<(), Error> {
+        (self.transform, self.motion) = replace_transform(transform, self.motion.as_ref())?;
+        Ok(())
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    // Infinite in x and z, so a plane is always unbounded
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Tuple::point(f64::NEG_INFINITY, 0., f64::NEG_INFINITY), Tuple::point(f64::INFINITY, 0., f64::INFINITY))
+    }
+
+    fn motion(&self) -> Option<&Motion> {
+        self.motion.as_ref()
+    }
+
+    fn transformation_at(&self, time: f64) -> Matrix {
+        transform_at_time(&self.transform, self.motion.as_ref(), time)
+    }
+
+    fn inverse_transformation_at(&self, time: f64) -> Matrix {
+        inverse_transform_at_time(&self.transform, self.motion.as_ref(), time)
+    }
+
+    fn normal_transformation_at(&self, time: f64) -> Matrix {
+        normal_transform_at_time(&self.transform, self.motion.as_ref(), time)
     }
 }
 
 impl Plane {
     pub fn new(material: Option<Material>, transform: Option<Matrix>) -> Self {
         Self {
-            transform: transform.unwrap_or_default(),
-            inverse_transform: inverse_transform_parameter(transform),
+            transform: InvertibleMatrix::from(transform),
+            motion: None,
             material: material.unwrap_or_default(),
         }
     }
+
+    // The shape moves from its transform to end_transform while the camera shutter is open.
+    // Panics if it can't, see Motion::try_new.
+    pub fn with_motion(self, end_transform: Matrix) -> Self {
+        self.try_with_motion(end_transform).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_with_motion(self, end_transform: Matrix) -> Result<Self, Error> {
+        let motion = Motion::try_new(&self.transform.matrix(), &end_transform)?;
+        Ok(Self { motion: Some(motion), ..self })
+    }
+
+    pub fn new_boxed(material: Option<Material>, transform: Option<Matrix>) -> BoxShape {
+        Box::new(Plane::new(material, transform))
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -106,7 +178,7 @@ mod tests {
 
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 1.);
-        assert_eq!(&xs[0].object, &box_plane(p));
+        assert_eq!(xs[0].object, &*box_plane(p.clone()));
     }
     #[test]
     fn ray_intersecting_plane_from_below() {
@@ -116,6 +188,35 @@ mod tests {
 
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 1.);
-        assert_eq!(&xs[0].object, &box_plane(p));
+        assert_eq!(xs[0].object, &*box_plane(p.clone()));
+    }
+
+    #[test]
+    fn ray_intersecting_hugely_scaled_plane() {
+        let p = Plane::new(None, Some(Matrix::scaling(1e6, 1e6, 1e6)));
+        let r = Ray::new(Tuple::point(0., 1e6, 0.), Tuple::vector(0., -1., 0.));
+        let xs = p.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1e6);
+    }
+
+    #[test]
+    fn set_transform_moves_plane() {
+        let mut p = Plane::new(None, None);
+        p.set_transform(Matrix::translation(0., 2., 0.));
+        let r = Ray::new(Tuple::point(0., 5., 0.), Tuple::vector(0., -1., 0.));
+        let xs = p.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 3.);
+    }
+
+    #[test]
+    fn plane_is_unbounded() {
+        let p = Plane::new(None, Some(Matrix::translation(0., 1., 0.)));
+
+        assert!(!p.bounds().is_bounded());
+        assert_eq!(p.parent_space_bounds(), BoundingBox::infinite());
     }
 }
@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+use super::camera::Camera;
+use super::canvas::Canvas;
+use super::color::Color;
+use super::tuple::Tuple;
+use super::world::World;
+
+// What one camera ray hit, for inspecting a scene in an external point cloud viewer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CloudPoint {
+    pub point: Tuple,
+    // The outward surface normal, not flipped towards the camera, so that inverted normals show
+    pub normal: Tuple,
+    pub color: Color
+}
+
+// Shoots one ray through the center of every pixel and keeps the first hit of each
+pub fn capture(camera: &Camera, world: &World) -> Vec<CloudPoint> {
+    let mut points = vec![];
+    for py in 0..camera.vsize() {
+        for px in 0..camera.hsize() {
+            let ray = match camera.try_ray_for_pixel(px, py) {
+                Some(r) => r,
+                None => continue
+            };
+            if let Some(hit) = world.intersect(ray).hit() {
+                let point = ray.position(hit.t);
+                points.push(CloudPoint {
+                    point,
+                    normal: hit.object.normal_at_time(point, ray.time),
+                    color: world.color_at(ray)
+                });
+            }
+        }
+    }
+    points
+}
+
+// ASCII PLY with positions, normals and 8 bit colors
+pub fn write_ply<W: Write>(points: &[CloudPoint], out: &mut W) -> Result<()> {
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    writeln!(out, "element vertex {}", points.len())?;
+    for property in ["float x", "float y", "float z", "float nx", "float ny", "float nz", "uchar red", "uchar green", "uchar blue"] {
+        writeln!(out, "property {}", property)?;
+    }
+    writeln!(out, "end_header")?;
+    for p in points {
+        writeln!(out, "{} {} {} {} {} {} {} {} {}",
+            p.point.x, p.point.y, p.point.z, p.normal.x, p.normal.y, p.normal.z,
+            Canvas::clamp_to_byte(p.color.r), Canvas::clamp_to_byte(p.color.g), Canvas::clamp_to_byte(p.color.b))?;
+    }
+    Ok(())
+}
+
+// One point per line: position, normal and color (in [0, 1])
+pub fn write_xyz<W: Write>(points: &[CloudPoint], out: &mut W) -> Result<()> {
+    for p in points {
+        writeln!(out, "{} {} {} {} {} {} {} {} {}",
+            p.point.x, p.point.y, p.point.z, p.normal.x, p.normal.y, p.normal.z, p.color.r, p.color.g, p.color.b)?;
+    }
+    Ok(())
+}
+
+// Writes PLY or XYZ depending on whether file_name ends with .ply
+pub fn save(points: &[CloudPoint], file_name: &str) -> Result<()> {
+    let mut out = BufWriter::new(File::create(file_name)?);
+    if file_name.ends_with(".ply") {
+        write_ply(points, &mut out)?;
+    } else {
+        write_xyz(points, &mut out)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use crate::matrix::Matrix;
+    use crate::tuple::ORIGO;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn cloud() -> Vec<CloudPoint> {
+        let transform = Matrix::view_transform(Tuple::point(0., 0., -5.), ORIGO, Tuple::vector(0., 1., 0.));
+        let camera = Camera::new(11, 11, rad(FRAC_PI_2), Some(transform));
+        capture(&camera, &World::default_world())
+    }
+
+    #[test]
+    fn capture_keeps_hits_only() {
+        let points = cloud();
+        let center = points.iter().find(|p| p.point == Tuple::point(0., 0., -1.)).unwrap();
+
+        assert!(!points.is_empty() && points.len() < 121);
+        assert_eq!(center.normal, Tuple::vector(0., 0., -1.));
+        assert_eq!(center.color, Color::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn captured_points_lie_on_surface_with_outward_normals() {
+        for p in cloud() {
+            let from_center = p.point - ORIGO;
+
+            assert!((from_center.magnitude() - 1.).abs() < 1e-4);
+            assert_eq!(p.normal, from_center.normalize());
+        }
+    }
+
+    #[test]
+    fn ply_has_header_and_one_line_per_point() {
+        let points = vec![CloudPoint { point: Tuple::point(1., 2., 3.), normal: Tuple::vector(0., 1., 0.), color: Color::new(1., 0.5, 0.) }];
+        let mut out = vec![];
+        write_ply(&points, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.starts_with("ply\nformat ascii 1.0\nelement vertex 1\n"));
+        assert!(text.ends_with("end_header\n1 2 3 0 1 0 255 128 0\n"));
+    }
+
+    #[test]
+    fn xyz_has_one_line_per_point() {
+        let points = vec![CloudPoint { point: Tuple::point(1., 2., 3.), normal: Tuple::vector(0., 1., 0.), color: Color::new(1., 0.5, 0.) }];
+        let mut out = vec![];
+        write_xyz(&points, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "1 2 3 0 1 0 1 0.5 0\n");
+    }
+}
@@ -0,0 +1,418 @@
+use super::intersection::{Intersection, Intersections, PrimitiveHit};
+use super::material::Material;
+use super::matrix::{Matrix, InvertibleMatrix};
+use super::ray::Ray;
+use super::transform::Motion;
+use super::error::Error;
+use super::shape::{transform_at_time, inverse_transform_at_time, normal_transform_at_time, replace_transform, BoxShape, Shape};
+use super::tuple::{Tuple, VECTOR_Y_UP};
+use super::bounding_box::BoundingBox;
+use super::tessellation::{Mesh, TessellationQuality};
+use super::EPSILON;
+use std::any::Any;
+use std::sync::Arc;
+
+// A point in the xz plane, as (x, z)
+pub type Point2 = (f64, f64);
+
+// A flat polygon in the xz plane (like Plane, facing up), given by its outline and any number of
+// holes. It is split into triangles once when it is created, by ear clipping. The triangles are
+// shared between clones, which are made for every intersection.
+/// A square turned up from the xz plane to face the camera of a thumbnail:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use std::f64::consts::FRAC_PI_2;
+/// use raytracer::color::BLACK;
+/// use raytracer::matrix::Matrix;
+/// use raytracer::polygon::Polygon;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let square = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)];
+/// let wall = Polygon::new_boxed(&square, &[], None, Some(Matrix::rotation_x(-FRAC_PI_2)));
+/// let image = render_thumbnail(thumbnail_world(vec![wall]));
+/// assert!(image.pixel_at(16, 16).g > 0.5);
+/// assert_eq!(image.pixel_at(4, 16), BLACK);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    transform: InvertibleMatrix,
+    motion: Option<Motion>,
+    material: Material,
+    triangles: Arc<Vec<[Point2; 3]>>,
+    min: Point2,
+    max: Point2
+}
+
+impl Shape for Polygon {
+    fn box_clone(&self) -> BoxShape {
+        Box::new((*self).clone())
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn inner_intersect(&self, object_ray: Ray) -> Intersections<'_> {
+        if object_ray.direction.y.abs() < EPSILON * object_ray.direction.magnitude() {
+            return Intersections::empty();
+        }
+        let t = -object_ray.origin.y / object_ray.direction.y;
+        let p = object_ray.position(t);
+        match self.primitive_at((p.x, p.z)) {
+            Some(hit) => Intersections::from_array([Intersection::new(t, self).with_primitive(hit)]),
+            None => Intersections::empty()
+        }
+    }
+
+    fn inner_normal_at(&self, _object_point: Tuple) -> Tuple {
+        VECTOR_Y_UP
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform.matrix()
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.transform.inverse()
+    }
+
+    fn normal_transformation(&self) -> Matrix {
+        self.transform.normal_matrix()
+    }
+
+    // A shape in motion keeps the end of it
+    fn try_set_transform(&mut self, transform: Matrix) -> Result<(), Error> {
+        (self.transform, self.motion) = replace_transform(transform, self.motion.as_ref())?;
+        Ok(())
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Tuple::point(self.min.0, 0., self.min.1), Tuple::point(self.max.0, 0., self.max.1))
+    }
+
+    // Already flat, so the quality doesn't matter. Triangles turning from +x towards +z face
+    // down, so they are reversed.
+    fn tessellate(&self, _quality: &TessellationQuality) -> Option<Mesh> {
+        let mut mesh = Mesh::default();
+        for [a, b, c] in self.triangles.iter() {
+            let first = mesh.vertices.len();
+            mesh.vertices.extend([a, c, b].iter().map(|p| Tuple::point(p.0, 0., p.1)));
+            mesh.normals.extend([VECTOR_Y_UP; 3]);
+            mesh.faces.push([first, first + 1, first + 2]);
+        }
+        Some(mesh)
+    }
+
+    fn motion(&self) -> Option<&Motion> {
+        self.motion.as_ref()
+    }
+
+    fn transformation_at(&self, time: f64) -> Matrix {
+        transform_at_time(&self.transform, self.motion.as_ref(), time)
+    }
+
+    fn inverse_transformation_at(&self, time: f64) -> Matrix {
+        inverse_transform_at_time(&self.transform, self.motion.as_ref(), time)
+    }
+
+    fn normal_transformation_at(&self, time: f64) -> Matrix {
+        normal_transform_at_time(&self.transform, self.motion.as_ref(), time)
+    }
+}
+
+impl Polygon {
+    // The outline and holes may be given in either winding order, but must not intersect
+    // themselves or each other. An outline of fewer than three points gives a polygon that
+    // nothing hits.
+    pub fn new(outline: &[Point2], holes: &[Vec<Point2>], material: Option<Material>, transform: Option<Matrix>) -> Self {
+        let triangles = triangulate(outline, holes);
+        let points = triangles.iter().flatten();
+        let min = points.clone().fold((f64::INFINITY, f64::INFINITY), |m, p| (m.0.min(p.0), m.1.min(p.1)));
+        let max = points.fold((f64::NEG_INFINITY, f64::NEG_INFINITY), |m, p| (m.0.max(p.0), m.1.max(p.1)));
+        Self {
+            transform: InvertibleMatrix::from(transform),
+            motion: None,
+            material: material.unwrap_or_default(),
+            triangles: Arc::new(triangles),
+            min,
+            max
+        }
+    }
+
+    // The shape moves from its transform to end_transform while the camera shutter is open.
+    // Panics if it can't, see Motion::try_new.
+    pub fn with_motion(self, end_transform: Matrix) -> Self {
+        self.try_with_motion(end_transform).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_with_motion(self, end_transform: Matrix) -> Result<Self, Error> {
+        let motion = Motion::try_new(&self.transform.matrix(), &end_transform)?;
+        Ok(Self { motion: Some(motion), ..self })
+    }
+
+    pub fn new_boxed(outline: &[Point2], holes: &[Vec<Point2>], material: Option<Material>, transform: Option<Matrix>) -> BoxShape {
+        Box::new(Polygon::new(outline, holes, material, transform))
+    }
+
+    // Counter clockwise (positive area) triangles covering the polygon
+    pub fn triangles(&self) -> &[[Point2; 3]] {
+        &self.triangles
+    }
+
+    // The triangle, as indexed in triangles, that p is in, and where in it. None if p is outside
+    // the polygon.
+    fn primitive_at(&self, p: Point2) -> Option<PrimitiveHit> {
+        if p.0 < self.min.0 - EPSILON || p.0 > self.max.0 + EPSILON || p.1 < self.min.1 - EPSILON || p.1 > self.max.1 + EPSILON {
+            return None;
+        }
+        // Points on a shared edge count as inside both triangles, so there are no cracks. Such
+        // points get the first of them.
+        self.triangles.iter().enumerate().find_map(|(face_index, [a, b, c])| {
+            let area = cross(*a, *b, *c);
+            let (wa, wb, wc) = (cross(*b, *c, p), cross(*c, *a, p), cross(*a, *b, p));
+            if area > 0. && wa >= -EPSILON && wb >= -EPSILON && wc >= -EPSILON {
+                Some(PrimitiveHit::new(face_index, wb / area, wc / area))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+// Positive when o, a, b turn counter clockwise (from +x towards +z)
+fn cross(o: Point2, a: Point2, b: Point2) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn signed_area(points: &[Point2]) -> f64 {
+    let n = points.len();
+    (0..n).map(|i| {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        a.0 * b.1 - b.0 * a.1
+    }).sum::<f64>() / 2.
+}
+
+fn with_winding(points: &[Point2], counter_clockwise: bool) -> Vec<Point2> {
+    let mut points = points.to_vec();
+    if (signed_area(&points) > 0.) != counter_clockwise {
+        points.reverse();
+    }
+    points
+}
+
+// True if the segments a-b and c-d cross at a point inside both of them
+fn segments_cross(a: Point2, b: Point2, c: Point2, d: Point2) -> bool {
+    let d1 = cross(a, b, c);
+    let d2 = cross(a, b, d);
+    let d3 = cross(c, d, a);
+    let d4 = cross(c, d, b);
+    d1 * d2 < 0. && d3 * d4 < 0.
+}
+
+fn edges(points: &[Point2]) -> impl Iterator<Item = (Point2, Point2)> + '_ {
+    (0..points.len()).map(move |i| (points[i], points[(i + 1) % points.len()]))
+}
+
+// Joins a hole to the polygon by a pair of coincident edges (a bridge) between a hole vertex
+// and the nearest polygon vertex it can see, which makes one polygon without holes
+fn bridge_hole(polygon: &[Point2], hole: &[Point2], other_holes: &[Vec<Point2>]) -> Vec<Point2> {
+    let blocked = |m: Point2, v: Point2| {
+        let crosses = |(a, b): (Point2, Point2)| a != m && b != m && a != v && b != v && segments_cross(m, v, a, b);
+        edges(polygon).any(crosses) || edges(hole).any(crosses) || other_holes.iter().any(|h| edges(h).any(crosses))
+    };
+    let mut best: Option<(f64, usize, usize)> = None;
+    for (hi, m) in hole.iter().enumerate() {
+        for (pi, v) in polygon.iter().enumerate() {
+            let distance = (v.0 - m.0).powi(2) + (v.1 - m.1).powi(2);
+            if best.is_none_or(|(d, _, _)| distance < d) && !blocked(*m, *v) {
+                best = Some((distance, hi, pi));
+            }
+        }
+    }
+    let (_, hi, pi) = match best {
+        Some(b) => b,
+        None => return polygon.to_vec()
+    };
+    let mut bridged = polygon[..=pi].to_vec();
+    bridged.extend_from_slice(&hole[hi..]);
+    bridged.extend_from_slice(&hole[..=hi]);
+    bridged.extend_from_slice(&polygon[pi..]);
+    bridged
+}
+
+fn is_ear(points: &[Point2], prev: Point2, cur: Point2, next: Point2) -> bool {
+    if cross(prev, cur, next) <= 0. {
+        return false;
+    }
+    // Vertices at the corners are copies made by bridging, and don't count
+    !points.iter().any(|&p| p != prev && p != cur && p != next &&
+        cross(prev, cur, p) >= 0. && cross(cur, next, p) >= 0. && cross(next, prev, p) >= 0.)
+}
+
+fn triangulate(outline: &[Point2], holes: &[Vec<Point2>]) -> Vec<[Point2; 3]> {
+    if outline.len() < 3 {
+        return vec![];
+    }
+    let mut points = with_winding(outline, true);
+    let mut holes: Vec<Vec<Point2>> = holes.iter().filter(|h| h.len() >= 3).map(|h| with_winding(h, false)).collect();
+    while let Some(hole) = holes.pop() {
+        points = bridge_hole(&points, &hole, &holes);
+    }
+
+    let mut triangles = vec![];
+    while points.len() > 3 {
+        let n = points.len();
+        let corners = |i: usize| (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+        // Without a proper ear the polygon is degenerate, fall back to any convex corner
+        let ear = (0..n).find(|&i| { let (a, b, c) = corners(i); is_ear(&points, a, b, c) })
+            .or_else(|| (0..n).find(|&i| { let (a, b, c) = corners(i); cross(a, b, c) > 0. }));
+        match ear {
+            Some(i) => {
+                let (a, b, c) = corners(i);
+                triangles.push([a, b, c]);
+                points.remove(i);
+            },
+            None => break
+        }
+    }
+    if points.len() == 3 && cross(points[0], points[1], points[2]) > 0. {
+        triangles.push([points[0], points[1], points[2]]);
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(p: &Polygon) -> f64 {
+        p.triangles().iter().map(|t| signed_area(t)).sum()
+    }
+
+    fn square(size: f64, center: Point2) -> Vec<Point2> {
+        let h = size / 2.;
+        vec![(center.0 - h, center.1 - h), (center.0 + h, center.1 - h), (center.0 + h, center.1 + h), (center.0 - h, center.1 + h)]
+    }
+
+    fn hits(p: &Polygon, x: f64, z: f64) -> bool {
+        let r = Ray::new(Tuple::point(x, 1., z), Tuple::vector(0., -1., 0.));
+        !p.intersect(r).is_empty()
+    }
+
+    #[test]
+    fn square_is_two_triangles() {
+        let p = Polygon::new(&square(2., (0., 0.)), &[], None, None);
+
+        assert_eq!(p.triangles().len(), 2);
+        assert!((area(&p) - 4.).abs() < EPSILON);
+    }
+
+    #[test]
+    fn winding_order_does_not_matter() {
+        let mut outline = square(2., (0., 0.));
+        outline.reverse();
+        let p = Polygon::new(&outline, &[], None, None);
+
+        assert!((area(&p) - 4.).abs() < EPSILON);
+        assert!(hits(&p, 0.5, 0.5));
+    }
+
+    #[test]
+    fn concave_polygon_leaves_notch_empty() {
+        let l_shape = vec![(0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 2.), (0., 2.)];
+        let p = Polygon::new(&l_shape, &[], None, None);
+
+        assert_eq!(p.triangles().len(), 4);
+        assert!((area(&p) - 3.).abs() < EPSILON);
+        assert!(hits(&p, 0.5, 1.5));
+        assert!(hits(&p, 1.5, 0.5));
+        assert!(!hits(&p, 1.5, 1.5));
+    }
+
+    #[test]
+    fn rays_through_holes_miss() {
+        let holes = vec![square(1., (-1., 0.)), square(1., (1., 0.))];
+        let p = Polygon::new(&square(4., (0., 0.)), &holes, None, None);
+
+        assert!((area(&p) - 14.).abs() < EPSILON);
+        assert!(!hits(&p, -1., 0.));
+        assert!(!hits(&p, 1.2, 0.3));
+        assert!(hits(&p, 0., 0.));
+        assert!(hits(&p, 1., 1.5));
+        assert!(!hits(&p, 2.5, 0.));
+    }
+
+    #[test]
+    fn intersect_gives_distance_to_plane() {
+        let p = Polygon::new(&square(2., (0., 0.)), &[], None, Some(Matrix::translation(0., -1., 0.)));
+        let r = Ray::new(Tuple::point(0., 1., 0.), Tuple::vector(0., -1., 0.));
+        let xs = p.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.);
+        assert_eq!(p.normal_at(Tuple::point(0., -1., 0.)), Tuple::vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn hits_tell_triangle_and_where_in_it() {
+        let p = Polygon::new(&square(2., (0., 0.)), &[], None, None);
+        for &(x, z) in &[(-0.5, -0.5), (0.5, 0.5), (0.9, -0.2)] {
+            let xs = p.intersect(Ray::new(Tuple::point(x, 1., z), Tuple::vector(0., -1., 0.)));
+            let hit = xs[0].primitive.unwrap();
+            let [a, b, c] = p.triangles()[hit.face_index()];
+            let w = 1. - hit.u() - hit.v();
+
+            assert!(hit.u() >= 0. && hit.v() >= 0. && w >= 0.);
+            assert!((w * a.0 + hit.u() * b.0 + hit.v() * c.0 - x).abs() < EPSILON);
+            assert!((w * a.1 + hit.u() * b.1 + hit.v() * c.1 - z).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn parallel_ray_misses() {
+        let p = Polygon::new(&square(2., (0., 0.)), &[], None, None);
+        let r = Ray::new(Tuple::point(-5., 0., 0.), Tuple::vector(1., 0., 0.));
+
+        assert!(p.intersect(r).is_empty());
+    }
+
+    #[test]
+    fn too_few_points_give_empty_polygon() {
+        let p = Polygon::new(&[(0., 0.), (1., 0.)], &[], None, None);
+
+        assert!(p.triangles().is_empty());
+        assert!(!hits(&p, 0.5, 0.));
+    }
+
+    #[test]
+    fn tessellated_polygon_faces_up() {
+        let p = Polygon::new(&square(2., (1., -1.)), &[], None, None);
+        let mesh = p.tessellate(&TessellationQuality::default()).unwrap();
+
+        assert_eq!(mesh.faces.len(), 2);
+        for [a, b, c] in mesh.faces.iter().map(|f| f.map(|i| mesh.vertices[i])) {
+            assert!((b - a).cross(&(c - a)).y > 0.);
+        }
+    }
+
+    #[test]
+    fn bounds_of_polygon_are_flat() {
+        let p = Polygon::new(&square(2., (1., -1.)), &[], None, None);
+
+        assert_eq!(p.bounds(), BoundingBox::new(Tuple::point(0., 0., -2.), Tuple::point(2., 0., 0.)));
+    }
+}
@@ -1,18 +1,50 @@
 use super::tuple::Tuple;
-use super::shape::BoxShape;
+use super::ray::Ray;
+use super::shape::Shape;
+use super::intersection::PrimitiveHit;
 
-pub struct PrecomputedData {
+pub struct PrecomputedData<'a> {
     pub t: f64,
-    pub object: BoxShape,
+    pub time: f64,
+    // Of the ray, see Ray::wavelength
+    pub wavelength: Option<f64>,
+    pub object: &'a dyn Shape,
     pub point: Tuple,
     pub eyev: Tuple,
     pub normalv: Tuple,
     pub inside: bool,
-    pub over_point: Tuple
+    pub over_point: Tuple,
+    // Just below the surface, where refracted rays start
+    pub under_point: Tuple,
+    pub reflectv: Tuple,
+    // The refractive indices of the materials on the side the ray comes from and the side it
+    // goes into
+    pub n1: f64,
+    pub n2: f64,
+    pub primitive: Option<PrimitiveHit>
 }
 
-impl PrecomputedData {
-    pub fn new(t: f64, object: BoxShape, point: Tuple, eyev: Tuple, normalv: Tuple, inside: bool, over_point: Tuple) -> Self {
-        Self { t, object, point, eyev, normalv, inside, over_point }
+impl PrecomputedData<'_> {
+    // A ray going on from the hit, at the same time and wavelength as the one that found it
+    pub fn spawn_ray(&self, origin: Tuple, direction: Tuple) -> Ray {
+        Ray { origin, direction, time: self.time, wavelength: self.wavelength }
     }
-}
\ No newline at end of file
+
+    pub fn reflected_ray(&self) -> Ray {
+        self.spawn_ray(self.over_point, self.reflectv)
+    }
+
+    // Bent by Snell's law going from n1 into n2, or None when all of the light is reflected
+    // back (total internal reflection)
+    pub fn refracted_ray(&self) -> Option<Ray> {
+        let n_ratio = self.n1 / self.n2;
+        let cos_i = self.eyev.dot(&self.normalv);
+        let sin2_t = n_ratio * n_ratio * (1. - cos_i * cos_i);
+        if sin2_t > 1. {
+            return None;
+        }
+        let cos_t = (1. - sin2_t).sqrt();
+        let direction = self.normalv * (n_ratio * cos_i - cos_t) - self.eyev * n_ratio;
+        Some(self.spawn_ray(self.under_point, direction))
+    }
+}
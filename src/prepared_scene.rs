@@ -0,0 +1,54 @@
+use super::world::World;
+use super::logging::PhaseTimer;
+use super::render_settings::EnergyCheck;
+use log::warn;
+
+// A world that has been made ready for rendering. Anything that only has to be worked out once
+// per scene, rather than once per render, belongs here, so that several renders of the same
+// scene (e g a quick preview followed by the final image) share it.
+pub struct PreparedScene {
+    pub world: World
+}
+
+impl PreparedScene {
+    pub fn new(mut world: World) -> Self {
+        let timer = PhaseTimer::start("prepare");
+        if world.settings.energy_check != EnergyCheck::Off {
+            for i in world.energy_violations() {
+                let o = &world.objects()[i];
+                warn!("{} {} reflects {:?} of the light it receives{}", o.type_name(), i, o.material().reflectance(),
+                    if world.settings.energy_check == EnergyCheck::Clamp { ", rendering it normalized" } else { "" });
+            }
+        }
+        world.build_bvh();
+        timer.finish(&format!("objects={}", world.objects().len()));
+        Self { world }
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plane::Plane;
+
+    #[test]
+    fn prepared_scene_keeps_world() {
+        let scene = PreparedScene::new(World::default_world());
+
+        assert_eq!(scene.world().objects().len(), 2);
+        assert!(scene.world().light.is_some());
+    }
+
+    #[test]
+    fn prepared_scene_has_bvh() {
+        let mut world = World::default_world();
+        world.add_object(Plane::new_boxed(None, None));
+        let scene = PreparedScene::new(world);
+
+        assert!(scene.world().has_bvh());
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+use std::f64::consts::PI;
+
+use super::tuple::Tuple;
+
+// A projection maps a point on the image to a ray direction in camera space, where the camera
+// looks towards -z with y up (and, as in the book, +x towards the left edge of the image). The
+// point (x, y) is given in pixels from the top left corner of a hsize x vsize image. Returns None
+// for points that are outside of what the projection can see. Send and Sync so that a camera can
+// render on several threads.
+pub trait Projection: fmt::Debug + Send + Sync {
+    fn box_clone(&self) -> BoxProjection;
+    fn direction(&self, x: f64, y: f64, hsize: usize, vsize: usize) -> Option<Tuple>;
+
+    // The inverse of direction, the point on the image that a camera space direction shows up
+    // at. None if it is outside of the image, or if the projection can't tell.
+    fn image_point(&self, _direction: Tuple, _hsize: usize, _vsize: usize) -> Option<(f64, f64)> {
+        None
+    }
+
+    // The same projection with another field of view, unchanged for those that have none
+    fn with_field_of_view(&self, _field_of_view: f64) -> BoxProjection {
+        self.box_clone()
+    }
+
+    // None for projections that show the same whatever the field of view of the camera
+    fn field_of_view(&self) -> Option<f64> {
+        None
+    }
+
+    // Fields of view have to be below this, infinite for projections that have none. Pi for a
+    // perspective projection, which can't see as far as to the side.
+    fn max_field_of_view(&self) -> f64 {
+        PI
+    }
+}
+
+pub type BoxProjection = Box<dyn Projection>;
+
+impl Clone for BoxProjection {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+// Half the width and height of the image plane at distance 1 from the camera, where field_of_view
+// covers the longer side of the image
+pub fn perspective_half_extent(hsize: usize, vsize: usize, field_of_view: f64) -> (f64, f64) {
+    let half_view = (field_of_view / 2.).tan();
+    let aspect_ratio = hsize as f64 / vsize as f64;
+    if aspect_ratio >= 1. {
+        (half_view, half_view / aspect_ratio)
+    } else {
+        (half_view * aspect_ratio, half_view)
+    }
+}
+
+// The pinhole camera of the book
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PerspectiveProjection {
+    pub field_of_view: f64
+}
+
+impl PerspectiveProjection {
+    pub fn new(field_of_view: f64) -> Self {
+        Self { field_of_view }
+    }
+
+    pub fn new_boxed(field_of_view: f64) -> BoxProjection {
+        Box::new(Self::new(field_of_view))
+    }
+}
+
+impl Projection for PerspectiveProjection {
+    fn box_clone(&self) -> BoxProjection {
+        Box::new(*self)
+    }
+
+    fn field_of_view(&self) -> Option<f64> {
+        Some(self.field_of_view)
+    }
+
+    fn with_field_of_view(&self, field_of_view: f64) -> BoxProjection {
+        Self::new_boxed(field_of_view)
+    }
+
+    fn direction(&self, x: f64, y: f64, hsize: usize, vsize: usize) -> Option<Tuple> {
+        let (half_width, half_height) = perspective_half_extent(hsize, vsize, self.field_of_view);
+        let pixel_size = half_width * 2. / hsize as f64;
+        Some(Tuple::vector(half_width - x * pixel_size, half_height - y * pixel_size, -1.).normalize())
+    }
+
+    fn image_point(&self, direction: Tuple, hsize: usize, vsize: usize) -> Option<(f64, f64)> {
+        if direction.z >= 0. {
+            return None;
+        }
+        let (half_width, half_height) = perspective_half_extent(hsize, vsize, self.field_of_view);
+        let pixel_size = half_width * 2. / hsize as f64;
+        let x = (half_width + direction.x / direction.z) / pixel_size;
+        let y = (half_height + direction.y / direction.z) / pixel_size;
+        if (0. ..hsize as f64).contains(&x) && (0. ..vsize as f64).contains(&y) { Some((x, y)) } else { None }
+    }
+}
+
+// Equidistant fisheye. The image circle fills the shorter side of the image and the angle from
+// the view direction grows linearly with the distance from the center, reaching field_of_view / 2
+// at the edge of the circle. A field of view of up to 2 pi is supported.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FisheyeProjection {
+    pub field_of_view: f64
+}
+
+impl FisheyeProjection {
+    pub fn new(field_of_view: f64) -> Self {
+        Self { field_of_view }
+    }
+
+    pub fn new_boxed(field_of_view: f64) -> BoxProjection {
+        Box::new(Self::new(field_of_view))
+    }
+}
+
+impl Projection for FisheyeProjection {
+    fn box_clone(&self) -> BoxProjection {
+        Box::new(*self)
+    }
+
+    fn with_field_of_view(&self, field_of_view: f64) -> BoxProjection {
+        Self::new_boxed(field_of_view)
+    }
+
+    fn field_of_view(&self) -> Option<f64> {
+        Some(self.field_of_view)
+    }
+
+    // At 2 pi the whole edge of the image would show the single point straight behind
+    fn max_field_of_view(&self) -> f64 {
+        2. * PI
+    }
+
+    fn direction(&self, x: f64, y: f64, hsize: usize, vsize: usize) -> Option<Tuple> {
+        let radius = hsize.min(vsize) as f64 / 2.;
+        let nx = (hsize as f64 / 2. - x) / radius;
+        let ny = (vsize as f64 / 2. - y) / radius;
+        let r = (nx * nx + ny * ny).sqrt();
+        if r > 1. {
+            return None;
+        }
+        let theta = r * self.field_of_view / 2.;
+        let phi = ny.atan2(nx);
+        Some(Tuple::vector(theta.sin() * phi.cos(), theta.sin() * phi.sin(), -theta.cos()))
+    }
+}
+
+// Equirectangular (latitude/longitude) projection of the full sphere around the camera, as used
+// for environment maps. The image should be twice as wide as it is high.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct EquirectangularProjection;
+
+impl EquirectangularProjection {
+    pub fn new_boxed() -> BoxProjection {
+        Box::new(EquirectangularProjection)
+    }
+}
+
+impl Projection for EquirectangularProjection {
+    fn box_clone(&self) -> BoxProjection {
+        Box::new(*self)
+    }
+
+    fn max_field_of_view(&self) -> f64 {
+        f64::INFINITY
+    }
+
+    fn direction(&self, x: f64, y: f64, hsize: usize, vsize: usize) -> Option<Tuple> {
+        let longitude = (0.5 - x / hsize as f64) * 2. * PI;
+        let latitude = (0.5 - y / vsize as f64) * PI;
+        Some(Tuple::vector(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, FRAC_1_SQRT_2};
+
+    #[test]
+    fn perspective_center_looks_forward() {
+        let p = PerspectiveProjection::new(FRAC_PI_2);
+
+        assert_eq!(p.direction(100.5, 50.5, 201, 101), Some(Tuple::vector(0., 0., -1.)));
+        assert_eq!(p.direction(0.5, 0.5, 201, 101), Some(Tuple::vector(0.66519, 0.33259, -0.66851)));
+    }
+
+    #[test]
+    fn perspective_image_point_inverts_direction() {
+        let p = PerspectiveProjection::new(FRAC_PI_2);
+        let (x, y) = p.image_point(p.direction(20.25, 70.5, 201, 101).unwrap(), 201, 101).unwrap();
+
+        assert!(crate::approx_eq(x, 20.25) && crate::approx_eq(y, 70.5));
+        assert_eq!(p.image_point(Tuple::vector(0., 0., 1.), 201, 101), None);
+        assert_eq!(p.image_point(Tuple::vector(-2., 0., -1.), 201, 101), None);
+        assert_eq!(FisheyeProjection::new(PI).image_point(Tuple::vector(0., 0., -1.), 100, 100), None);
+    }
+
+    #[test]
+    fn fisheye_angle_grows_linearly_from_center() {
+        let p = FisheyeProjection::new(PI);
+
+        assert_eq!(p.direction(50., 50., 100, 100), Some(Tuple::vector(0., 0., -1.)));
+        assert_eq!(p.direction(0., 50., 100, 100), Some(Tuple::vector(1., 0., 0.)));
+        assert_eq!(p.direction(50., 25., 100, 100), Some(Tuple::vector(0., FRAC_1_SQRT_2, -FRAC_1_SQRT_2)));
+    }
+
+    #[test]
+    fn fisheye_sees_nothing_outside_image_circle() {
+        let p = FisheyeProjection::new(PI);
+
+        assert_eq!(p.direction(0., 0., 100, 100), None);
+        assert_eq!(p.direction(10., 50., 200, 100), None);
+    }
+
+    #[test]
+    fn equirectangular_covers_full_sphere() {
+        let p = EquirectangularProjection;
+
+        assert_eq!(p.direction(100., 50., 200, 100), Some(Tuple::vector(0., 0., -1.)));
+        assert_eq!(p.direction(50., 50., 200, 100), Some(Tuple::vector(1., 0., 0.)));
+        assert_eq!(p.direction(0., 50., 200, 100), Some(Tuple::vector(0., 0., 1.)));
+        assert_eq!(p.direction(100., 0., 200, 100), Some(Tuple::vector(0., 1., 0.)));
+    }
+}
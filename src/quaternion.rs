@@ -0,0 +1,248 @@
+use core::ops;
+
+use super::matrix::{Matrix, IDENTITY_MATRIX};
+use super::tuple::Tuple;
+
+// A rotation as a unit quaternion w + xi + yj + zk, for rotations that have to be combined or
+// interpolated, e g keyframes of an animation. Unlike angles for rotation_x, rotation_y and
+// rotation_z it has no gimbal lock, and slerp turns at an even rate along the shortest way.
+// q and -q are the same rotation.
+#[derive(Debug, Copy, Clone)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64
+}
+
+pub const IDENTITY_QUATERNION: Quaternion = Quaternion { w: 1., x: 0., y: 0., z: 0. };
+
+impl PartialEq for Quaternion {
+    fn eq(&self, other: &Self) -> bool {
+        super::approx_eq(self.w, other.w) &&
+        super::approx_eq(self.x, other.x) &&
+        super::approx_eq(self.y, other.y) &&
+        super::approx_eq(self.z, other.z)
+    }
+}
+
+// Rotates by rhs first, then by self, like the product of the rotation matrices
+impl ops::Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w)
+    }
+}
+
+impl ops::Neg for Quaternion {
+    type Output = Quaternion;
+    fn neg(self) -> Quaternion {
+        Quaternion::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+forward_ref_binop! { impl Mul, mul for Quaternion, Quaternion }
+forward_ref_unop! { impl Neg, neg for Quaternion }
+assign_op! { impl MulAssign, mul_assign from Mul, mul for Quaternion, Quaternion }
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Self { w, x, y, z }
+    }
+
+    // Rotates angle radians around axis, counter clockwise looking towards the origin from the
+    // tip of axis like rotation_x and friends. Gives the identity for a zero axis.
+    pub fn from_axis_angle(axis: Tuple, angle: f64) -> Self {
+        let length = axis.magnitude();
+        if length == 0. {
+            return IDENTITY_QUATERNION;
+        }
+        let s = (angle / 2.).sin() / length;
+        Self::new((angle / 2.).cos(), axis.x * s, axis.y * s, axis.z * s)
+    }
+
+    // The rotation of the upper left 3x3 part of m, which has to be a rotation matrix, i e
+    // orthonormal without mirroring. Use Matrix::decompose first for matrices that also scale.
+    pub fn from_matrix(m: &Matrix) -> Self {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        // Divides by the largest of the four components, for precision
+        let q = if trace > 0. {
+            let s = (trace + 1.).sqrt() * 2.;
+            Self::new(s / 4., (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1. + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.;
+            Self::new((m[2][1] - m[1][2]) / s, s / 4., (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1. + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.;
+            Self::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, s / 4., (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = (1. + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.;
+            Self::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, s / 4.)
+        };
+        q.normalize()
+    }
+
+    pub fn to_matrix(&self) -> Matrix {
+        let Quaternion { w, x, y, z } = self.normalize();
+        let mut m = IDENTITY_MATRIX;
+        m.set(0, 0, 1. - 2. * (y * y + z * z));
+        m.set(0, 1, 2. * (x * y - w * z));
+        m.set(0, 2, 2. * (x * z + w * y));
+        m.set(1, 0, 2. * (x * y + w * z));
+        m.set(1, 1, 1. - 2. * (x * x + z * z));
+        m.set(1, 2, 2. * (y * z - w * x));
+        m.set(2, 0, 2. * (x * z - w * y));
+        m.set(2, 1, 2. * (y * z + w * x));
+        m.set(2, 2, 1. - 2. * (x * x + y * y));
+        m
+    }
+
+    // The unit axis and the angle in [0, pi] of the rotation. The axis is arbitrary for the
+    // identity.
+    pub fn axis_angle(&self) -> (Tuple, f64) {
+        let q = if self.w < 0. { -self.normalize() } else { self.normalize() };
+        let s = (q.x * q.x + q.y * q.y + q.z * q.z).sqrt();
+        if s == 0. {
+            return (Tuple::vector(1., 0., 0.), 0.);
+        }
+        (Tuple::vector(q.x / s, q.y / s, q.z / s), 2. * s.atan2(q.w))
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn magnitude(&self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        let m = self.magnitude();
+        Quaternion::new(self.w / m, self.x / m, self.y / m, self.z / m)
+    }
+
+    // The inverse rotation, for a unit quaternion
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn rotate(&self, v: Tuple) -> Tuple {
+        self.to_matrix() * v
+    }
+
+    // Spherical linear interpolation from self at t = 0 to other at t = 1, turning at an even
+    // rate the shortest way around
+    pub fn slerp(&self, other: &Quaternion, t: f64) -> Quaternion {
+        let a = self.normalize();
+        let mut b = other.normalize();
+        let mut cos_theta = a.dot(&b);
+        if cos_theta < 0. {
+            b = -b;
+            cos_theta = -cos_theta;
+        }
+        // Almost the same rotation, where sin(theta) is too small to divide by and a straight
+        // line is just as good
+        let (wa, wb) = if cos_theta > 1. - 1e-9 {
+            (1. - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            (((1. - t) * theta).sin() / theta.sin(), (t * theta).sin() / theta.sin())
+        };
+        Quaternion::new(
+            wa * a.w + wb * b.w,
+            wa * a.x + wb * b.x,
+            wa * a.y + wb * b.y,
+            wa * a.z + wb * b.z).normalize()
+    }
+}
+
+impl From<Quaternion> for Matrix {
+    fn from(q: Quaternion) -> Self {
+        q.to_matrix()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::*;
+
+    fn x_axis() -> Tuple {
+        Tuple::vector(1., 0., 0.)
+    }
+
+    #[test]
+    fn axis_angle_matches_rotation_matrices() {
+        assert_eq!(Quaternion::from_axis_angle(x_axis(), 0.7).to_matrix(), Matrix::rotation_x(rad(0.7)));
+        assert_eq!(Quaternion::from_axis_angle(Tuple::vector(0., 2., 0.), -1.2).to_matrix(), Matrix::rotation_y(rad(-1.2)));
+        assert_eq!(Quaternion::from_axis_angle(Tuple::vector(0., 0., 1.), PI).to_matrix(), Matrix::rotation_z(rad(PI)));
+        assert_eq!(Quaternion::from_axis_angle(Tuple::vector(0., 0., 0.), 1.), IDENTITY_QUATERNION);
+    }
+
+    #[test]
+    fn product_combines_rotations_like_matrices() {
+        let a = Quaternion::from_axis_angle(x_axis(), FRAC_PI_2);
+        let b = Quaternion::from_axis_angle(Tuple::vector(0., 1., 0.), FRAC_PI_3);
+        let mut c = a;
+        c *= &b;
+
+        assert_eq!((a * b).to_matrix(), Matrix::rotation_x(rad(FRAC_PI_2)) * Matrix::rotation_y(rad(FRAC_PI_3)));
+        assert_eq!(c, a * b);
+        assert_eq!(a * a.conjugate(), IDENTITY_QUATERNION);
+        assert_eq!(a.rotate(Tuple::vector(0., 1., 0.)), Tuple::vector(0., 0., 1.));
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        // Angles that make each of the four cases in from_matrix the largest
+        let rotations = [
+            Matrix::rotation_z(rad(0.3)) * Matrix::rotation_y(rad(-0.2)) * Matrix::rotation_x(rad(FRAC_PI_4)),
+            Matrix::rotation_x(rad(3.)),
+            Matrix::rotation_y(rad(3.)),
+            Matrix::rotation_z(rad(3.)) * Matrix::rotation_x(rad(0.1))];
+        for m in rotations {
+            let q = Quaternion::from_matrix(&m);
+
+            assert_eq!(q.to_matrix(), m);
+            assert_eq!(Matrix::from(q), m);
+            assert!(crate::approx_eq(q.magnitude(), 1.));
+        }
+    }
+
+    #[test]
+    fn axis_angle_round_trip() {
+        let axis = Tuple::vector(1., 2., -2.).normalize();
+        let (a, angle) = Quaternion::from_axis_angle(axis, 2.5).axis_angle();
+        let (b, negative) = Quaternion::from_axis_angle(axis, -0.5).axis_angle();
+
+        assert_eq!((a, angle), (axis, 2.5));
+        assert_eq!(b, -axis);
+        assert!(crate::approx_eq(negative, 0.5));
+        assert_eq!(IDENTITY_QUATERNION.axis_angle().1, 0.);
+    }
+
+    #[test]
+    fn slerp_turns_at_even_rate() {
+        let a = Quaternion::from_axis_angle(Tuple::vector(0., 1., 0.), 0.2);
+        let b = Quaternion::from_axis_angle(Tuple::vector(0., 1., 0.), 1.8);
+
+        assert_eq!(a.slerp(&b, 0.), a);
+        assert_eq!(a.slerp(&b, 1.), b);
+        assert_eq!(a.slerp(&b, 0.25), Quaternion::from_axis_angle(Tuple::vector(0., 1., 0.), 0.6));
+        assert_eq!(a.slerp(&a, 0.5), a);
+    }
+
+    #[test]
+    fn slerp_takes_shortest_way() {
+        let a = Quaternion::from_axis_angle(x_axis(), 0.1);
+        let b = -Quaternion::from_axis_angle(x_axis(), 0.3);
+        let (_, angle) = a.slerp(&b, 0.5).axis_angle();
+
+        assert!(crate::approx_eq(angle, 0.2));
+    }
+}
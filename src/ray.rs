@@ -1,17 +1,46 @@
+use super::error::Error;
 use super::matrix::Matrix;
 use super::tuple::Tuple;
+use super::EPSILON;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Tuple,
-    pub direction: Tuple
+    pub direction: Tuple,
+    pub time: f64,
+    // In nm, for rays of a single wavelength when rendering spectrally, see Camera::spectral.
+    // None for rays that carry all of RGB at once.
+    pub wavelength: Option<f64>
 }
 
 impl Ray {
+    // Panics if origin isn't a point or direction isn't a vector
     pub fn new(origin: Tuple, direction: Tuple) -> Self {
-        if !origin.is_point() { panic!("origin should be a point"); }
-        if !direction.is_vector() { panic!("direction should be a vector"); }
-        Ray { origin, direction }
+        Ray::try_new(origin, direction).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new(origin: Tuple, direction: Tuple) -> Result<Self, Error> {
+        if !origin.is_point() { return Err(Error::InvalidRay("origin should be a point")); }
+        if !direction.is_vector() { return Err(Error::InvalidRay("direction should be a vector")); }
+        Ok(Ray { origin, direction, time: 0., wavelength: None })
+    }
+
+    pub fn with_time(self, time: f64) -> Ray {
+        Ray { time, ..self }
+    }
+
+    pub fn with_wavelength(self, wavelength: f64) -> Ray {
+        Ray { wavelength: Some(wavelength), ..self }
+    }
+
+    // t is only a world space distance along the ray when the direction has unit length, so rays
+    // whose hits are compared against distances should be built with this
+    pub fn with_unit_direction(self) -> Ray {
+        Ray { direction: self.direction.normalize(), ..self }
+    }
+
+    pub fn has_unit_direction(&self) -> bool {
+        (self.direction.magnitude() - 1.).abs() < EPSILON
     }
 
     pub fn position(&self, t: f64) -> Tuple {
@@ -19,7 +48,7 @@ impl Ray {
     }
 
     pub fn transform(&self, m: Matrix) -> Ray {
-        Ray::new(m * self.origin, m * self.direction)
+        Ray { origin: m * self.origin, direction: m * self.direction, ..*self }
     }
 }
 
@@ -36,6 +65,45 @@ mod tests {
 
         assert_eq!(r.origin, origin);
         assert_eq!(r.direction, direction);
+        assert_eq!(r.time, 0.);
+        assert_eq!(r.wavelength, None);
+    }
+
+    #[test]
+    fn ray_with_time() {
+        let r = Ray::new(Tuple::point(1., 2., 3.), Tuple::vector(4., 5., 6.)).with_time(0.25);
+
+        assert_eq!(r.origin, Tuple::point(1., 2., 3.));
+        assert_eq!(r.time, 0.25);
+    }
+
+    #[test]
+    fn transforming_ray_keeps_time() {
+        let r = Ray::new(Tuple::point(1., 2., 3.), Tuple::vector(0., 1., 0.)).with_time(0.75);
+        let r2 = r.transform(Matrix::translation(3., 4., 5.));
+
+        assert_eq!(r2.time, 0.75);
+    }
+
+    #[test]
+    fn transforming_ray_keeps_wavelength() {
+        let r = Ray::new(Tuple::point(1., 2., 3.), Tuple::vector(0., 1., 0.)).with_wavelength(450.);
+        let r2 = r.transform(Matrix::scaling(2., 3., 4.));
+
+        assert_eq!(r2.wavelength, Some(450.));
+        assert_eq!(r2.direction, Tuple::vector(0., 3., 0.));
+    }
+
+    #[test]
+    fn ray_with_unit_direction() {
+        let r = Ray::new(Tuple::point(1., 2., 3.), Tuple::vector(0., 3., 4.)).with_time(0.5);
+        let unit = r.with_unit_direction();
+
+        assert!(!r.has_unit_direction());
+        assert!(unit.has_unit_direction());
+        assert_eq!(unit.origin, r.origin);
+        assert_eq!(unit.direction, Tuple::vector(0., 0.6, 0.8));
+        assert_eq!(unit.time, 0.5);
     }
 
     #[should_panic]
@@ -56,6 +124,16 @@ mod tests {
         Ray::new(origin, direction);
     }
 
+    #[test]
+    fn creating_invalid_ray_without_panicking() {
+        let invalid_origin = Ray::try_new(Tuple::vector(1., 2., 3.), Tuple::vector(4., 5., 6.));
+        let invalid_direction = Ray::try_new(Tuple::point(1., 2., 3.), Tuple::point(4., 5., 6.));
+
+        assert!(matches!(invalid_origin, Err(Error::InvalidRay("origin should be a point"))));
+        assert!(matches!(invalid_direction, Err(Error::InvalidRay("direction should be a vector"))));
+        assert_eq!(Ray::try_new(Tuple::point(1., 2., 3.), Tuple::vector(4., 5., 6.)).unwrap().direction, Tuple::vector(4., 5., 6.));
+    }
+
     #[test]
     fn computing_point_from_distance()
     {
@@ -0,0 +1,225 @@
+use super::camera::Camera;
+use super::canvas::Canvas;
+use super::color::{Color, BLACK, WHITE};
+use super::intersection::Intersections;
+use super::light::PointLight;
+use super::material::Material;
+use super::ray::Ray;
+use super::tuple::Tuple;
+use super::world::{World, MAX_REFLECTION_DEPTH};
+use super::EPSILON;
+
+// A slow but simple renderer of what Camera::render should give, for checking the fast paths
+// (the bvh, shadow rays that stop at the first occluder, material overrides) against on small
+// canonical scenes. Every ray is tested against every object, shadows are found from the
+// closest hit towards the light, and each pixel averages many uniformly random samples.
+// Covers Phong lighting from the point light, the ambient light of the world, shadows, mirror
+// reflections and the environment seen by rays that miss, but not environment lighting or
+// refraction.
+pub struct ReferenceIntegrator {
+    pub samples_per_pixel: usize,
+    pub max_depth: usize
+}
+
+impl Default for ReferenceIntegrator {
+    fn default() -> Self {
+        Self { samples_per_pixel: 256, max_depth: MAX_REFLECTION_DEPTH }
+    }
+}
+
+impl ReferenceIntegrator {
+    pub fn render(&self, camera: &Camera, world: &World) -> Canvas {
+        let mut image = Canvas::new(camera.hsize(), camera.vsize());
+        for py in 0..camera.vsize() {
+            for px in 0..camera.hsize() {
+                // From the seed and frame of the camera, like its own samples, but a sequence of
+                // its own so that the positions differ from those of the camera's sampler
+                let mut rng = camera.sample_id(px, py, 0).rng();
+                let mut color = BLACK;
+                for _ in 0..self.samples_per_pixel {
+                    if let Some(r) = camera.try_ray_for_sample(px, py, rng.next_f64(), rng.next_f64()) {
+                        color += self.color_at(world, r);
+                    }
+                }
+                image.write_pixel(px, py, color * (1. / self.samples_per_pixel as f64));
+            }
+        }
+        image
+    }
+
+    pub fn color_at(&self, world: &World, ray: Ray) -> Color {
+        self.color_at_depth(world, ray, self.max_depth)
+    }
+
+    fn color_at_depth(&self, world: &World, ray: Ray, remaining: usize) -> Color {
+        let xs = all_intersections(world, ray);
+        let hit = match xs.hit() {
+            Some(h) => h,
+            None => return world.environment.as_ref().map_or(BLACK, |e| e.color_in_direction(ray.direction))
+        };
+        // The hit is worked out here rather than with prepare_computations, and the surface
+        // shaded rather than with Material::lighting, so that a bug in either doesn't show up
+        // on both sides of a comparison
+        let point = ray.position(hit.t);
+        let eyev = -ray.direction.normalize();
+        let mut normalv = hit.object.normal_at_time(point, ray.time);
+        if normalv.dot(&eyev) < 0. {
+            normalv = -normalv;
+        }
+        // A fixed offset, as in the book, is enough for the unit sized scenes this is used on
+        let over_point = point + normalv * EPSILON;
+        let material = world.settings.resolve_material(hit.object.material());
+        let color = material.color_at_hit(hit.object, point, hit.primitive.as_ref());
+        let light = match world.light {
+            Some(l) => l,
+            None => return BLACK
+        };
+        let to_light = light.position - over_point;
+        let lightv = to_light.normalize();
+        let light_transmission = if hit.object.material().receives_shadow {
+            shadow_transmission(world, Ray::new(over_point, lightv).with_time(ray.time), to_light.magnitude())
+        } else {
+            WHITE
+        };
+        let surface = phong(&material, color, &light, point, eyev, normalv) * light_transmission
+            + color * light.intensity_at(point) * material.ambient
+            + material.emissive.unwrap_or(BLACK)
+            + color * world.ambient_light;
+        let reflected = match material.reflective {
+            Some(r) if remaining > 0 && r != BLACK => {
+                let d = ray.direction;
+                let reflectv = (d - normalv * 2. * d.dot(&normalv)).normalize();
+                self.color_at_depth(world, Ray::new(over_point, reflectv).with_time(ray.time), remaining - 1) * r
+            },
+            _ => BLACK
+        };
+        surface + reflected
+    }
+}
+
+// The diffuse and specular terms of the Phong model from the point light, as if nothing was in
+// the way
+fn phong(material: &Material, color: Color, light: &PointLight, point: Tuple, eyev: Tuple, normalv: Tuple) -> Color {
+    let intensity = light.intensity_at(point);
+    let lightv = (light.position - point).normalize();
+    let cos_incidence = lightv.dot(&normalv);
+    if cos_incidence < 0. {
+        return BLACK;
+    }
+    let diffuse = color * intensity * (material.diffuse * cos_incidence);
+    // The light mirrored in the surface, towards the eye or not
+    let mirrored = normalv * 2. * cos_incidence - lightv;
+    let cos_reflection = mirrored.dot(&eyev);
+    if cos_reflection <= 0. {
+        return diffuse;
+    }
+    diffuse + intensity * (material.specular * cos_reflection.powf(material.shininess))
+}
+
+fn all_intersections(world: &World, ray: Ray) -> Intersections<'_> {
+    let mut xs = Intersections::empty();
+    for o in world.objects() {
+        xs.extend(o.intersect(ray));
+    }
+    xs
+}
+
+// Every object between the start of ray and distance lets through its transparency, tinted
+// by its color
+fn shadow_transmission(world: &World, ray: Ray, distance: f64) -> Color {
+    world.objects().iter()
+        .filter(|o| o.material().casts_shadow && o.intersect(ray).iter().any(|i| i.t >= 0. && i.t < distance))
+        .map(|o| {
+            let material = world.settings.resolve_material(o.material());
+            material.color * material.transparency
+        })
+        .fold(WHITE, |transmission, t| transmission * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::FRAC_PI_3;
+    use crate::color::WHITE;
+    use crate::environment::Environment;
+    use crate::matrix::Matrix;
+    use crate::plane::Plane;
+    use crate::prepared_scene::PreparedScene;
+    use crate::sampler::StratifiedSampler;
+    use crate::sphere::Sphere;
+
+    fn camera() -> Camera {
+        let view = Matrix::view_transform(Tuple::point(0., 1.5, -5.), Tuple::point(0., 0.5, 0.), Tuple::vector(0., 1., 0.));
+        Camera::new(12, 9, rad(FRAC_PI_3), Some(view))
+    }
+
+    // A sphere casting a shadow on a floor
+    fn shadow_scene() -> World {
+        let floor = Plane::new_boxed(None, None);
+        let sphere = Sphere::new_boxed(
+            Some(Material::new(Color::new(0.8, 0.3, 0.3), 0.1, 0.7, 0.3, 50., None)),
+            Some(Matrix::translation(0., 1., 0.)));
+        World::new(Some(PointLight::new(Tuple::point(-5., 8., -5.), WHITE)), vec![floor, sphere])
+    }
+
+    // Mirrors facing each other, reflecting to the full depth, with a sky behind
+    fn mirror_scene() -> World {
+        let mirror = Material { reflective: Some(Color::new(0.8, 0.8, 0.9)), ..Material::new(BLACK, 0., 0.2, 0.9, 200., None) };
+        let mut w = World::default_world();
+        w.add_object(Plane::new_boxed(Some(mirror.clone()), Some(Matrix::translation(0., -1., 0.))));
+        w.add_object(Sphere::new_boxed(Some(mirror), Some(Matrix::translation(2., 0.5, 0.5) * Matrix::scaling(0.7, 0.7, 0.7))));
+        w.environment = Some(Environment::SkyGradient { horizon: Color::new(0.9, 0.9, 1.), zenith: Color::new(0.3, 0.5, 1.) });
+        w
+    }
+
+    fn scenes() -> Vec<World> {
+        let mut clay = mirror_scene();
+        clay.settings.clay_mode = true;
+        vec![World::default_world(), shadow_scene(), mirror_scene(), clay]
+    }
+
+    #[test]
+    fn fast_shading_matches_reference_at_pixel_centers() {
+        let c = camera();
+        let reference = ReferenceIntegrator::default();
+        for world in scenes() {
+            let scene = PreparedScene::new(world);
+            for py in 0..c.vsize() {
+                for px in 0..c.hsize() {
+                    let ray = c.ray_for_pixel(px, py);
+                    assert_eq!(scene.world().color_at(ray), reference.color_at(scene.world(), ray), "pixel ({}, {})", px, py);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn anti_aliased_render_converges_to_reference() {
+        let mut c = camera();
+        c.sampler = StratifiedSampler::new_boxed(8);
+        let reference = ReferenceIntegrator::default();
+        for world in scenes() {
+            let expected = reference.render(&c, &world);
+            let diff = c.render(world).diff(&expected);
+
+            assert!(diff.mean_error.max_component() < 0.01, "mean error {:?}", diff.mean_error);
+            // Pixels on an edge next to a highlight are still noisy with 256 random samples
+            assert!(diff.approx_eq(0.2), "max error {:?}", diff.max_error);
+        }
+    }
+
+    #[test]
+    fn reference_noise_follows_camera_seed() {
+        let mut c = camera();
+        let reference = ReferenceIntegrator { samples_per_pixel: 4, ..ReferenceIntegrator::default() };
+        let world = shadow_scene();
+        let first = reference.render(&c, &world);
+        let again = reference.render(&c, &world);
+        c.seed = 5;
+        let other_seed = reference.render(&c, &world);
+
+        assert_eq!(first.diff(&again).largest_error(), 0.);
+        assert!(first.diff(&other_seed).largest_error() > 0.);
+    }
+}
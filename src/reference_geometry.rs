@@ -0,0 +1,100 @@
+use super::color::{Color, RED, GREEN, BLUE};
+use super::matrix::Matrix;
+use super::material::{Material, DEFAULT_AMBIENT, DEFAULT_DIFFUSE, DEFAULT_SHININESS};
+use super::pattern::GridPattern;
+use super::plane::Plane;
+use super::sphere::Sphere;
+use super::shape::BoxShape;
+
+// Debug geometry for finding your way around a new scene: a grid on the ground plane and colored
+// markers along the axes (x red, y green, z blue, pointing towards the positive direction).
+// There is no group shape yet, so the helpers return plain shapes to add to World::objects.
+
+pub const GRID_LINE_COLOR: Color = Color { r: 0.7, g: 0.7, b: 0.7 };
+pub const GRID_BACKGROUND_COLOR: Color = Color { r: 0.2, g: 0.2, b: 0.2 };
+// Width of the grid lines, as a fraction of the grid spacing
+pub const GRID_LINE_WIDTH: f64 = 0.04;
+// Thickness of the axis markers, as a fraction of their length
+pub const AXIS_THICKNESS: f64 = 0.02;
+
+// The xz plane with grid lines every spacing units
+pub fn ground_grid(spacing: f64) -> BoxShape {
+    let scale = Matrix::scaling(spacing, spacing, spacing);
+    let pattern = GridPattern::new_boxed(GRID_LINE_COLOR, GRID_BACKGROUND_COLOR, GRID_LINE_WIDTH, Some(scale));
+    let m = Material::new(GRID_BACKGROUND_COLOR, DEFAULT_AMBIENT, DEFAULT_DIFFUSE, 0., DEFAULT_SHININESS, Some(pattern));
+    Plane::new_boxed(Some(m), None)
+}
+
+// Axis markers are stretched spheres reaching from the origin to length along each positive
+// axis. They are shaded by their ambient color only, so that they stay visible in shadow.
+pub fn axis_markers(length: f64) -> Vec<BoxShape> {
+    let half = length / 2.;
+    let thickness = length * AXIS_THICKNESS;
+    let marker = |color: Color, transform: Matrix| {
+        let m = Material::new(color, 1., 0., 0., DEFAULT_SHININESS, None);
+        Sphere::new_boxed(Some(m), Some(transform))
+    };
+    vec![
+        marker(RED, Matrix::translation(half, 0., 0.) * Matrix::scaling(half, thickness, thickness)),
+        marker(GREEN, Matrix::translation(0., half, 0.) * Matrix::scaling(thickness, half, thickness)),
+        marker(BLUE, Matrix::translation(0., 0., half) * Matrix::scaling(thickness, thickness, half))]
+}
+
+// Ground grid and axis markers together
+pub fn reference_geometry(spacing: f64, axis_length: f64) -> Vec<BoxShape> {
+    let mut shapes = vec![ground_grid(spacing)];
+    shapes.extend(axis_markers(axis_length));
+    shapes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::WHITE;
+    use crate::tuple::Tuple;
+    use crate::ray::Ray;
+    use crate::light::PointLight;
+    use crate::world::World;
+
+    fn reference_world() -> World {
+        let light = Some(PointLight::new(Tuple::point(-10., 10., -10.), WHITE));
+        World::new(light, reference_geometry(1., 4.))
+    }
+
+    #[test]
+    fn reference_geometry_has_grid_and_three_axes() {
+        assert_eq!(reference_geometry(1., 4.).len(), 4);
+    }
+
+    #[test]
+    fn axis_markers_reach_along_positive_axes() {
+        let axes = axis_markers(4.);
+        let down = Ray::new(Tuple::point(3.9, 10., 0.), Tuple::vector(0., -1., 0.));
+        let left = Ray::new(Tuple::point(-10., 3.9, 0.), Tuple::vector(1., 0., 0.));
+        let negative = Ray::new(Tuple::point(-3., 10., 0.), Tuple::vector(0., -1., 0.));
+
+        assert!(!axes[0].intersect(down).is_empty());
+        assert!(!axes[1].intersect(left).is_empty());
+        assert!(axes[0].intersect(negative).is_empty());
+    }
+
+    #[test]
+    fn axis_markers_are_colored_by_axis() {
+        let w = reference_world();
+        let x = Ray::new(Tuple::point(2., 10., 0.), Tuple::vector(0., -1., 0.));
+        let z = Ray::new(Tuple::point(0., 10., 2.), Tuple::vector(0., -1., 0.));
+
+        assert_eq!(w.color_at(x), RED);
+        assert_eq!(w.color_at(z), BLUE);
+    }
+
+    #[test]
+    fn ground_grid_shows_lines_at_spacing() {
+        let grid = ground_grid(2.);
+        let m = grid.material();
+        let pattern = m.pattern.as_ref().unwrap();
+
+        assert_eq!(pattern.pattern_at_shape(&*grid, Tuple::point(4., 0., 1.)), GRID_LINE_COLOR);
+        assert_eq!(pattern.pattern_at_shape(&*grid, Tuple::point(3., 0., 1.)), GRID_BACKGROUND_COLOR);
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+
+use super::canvas::{Canvas, UNRENDERED_COLOR};
+use super::color::Color;
+
+pub const DEFAULT_TILE_SIZE: usize = 32;
+
+const MAGIC: &[u8] = b"RTCHECKPOINT 2\n";
+// Without the camera line
+const MAGIC_V1: &[u8] = b"RTCHECKPOINT 1\n";
+// Far beyond any image rendered in one go, a header asking for more is taken to be corrupt
+// rather than allocating gigabytes for it
+const MAX_PIXELS: usize = 1 << 26;
+const MAX_LINE_LENGTH: usize = 256;
+// Any character takes at most 4 bytes in UTF-8, so names this long always fit on a line
+const MAX_CAMERA_NAME_LENGTH: usize = MAX_LINE_LENGTH / 4;
+
+// A rectangle of pixels rendered in one go, clipped to the image
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tile {
+    pub index: usize,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+// The state of a tiled render, so that a long render that is stopped can continue where it was
+// instead of starting over. Holds which tiles are done and their finished pixels, tiles are
+// either done or not rendered at all. It doesn't know the scene, and of the camera only the name
+// it is given, resume with the same ones or the image will be a mix.
+pub struct RenderCheckpoint {
+    tile_size: usize,
+    camera: String,
+    completed: Vec<bool>,
+    image: Canvas
+}
+
+impl RenderCheckpoint {
+    pub fn new(width: usize, height: usize, tile_size: usize) -> Self {
+        let tile_size = tile_size.max(1);
+        let tiles = width.div_ceil(tile_size) * height.div_ceil(tile_size);
+        Self { tile_size, camera: String::new(), completed: vec![false; tiles], image: Canvas::new_filled(width, height, UNRENDERED_COLOR) }
+    }
+
+    // Names the camera the render is for, so that resuming can tell if it is another one. Line
+    // breaks are replaced by spaces, since the name is stored on a line of its own, and names
+    // longer than MAX_CAMERA_NAME_LENGTH characters are cut short.
+    pub fn with_camera(self, camera: &str) -> Self {
+        let camera = camera.chars().take(MAX_CAMERA_NAME_LENGTH).map(|c| if c == '\n' || c == '\r' { ' ' } else { c }).collect();
+        Self { camera, ..self }
+    }
+
+    // Empty if not named, as for checkpoints saved before cameras were
+    pub fn camera(&self) -> &str {
+        &self.camera
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height
+    }
+
+    pub fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+
+    // Row by row from the top left corner
+    pub fn tiles(&self) -> impl Iterator<Item = Tile> + '_ {
+        let columns = self.width().div_ceil(self.tile_size);
+        (0..self.completed.len()).map(move |index| {
+            let (x, y) = ((index % columns) * self.tile_size, (index / columns) * self.tile_size);
+            Tile { index, x, y, width: self.tile_size.min(self.width() - x), height: self.tile_size.min(self.height() - y) }
+        })
+    }
+
+    pub fn remaining_tiles(&self) -> Vec<Tile> {
+        self.tiles().filter(|t| !self.completed[t.index]).collect()
+    }
+
+    pub fn completed_tiles(&self) -> usize {
+        self.completed.iter().filter(|&&c| c).count()
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.completed.len()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed.iter().all(|&c| c)
+    }
+
+    // Puts the rendered pixels of tile in place and marks it as done
+    pub fn complete_tile(&mut self, tile: &Tile, pixels: &Canvas) {
+        self.image.blit(pixels, tile.x, tile.y);
+        self.completed[tile.index] = true;
+    }
+
+    // Tiles not rendered yet are UNRENDERED_COLOR
+    pub fn image(&self) -> &Canvas {
+        &self.image
+    }
+
+    pub fn into_image(self) -> Canvas {
+        self.image
+    }
+
+    // A text line with the format and version, one with width, height and tile size, one with
+    // the name of the camera, then one byte per tile that is 1 when it is done, then the pixels as little endian f64 rgb triples.
+    // Colors are stored at full precision so that a resumed render is the same as an
+    // uninterrupted one.
+    pub fn write<W: Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(MAGIC)?;
+        writeln!(w, "{} {} {}", self.width(), self.height(), self.tile_size)?;
+        writeln!(w, "{}", self.camera)?;
+        let completed: Vec<u8> = self.completed.iter().map(|&c| c as u8).collect();
+        w.write_all(&completed)?;
+        for c in self.image.pixels() {
+            for v in [c.r, c.g, c.b] {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+        w.flush()
+    }
+
+    pub fn read<R: Read>(mut r: R) -> Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC && magic != MAGIC_V1 {
+            return Err(invalid_data("Not a render checkpoint"));
+        }
+        let header = read_line(&mut r)?;
+        let sizes: Vec<usize> = header.split(' ').map(|n| n.parse()).collect::<std::result::Result<_, _>>()
+            .map_err(|_| invalid_data(&format!("Invalid checkpoint header '{}'", header)))?;
+        if sizes.len() != 3 || sizes[2] == 0 {
+            return Err(invalid_data(&format!("Invalid checkpoint header '{}'", header)));
+        }
+        let pixels = sizes[0].checked_mul(sizes[1]);
+        let tiles = sizes[0].div_ceil(sizes[2]).checked_mul(sizes[1].div_ceil(sizes[2]));
+        if pixels.is_none_or(|p| p > MAX_PIXELS) || tiles.is_none_or(|t| t > MAX_PIXELS) {
+            return Err(invalid_data(&format!("Checkpoint of {}x{} pixels is too large", sizes[0], sizes[1])));
+        }
+        let camera = if magic == MAGIC { read_line(&mut r)? } else { String::new() };
+        let mut checkpoint = RenderCheckpoint::new(sizes[0], sizes[1], sizes[2]).with_camera(&camera);
+        let mut completed = vec![0; checkpoint.tile_count()];
+        r.read_exact(&mut completed)?;
+        checkpoint.completed = completed.iter().map(|&c| c != 0).collect();
+        let mut value = [0; 8];
+        let mut next = || -> Result<f64> {
+            r.read_exact(&mut value)?;
+            Ok(f64::from_le_bytes(value))
+        };
+        for (_, _, pixel) in checkpoint.image.enumerate_pixels_mut() {
+            *pixel = Color::new(next()?, next()?, next()?);
+        }
+        Ok(checkpoint)
+    }
+
+    // Written to a temporary file that then replaces path, so that being stopped while saving
+    // leaves the previous checkpoint intact
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        self.write(BufWriter::new(File::create(&temporary)?))?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        RenderCheckpoint::read(BufReader::new(File::open(path)?))
+    }
+}
+
+fn read_line<R: Read>(r: &mut R) -> Result<String> {
+    let mut line = vec![];
+    let mut byte = [0];
+    loop {
+        r.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+        if line.len() > MAX_LINE_LENGTH {
+            return Err(invalid_data("Checkpoint header is too long"));
+        }
+    }
+    String::from_utf8(line).map_err(|_| invalid_data("Invalid checkpoint header"))
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{RED, BLUE};
+
+    #[test]
+    fn tiles_cover_the_image() {
+        let checkpoint = RenderCheckpoint::new(70, 40, 32);
+        let tiles: Vec<Tile> = checkpoint.tiles().collect();
+
+        assert_eq!(checkpoint.tile_count(), 6);
+        assert_eq!(tiles[2], Tile { index: 2, x: 64, y: 0, width: 6, height: 32 });
+        assert_eq!(tiles[5], Tile { index: 5, x: 64, y: 32, width: 6, height: 8 });
+        assert_eq!(tiles.iter().map(|t| t.width * t.height).sum::<usize>(), 70 * 40);
+    }
+
+    #[test]
+    fn completing_tiles() {
+        let mut checkpoint = RenderCheckpoint::new(4, 2, 2);
+        let tile = checkpoint.tiles().nth(1).unwrap();
+        checkpoint.complete_tile(&tile, &Canvas::new_filled(2, 2, RED));
+
+        assert_eq!(checkpoint.completed_tiles(), 1);
+        assert!(!checkpoint.is_complete());
+        assert_eq!(checkpoint.remaining_tiles().iter().map(|t| t.index).collect::<Vec<usize>>(), vec![0]);
+        assert_eq!(checkpoint.image()[(2, 1)], RED);
+        assert_eq!(checkpoint.image()[(1, 1)], UNRENDERED_COLOR);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut checkpoint = RenderCheckpoint::new(5, 3, 2).with_camera("left");
+        let tile = checkpoint.tiles().next().unwrap();
+        checkpoint.complete_tile(&tile, &Canvas::new_filled(2, 2, Color::new(0.1, 1. / 3., 2.5)));
+        let file_name = format!("test_output_checkpoint_{}.ckpt", std::process::id());
+        checkpoint.save(&file_name).unwrap();
+        let loaded = RenderCheckpoint::load(&file_name);
+        std::fs::remove_file(&file_name).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!((loaded.width(), loaded.height(), loaded.tile_size()), (5, 3, 2));
+        assert_eq!(loaded.camera(), "left");
+        assert_eq!(loaded.completed, checkpoint.completed);
+        assert!(loaded.image().pixels().zip(checkpoint.image().pixels()).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+    }
+
+    #[test]
+    fn reading_checkpoint_without_camera() {
+        let mut v1 = b"RTCHECKPOINT 1\n1 1 1\n\x01".to_vec();
+        v1.extend([0.5f64, 0.25, 1.].iter().flat_map(|v| v.to_le_bytes()));
+        let checkpoint = RenderCheckpoint::read(&v1[..]).unwrap();
+
+        assert_eq!(checkpoint.camera(), "");
+        assert!(checkpoint.is_complete());
+        assert_eq!(checkpoint.image()[(0, 0)], Color::new(0.5, 0.25, 1.));
+    }
+
+    #[test]
+    fn camera_names_stay_on_one_line() {
+        let long = "x".repeat(1000);
+        let mut written = vec![];
+        RenderCheckpoint::new(1, 1, 1).with_camera("two\nlines").write(&mut written).unwrap();
+
+        assert_eq!(RenderCheckpoint::read(&written[..]).unwrap().camera(), "two lines");
+        assert_eq!(RenderCheckpoint::new(1, 1, 1).with_camera(&long).camera().len(), MAX_CAMERA_NAME_LENGTH);
+    }
+
+    #[test]
+    fn reading_invalid_checkpoints_fails() {
+        let mut truncated = vec![];
+        let mut checkpoint = RenderCheckpoint::new(2, 2, 2);
+        let tile = checkpoint.tiles().next().unwrap();
+        checkpoint.complete_tile(&tile, &Canvas::new_filled(2, 2, BLUE));
+        checkpoint.write(&mut truncated).unwrap();
+        truncated.truncate(truncated.len() - 1);
+
+        assert!(RenderCheckpoint::read(&b"P3\n2 2\n255\n"[..]).is_err());
+        assert_eq!(RenderCheckpoint::read(&b"RTCHECKPOINT 1\n2 x 2\n"[..]).err().unwrap().kind(), ErrorKind::InvalidData);
+        assert!(RenderCheckpoint::read(&truncated[..]).is_err());
+    }
+
+    #[test]
+    fn reading_oversized_checkpoint_fails_without_allocating() {
+        let huge = format!("RTCHECKPOINT 1\n{} {} 1\n", usize::MAX, 2);
+        let large = b"RTCHECKPOINT 1\n100000 100000 32\n";
+
+        assert_eq!(RenderCheckpoint::read(huge.as_bytes()).err().unwrap().kind(), ErrorKind::InvalidData);
+        assert_eq!(RenderCheckpoint::read(&large[..]).err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,153 @@
+use std::str::FromStr;
+
+use super::color::{Color, BLACK, WHITE};
+use super::world::ShadingComponents;
+use super::material_palette::MaterialPalette;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassKind {
+    // The final image, same as Camera::render
+    Beauty,
+    // White where the visible surface is in shadow
+    Shadow,
+    // Only the light arriving through reflections
+    Reflection,
+    // White where one of the listed objects (by ObjectId number) is visible
+    Mask(Vec<usize>),
+    // The material id of the visible object as a color, given the material id of each object by
+    // ObjectId number, see MaterialPalette
+    MaterialId(Vec<usize>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderPass {
+    pub name: String,
+    pub kind: PassKind
+}
+
+impl RenderPass {
+    pub fn new(name: &str, kind: PassKind) -> Self {
+        Self { name: name.to_string(), kind }
+    }
+
+    pub fn value(&self, shading: Option<&ShadingComponents>) -> Color {
+        match shading {
+            None => BLACK,
+            Some(sc) => match &self.kind {
+                PassKind::Beauty => sc.color(),
+                PassKind::Shadow => if sc.in_shadow { WHITE } else { BLACK },
+                PassKind::Reflection => sc.reflected,
+                PassKind::Mask(objects) => match sc.object {
+                    Some(id) if objects.contains(&id.number()) => WHITE,
+                    _ => BLACK
+                },
+                PassKind::MaterialId(ids) => match sc.object.and_then(|o| ids.get(o.number())) {
+                    Some(&id) => MaterialPalette::id_color(id),
+                    None => BLACK
+                }
+            }
+        }
+    }
+
+    // Ids can't be averaged over several samples, so id passes keep the first sample of a pixel
+    pub fn averages_samples(&self) -> bool {
+        !matches!(self.kind, PassKind::MaterialId(_))
+    }
+}
+
+// Parses "beauty", "shadow", "reflection" or "mask:<id>,<id>...". The pass is named after
+// its kind, e g "mask" for a mask pass.
+impl FromStr for RenderPass {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut parts = spec.trim().splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let kind = match (name, parts.next()) {
+            ("beauty", None) => PassKind::Beauty,
+            ("shadow", None) => PassKind::Shadow,
+            ("reflection", None) => PassKind::Reflection,
+            ("mask", Some(objects)) => {
+                let ids: Result<Vec<usize>, _> = objects.split(',').map(|i| i.trim().parse::<usize>()).collect();
+                PassKind::Mask(ids.map_err(|_| format!("Invalid object id in render pass '{}'", spec))?)
+            },
+            _ => return Err(format!("Unknown render pass '{}'", spec))
+        };
+        Ok(RenderPass::new(name, kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::ObjectId;
+
+    fn shading(in_shadow: bool, object_id: Option<usize>) -> ShadingComponents {
+        ShadingComponents {
+            surface: Color::new(0.1, 0.2, 0.3),
+            reflected: Color::new(0.3, 0.2, 0.1),
+            refracted: Color::new(0.1, 0.1, 0.1),
+            in_shadow,
+            object: object_id.map(ObjectId::from_number)
+        }
+    }
+
+    #[test]
+    fn beauty_pass_is_full_color() {
+        let pass = RenderPass::new("beauty", PassKind::Beauty);
+
+        assert_eq!(pass.value(Some(&shading(false, None))), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(pass.value(None), BLACK);
+    }
+
+    #[test]
+    fn shadow_pass_marks_shadowed_surfaces() {
+        let pass = RenderPass::new("shadow", PassKind::Shadow);
+
+        assert_eq!(pass.value(Some(&shading(true, None))), WHITE);
+        assert_eq!(pass.value(Some(&shading(false, None))), BLACK);
+    }
+
+    #[test]
+    fn reflection_pass_is_reflected_light() {
+        let pass = RenderPass::new("reflection", PassKind::Reflection);
+
+        assert_eq!(pass.value(Some(&shading(false, None))), Color::new(0.3, 0.2, 0.1));
+    }
+
+    #[test]
+    fn mask_pass_selects_objects() {
+        let pass = RenderPass::new("floor", PassKind::Mask(vec![0, 2]));
+
+        assert_eq!(pass.value(Some(&shading(false, Some(2)))), WHITE);
+        assert_eq!(pass.value(Some(&shading(false, Some(1)))), BLACK);
+        assert_eq!(pass.value(Some(&shading(false, None))), BLACK);
+    }
+
+    #[test]
+    fn material_id_pass_packs_ids() {
+        let pass = RenderPass::new("material_id", PassKind::MaterialId(vec![1, 2, 2]));
+
+        assert_eq!(pass.value(Some(&shading(false, Some(2)))), MaterialPalette::id_color(2));
+        assert_eq!(pass.value(Some(&shading(false, Some(3)))), BLACK);
+        assert_eq!(pass.value(Some(&shading(false, None))), BLACK);
+        assert!(!pass.averages_samples());
+        assert!(RenderPass::new("beauty", PassKind::Beauty).averages_samples());
+    }
+
+    #[test]
+    fn parse_render_passes() {
+        assert_eq!("beauty".parse(), Ok(RenderPass::new("beauty", PassKind::Beauty)));
+        assert_eq!(" shadow".parse(), Ok(RenderPass::new("shadow", PassKind::Shadow)));
+        assert_eq!("reflection".parse(), Ok(RenderPass::new("reflection", PassKind::Reflection)));
+        assert_eq!("mask:1, 3".parse(), Ok(RenderPass::new("mask", PassKind::Mask(vec![1, 3]))));
+    }
+
+    #[test]
+    fn parse_invalid_render_passes() {
+        assert!("glow".parse::<RenderPass>().is_err());
+        assert!("mask".parse::<RenderPass>().is_err());
+        assert!("mask:a".parse::<RenderPass>().is_err());
+        assert!("beauty:1".parse::<RenderPass>().is_err());
+    }
+}
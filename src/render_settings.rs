@@ -0,0 +1,154 @@
+use std::borrow::Cow;
+
+use super::color::Color;
+use super::material::{Material, DEFAULT_AMBIENT, DEFAULT_DIFFUSE, DEFAULT_SHININESS};
+use super::bvh::BvhOptions;
+
+// Debugging overrides applied to the material of every hit, so lighting and geometry can be
+// inspected independently of the materials in the scene
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RenderSettings {
+    // Replace all materials with a neutral, matte gray
+    pub clay_mode: bool,
+    pub disable_reflections: bool,
+    // Flat shading from the ambient term only, showing each object's color without lighting
+    pub ambient_only: bool,
+    // Log NaN or infinite intersection distances and colors, with the shape and ray involved.
+    // Affected pixels are painted NON_FINITE_COLOR. This checks the numbers rather than
+    // overriding materials.
+    pub detect_non_finite: bool,
+    // Like detect_non_finite, but panic at the first non finite number
+    pub halt_on_non_finite: bool,
+    // How World::build_bvh builds the tree of object bounds
+    pub bvh: BvhOptions,
+    // What to do about materials that give back more light than they receive, see
+    // Material::is_energy_conserving
+    pub energy_check: EnergyCheck
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum EnergyCheck {
+    #[default]
+    Off,
+    // Log a warning for each object with such a material when the scene is prepared
+    Warn,
+    // Warn, and render them with Material::normalize
+    Clamp
+}
+
+pub const CLAY_COLOR: Color = Color { r: 0.8, g: 0.8, b: 0.8 };
+
+impl RenderSettings {
+    pub fn checks_non_finite(&self) -> bool {
+        self.detect_non_finite || self.halt_on_non_finite
+    }
+
+    pub fn has_overrides(&self) -> bool {
+        self.clay_mode || self.disable_reflections || self.ambient_only
+    }
+
+    pub fn resolve_material<'a>(&self, material: &'a Material) -> Cow<'a, Material> {
+        let clamp = self.energy_check == EnergyCheck::Clamp && !material.is_energy_conserving();
+        if !self.has_overrides() && !clamp {
+            return Cow::Borrowed(material);
+        }
+        let mut resolved = if self.clay_mode {
+            Material::new(CLAY_COLOR, DEFAULT_AMBIENT, DEFAULT_DIFFUSE, 0., DEFAULT_SHININESS, None)
+        } else if clamp {
+            material.normalize()
+        } else {
+            material.clone()
+        };
+        if self.disable_reflections {
+            resolved.reflective = None;
+        }
+        if self.ambient_only {
+            resolved.ambient = 1.;
+            resolved.diffuse = 0.;
+            resolved.specular = 0.;
+        }
+        Cow::Owned(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{RED, WHITE};
+    use crate::pattern::StripePattern;
+
+    fn shiny_red_mirror() -> Material {
+        Material {
+            reflective: Some(WHITE),
+            pattern: Some(StripePattern::new_boxed(RED, WHITE, None)),
+            ..Material::new(RED, 0.2, 0.5, 0.9, 50., None)
+        }
+    }
+
+    #[test]
+    fn default_settings_leave_material_untouched() {
+        let settings = RenderSettings::default();
+        let m = shiny_red_mirror();
+
+        assert!(!settings.has_overrides());
+        assert!(matches!(settings.resolve_material(&m), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn non_finite_checks_are_not_material_overrides() {
+        let settings = RenderSettings { detect_non_finite: true, ..RenderSettings::default() };
+        let halt = RenderSettings { halt_on_non_finite: true, ..RenderSettings::default() };
+
+        assert!(settings.checks_non_finite() && halt.checks_non_finite());
+        assert!(!settings.has_overrides());
+        assert!(!RenderSettings::default().checks_non_finite());
+    }
+
+    #[test]
+    fn clay_mode_gives_neutral_diffuse_material() {
+        let settings = RenderSettings { clay_mode: true, ..RenderSettings::default() };
+        let material = shiny_red_mirror();
+        let m = settings.resolve_material(&material);
+
+        assert_eq!(m.color, CLAY_COLOR);
+        assert_eq!(m.specular, 0.);
+        assert_eq!(m.pattern, None);
+        assert_eq!(m.reflective, None);
+    }
+
+    #[test]
+    fn disable_reflections_keeps_rest_of_material() {
+        let settings = RenderSettings { disable_reflections: true, ..RenderSettings::default() };
+        let material = shiny_red_mirror();
+        let m = settings.resolve_material(&material);
+
+        assert_eq!(m.reflective, None);
+        assert_eq!(m.color, RED);
+        assert_eq!(m.specular, 0.9);
+    }
+
+    #[test]
+    fn clamping_normalizes_only_materials_that_need_it() {
+        let settings = RenderSettings { energy_check: EnergyCheck::Clamp, ..RenderSettings::default() };
+        let warn = RenderSettings { energy_check: EnergyCheck::Warn, ..RenderSettings::default() };
+        let material = shiny_red_mirror();
+        let conserving = Material::default();
+
+        assert_eq!(*settings.resolve_material(&material), material.normalize());
+        assert!(matches!(warn.resolve_material(&material), Cow::Borrowed(_)));
+        assert!(matches!(settings.resolve_material(&conserving), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn ambient_only_keeps_color_but_not_lighting() {
+        let settings = RenderSettings { ambient_only: true, ..RenderSettings::default() };
+        let material = shiny_red_mirror();
+        let m = settings.resolve_material(&material);
+
+        assert_eq!(m.color, RED);
+        assert!(m.pattern.is_some());
+        assert_eq!(m.ambient, 1.);
+        assert_eq!(m.diffuse, 0.);
+        assert_eq!(m.specular, 0.);
+    }
+}
@@ -0,0 +1,212 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{debug, warn};
+
+use super::canvas::Canvas;
+use super::file_resolver::{FileResolver, FsResolver};
+use super::logging::PhaseTimer;
+
+// Identifies a loaded resource. The same file loaded with different options (e g a texture
+// decoded in another color space) is a different resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResourceKey {
+    pub path: PathBuf,
+    pub options: String
+}
+
+impl ResourceKey {
+    // Paths are canonicalized when the file exists, so that different ways of naming the same
+    // file share one resource
+    pub fn new<P: AsRef<Path>>(path: P, options: &str) -> Self {
+        let path = path.as_ref();
+        Self {
+            path: fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+            options: options.to_string()
+        }
+    }
+}
+
+// Loads each resource (image, environment map, mesh...) only once and hands out shared
+// references to it, so that any number of shapes and scenes can use the same resource. Paths
+// are found through a FileResolver, by default relative to the current directory.
+pub struct ResourceManager {
+    resources: HashMap<(TypeId, ResourceKey), Arc<dyn Any + Send + Sync>>,
+    resolver: Arc<dyn FileResolver>
+}
+
+impl Default for ResourceManager {
+    fn default() -> Self {
+        Self::with_resolver(FsResolver::default())
+    }
+}
+
+impl ResourceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // E g FsResolver::relative_to(scene_file) for the resources of a scene, or a MemoryResolver
+    pub fn with_resolver<R: FileResolver + 'static>(resolver: R) -> Self {
+        Self { resources: HashMap::new(), resolver: Arc::new(resolver) }
+    }
+
+    pub fn resolver(&self) -> Arc<dyn FileResolver> {
+        self.resolver.clone()
+    }
+
+    // Returns the cached resource of type T for path and options, or loads it with load, which
+    // is given the path as resolved by the resolver. Failed loads are not cached.
+    pub fn get_or_load<T, F>(&mut self, path: &str, options: &str, load: F) -> Result<Arc<T>>
+    where
+        T: Any + Send + Sync,
+        F: FnOnce(&Path) -> Result<T>
+    {
+        let key = (TypeId::of::<T>(), ResourceKey { path: self.resolver.resolve(path), options: options.to_string() });
+        if let Some(resource) = self.resources.get(&key) {
+            debug!("reusing loaded resource {}", path);
+            return Ok(resource.clone().downcast::<T>().unwrap());
+        }
+        let timer = PhaseTimer::start("load");
+        let resource = match load(&key.1.path) {
+            Ok(r) => Arc::new(r),
+            Err(e) => {
+                warn!("failed to load {}: {}", path, e);
+                return Err(e);
+            }
+        };
+        timer.finish(&format!("path={}", path));
+        self.resources.insert(key, resource.clone());
+        Ok(resource)
+    }
+
+    // Images are used both as textures and as environment maps
+    pub fn load_image(&mut self, path: &str) -> Result<Arc<Canvas>> {
+        let resolver = self.resolver.clone();
+        self.get_or_load(path, "", move |p| Canvas::decode(p, &resolver.read(p)?))
+    }
+
+    // The resolved paths of the loaded resources, each once, e g for a SceneWatcher
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.resources.keys().map(|(_, key)| key.path.clone()).collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    // Forgets the resources loaded from path, resolved like by get_or_load, so that they are
+    // loaded again the next time they are asked for while the others stay cached
+    pub fn forget(&mut self, path: &Path) {
+        self.resources.retain(|(_, key), _| key.path != path);
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    // Forgets all resources. Resources still in use by shapes stay alive until they are dropped.
+    pub fn clear(&mut self) {
+        self.resources.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::RED;
+    use crate::file_resolver::MemoryResolver;
+
+    fn saved_image(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("raytracer_{}_{}.png", name, std::process::id()));
+        let mut c = Canvas::new(2, 2);
+        c.write_pixel(1, 1, RED);
+        c.save(path.to_str().unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn same_image_is_loaded_once() {
+        let path = saved_image("same_image_is_loaded_once");
+        let mut rm = ResourceManager::new();
+        let a = rm.load_image(path.to_str().unwrap()).unwrap();
+        let b = rm.load_image(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.pixel_at(1, 1), RED);
+        assert_eq!(rm.len(), 1);
+    }
+
+    #[test]
+    fn options_and_types_give_separate_resources() {
+        let mut rm = ResourceManager::new();
+        let loads = std::cell::Cell::new(0);
+        let load = |_: &Path| { loads.set(loads.get() + 1); Ok(loads.get()) };
+        let a = rm.get_or_load("mesh.obj", "", load).unwrap();
+        let b = rm.get_or_load("mesh.obj", "smooth", load).unwrap();
+        let c = rm.get_or_load("mesh.obj", "", |_| Ok("other type".to_string())).unwrap();
+        let d = rm.get_or_load("mesh.obj", "smooth", load).unwrap();
+
+        assert_eq!((*a, *b, *d), (1, 2, 2));
+        assert_eq!(*c, "other type");
+        assert_eq!(loads.get(), 2);
+        assert_eq!(rm.len(), 3);
+    }
+
+    #[test]
+    fn images_from_resolver() {
+        let path = saved_image("images_from_resolver");
+        let png = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut rm = ResourceManager::with_resolver(MemoryResolver::new().with_file("textures/red.png", png));
+        let a = rm.load_image("textures/red.png").unwrap();
+        let b = rm.load_image("./textures/red.png").unwrap();
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.pixel_at(1, 1), RED);
+        assert!(rm.load_image("red.png").is_err());
+    }
+
+    #[test]
+    fn forgotten_resources_are_loaded_again() {
+        let mut rm = ResourceManager::new();
+        let loads = std::cell::Cell::new(0);
+        let load = |_: &Path| { loads.set(loads.get() + 1); Ok(loads.get()) };
+        rm.get_or_load("a.obj", "", load).unwrap();
+        rm.get_or_load("a.obj", "smooth", load).unwrap();
+        rm.get_or_load("b.obj", "", load).unwrap();
+        let paths = rm.paths();
+        rm.forget(Path::new("a.obj"));
+
+        assert_eq!(paths, vec![PathBuf::from("a.obj"), PathBuf::from("b.obj")]);
+        assert_eq!(rm.len(), 1);
+        assert_eq!(*rm.get_or_load("a.obj", "", load).unwrap(), 4);
+        assert_eq!(*rm.get_or_load("b.obj", "", load).unwrap(), 3);
+    }
+
+    #[test]
+    fn failed_load_is_not_cached() {
+        let mut rm = ResourceManager::new();
+
+        assert!(rm.load_image("no_such_image.png").is_err());
+        assert!(rm.is_empty());
+    }
+
+    #[test]
+    fn cleared_resources_stay_alive_while_used() {
+        let mut rm = ResourceManager::new();
+        let a = rm.get_or_load("a", "", |_| Ok(42)).unwrap();
+        rm.clear();
+
+        assert!(rm.is_empty());
+        assert_eq!(*a, 42);
+    }
+}
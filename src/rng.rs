@@ -0,0 +1,56 @@
+// Small, fast pseudo random number generator (splitmix64) for the stochastic parts of rendering.
+// Seeded explicitly so that renders are repeatable.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.state)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// The splitmix64 output function, which scrambles the bits of z so that nearby inputs give
+// unrelated outputs
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+
+        let first = a.next_u64();
+        assert_eq!(first, b.next_u64());
+        assert_ne!(first, c.next_u64());
+    }
+
+    #[test]
+    fn uniform_numbers_cover_unit_interval() {
+        let mut rng = Rng::new(1);
+        let numbers: Vec<f64> = (0..100000).map(|_| rng.next_f64()).collect();
+        let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+
+        assert!(numbers.iter().all(|n| (0. ..1.).contains(n)));
+        assert!((mean - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+
+use super::rng::{mix, Rng};
+
+// Which sample of which pixel in which frame of a render, from which all the randomness of the
+// sample is derived by hashing rather than drawn from a running sequence. A sample is the same
+// no matter which thread renders it or in which order, on any machine, and the noise of an
+// animation only changes from frame to frame as much as the frame number makes it. Custom
+// integrators get their random numbers from rng, see Camera::sample_id.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SampleId {
+    // Another seed gives another, equally good, noise pattern
+    pub seed: u64,
+    pub frame: u64,
+    pub px: usize,
+    pub py: usize,
+    pub index: usize
+}
+
+impl SampleId {
+    // Sample index of pixel (px, py) in frame 0 with seed 0
+    pub fn new(px: usize, py: usize, index: usize) -> Self {
+        Self { seed: 0, frame: 0, px, py, index }
+    }
+
+    // The hash of all the fields. Only depends on wrapping 64 bit arithmetic, so it is the same
+    // on every platform and will stay the same between versions.
+    pub fn key(&self) -> u64 {
+        [self.frame, self.px as u64, self.py as u64, self.index as u64]
+            .iter()
+            .fold(mix(self.seed), |h, &v| mix(h.wrapping_add(0x9E37_79B9_7F4A_7C15) ^ v))
+    }
+
+    // Uniform in [0, 1), the same every time for the same sample and dimension. Each of the
+    // numbers a sampler needs for a sample, e g x, y and time, has a dimension of its own.
+    pub fn random_unit(&self, dimension: u64) -> f64 {
+        Rng::new(self.key() ^ dimension.wrapping_mul(0x27D4_EB2F_1656_67C5)).next_f64()
+    }
+
+    // A sequence of its own for the sample, for integrators that need any number of random
+    // numbers, e g path tracing. Independent of the numbers from random_unit.
+    pub fn rng(&self) -> Rng {
+        Rng::new(mix(!self.key()))
+    }
+}
+
+// A sampler decides where within a pixel the camera rays are shot. Samples are offsets in
+// [0, 1) x [0, 1) from the top left corner of the pixel. Send and Sync so that a camera can
+// render on several threads.
+pub trait Sampler: fmt::Debug + Send + Sync {
+    fn samples_per_pixel(&self) -> usize;
+    fn sample(&self, id: SampleId) -> (f64, f64);
+
+    // Where within the shutter interval a sample is taken, in the range [0, 1)
+    fn time_sample(&self, _id: SampleId) -> f64 {
+        0.5
+    }
+
+    // Where within the visible spectrum a sample is taken when rendering spectrally, in the
+    // range [0, 1). Unlike time, a sample in the middle would tint the whole image, so it is
+    // random by default.
+    fn wavelength_sample(&self, id: SampleId) -> f64 {
+        id.random_unit(3)
+    }
+}
+
+pub type BoxSampler = Box<dyn Sampler>;
+
+fn radical_inverse(base: usize, mut index: usize) -> f64 {
+    let inv_base = 1. / base as f64;
+    let mut factor = inv_base;
+    let mut result = 0.;
+    while index > 0 {
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inv_base;
+    }
+    result
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct CenterSampler;
+
+impl CenterSampler {
+    pub fn new_boxed() -> BoxSampler {
+        Box::new(CenterSampler)
+    }
+}
+
+impl Sampler for CenterSampler {
+    fn samples_per_pixel(&self) -> usize {
+        1
+    }
+
+    fn sample(&self, _id: SampleId) -> (f64, f64) {
+        (0.5, 0.5)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct JitteredSampler {
+    samples: usize
+}
+
+impl JitteredSampler {
+    pub fn new(samples: usize) -> Self {
+        Self { samples: samples.max(1) }
+    }
+
+    pub fn new_boxed(samples: usize) -> BoxSampler {
+        Box::new(Self::new(samples))
+    }
+}
+
+impl Sampler for JitteredSampler {
+    fn samples_per_pixel(&self) -> usize {
+        self.samples
+    }
+
+    fn sample(&self, id: SampleId) -> (f64, f64) {
+        (id.random_unit(0), id.random_unit(1))
+    }
+
+    fn time_sample(&self, id: SampleId) -> f64 {
+        id.random_unit(2)
+    }
+}
+
+// Divides the pixel into strata x strata cells and places one jittered sample in each cell
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StratifiedSampler {
+    strata: usize
+}
+
+impl StratifiedSampler {
+    pub fn new(strata: usize) -> Self {
+        Self { strata: strata.max(1) }
+    }
+
+    pub fn new_boxed(strata: usize) -> BoxSampler {
+        Box::new(Self::new(strata))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn samples_per_pixel(&self) -> usize {
+        self.strata * self.strata
+    }
+
+    fn sample(&self, id: SampleId) -> (f64, f64) {
+        let n = self.strata as f64;
+        let sx = (id.index % self.strata) as f64;
+        let sy = (id.index / self.strata) as f64;
+        ((sx + id.random_unit(0)) / n, (sy + id.random_unit(1)) / n)
+    }
+
+    // Stratified in time as well, so that every sample of the pixel sees a different part of
+    // the exposure
+    fn time_sample(&self, id: SampleId) -> f64 {
+        (id.index as f64 + id.random_unit(2)) / self.samples_per_pixel() as f64
+    }
+
+    // Spread evenly over the spectrum by the base 2 van der Corput sequence, shifted at random
+    // for each pixel. Stratifying by index like time would tie the wavelength to where in the
+    // pixel and when the sample is taken.
+    fn wavelength_sample(&self, id: SampleId) -> f64 {
+        (radical_inverse(2, id.index) + SampleId { index: 0, ..id }.random_unit(3)).fract()
+    }
+}
+
+// Low discrepancy samples from the base 2 and base 3 Halton sequences. Each pixel gets its own
+// random toroidal shift of the sequence so that neighbouring pixels don't share the same pattern.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HaltonSampler {
+    samples: usize
+}
+
+impl HaltonSampler {
+    pub fn new(samples: usize) -> Self {
+        Self { samples: samples.max(1) }
+    }
+
+    pub fn new_boxed(samples: usize) -> BoxSampler {
+        Box::new(Self::new(samples))
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn samples_per_pixel(&self) -> usize {
+        self.samples
+    }
+
+    fn sample(&self, id: SampleId) -> (f64, f64) {
+        let shift = SampleId { index: 0, ..id };
+        let x = radical_inverse(2, id.index + 1) + shift.random_unit(0);
+        let y = radical_inverse(3, id.index + 1) + shift.random_unit(1);
+        (x.fract(), y.fract())
+    }
+
+    fn time_sample(&self, id: SampleId) -> f64 {
+        (radical_inverse(5, id.index + 1) + SampleId { index: 0, ..id }.random_unit(2)).fract()
+    }
+
+    fn wavelength_sample(&self, id: SampleId) -> f64 {
+        (radical_inverse(7, id.index + 1) + SampleId { index: 0, ..id }.random_unit(3)).fract()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_samples_inside_pixel(sampler: &dyn Sampler) {
+        for py in 0..4 {
+            for px in 0..4 {
+                for i in 0..sampler.samples_per_pixel() {
+                    let id = SampleId::new(px, py, i);
+                    let (x, y) = sampler.sample(id);
+                    assert!((0. ..1.).contains(&x));
+                    assert!((0. ..1.).contains(&y));
+                    assert!((0. ..1.).contains(&sampler.time_sample(id)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn center_sampler_gives_single_center_sample() {
+        let s = CenterSampler;
+
+        assert_eq!(s.samples_per_pixel(), 1);
+        assert_eq!(s.sample(SampleId::new(3, 7, 0)), (0.5, 0.5));
+        assert_eq!(s.time_sample(SampleId::new(3, 7, 0)), 0.5);
+    }
+
+    #[test]
+    fn jittered_samples_are_inside_pixel_and_repeatable() {
+        let s = JitteredSampler::new(16);
+
+        assert_eq!(s.samples_per_pixel(), 16);
+        assert_samples_inside_pixel(&s);
+        assert_eq!(s.sample(SampleId::new(10, 20, 3)), s.sample(SampleId::new(10, 20, 3)));
+        assert_ne!(s.sample(SampleId::new(10, 20, 3)), s.sample(SampleId::new(11, 20, 3)));
+    }
+
+    #[test]
+    fn stratified_sampler_puts_one_sample_in_each_cell() {
+        let s = StratifiedSampler::new(4);
+
+        assert_eq!(s.samples_per_pixel(), 16);
+        assert_samples_inside_pixel(&s);
+        for i in 0..16 {
+            let (x, y) = s.sample(SampleId::new(5, 5, i));
+            assert_eq!((x * 4.) as usize, i % 4);
+            assert_eq!((y * 4.) as usize, i / 4);
+            assert_eq!((s.time_sample(SampleId::new(5, 5, i)) * 16.) as usize, i);
+        }
+    }
+
+    #[test]
+    fn stratified_wavelengths_spread_over_spectrum() {
+        let s = StratifiedSampler::new(4);
+        let mut strata: Vec<usize> = (0..16).map(|i| (s.wavelength_sample(SampleId::new(5, 5, i)) * 16.) as usize).collect();
+        strata.sort_unstable();
+
+        assert_eq!(strata, (0..16).collect::<Vec<usize>>());
+        assert_ne!(s.wavelength_sample(SampleId::new(5, 5, 0)), s.wavelength_sample(SampleId::new(6, 5, 0)));
+    }
+
+    #[test]
+    fn radical_inverse_mirrors_digits_around_decimal_point() {
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 2), 0.25);
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert_eq!(radical_inverse(3, 1), 1. / 3.);
+        assert_eq!(radical_inverse(3, 4), 1. / 3. + 1. / 9.);
+    }
+
+    #[test]
+    fn halton_samples_are_inside_pixel() {
+        let s = HaltonSampler::new(8);
+
+        assert_eq!(s.samples_per_pixel(), 8);
+        assert_samples_inside_pixel(&s);
+    }
+
+    #[test]
+    fn sample_ids_differ_in_every_field() {
+        let id = SampleId { seed: 1, frame: 2, px: 3, py: 4, index: 5 };
+        let others = [
+            SampleId { seed: 2, ..id },
+            SampleId { frame: 3, ..id },
+            SampleId { px: 4, ..id },
+            SampleId { py: 5, ..id },
+            SampleId { index: 6, ..id },
+            SampleId { px: 4, py: 3, ..id }];
+
+        assert_eq!(id.key(), SampleId { seed: 1, frame: 2, px: 3, py: 4, index: 5 }.key());
+        assert!(others.iter().all(|o| o.key() != id.key()));
+        assert_ne!(id.random_unit(0), id.random_unit(1));
+        assert_ne!(id.rng().next_f64(), id.random_unit(0));
+        assert_eq!(id.rng(), id.rng());
+    }
+
+    // Renders of the same scene have to give the same noise on every machine and with every
+    // version, so the hash must never change
+    #[test]
+    fn sample_ids_hash_to_fixed_values() {
+        assert_eq!(SampleId::new(0, 0, 0).key(), 0x2130_748A_AAC8_0268);
+        assert_eq!(SampleId { seed: 42, frame: 7, px: 640, py: 480, index: 15 }.key(), 0xABA5_6005_4845_D87D);
+    }
+}
@@ -0,0 +1,200 @@
+use std::f64::consts::{PI, FRAC_PI_4};
+
+use super::rng::Rng;
+use super::tuple::Tuple;
+
+// Maps uniform numbers u, v in [0, 1) (from an Rng or a Sampler) to points and directions with
+// the distributions needed by stochastic effects, e g path tracing, ambient occlusion, area
+// lights and depth of field. Directions are unit vectors.
+
+pub const UNIFORM_SPHERE_PDF: f64 = 1. / (4. * PI);
+pub const UNIFORM_HEMISPHERE_PDF: f64 = 1. / (2. * PI);
+
+// The golden angle in radians, the turn between consecutive fibonacci sphere points
+const GOLDEN_ANGLE: f64 = 2.399_963_229_728_653;
+
+// Two unit vectors that make a right handed orthonormal basis together with the unit vector normal
+pub fn orthonormal_basis(normal: Tuple) -> (Tuple, Tuple) {
+    let helper = if normal.x.abs() > 0.9 { Tuple::vector(0., 1., 0.) } else { Tuple::vector(1., 0., 0.) };
+    let tangent = normal.cross(&helper).normalize();
+    let bitangent = normal.cross(&tangent);
+    (tangent, bitangent)
+}
+
+// A point in the unit disc, uniformly distributed. The concentric mapping keeps neighboring
+// (u, v) close together on the disc, so stratified samples stay stratified.
+pub fn concentric_disc(u: f64, v: f64) -> (f64, f64) {
+    let a = 2. * u - 1.;
+    let b = 2. * v - 1.;
+    if a == 0. && b == 0. {
+        return (0., 0.);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, 2. * FRAC_PI_4 - FRAC_PI_4 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+pub fn uniform_sphere(u: f64, v: f64) -> Tuple {
+    let z = 1. - 2. * u;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * v;
+    Tuple::vector(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Uniformly distributed over the half of the sphere on the side of normal
+pub fn uniform_hemisphere(normal: Tuple, u: f64, v: f64) -> Tuple {
+    let up = u;
+    let r = (1. - up * up).max(0.).sqrt();
+    let phi = 2. * PI * v;
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    (tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * up).normalize()
+}
+
+// More likely close to normal in proportion to the cosine of the angle, which is how a matte
+// surface scatters light. Made by lifting a uniform point on the disc up onto the hemisphere.
+pub fn cosine_hemisphere(normal: Tuple, u: f64, v: f64) -> Tuple {
+    let (x, y) = concentric_disc(u, v);
+    let up = (1. - x * x - y * y).max(0.).sqrt();
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    (tangent * x + bitangent * y + normal * up).normalize()
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.) / PI
+}
+
+// The same distributions with u and v drawn from rng, for when there is no sampler to take
+// them from
+pub fn random_uniform_sphere(rng: &mut Rng) -> Tuple {
+    uniform_sphere(rng.next_f64(), rng.next_f64())
+}
+
+pub fn random_uniform_hemisphere(normal: Tuple, rng: &mut Rng) -> Tuple {
+    uniform_hemisphere(normal, rng.next_f64(), rng.next_f64())
+}
+
+pub fn random_cosine_hemisphere(normal: Tuple, rng: &mut Rng) -> Tuple {
+    cosine_hemisphere(normal, rng.next_f64(), rng.next_f64())
+}
+
+// Point index of count points spread evenly over the unit sphere along a golden angle spiral,
+// from the top (+y) to the bottom. Deterministic, for when an even spread matters more than
+// randomness.
+pub fn fibonacci_sphere(index: usize, count: usize) -> Tuple {
+    let y = 1. - (2. * index as f64 + 1.) / count as f64;
+    let r = (1. - y * y).max(0.).sqrt();
+    let phi = GOLDEN_ANGLE * index as f64;
+    Tuple::vector(r * phi.cos(), y, r * phi.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approx_eq;
+
+    fn directions<F: Fn(f64, f64) -> Tuple>(sample: F) -> Vec<Tuple> {
+        let mut rng = Rng::new(3);
+        (0..5000).map(|_| sample(rng.next_f64(), rng.next_f64())).collect()
+    }
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn orthonormal_basis_is_orthonormal() {
+        let normal = Tuple::vector(0.6, 0., 0.8);
+        let (t, b) = orthonormal_basis(normal);
+
+        assert!(approx_eq(t.magnitude(), 1.) && approx_eq(b.magnitude(), 1.));
+        assert!(approx_eq(t.dot(&normal), 0.) && approx_eq(b.dot(&normal), 0.) && approx_eq(t.dot(&b), 0.));
+    }
+
+    #[test]
+    fn concentric_disc_maps_square_onto_disc() {
+        assert_eq!(concentric_disc(0.5, 0.5), (0., 0.));
+        let (x, y) = concentric_disc(1., 0.5);
+        assert!(approx_eq(x, 1.) && approx_eq(y, 0.));
+        let (x, y) = concentric_disc(0.5, 0.);
+        assert!(approx_eq(x, 0.) && approx_eq(y, -1.));
+
+        let mut rng = Rng::new(5);
+        let radii: Vec<f64> = (0..5000).map(|_| {
+            let (x, y) = concentric_disc(rng.next_f64(), rng.next_f64());
+            (x * x + y * y).sqrt()
+        }).collect();
+        assert!(radii.iter().all(|&r| r <= 1. + 1e-12));
+        // Uniform over the disc, so the mean distance from the center is 2/3
+        assert!((mean(&radii) - 2. / 3.).abs() < 0.02);
+    }
+
+    #[test]
+    fn uniform_sphere_covers_sphere_evenly() {
+        let ds = directions(uniform_sphere);
+        let ys: Vec<f64> = ds.iter().map(|d| d.y).collect();
+        let zs: Vec<f64> = ds.iter().map(|d| d.z.abs()).collect();
+
+        assert!(ds.iter().all(|d| approx_eq(d.magnitude(), 1.)));
+        assert!(mean(&ys).abs() < 0.03);
+        assert!((mean(&zs) - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn uniform_hemisphere_stays_on_normal_side() {
+        let normal = Tuple::vector(0., 0.6, 0.8);
+        let cosines: Vec<f64> = directions(|u, v| uniform_hemisphere(normal, u, v)).iter().map(|d| d.dot(&normal)).collect();
+
+        assert!(cosines.iter().all(|&c| c >= 0.));
+        assert!((mean(&cosines) - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn cosine_weighted_directions_are_in_hemisphere_around_normal() {
+        let normal = Tuple::vector(0., 0.6, 0.8);
+        let cosines: Vec<f64> = directions(|u, v| cosine_hemisphere(normal, u, v)).iter().map(|d| d.dot(&normal)).collect();
+
+        assert!(cosines.iter().all(|&c| c >= 0.));
+        // The mean cosine of a cosine weighted distribution is 2/3
+        assert!((mean(&cosines) - 2. / 3.).abs() < 0.02);
+    }
+
+    #[test]
+    fn random_directions_take_two_numbers_from_rng() {
+        let normal = Tuple::vector(0., 1., 0.);
+        let mut rng = Rng::new(9);
+        let mut numbers = Rng::new(9);
+        let mut next = || (numbers.next_f64(), numbers.next_f64());
+
+        let (u, v) = next();
+        assert_eq!(random_uniform_sphere(&mut rng), uniform_sphere(u, v));
+        let (u, v) = next();
+        assert_eq!(random_uniform_hemisphere(normal, &mut rng), uniform_hemisphere(normal, u, v));
+        let (u, v) = next();
+        assert_eq!(random_cosine_hemisphere(normal, &mut rng), cosine_hemisphere(normal, u, v));
+    }
+
+    #[test]
+    fn pdfs_integrate_to_one() {
+        assert!(approx_eq(UNIFORM_SPHERE_PDF * 4. * PI, 1.));
+        assert!(approx_eq(UNIFORM_HEMISPHERE_PDF * 2. * PI, 1.));
+        assert!(approx_eq(cosine_hemisphere_pdf(1.), 1. / PI));
+        assert_eq!(cosine_hemisphere_pdf(-0.5), 0.);
+    }
+
+    #[test]
+    fn fibonacci_sphere_spreads_points_evenly() {
+        let count = 200;
+        let points: Vec<Tuple> = (0..count).map(|i| fibonacci_sphere(i, count)).collect();
+        let nearest = |i: usize| (0..count).filter(|&j| j != i).map(|j| (points[i] - points[j]).magnitude()).fold(f64::INFINITY, f64::min);
+        let spacing: Vec<f64> = (0..count).map(nearest).collect();
+        let smallest = spacing.iter().cloned().fold(f64::INFINITY, f64::min);
+        let largest = spacing.iter().cloned().fold(0., f64::max);
+
+        assert!(points.iter().all(|p| approx_eq(p.magnitude(), 1.)));
+        assert!(points[0].y > 0.99 && points[count - 1].y < -0.99);
+        assert!(largest < 2. * smallest);
+    }
+}
@@ -0,0 +1,233 @@
+use super::camera::Camera;
+use super::color::BLACK;
+use super::material::Material;
+use super::ray::Ray;
+use super::scene_summary::json_string;
+use super::world::{World, MAX_REFLECTION_DEPTH};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    // The scene won't render, or renders wrong
+    Error,
+    // Probably not what was meant
+    Warning
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LintKind {
+    // Whitted rendering needs a light
+    NoLight,
+    // Negative or non finite numbers in the material
+    InvalidMaterial,
+    // Reflects more light than it receives, see Material::is_energy_conserving
+    EnergyViolation,
+    // Black whatever the lighting, e g without ambient, diffuse, specular and emission
+    AlwaysBlack,
+    // Outside of the view of the camera, not seen in any reflection and casting no shadow on
+    // anything that is
+    Unreachable
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    pub kind: LintKind,
+    // Index into World::objects, None for issues with the whole scene
+    pub object: Option<usize>,
+    pub message: String
+}
+
+impl LintIssue {
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            LintKind::NoLight | LintKind::InvalidMaterial => Severity::Error,
+            _ => Severity::Warning
+        }
+    }
+}
+
+// Mistakes in a scene that are easy to make when building it by code, found without rendering
+// it. Objects are named by type and index, like in SceneSummary.
+pub struct LintReport {
+    pub issues: Vec<LintIssue>
+}
+
+impl LintReport {
+    // Reachability is checked with a ray through the center of every pixel of camera, so
+    // objects smaller than a pixel may be reported as unreachable
+    pub fn new(world: &World, camera: &Camera) -> Self {
+        let mut issues = vec![];
+        if world.light.is_none() {
+            issues.push(LintIssue { kind: LintKind::NoLight, object: None, message: "The scene has no light".to_string() });
+        }
+        let name = |i: usize| format!("{} {}", world.objects()[i].type_name(), i);
+        for (i, o) in world.objects().iter().enumerate() {
+            let m = world.settings.resolve_material(o.material());
+            let issue = |kind, message: &str| LintIssue { kind, object: Some(i), message: format!("{} {}", name(i), message) };
+            if !is_valid(&m) {
+                issues.push(issue(LintKind::InvalidMaterial, "has a material with negative or non finite numbers"));
+            } else if !m.is_energy_conserving() {
+                issues.push(issue(LintKind::EnergyViolation, &format!("reflects {:?} of the light it receives", m.reflectance())));
+            } else if m.reflectance() == BLACK && m.specular == 0. && m.emission() == BLACK {
+                issues.push(issue(LintKind::AlwaysBlack, "is black whatever the lighting"));
+            }
+        }
+        for i in unreachable_objects(world, camera) {
+            issues.push(LintIssue { kind: LintKind::Unreachable, object: Some(i),
+                message: format!("{} is neither in view, nor reflected, nor casting a visible shadow", name(i)) });
+        }
+        Self { issues }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.severity() == Severity::Error)
+    }
+
+    // One line per issue
+    pub fn to_text(&self) -> String {
+        self.issues.iter().map(|i| format!("{:?}: {}\n", i.severity(), i.message)).collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let issues: Vec<String> = self.issues.iter().map(|i| format!(
+            "{{\"severity\": {}, \"kind\": {}, \"object\": {}, \"message\": {}}}",
+            json_string(&format!("{:?}", i.severity())), json_string(&format!("{:?}", i.kind)),
+            i.object.map_or("null".to_string(), |o| o.to_string()), json_string(&i.message))).collect();
+        format!("{{\"errors\": {}, \"issues\": [{}]}}", self.has_errors(), issues.join(", "))
+    }
+}
+
+fn is_valid(m: &Material) -> bool {
+    let numbers = [m.ambient, m.diffuse, m.specular, m.shininess, m.transparency, m.refractive_index, m.color.r, m.color.g, m.color.b];
+    numbers.iter().all(|n| n.is_finite() && *n >= 0.)
+}
+
+// Follows the camera rays through their reflections. An object counts as reachable if any of
+// those rays passes through it, hidden or not, or if it is between a visible point and the
+// light. Hidden objects are left alone since they can be moved into view by an animation.
+fn unreachable_objects(world: &World, camera: &Camera) -> Vec<usize> {
+    let mut reachable = vec![false; world.objects().len()];
+    for y in 0..camera.vsize() {
+        for x in 0..camera.hsize() {
+            let mut ray = camera.try_ray_for_pixel(x, y);
+            for _ in 0..=MAX_REFLECTION_DEPTH {
+                let r = match ray {
+                    Some(r) => r,
+                    None => break
+                };
+                mark_passed(world, r, f64::INFINITY, &mut reachable);
+                let xs = world.intersect(r);
+                let comps = match xs.hit() {
+                    Some(h) => h.prepare_computations(r),
+                    None => break
+                };
+                if let Some(light) = world.light {
+                    let v = light.position - comps.over_point;
+                    mark_passed(world, Ray::new(comps.over_point, v).with_unit_direction(), v.magnitude(), &mut reachable);
+                }
+                let material = world.settings.resolve_material(comps.object.material());
+                ray = if material.is_reflective() { Some(Ray::new(comps.over_point, comps.reflectv)) } else { None };
+            }
+        }
+    }
+    (0..reachable.len()).filter(|&i| !reachable[i]).collect()
+}
+
+fn mark_passed(world: &World, ray: Ray, distance: f64, reachable: &mut [bool]) {
+    for (i, o) in world.objects().iter().enumerate() {
+        if !reachable[i] && o.intersect(ray).iter().any(|x| x.t >= 0. && x.t < distance) {
+            reachable[i] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_3};
+    use crate::color::{Color, WHITE};
+    use crate::light::PointLight;
+    use crate::matrix::Matrix;
+    use crate::plane::Plane;
+    use crate::sphere::Sphere;
+    use crate::tuple::{Tuple, ORIGO};
+
+    fn camera() -> Camera {
+        let view = Matrix::view_transform(Tuple::point(0., 0., -5.), ORIGO, Tuple::vector(0., 1., 0.));
+        Camera::new(20, 20, rad(FRAC_PI_3), Some(view))
+    }
+
+    fn kinds(report: &LintReport) -> Vec<(LintKind, Option<usize>)> {
+        report.issues.iter().map(|i| (i.kind, i.object)).collect()
+    }
+
+    #[test]
+    fn default_world_is_clean() {
+        let report = LintReport::new(&World::default_world(), &camera());
+
+        assert!(report.issues.is_empty());
+        assert!(!report.has_errors());
+        assert_eq!(report.to_json(), "{\"errors\": false, \"issues\": []}");
+    }
+
+    #[test]
+    fn objects_behind_camera_are_unreachable() {
+        let mut w = World::default_world();
+        w.add_object(Sphere::new_boxed(None, Some(Matrix::translation(0., 0., -10.))));
+        let report = LintReport::new(&w, &camera());
+
+        assert_eq!(kinds(&report), vec![(LintKind::Unreachable, Some(2))]);
+        assert_eq!(report.issues[0].severity(), Severity::Warning);
+        assert!(report.to_text().starts_with("Warning: Sphere 2 is neither in view"));
+    }
+
+    #[test]
+    fn reflected_hidden_and_shadowing_objects_are_reachable() {
+        let mirror = Material { reflective: Some(WHITE), ..Material::new(Color::new(0., 0., 0.), 0., 0., 0.9, 200., None) };
+        let mut w = World::default_world();
+        w.set_objects(vec![
+            // Facing the camera behind everything else
+            Plane::new_boxed(Some(mirror), Some(Matrix::translation(0., 0., 3.) * Matrix::rotation_x(rad(FRAC_PI_2)))),
+            // Behind the camera, but seen in the mirror
+            Sphere::new_boxed(None, Some(Matrix::translation(0., 0., -10.) * Matrix::scaling(3., 3., 3.))),
+            Sphere::new_boxed(None, Some(Matrix::translation(0., 0., 1.) * Matrix::scaling(0.5, 0.5, 0.5))),
+            // Hidden behind the sphere before it
+            Sphere::new_boxed(None, Some(Matrix::translation(0., 0., 2.) * Matrix::scaling(0.2, 0.2, 0.2)))]);
+        let mut lit_from_behind = World::default_world();
+        lit_from_behind.light = Some(PointLight::new(Tuple::point(0., 0., -20.), WHITE));
+        // Out of view between the spheres and the light
+        lit_from_behind.add_object(Sphere::new_boxed(None, Some(Matrix::translation(0., 0., -12.))));
+
+        assert_eq!(kinds(&LintReport::new(&w, &camera())), vec![]);
+        assert_eq!(kinds(&LintReport::new(&lit_from_behind, &camera())), vec![]);
+    }
+
+    #[test]
+    fn suspicious_materials() {
+        let mut w = World::default_world();
+        let invalid = Material { diffuse: -1., ..Material::default() };
+        let glowing = Material { ambient: 0.5, ..Material::default() };
+        let black = Material::new(Color::new(0., 0., 0.), 0.1, 0.9, 0., 200., None);
+        w.set_objects(vec![
+            Sphere::new_boxed(Some(invalid), None),
+            Sphere::new_boxed(Some(glowing), Some(Matrix::translation(1., 0., 0.))),
+            Sphere::new_boxed(Some(black), Some(Matrix::translation(-1., 0., 0.)))]);
+        let report = LintReport::new(&w, &camera());
+
+        assert_eq!(kinds(&report), vec![
+            (LintKind::InvalidMaterial, Some(0)),
+            (LintKind::EnergyViolation, Some(1)),
+            (LintKind::AlwaysBlack, Some(2))]);
+        assert!(report.has_errors());
+    }
+
+    #[test]
+    fn missing_light_is_an_error() {
+        let mut w = World::default_world();
+        w.light = None;
+        let report = LintReport::new(&w, &camera());
+
+        assert_eq!(kinds(&report), vec![(LintKind::NoLight, None)]);
+        assert!(report.to_json().starts_with("{\"errors\": true, \"issues\": [{\"severity\": \"Error\", \"kind\": \"NoLight\", \"object\": null"));
+    }
+}
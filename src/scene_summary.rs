@@ -0,0 +1,249 @@
+use super::camera::{Camera, RenderMode};
+use super::color::Color;
+use super::environment::Environment;
+use super::material::Material;
+use super::transform::Decomposition;
+use super::tuple::{Tuple, ORIGO};
+use super::world::World;
+
+// A human readable description of a scene, for reviewing scenes that were built by code rather
+// than by hand. Objects have no names of their own, so they are named by type and index.
+pub struct SceneSummary {
+    pub objects: Vec<ObjectSummary>,
+    pub lights: Vec<LightSummary>,
+    pub environment: Option<String>,
+    pub settings: String,
+    pub camera: Option<CameraSummary>
+}
+
+pub struct ObjectSummary {
+    pub name: String,
+    pub transform: Decomposition,
+    pub material: Material
+}
+
+pub struct LightSummary {
+    pub position: Tuple,
+    pub intensity: Color
+}
+
+pub struct CameraSummary {
+    pub hsize: usize,
+    pub vsize: usize,
+    // In radians, None for projections without one
+    pub field_of_view: Option<f64>,
+    pub position: Tuple,
+    pub direction: Tuple,
+    pub projection: String,
+    pub samples_per_pixel: usize,
+    pub render_mode: RenderMode
+}
+
+impl SceneSummary {
+    pub fn new(world: &World, camera: Option<&Camera>) -> Self {
+        let objects = world.objects().iter().enumerate().map(|(i, o)| ObjectSummary {
+            name: format!("{} {}", o.type_name(), i),
+            transform: o.transformation().decompose(),
+            material: o.material().clone()
+        }).collect();
+        let lights = world.light.iter().map(|l| LightSummary { position: l.position, intensity: l.intensity }).collect();
+        let environment = world.environment.as_ref().map(|e| match e {
+            Environment::SkyGradient { horizon, zenith } =>
+                format!("sky gradient from {} to {}", color_text(*horizon), color_text(*zenith)),
+            Environment::Equirectangular(image) =>
+                format!("equirectangular map {}x{}", image.width, image.height)
+        });
+        let camera = camera.map(|c| {
+            let inverse = c.inverse_transform();
+            CameraSummary {
+                hsize: c.hsize(),
+                vsize: c.vsize(),
+                field_of_view: c.field_of_view(),
+                position: inverse * ORIGO,
+                direction: (inverse * Tuple::vector(0., 0., -1.)).normalize(),
+                projection: format!("{:?}", c.projection()),
+                samples_per_pixel: c.sampler.samples_per_pixel(),
+                render_mode: c.render_mode
+            }
+        });
+        Self { objects, lights, environment, settings: format!("{:?}", world.settings), camera }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut s = String::from("# Scene\n");
+        if let Some(c) = &self.camera {
+            s += "\n## Camera\n\n";
+            s += &format!("- Size: {}x{}\n", c.hsize, c.vsize);
+            if let Some(f) = c.field_of_view {
+                s += &format!("- Field of view: {} degrees\n", number(f.to_degrees()));
+            }
+            s += &format!("- Position: {}\n", tuple_text(c.position));
+            s += &format!("- Direction: {}\n", tuple_text(c.direction));
+            s += &format!("- Projection: {}\n", c.projection);
+            s += &format!("- Samples per pixel: {}\n", c.samples_per_pixel);
+            s += &format!("- Render mode: {:?}\n", c.render_mode);
+        }
+        s += "\n## Lights\n\n";
+        if self.lights.is_empty() {
+            s += "None\n";
+        }
+        for l in &self.lights {
+            s += &format!("- Point light at {}, intensity {}\n", tuple_text(l.position), color_text(l.intensity));
+        }
+        s += &format!("\n## Environment\n\n{}\n", self.environment.as_deref().unwrap_or("None"));
+        s += &format!("\n## Render settings\n\n{}\n", self.settings);
+        s += "\n## Objects\n";
+        for o in &self.objects {
+            let t = &o.transform;
+            let m = &o.material;
+            s += &format!("\n### {}\n\n", o.name);
+            s += &format!("- Translation: {}\n", tuple_text(t.translation));
+            s += &format!("- Rotation: {} degrees\n", tuple_text(degrees(t.euler_angles())));
+            s += &format!("- Scale: {}\n", tuple_text(t.scale));
+            if t.sheared {
+                s += "- Sheared, rotation and scale are approximate\n";
+            }
+            s += &format!("- Color: {}\n", color_text(m.color));
+            s += &format!("- Ambient {}, diffuse {}, specular {}, shininess {}\n",
+                number(m.ambient), number(m.diffuse), number(m.specular), number(m.shininess));
+            if let Some(p) = &m.pattern {
+                s += &format!("- Pattern: {:?}\n", p);
+            }
+            if let Some(r) = m.reflective {
+                s += &format!("- Reflective: {}\n", color_text(r));
+            }
+            if let Some(e) = m.emissive {
+                s += &format!("- Emissive: {}\n", color_text(e));
+            }
+            if !m.casts_shadow {
+                s += "- Casts no shadow\n";
+            }
+            if !m.receives_shadow {
+                s += "- Receives no shadow\n";
+            }
+            if m.transparency > 0. {
+                s += &format!("- Transparency {}, refractive index {}\n", number(m.transparency), number(m.refractive_index));
+            }
+        }
+        s
+    }
+
+    pub fn to_json(&self) -> String {
+        let camera = match &self.camera {
+            None => "null".to_string(),
+            Some(c) => format!(
+                "{{\"hsize\": {}, \"vsize\": {}, \"field_of_view\": {}, \"position\": {}, \"direction\": {}, \"projection\": {}, \"samples_per_pixel\": {}, \"render_mode\": {}}}",
+                c.hsize, c.vsize, c.field_of_view.map_or("null".to_string(), |f| number(f.to_degrees())), tuple_json(c.position), tuple_json(c.direction),
+                json_string(&c.projection), c.samples_per_pixel, json_string(&format!("{:?}", c.render_mode)))
+        };
+        let lights: Vec<String> = self.lights.iter().map(|l| format!(
+            "{{\"position\": {}, \"intensity\": {}}}", tuple_json(l.position), color_json(l.intensity))).collect();
+        let objects: Vec<String> = self.objects.iter().map(|o| {
+            let t = &o.transform;
+            format!(
+                "{{\"name\": {}, \"translation\": {}, \"rotation\": {}, \"scale\": {}, \"sheared\": {}, \"material\": {}}}",
+                json_string(&o.name), tuple_json(t.translation), tuple_json(degrees(t.euler_angles())), tuple_json(t.scale), t.sheared,
+                material_json(&o.material))
+        }).collect();
+        format!(
+            "{{\"camera\": {}, \"lights\": [{}], \"environment\": {}, \"settings\": {}, \"objects\": [{}]}}",
+            camera, lights.join(", "), self.environment.as_deref().map_or("null".to_string(), json_string),
+            json_string(&self.settings), objects.join(", "))
+    }
+}
+
+// Rounded to 5 decimals, the precision of EPSILON
+fn number(n: f64) -> String {
+    format!("{}", (n * 1e5).round() / 1e5 + 0.)
+}
+
+fn degrees(rotation: Tuple) -> Tuple {
+    Tuple::vector(rotation.x.to_degrees(), rotation.y.to_degrees(), rotation.z.to_degrees())
+}
+
+fn tuple_text(t: Tuple) -> String {
+    format!("({}, {}, {})", number(t.x), number(t.y), number(t.z))
+}
+
+fn color_text(c: Color) -> String {
+    format!("rgb({}, {}, {})", number(c.r), number(c.g), number(c.b))
+}
+
+fn tuple_json(t: Tuple) -> String {
+    format!("[{}, {}, {}]", number(t.x), number(t.y), number(t.z))
+}
+
+fn color_json(c: Color) -> String {
+    format!("[{}, {}, {}]", number(c.r), number(c.g), number(c.b))
+}
+
+pub(crate) fn material_json(m: &Material) -> String {
+    let optional_color = |c: Option<Color>| c.map_or("null".to_string(), color_json);
+    format!(
+        "{{\"color\": {}, \"ambient\": {}, \"diffuse\": {}, \"specular\": {}, \"shininess\": {}, \"pattern\": {}, \"reflective\": {}, \"emissive\": {}, \"transparency\": {}, \"refractive_index\": {}, \"casts_shadow\": {}, \"receives_shadow\": {}}}",
+        color_json(m.color), number(m.ambient), number(m.diffuse), number(m.specular), number(m.shininess),
+        m.pattern.as_ref().map_or("null".to_string(), |p| json_string(&format!("{:?}", p))),
+        optional_color(m.reflective), optional_color(m.emissive), number(m.transparency), number(m.refractive_index),
+        m.casts_shadow, m.receives_shadow)
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::FRAC_PI_2;
+    use crate::matrix::Matrix;
+
+    #[test]
+    fn summary_lists_objects_and_lights() {
+        let summary = SceneSummary::new(&World::default_world(), None);
+
+        assert_eq!(summary.objects.len(), 2);
+        assert_eq!(summary.objects[1].name, "Sphere 1");
+        assert_eq!(summary.objects[1].transform.scale, Tuple::vector(0.5, 0.5, 0.5));
+        assert_eq!(summary.lights[0].position, Tuple::point(-10., 10., -10.));
+    }
+
+    #[test]
+    fn markdown_describes_scene() {
+        let transform = Matrix::view_transform(Tuple::point(0., 0., -5.), ORIGO, Tuple::vector(0., 1., 0.));
+        let camera = Camera::new(160, 120, rad(FRAC_PI_2), Some(transform));
+        let md = SceneSummary::new(&World::default_world(), Some(&camera)).to_markdown();
+
+        assert!(md.contains("- Size: 160x120\n"));
+        assert!(md.contains("- Field of view: 90 degrees\n"));
+        assert!(md.contains("- Position: (0, 0, -5)\n"));
+        assert!(md.contains("- Direction: (0, 0, 1)\n"));
+        assert!(md.contains("### Sphere 0\n"));
+        assert!(md.contains("- Color: rgb(0.8, 1, 0.6)\n"));
+        assert!(md.contains("- Point light at (-10, 10, -10), intensity rgb(1, 1, 1)\n"));
+    }
+
+    #[test]
+    fn json_describes_scene() {
+        let json = SceneSummary::new(&World::default_world(), None).to_json();
+
+        assert!(json.starts_with("{\"camera\": null, \"lights\": [{\"position\": [-10, 10, -10], \"intensity\": [1, 1, 1]}]"));
+        assert!(json.contains("{\"name\": \"Sphere 1\", \"translation\": [0, 0, 0], \"rotation\": [0, 0, 0], \"scale\": [0.5, 0.5, 0.5], \"sheared\": false"));
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\n\"");
+    }
+}
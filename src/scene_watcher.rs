@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use log::debug;
+
+use super::resource_manager::ResourceManager;
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// What is compared to tell that a file has changed. The length catches most edits made within
+// the resolution of the modification time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64
+}
+
+fn stamp(path: &Path) -> Option<FileStamp> {
+    fs::metadata(path).ok().map(|m| FileStamp { modified: m.modified().ok(), len: m.len() })
+}
+
+// Keeps an eye on a scene file and the assets it refers to, for re-rendering whenever one of
+// them is saved. Polls the files rather than relying on notifications from the system, which
+// differ from platform to platform. Files that don't exist are watched for being created, and
+// files that are removed count as changed.
+#[derive(Debug, Clone, Default)]
+pub struct SceneWatcher {
+    files: Vec<(PathBuf, Option<FileStamp>)>
+}
+
+impl SceneWatcher {
+    pub fn new<P: AsRef<Path>>(scene_file: P) -> Self {
+        let mut watcher = Self::default();
+        watcher.watch(scene_file);
+        watcher
+    }
+
+    // Files already watched are left as they are
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        if !self.files.iter().any(|(p, _)| p == path) {
+            self.files.push((path.to_path_buf(), stamp(path)));
+        }
+    }
+
+    // The files the resources were loaded from, e g the textures of the scene once it has been
+    // rendered
+    pub fn watch_resources(&mut self, resources: &ResourceManager) {
+        for path in resources.paths() {
+            self.watch(path);
+        }
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(p, _)| p.as_path())
+    }
+
+    // The files that have changed since they were last checked
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![];
+        for (path, last) in self.files.iter_mut() {
+            let now = stamp(path);
+            if now != *last {
+                debug!("{} changed", path.display());
+                *last = now;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+
+    // Blocks until a file changes, checking every poll_interval, and returns the changed files.
+    // Returns nothing if stop returns true first, e g after Ctrl-C.
+    pub fn wait_for_change<F: Fn() -> bool>(&mut self, poll_interval: Duration, stop: F) -> Vec<PathBuf> {
+        loop {
+            let changed = self.changed();
+            if !changed.is_empty() || stop() {
+                return changed;
+            }
+            thread::sleep(poll_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raytracer_{}_{}.txt", name, std::process::id()))
+    }
+
+    #[test]
+    fn edited_created_and_removed_files_are_changed() {
+        let scene = temp_file("watched_scene");
+        let texture = temp_file("watched_texture");
+        fs::write(&scene, "sphere").unwrap();
+        let _ = fs::remove_file(&texture);
+        let mut watcher = SceneWatcher::new(&scene);
+        watcher.watch(&texture);
+        watcher.watch(&scene);
+        let unchanged = watcher.changed();
+        fs::write(&scene, "two spheres").unwrap();
+        fs::write(&texture, "wood").unwrap();
+        let edited = watcher.changed();
+        fs::remove_file(&scene).unwrap();
+        let removed = watcher.changed();
+        fs::remove_file(&texture).unwrap();
+
+        assert_eq!(watcher.files().count(), 2);
+        assert!(unchanged.is_empty());
+        assert_eq!(edited, vec![scene.clone(), texture]);
+        assert_eq!(removed, vec![scene]);
+    }
+
+    #[test]
+    fn waiting_stops_when_asked() {
+        let mut watcher = SceneWatcher::new(temp_file("never_written"));
+        let polls = Cell::new(0);
+        let changed = watcher.wait_for_change(Duration::from_millis(1), || { polls.set(polls.get() + 1); polls.get() == 3 });
+
+        assert!(changed.is_empty());
+        assert_eq!(polls.get(), 3);
+    }
+}
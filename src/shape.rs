@@ -1,29 +1,114 @@
 use std::any::Any;
 use std::fmt;
+use std::sync::Arc;
+
+use log::error;
 
 use super::tuple::Tuple;
 use super::ray::Ray;
 use super::intersection::Intersections;
 use super::material::Material;
-use super::matrix::{Matrix, IDENTITY_MATRIX};
+use super::matrix::{Matrix, InvertibleMatrix, IDENTITY_MATRIX};
+use super::transform::Motion;
+use super::bounding_box::BoundingBox;
+use super::error::Error;
+use super::tessellation::{Mesh, TessellationQuality};
 
-pub trait Shape: Any + fmt::Debug {
+pub trait Shape: Any + fmt::Debug + Send + Sync {
     fn box_clone(&self) -> BoxShape;
     fn box_eq(&self, other: &dyn Any) -> bool;
     fn as_any(&self) -> &dyn Any;
-    fn inner_intersect(&self, object_ray: Ray) -> Intersections;
+    fn inner_intersect(&self, object_ray: Ray) -> Intersections<'_>;
     fn inner_normal_at(&self, object_point: Tuple) -> Tuple;
     fn material(&self) -> &Material;
     fn transformation(&self) -> Matrix;
     fn inverse_transformation(&self) -> Matrix;
 
-    fn intersect(&self, world_ray: Ray) -> Intersections {
-        self.inner_intersect(world_ray.transform(self.inverse_transformation()))
+    // Replaces the transformation along with everything derived from it. Leaves the shape
+    // unchanged and returns an error if transform is not invertible. Shapes that can't be
+    // transformed once made keep this default, which always fails.
+    fn try_set_transform(&mut self, _transform: Matrix) -> Result<(), Error> {
+        Err(Error::FixedTransform(self.type_name()))
+    }
+
+    // Like try_set_transform, but panics on errors
+    fn set_transform(&mut self, transform: Matrix) {
+        self.try_set_transform(transform).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    // Shapes whose material is fixed once made keep this default, which panics
+    fn set_material(&mut self, _material: Material) {
+        panic!("A {} can't change its material after it has been made", self.type_name());
+    }
+
+    // The name of the shape type without module path, e g "Sphere", for listing scenes
+    fn type_name(&self) -> &'static str {
+        let full = std::any::type_name::<Self>();
+        full.rsplit("::").next().unwrap_or(full)
+    }
+
+    // In object space. Shapes that don't know their extent are treated as unbounded, which is
+    // always correct but means that no ray can skip them.
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::infinite()
+    }
+
+    // Triangles approximating the shape in object space, for exporting it. None for shapes that
+    // can't be tessellated, like infinite planes.
+    fn tessellate(&self, _quality: &TessellationQuality) -> Option<Mesh> {
+        None
+    }
+
+    // The bounds in the space of the parent (the world), covering the whole movement of a shape
+    // in motion
+    fn parent_space_bounds(&self) -> BoundingBox {
+        match self.motion() {
+            Some(m) => m.parent_space_bounds(&self.bounds()),
+            None => self.bounds().transform(&self.transformation())
+        }
+    }
+
+    // The transposed inverse, shapes that cache it should override this
+    fn normal_transformation(&self) -> Matrix {
+        self.inverse_transformation().transpose()
+    }
+
+    // Shapes that move during the exposure override this and the ones below, time is in the
+    // range [0, 1]
+    fn motion(&self) -> Option<&Motion> {
+        None
+    }
+
+    fn transformation_at(&self, _time: f64) -> Matrix {
+        self.transformation()
+    }
+
+    fn inverse_transformation_at(&self, _time: f64) -> Matrix {
+        self.inverse_transformation()
+    }
+
+    fn normal_transformation_at(&self, _time: f64) -> Matrix {
+        self.normal_transformation()
+    }
+
+    fn intersect(&self, world_ray: Ray) -> Intersections<'_> {
+        self.inner_intersect(world_ray.transform(self.inverse_transformation_at(world_ray.time)))
+    }
+
+    // Whether world_ray hits the shape at a t in [0, max_t), for shadow rays that only need to
+    // know if anything is in the way. Shapes that can answer without building and sorting all
+    // the intersections should override this.
+    fn occludes(&self, world_ray: Ray, max_t: f64) -> bool {
+        self.intersect(world_ray).iter().any(|i| i.t >= 0. && i.t < max_t)
     }
 
     fn normal_at(&self, world_point: Tuple) -> Tuple {
-        let object_normal = self.inner_normal_at(self.inverse_transformation() * world_point);
-        let mut world_normal = self.inverse_transformation().transpose() * object_normal;
+        self.normal_at_time(world_point, 0.)
+    }
+
+    fn normal_at_time(&self, world_point: Tuple, time: f64) -> Tuple {
+        let object_normal = self.inner_normal_at(self.inverse_transformation_at(time) * world_point);
+        let mut world_normal = self.normal_transformation_at(time) * object_normal;
         world_normal.w = 0.;
 
         world_normal.normalize()
@@ -31,22 +116,57 @@ pub trait Shape: Any + fmt::Debug {
 }
 
 pub type BoxShape = Box<dyn Shape>;
+// For shapes owned by a world, so that copies of the world share them
+pub type SharedShape = Arc<dyn Shape>;
 
 pub fn inverse_transform_parameter(transform: Option<Matrix>) -> Matrix {
     match transform {
         None => IDENTITY_MATRIX,
-        Some(t) => t.inverse().unwrap()
+        Some(t) => t.inverse().unwrap_or_else(|| {
+            error!("singular transformation {:?}", t);
+            panic!("transformation should be invertible")
+        })
+    }
+}
+
+// The transformation of a shape that is either still at transform or has motion
+pub fn transform_at_time(transform: &InvertibleMatrix, motion: Option<&Motion>, time: f64) -> Matrix {
+    match motion {
+        None => transform.matrix(),
+        Some(m) => m.transformation_at(time)
+    }
+}
+
+pub fn inverse_transform_at_time(transform: &InvertibleMatrix, motion: Option<&Motion>, time: f64) -> Matrix {
+    match motion {
+        None => transform.inverse(),
+        Some(m) => m.inverse_transformation_at(time)
     }
 }
 
+pub fn normal_transform_at_time(transform: &InvertibleMatrix, motion: Option<&Motion>, time: f64) -> Matrix {
+    match motion {
+        None => transform.normal_matrix(),
+        Some(m) => m.inverse_transformation_at(time).transpose()
+    }
+}
+
+// A new transform for a shape with motion, which keeps the end of it. Leaves the shape as it is
+// on errors, when assigned to its transform and motion.
+pub fn replace_transform(transform: Matrix, motion: Option<&Motion>) -> Result<(InvertibleMatrix, Option<Motion>), Error> {
+    let motion = motion.map(|m| Motion::try_new(&transform, &m.end_transformation())).transpose()?;
+    Ok((InvertibleMatrix::try_new(transform)?, motion))
+}
+
 impl Clone for BoxShape {
     fn clone(&self) -> Self {
         self.box_clone()
     }
 }
 
-impl PartialEq for BoxShape {
-    fn eq(&self, other: &BoxShape) -> bool {
+// Also makes BoxShape comparable
+impl PartialEq for dyn Shape + '_ {
+    fn eq(&self, other: &Self) -> bool {
         self.box_eq(other.as_any())
     }
 }
@@ -54,85 +174,114 @@ impl PartialEq for BoxShape {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::f64::consts::{PI, SQRT_2};
+    use crate::angle::rad;
+    use std::f64::consts::{PI, SQRT_2, FRAC_1_SQRT_2};
     use crate::color::GREEN;
-    use crate::tuple::{ORIGO, VECTOR_Y_UP};
+    use crate::debug::DebugShape;
     use crate::material::DEFAULT_MATERIAL;
 
-    static mut SAVED_RAY: Ray = Ray { origin: ORIGO, direction: VECTOR_Y_UP };
+    #[test]
+    fn default_transformation() {
+        let s = DebugShape::new(None, None);
 
-    #[derive(Clone, Debug, PartialEq)]
-    struct TestShape {
-        material: Material,
-        inverse_transform: Matrix,
-        transform: Matrix
+        assert_eq!(s.transformation(), IDENTITY_MATRIX);
     }
 
-    impl Shape for TestShape {
-        fn as_any(&self) -> &dyn Any {
-            self
+    #[test]
+    fn assign_transformation() {
+        let tr = Matrix::translation(2., 3., 4.);
+        let s = DebugShape::new(None, Some(tr));
+
+        assert_eq!(s.transformation(), tr);
+    }
+
+    #[test]
+    fn boxed_shape_knows_its_type_name() {
+        let s: BoxShape = Box::new(DebugShape::new(None, None));
+
+        assert_eq!(s.type_name(), "DebugShape");
+    }
+
+    #[test]
+    fn set_transformation_of_boxed_shape() {
+        let mut s: BoxShape = Box::new(DebugShape::new(None, None));
+        s.set_transform(Matrix::scaling(2., 2., 2.));
+
+        assert_eq!(s.transformation(), Matrix::scaling(2., 2., 2.));
+        assert_eq!(s.inverse_transformation(), Matrix::scaling(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn setting_singular_transformation_without_panicking() {
+        let mut s: BoxShape = Box::new(DebugShape::new(None, Some(Matrix::scaling(2., 2., 2.))));
+
+        assert!(matches!(s.try_set_transform(Matrix::scaling(1., 0., 1.)), Err(Error::NotInvertible(_))));
+        assert_eq!(s.transformation(), Matrix::scaling(2., 2., 2.));
+        s.try_set_transform(Matrix::translation(1., 0., 0.)).unwrap();
+        assert_eq!(s.transformation(), Matrix::translation(1., 0., 0.));
+    }
+
+    // Implements only what a shape has to
+    #[derive(Debug, PartialEq)]
+    struct FixedShape;
+
+    impl Shape for FixedShape {
+        fn box_clone(&self) -> BoxShape {
+            Box::new(FixedShape)
         }
 
         fn box_eq(&self, other: &dyn Any) -> bool {
-            other.downcast_ref::<Self>().map_or(false, |a| self == a)
+            other.downcast_ref::<Self>() == Some(self)
         }
 
-        fn box_clone(&self) -> BoxShape {
-            Box::new((*self).clone())
+        fn as_any(&self) -> &dyn Any {
+            self
         }
 
-        fn inner_intersect(&self, object_ray: Ray) -> Intersections {
-            unsafe {
-                SAVED_RAY = object_ray;
-            }
-            Intersections::new(vec![])
+        fn inner_intersect(&self, _object_ray: Ray) -> Intersections<'_> {
+            Intersections::empty()
         }
 
-        fn inner_normal_at(&self, object_point: Tuple) -> Tuple {
-            Tuple::vector(object_point.x, object_point.y, object_point.z)
+        fn inner_normal_at(&self, _object_point: Tuple) -> Tuple {
+            Tuple::vector(0., 1., 0.)
         }
 
         fn material(&self) -> &Material {
-            &self.material
+            &DEFAULT_MATERIAL
         }
 
         fn transformation(&self) -> Matrix {
-            self.transform
+            IDENTITY_MATRIX
         }
 
         fn inverse_transformation(&self) -> Matrix {
-            self.inverse_transform
-        }
-    }
-
-    impl TestShape {
-        fn new(material: Option<Material>, transform: Option<Matrix>) -> Self {
-            Self { 
-                material: material.unwrap_or_default(), 
-                transform: transform.unwrap_or_default(),
-                inverse_transform: inverse_transform_parameter(transform)
-            }
+            IDENTITY_MATRIX
         }
     }
 
     #[test]
-    fn default_transformation() {
-        let s = TestShape::new(None, None);
+    fn shape_without_set_transform_cant_be_transformed() {
+        let mut s: BoxShape = Box::new(FixedShape);
 
+        assert!(matches!(s.try_set_transform(Matrix::translation(1., 0., 0.)), Err(Error::FixedTransform("FixedShape"))));
         assert_eq!(s.transformation(), IDENTITY_MATRIX);
     }
 
     #[test]
-    fn assign_transformation() {
-        let tr = Matrix::translation(2., 3., 4.);
-        let s = TestShape::new(None, Some(tr));
+    #[should_panic(expected = "A FixedShape can't be transformed")]
+    fn setting_transform_of_fixed_shape_panics() {
+        FixedShape.set_transform(Matrix::translation(1., 0., 0.));
+    }
 
-        assert_eq!(s.transformation(), tr);
+    #[test]
+    #[should_panic(expected = "A FixedShape can't change its material")]
+    fn setting_material_of_fixed_shape_panics() {
+        FixedShape.set_material(Material::default());
     }
 
     #[test]
     fn default_material() {
-        let s = TestShape::new(None, None);
+        let s = DebugShape::new(None, None);
         let m = s.material();
 
         assert_eq!(*m, DEFAULT_MATERIAL);
@@ -141,7 +290,7 @@ mod tests {
     #[test]
     fn assign_material() {
         let m = Material::new(GREEN, 0.1, 0.2, 0.3, 0.4, None);
-        let s = TestShape::new(Some(m.clone()), None);
+        let s = DebugShape::new(Some(m.clone()), None);
 
         assert_eq!(*s.material(), m);
     }
@@ -151,13 +300,12 @@ mod tests {
     {
         let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
         let tr = Matrix::scaling(2., 2., 2.);
-        let s = TestShape::new(None, Some(tr));
+        let s = DebugShape::new(None, Some(tr));
         s.intersect(r);
 
-        unsafe {
-            assert_eq!(SAVED_RAY.origin, Tuple::point(0., 0., -2.5));
-            assert_eq!(SAVED_RAY.direction, Tuple::vector(0., 0., 0.5));
-        }
+        let saved_ray = s.last_ray().unwrap();
+        assert_eq!(saved_ray.origin, Tuple::point(0., 0., -2.5));
+        assert_eq!(saved_ray.direction, Tuple::vector(0., 0., 0.5));
     }
 
     #[test]
@@ -165,31 +313,59 @@ mod tests {
     {
         let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
         let tr = Matrix::translation(5., 0., 0.);
-        let s = TestShape::new(None, Some(tr));
+        let s = DebugShape::new(None, Some(tr));
         s.intersect(r);
 
-        unsafe {
-            assert_eq!(SAVED_RAY.origin, Tuple::point(-5., 0., -5.));
-            assert_eq!(SAVED_RAY.direction, Tuple::vector(0., 0., 1.));
-        }
+        let saved_ray = s.last_ray().unwrap();
+        assert_eq!(saved_ray.origin, Tuple::point(-5., 0., -5.));
+        assert_eq!(saved_ray.direction, Tuple::vector(0., 0., 1.));
     }
 
     #[test]
     fn compute_normal_on_translated_shape() {
         let tr = Matrix::translation(0., 1., 0.);
-        let s = TestShape::new(None, Some(tr));
-        let n = s.normal_at(Tuple::point(0., 1.70711, -0.70711));
+        let s = DebugShape::new(None, Some(tr));
+        let n = s.normal_at(Tuple::point(0., 1. + FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
 
-        assert_eq!(n, Tuple::vector(0., 0.70711, -0.70711));
+        assert_eq!(n, Tuple::vector(0., FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
     }
 
     #[test]
     fn compute_normal_on_transformed_shape() {
-        let tr = Matrix::scaling(1., 0.5, 1.) * Matrix::rotation_z(PI / 5.);
-        let s = TestShape::new(None, Some(tr));
+        let tr = Matrix::scaling(1., 0.5, 1.) * Matrix::rotation_z(rad(PI / 5.));
+        let s = DebugShape::new(None, Some(tr));
         let n = s.normal_at(Tuple::point(0., SQRT_2 / 2., -SQRT_2 / 2.));
 
         assert_eq!(n, Tuple::vector(0., 0.97014, -0.24254));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn transform_without_motion_is_constant() {
+        let tr = Matrix::translation(1., 0., 0.) * Matrix::scaling(1., 2., 1.);
+        let it = InvertibleMatrix::new(tr);
+
+        assert_eq!(transform_at_time(&it, None, 0.7), tr);
+        assert_eq!(inverse_transform_at_time(&it, None, 0.7), tr.inverse().unwrap());
+        assert_eq!(normal_transform_at_time(&it, None, 0.7), tr.inverse().unwrap().transpose());
+    }
+
+    #[test]
+    fn transform_with_motion_is_interpolated() {
+        let start = InvertibleMatrix::new(Matrix::translation(0., 0., 0.));
+        let motion = Motion::new(&start.matrix(), &Matrix::translation(2., 0., 0.));
+
+        assert_eq!(transform_at_time(&start, Some(&motion), 0.5), Matrix::translation(1., 0., 0.));
+        assert_eq!(inverse_transform_at_time(&start, Some(&motion), 0.5), Matrix::translation(-1., 0., 0.));
+        assert_eq!(normal_transform_at_time(&start, Some(&motion), 0.5), Matrix::translation(-1., 0., 0.).transpose());
+    }
+
+    #[test]
+    fn replacing_transform_keeps_end_of_motion() {
+        let motion = Motion::new(&IDENTITY_MATRIX, &Matrix::translation(2., 0., 0.));
+        let (transform, moved) = replace_transform(Matrix::translation(0., 4., 0.), Some(&motion)).unwrap();
+
+        assert_eq!(transform.matrix(), Matrix::translation(0., 4., 0.));
+        assert_eq!(moved.unwrap().transformation_at(0.5), Matrix::translation(1., 2., 0.));
+        assert!(matches!(replace_transform(Matrix::scaling(-1., 1., 1.), Some(&motion)), Err(Error::InvalidMotion(_))));
+    }
+}
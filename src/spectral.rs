@@ -0,0 +1,125 @@
+use std::sync::OnceLock;
+
+use super::color::{cie_matching, xyz_to_linear_srgb, Color, WHITE};
+use super::ray::Ray;
+
+// The visible spectrum in nm, the range wavelengths are sampled from
+pub const MIN_WAVELENGTH: f64 = 380.;
+pub const MAX_WAVELENGTH: f64 = 780.;
+// The yellow helium d line, where glass catalogues give the refractive index
+pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+// Borosilicate crown glass, the common optical glass
+pub const BK7_GLASS: CauchyIor = CauchyIor { a: 1.5046, b: 0.00420 };
+// Dense flint glass, which disperses light about three times as much, as in prisms
+pub const SF11_GLASS: CauchyIor = CauchyIor { a: 1.7515, b: 0.01359 };
+
+// Rendering with rays that carry a single wavelength each rather than RGB, for effects that
+// depend on the wavelength like the dispersion of a prism. With Camera::spectral each sample
+// picks a wavelength with sample_wavelength, is traced with the refractive indices at that
+// wavelength (see Material::with_dispersion), and adds its result times
+// wavelength_to_rgb(wavelength) to the pixel. Colors of lights and surfaces stay RGB, so a
+// scene without dispersion renders the same on average as without spectral sampling.
+
+// Index of refraction that falls with the wavelength by Cauchy's equation n = a + b / λ², with λ
+// in µm
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CauchyIor {
+    pub a: f64,
+    pub b: f64
+}
+
+impl CauchyIor {
+    pub fn new(a: f64, b: f64) -> Self {
+        Self { a, b }
+    }
+
+    // Without dispersion, the same at every wavelength
+    pub fn constant(ior: f64) -> Self {
+        Self::new(ior, 0.)
+    }
+
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let micrometers = wavelength / 1000.;
+        self.a + self.b / (micrometers * micrometers)
+    }
+}
+
+// Uniform over the visible spectrum, for u uniform in [0, 1)
+pub fn sample_wavelength(u: f64) -> f64 {
+    MIN_WAVELENGTH + u * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+// What a sample of a single wavelength adds to a pixel, so that the average over wavelengths
+// from sample_wavelength of a spectrum that is the same at every wavelength is WHITE. Deep blues
+// and reds are outside of the sRGB gamut and have negative components, which are needed for the
+// average to come out right.
+pub fn wavelength_to_rgb(wavelength: f64) -> Color {
+    let (x, y, z) = cie_matching(wavelength);
+    xyz_to_linear_srgb(x, y, z) * white_balance()
+}
+
+// What the color seen along a camera ray counts for in its pixel
+pub fn sample_weight(ray: &Ray) -> Color {
+    ray.wavelength.map_or(WHITE, wavelength_to_rgb)
+}
+
+// Per channel, the inverse of the average of the unbalanced colors over the spectrum
+fn white_balance() -> Color {
+    static BALANCE: OnceLock<Color> = OnceLock::new();
+    *BALANCE.get_or_init(|| {
+        let steps = 400;
+        let mut sum = Color::new(0., 0., 0.);
+        for i in 0..steps {
+            let (x, y, z) = cie_matching(sample_wavelength((i as f64 + 0.5) / steps as f64));
+            sum += xyz_to_linear_srgb(x, y, z);
+        }
+        let average = sum * (1. / steps as f64);
+        Color::new(1. / average.r, 1. / average.g, 1. / average.b)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn glass_bends_blue_more_than_red() {
+        // The refractive index of BK7 for the yellow helium line is 1.5168
+        assert!((BK7_GLASS.at(REFERENCE_WAVELENGTH) - 1.5168).abs() < 0.001);
+        assert!(BK7_GLASS.at(450.) > BK7_GLASS.at(650.));
+        assert!(SF11_GLASS.at(450.) - SF11_GLASS.at(650.) > 2. * (BK7_GLASS.at(450.) - BK7_GLASS.at(650.)));
+        assert_eq!(CauchyIor::constant(1.33).at(400.), CauchyIor::constant(1.33).at(700.));
+    }
+
+    #[test]
+    fn wavelengths_cover_visible_spectrum() {
+        assert_eq!(sample_wavelength(0.), MIN_WAVELENGTH);
+        assert_eq!(sample_wavelength(0.5), 580.);
+    }
+
+    #[test]
+    fn wavelengths_have_their_colors() {
+        let blue = wavelength_to_rgb(450.);
+        let green = wavelength_to_rgb(530.);
+        let red = wavelength_to_rgb(630.);
+
+        assert!(blue.b > blue.r && blue.b > blue.g);
+        assert!(green.g > green.r && green.g > green.b);
+        assert!(red.r > red.g && red.r > red.b);
+    }
+
+    #[test]
+    fn flat_spectrum_averages_to_white() {
+        let mut rng = Rng::new(11);
+        let n = 100000;
+        let mut sum = Color::new(0., 0., 0.);
+        for _ in 0..n {
+            sum += wavelength_to_rgb(sample_wavelength(rng.next_f64()));
+        }
+        let average = sum * (1. / n as f64);
+
+        assert!([average.r - WHITE.r, average.g - WHITE.g, average.b - WHITE.b].iter().all(|d| d.abs() < 0.05));
+    }
+}
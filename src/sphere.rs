@@ -1,31 +1,59 @@
 use super::intersection::{Intersection, Intersections};
 use super::material::Material;
-use super::matrix::{Matrix, IDENTITY_MATRIX};
+use super::matrix::{Matrix, InvertibleMatrix};
 use super::ray::Ray;
-use super::shape::{inverse_transform_parameter, Shape, BoxShape};
+use super::transform::Motion;
+use super::error::Error;
+use super::shape::{transform_at_time, inverse_transform_at_time, normal_transform_at_time, replace_transform, Shape, BoxShape};
 use super::tuple::{Tuple, ORIGO};
+use super::bounding_box::BoundingBox;
+use super::tessellation::{Mesh, TessellationQuality};
 use std::any::Any;
 
-#[derive(Debug, Clone)]
+/// A red unit sphere in the middle of a thumbnail, with the black background around it:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::color::{BLACK, RED};
+/// use raytracer::material::Material;
+/// use raytracer::sphere::Sphere;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let red = Material { color: RED, ..Material::default() };
+/// let image = render_thumbnail(thumbnail_world(vec![Sphere::new_boxed(Some(red), None)]));
+/// let middle = image.pixel_at(16, 16);
+/// assert!(middle.r > 0.5 && middle.g < 0.1 && middle.b < 0.1);
+/// assert_eq!(image.pixel_at(0, 0), BLACK);
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
 pub struct Sphere {
-    inverse_transform: Matrix,
-    transform: Matrix,
+    transform: InvertibleMatrix,
+    motion: Option<Motion>,
     material: Material,
 }
 
 impl PartialEq for Sphere {
     fn eq(&self, other: &Self) -> bool {
-        self.transform == other.transform && self.material == other.material
+        self.transform == other.transform &&
+        self.motion == other.motion &&
+        self.material == other.material
     }
 }
 
-impl Default for Sphere {
-    fn default() -> Self {
-        Self {
-            transform: IDENTITY_MATRIX,
-            inverse_transform: IDENTITY_MATRIX,
-            material: Material::default(),
+impl Sphere {
+    // The distances along object_ray where it enters and leaves the unit sphere, if it hits it
+    fn roots(object_ray: Ray) -> Option<(f64, f64)> {
+        let sphere_to_ray = object_ray.origin - ORIGO;
+        let a = object_ray.direction.dot(&object_ray.direction);
+        let b = 2.0 * object_ray.direction.dot(&sphere_to_ray);
+        let c = sphere_to_ray.dot(&sphere_to_ray) - 1.;
+        let discriminant = b * b - 4. * a * c;
+
+        if discriminant < 0. {
+            return None;
         }
+        Some(((-b - discriminant.sqrt()) / (2. * a), (-b + discriminant.sqrt()) / (2. * a)))
     }
 }
 
@@ -35,33 +63,24 @@ impl Shape for Sphere {
     }
 
     fn box_eq(&self, other: &dyn Any) -> bool {
-        other.downcast_ref::<Self>().map_or(false, |a| self == a)
+        other.downcast_ref::<Self>() == Some(self)
     }
 
     fn box_clone(&self) -> BoxShape {
         Box::new((*self).clone())
     }
 
-    fn inner_intersect(&self, object_ray: Ray) -> Intersections {
-        let sphere_to_ray = object_ray.origin - ORIGO;
-        let a = object_ray.direction.dot(&object_ray.direction);
-        let b = 2.0 * object_ray.direction.dot(&sphere_to_ray);
-        let c = sphere_to_ray.dot(&sphere_to_ray) - 1.;
-        let discriminant = b * b - 4. * a * c;
-
-        if discriminant < 0. {
-            return Intersections::new(vec![]);
+    fn inner_intersect(&self, object_ray: Ray) -> Intersections<'_> {
+        match Sphere::roots(object_ray) {
+            None => Intersections::empty(),
+            Some((t1, t2)) => Intersections::from_array([Intersection::new(t2, self), Intersection::new(t1, self)])
         }
+    }
 
-        let i1 = Intersection::new(
-            (-b - discriminant.sqrt()) / (2. * a),
-            Box::new(self.clone()),
-        );
-        let i2 = Intersection::new(
-            (-b + discriminant.sqrt()) / (2. * a),
-            Box::new(self.clone()),
-        );
-        Intersections::new(vec![i2, i1])
+    // Transforming the ray to object space leaves its t values unchanged
+    fn occludes(&self, world_ray: Ray, max_t: f64) -> bool {
+        let object_ray = world_ray.transform(self.inverse_transformation_at(world_ray.time));
+        Sphere::roots(object_ray).is_some_and(|(t1, t2)| (t1 >= 0. && t1 < max_t) || (t2 >= 0. && t2 < max_t))
     }
 
     fn inner_normal_at(&self, object_point: Tuple) -> Tuple {
@@ -73,23 +92,72 @@ impl Shape for Sphere {
     }
 
     fn transformation(&self) -> Matrix {
-        self.transform
+        self.transform.matrix()
     }
 
     fn inverse_transformation(&self) -> Matrix {
-        self.inverse_transform
+        self.transform.inverse()
+    }
+
+    fn normal_transformation(&self) -> Matrix {
+        self.transform.normal_matrix()
+    }
+
+    // A shape in motion keeps the end of it
+    fn try_set_transform(&mut self, transform: Matrix) -> Result<(), Error> {
+        (self.transform, self.motion) = replace_transform(transform, self.motion.as_ref())?;
+        Ok(())
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Tuple::point(-1., -1., -1.), Tuple::point(1., 1., 1.))
+    }
+
+    fn tessellate(&self, quality: &TessellationQuality) -> Option<Mesh> {
+        Some(Mesh::unit_sphere(quality.segments(1.)))
+    }
+
+    fn motion(&self) -> Option<&Motion> {
+        self.motion.as_ref()
+    }
+
+    fn transformation_at(&self, time: f64) -> Matrix {
+        transform_at_time(&self.transform, self.motion.as_ref(), time)
+    }
+
+    fn inverse_transformation_at(&self, time: f64) -> Matrix {
+        inverse_transform_at_time(&self.transform, self.motion.as_ref(), time)
+    }
+
+    fn normal_transformation_at(&self, time: f64) -> Matrix {
+        normal_transform_at_time(&self.transform, self.motion.as_ref(), time)
     }
 }
 
 impl Sphere {
     pub fn new(material: Option<Material>, transform: Option<Matrix>) -> Self {
         Self {
-            transform: transform.unwrap_or_default(),
-            inverse_transform: inverse_transform_parameter(transform),
+            transform: InvertibleMatrix::from(transform),
+            motion: None,
             material: material.unwrap_or_default(),
         }
     }
 
+    // The shape moves from its transform to end_transform while the camera shutter is open.
+    // Panics if it can't, see Motion::try_new.
+    pub fn with_motion(self, end_transform: Matrix) -> Self {
+        self.try_with_motion(end_transform).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_with_motion(self, end_transform: Matrix) -> Result<Self, Error> {
+        let motion = Motion::try_new(&self.transform.matrix(), &end_transform)?;
+        Ok(Self { motion: Some(motion), ..self })
+    }
+
     pub fn new_boxed(material: Option<Material>, transform: Option<Matrix>) -> BoxShape {
         Box::new(Sphere::new(material, transform))
     }
@@ -102,6 +170,7 @@ impl Sphere {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::matrix::IDENTITY_MATRIX;
 
     #[test]
     fn ray_intersect_sphere_at_two_points() {
@@ -197,4 +266,81 @@ mod tests {
 
         assert_eq!(n, n.normalize());
     }
+
+    #[test]
+    fn moving_sphere_is_intersected_where_it_is_at_ray_time() {
+        let s = Sphere::new(None, None).with_motion(Matrix::translation(0., 2., 0.));
+        let r = Ray::new(Tuple::point(0., 2., -5.), Tuple::vector(0., 0., 1.));
+
+        assert_eq!(s.intersect(r).len(), 0);
+        assert_eq!(s.intersect(r.with_time(1.)).len(), 2);
+        assert_eq!(s.intersect(r.with_time(0.5)).len(), 2);
+    }
+
+    #[test]
+    fn normal_on_moving_sphere_depends_on_time() {
+        let s = Sphere::new(None, None).with_motion(Matrix::translation(2., 0., 0.));
+
+        assert_eq!(s.normal_at_time(Tuple::point(1., 0., 0.), 0.), Tuple::vector(1., 0., 0.));
+        assert_eq!(s.normal_at_time(Tuple::point(1., 0., 0.), 1.), Tuple::vector(-1., 0., 0.));
+    }
+
+    #[test]
+    fn moving_sphere_differs_from_static_sphere() {
+        let s = Sphere::new(None, None);
+
+        assert_ne!(s.clone().with_motion(Matrix::translation(1., 0., 0.)), s);
+    }
+
+    #[test]
+    fn set_transform_updates_intersection_and_normal() {
+        let mut s = Sphere::default();
+        s.set_transform(Matrix::translation(0., 1., 0.) * Matrix::scaling(1., 0.5, 1.));
+        let r = Ray::new(Tuple::point(0., 5., 0.), Tuple::vector(0., -1., 0.));
+        let xs = s.intersect(r);
+
+        assert_eq!(s, Sphere::new(None, Some(Matrix::translation(0., 1., 0.) * Matrix::scaling(1., 0.5, 1.))));
+        assert_eq!(xs[0].t, 3.5);
+        assert_eq!(s.normal_at(Tuple::point(0., 1.5, 0.)), Tuple::vector(0., 1., 0.));
+        assert_eq!(s.normal_at(Tuple::point(1., 1., 0.)), Tuple::vector(1., 0., 0.));
+    }
+
+    #[should_panic]
+    #[test]
+    fn set_transform_rejects_singular_matrix() {
+        let mut s = Sphere::default();
+        s.set_transform(Matrix::scaling(1., 0., 1.));
+    }
+
+    #[test]
+    fn motion_through_zero_scale_is_rejected() {
+        let s = Sphere::new(None, Some(Matrix::scaling(1., 1., 1.)));
+
+        assert!(matches!(s.clone().try_with_motion(Matrix::scaling(1., -1., 1.)), Err(Error::InvalidMotion(_))));
+        let mut moving = s.with_motion(Matrix::translation(1., 0., 0.));
+        assert!(moving.try_set_transform(Matrix::scaling(1., 1., -2.)).is_err());
+        assert_eq!(moving.transformation(), IDENTITY_MATRIX);
+    }
+
+    #[test]
+    fn bounds_of_moving_sphere_cover_movement() {
+        let s = Sphere::new(None, Some(Matrix::scaling(2., 2., 2.))).with_motion(Matrix::translation(5., 0., 0.) * Matrix::scaling(2., 2., 2.));
+
+        assert_eq!(s.bounds(), BoundingBox::new(Tuple::point(-1., -1., -1.), Tuple::point(1., 1., 1.)));
+        assert_eq!(s.parent_space_bounds(), BoundingBox::new(Tuple::point(-2., -2., -2.), Tuple::point(7., 2., 2.)));
+    }
+
+    #[test]
+    fn occludes_only_between_origin_and_max_t() {
+        let s = Sphere::new(None, Some(Matrix::scaling(2., 2., 2.)));
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let inside = Ray::new(ORIGO, Tuple::vector(0., 0., 1.));
+        let behind = Ray::new(Tuple::point(0., 0., 5.), Tuple::vector(0., 0., 1.));
+
+        assert!(s.occludes(r, 3.5));
+        assert!(!s.occludes(r, 3.));
+        assert!(s.occludes(inside, 2.5));
+        assert!(!s.occludes(inside, 2.));
+        assert!(!s.occludes(behind, 100.));
+    }
 }
@@ -0,0 +1,232 @@
+use super::camera::Camera;
+use super::canvas::Canvas;
+use super::color::Color;
+use super::logging::PhaseTimer;
+use super::prepared_scene::PreparedScene;
+use super::tuple::{Tuple, ORIGO};
+use super::world::World;
+
+pub const DEFAULT_MIN_BLEND: f64 = 0.1;
+pub const DEFAULT_DEPTH_TOLERANCE: f64 = 0.05;
+// Normals of the same surface seen in two frames may differ this much (as a cosine) with
+// rounding, any more and it is taken to be another surface
+const NORMAL_TOLERANCE: f64 = 0.9;
+
+// Reduces the flicker of path traced animations by blending each frame with the frames before
+// it. Every pixel finds where the surface it shows was in the previous frame, from the camera
+// transforms and the point and normal of the surface (a small depth and normal buffer), and
+// reuses what was accumulated there if it is still the same surface. Disoccluded surfaces and
+// objects that moved start over from the new frame. Lighting that changes on a static surface
+// lags behind by a few frames, min_blend sets how many. Only projections that can be inverted
+// are reprojected, with others every frame is rendered on its own.
+pub struct TemporalAccumulator {
+    // The least weight of a new frame. Lower removes more noise, but lags more behind changes
+    // in lighting.
+    pub min_blend: f64,
+    // How much the depths of the points seen in two frames may differ and still count as the
+    // same surface, relative to the depth
+    pub depth_tolerance: f64,
+    history: Option<History>
+}
+
+impl Default for TemporalAccumulator {
+    fn default() -> Self {
+        Self { min_blend: DEFAULT_MIN_BLEND, depth_tolerance: DEFAULT_DEPTH_TOLERANCE, history: None }
+    }
+}
+
+// The previous frame, before the film response
+struct History {
+    camera: Camera,
+    colors: Vec<Color>,
+    surfaces: Vec<Option<Surface>>,
+    // Frames accumulated in each pixel
+    frames: Vec<usize>
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Surface {
+    point: Tuple,
+    normal: Tuple
+}
+
+impl TemporalAccumulator {
+    // Renders the next frame of an animation. Set camera.frame to the frame number, so that
+    // the noise differs between frames and averages out. Doesn't detect non finite colors.
+    pub fn render(&mut self, camera: &Camera, scene: &PreparedScene) -> Canvas {
+        let timer = PhaseTimer::start("render_temporal");
+        let world = scene.world();
+        let pixel_count = camera.hsize() * camera.vsize();
+        let mut colors = Vec::with_capacity(pixel_count);
+        let mut surfaces = Vec::with_capacity(pixel_count);
+        let mut frames = Vec::with_capacity(pixel_count);
+        let mut reused = 0;
+        for y in 0..camera.vsize() {
+            for x in 0..camera.hsize() {
+                let color = camera.average_samples(world, x, y);
+                let surface = surface_at(camera, world, x, y);
+                match surface.and_then(|s| self.previous(s)) {
+                    Some((previous, count)) => {
+                        let blend = (1. / (count + 1) as f64).max(self.min_blend);
+                        colors.push(previous * (1. - blend) + color * blend);
+                        frames.push(count + 1);
+                        reused += 1;
+                    },
+                    None => {
+                        colors.push(color);
+                        frames.push(1);
+                    }
+                }
+                surfaces.push(surface);
+            }
+        }
+        let mut image = Canvas::new(camera.hsize(), camera.vsize());
+        for (pixel, color) in image.enumerate_pixels_mut().map(|(_, _, p)| p).zip(&colors) {
+            *pixel = camera.develop(*color);
+        }
+        // Only the view of the camera is needed, which the preview keeps
+        self.history = Some(History { camera: camera.preview(1), colors, surfaces, frames });
+        timer.finish(&format!("width={} height={} frame={} reused={}", camera.hsize(), camera.vsize(), camera.frame, reused));
+        image
+    }
+
+    // Forgets the previous frames, e g at a cut to another shot
+    pub fn reset(&mut self) {
+        self.history = None;
+    }
+
+    // The accumulated color and frame count of surface in the previous frame, if it was visible
+    fn previous(&self, surface: Surface) -> Option<(Color, usize)> {
+        let history = self.history.as_ref()?;
+        let camera = &history.camera;
+        let (x, y) = camera.pixel_for_point(surface.point)?;
+        let i = y as usize * camera.hsize() + x as usize;
+        let previous = history.surfaces[i]?;
+        // Compared by their distances from the previous camera, like with a depth buffer, since
+        // the point in the previous frame was seen through the center of another pixel
+        let origin = camera.inverse_transform() * ORIGO;
+        let depth = (surface.point - origin).magnitude();
+        let same_surface = ((previous.point - origin).magnitude() - depth).abs() <= self.depth_tolerance * depth
+            && previous.normal.dot(&surface.normal) >= NORMAL_TOLERANCE;
+        if same_surface { Some((history.colors[i], history.frames[i])) } else { None }
+    }
+}
+
+// What the center of the pixel sees, None for the background
+fn surface_at(camera: &Camera, world: &World, x: usize, y: usize) -> Option<Surface> {
+    let ray = camera.try_ray_for_pixel(x, y)?.with_time(camera.shutter_open);
+    let xs = world.intersect(ray);
+    let comps = xs.hit()?.prepare_computations(ray);
+    Some(Surface { point: comps.point, normal: comps.normalv })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::FRAC_PI_3;
+    use crate::camera::RenderMode;
+    use crate::color::BLACK;
+    use crate::film::FilmResponse;
+    use crate::matrix::Matrix;
+    use crate::sampler::JitteredSampler;
+    use crate::sphere::Sphere;
+
+    fn camera_at(x: f64) -> Camera {
+        let view = Matrix::view_transform(Tuple::point(x, 1., -5.), Tuple::point(x, 0.5, 0.), Tuple::vector(0., 1., 0.));
+        let mut c = Camera::new(16, 12, rad(FRAC_PI_3), Some(view));
+        c.render_mode = RenderMode::PathTraced;
+        c.sampler = JitteredSampler::new_boxed(2);
+        c
+    }
+
+    fn scene() -> PreparedScene {
+        PreparedScene::new(World::default_world())
+    }
+
+    #[test]
+    fn first_frame_is_rendered_as_is() {
+        let mut c = camera_at(0.);
+        c.film_response = Some(FilmResponse::filmic());
+        let scene = scene();
+
+        assert!(TemporalAccumulator::default().render(&c, &scene).diff(&c.render_scene(&scene)).approx_eq(0.));
+    }
+
+    #[test]
+    fn static_frames_are_averaged() {
+        let scene = scene();
+        let mut t = TemporalAccumulator::default();
+        let mut c = camera_at(0.);
+        let first = c.render_scene(&scene);
+        t.render(&c, &scene);
+        c.frame = 1;
+        let second = c.render_scene(&scene);
+        let accumulated = t.render(&c, &scene);
+
+        assert_ne!(first.pixel_at(8, 6), second.pixel_at(8, 6));
+        assert_eq!(accumulated.pixel_at(8, 6), (first.pixel_at(8, 6) + second.pixel_at(8, 6)) * 0.5);
+        // Nothing to reuse for the background
+        assert_eq!(accumulated.pixel_at(0, 0), BLACK);
+    }
+
+    #[test]
+    fn surfaces_are_followed_when_camera_moves() {
+        let scene = scene();
+        let mut t = TemporalAccumulator::default();
+        let first = t.render(&camera_at(0.), &scene);
+        let mut c = camera_at(0.2);
+        c.frame = 1;
+        let moved = c.render_scene(&scene);
+        let accumulated = t.render(&c, &scene);
+        let reused = accumulated.enumerate_pixels().filter(|&(x, y, p)| *p != moved.pixel_at(x, y)).count();
+        let hits = moved.pixels().filter(|p| **p != BLACK).count();
+
+        assert!(reused >= hits * 3 / 4);
+        assert!(accumulated.diff(&first).largest_error() > 0.);
+    }
+
+    #[test]
+    fn moved_objects_start_over() {
+        let mut t = TemporalAccumulator::default();
+        let c = camera_at(0.);
+        t.render(&c, &scene());
+        let mut w = World::default_world();
+        w.set_objects(vec![Sphere::new_boxed(None, Some(Matrix::translation(0., 0., 2.)))]);
+        let scene = PreparedScene::new(w);
+
+        assert!(t.render(&c, &scene).diff(&c.render_scene(&scene)).approx_eq(0.));
+    }
+
+    #[test]
+    fn reset_forgets_previous_frames() {
+        let scene = scene();
+        let mut t = TemporalAccumulator::default();
+        let mut c = camera_at(0.);
+        t.render(&c, &scene);
+        t.reset();
+        c.frame = 1;
+
+        assert!(t.render(&c, &scene).diff(&c.render_scene(&scene)).approx_eq(0.));
+    }
+
+    #[test]
+    fn accumulation_reduces_noise() {
+        let scene = scene();
+        let mut reference = camera_at(0.);
+        reference.sampler = JitteredSampler::new_boxed(256);
+        let converged = reference.render_scene(&scene);
+        let mut t = TemporalAccumulator { min_blend: 0., ..TemporalAccumulator::default() };
+        let mut c = camera_at(0.);
+        let single = c.render_scene(&scene);
+        let mut accumulated = t.render(&c, &scene);
+        for frame in 1..16 {
+            c.frame = frame;
+            accumulated = t.render(&c, &scene);
+        }
+        let error = |image: &Canvas| image.pixels().zip(converged.pixels())
+            .map(|(a, b)| { let d = *a - *b; d.r * d.r + d.g * d.g + d.b * d.b }).sum::<f64>();
+
+        assert!(error(&accumulated) < error(&single) / 4.);
+    }
+}
@@ -0,0 +1,253 @@
+use std::f64::consts::PI;
+
+use super::error::Error;
+use super::matrix::Matrix;
+use super::tuple::Tuple;
+
+pub const DEFAULT_SEGMENTS_PER_REVOLUTION: usize = 32;
+pub const DEFAULT_MAX_CHORD_ERROR: f64 = 0.001;
+
+// How finely curved surfaces are split into triangles when they are exported. Curves get at
+// least segments_per_revolution segments per full turn, and more where needed to keep every
+// point of the triangles within max_chord_error (in world units) of the analytic surface.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TessellationQuality {
+    pub segments_per_revolution: usize,
+    pub max_chord_error: f64
+}
+
+impl Default for TessellationQuality {
+    fn default() -> Self {
+        Self { segments_per_revolution: DEFAULT_SEGMENTS_PER_REVOLUTION, max_chord_error: DEFAULT_MAX_CHORD_ERROR }
+    }
+}
+
+impl TessellationQuality {
+    // The same quality for a shape that transform scales up by scale, since shapes are
+    // tessellated in object space
+    pub fn in_object_space(&self, scale: f64) -> Self {
+        Self { max_chord_error: self.max_chord_error / scale, ..*self }
+    }
+
+    // Segments for a full turn of a surface curved with radius in both directions, like a
+    // sphere. A triangle between two such segments spans the angle of a segment along its
+    // diagonal, so the angle is chosen for the diagonal to stay within max_chord_error.
+    pub fn segments(&self, radius: f64) -> usize {
+        let minimum = self.segments_per_revolution.max(3);
+        if self.max_chord_error <= 0. || self.max_chord_error >= radius {
+            return minimum;
+        }
+        let angle = 2f64.sqrt() * (1. - self.max_chord_error / radius).acos();
+        minimum.max((2. * PI / angle).ceil() as usize)
+    }
+}
+
+// Triangles sharing vertices, each vertex with its normal. Faces are counter clockwise seen
+// from outside.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Tuple>,
+    pub normals: Vec<Tuple>,
+    pub faces: Vec<[usize; 3]>
+}
+
+impl Mesh {
+    pub fn transform(&self, transform: &Matrix) -> Result<Mesh, Error> {
+        let normal_transform = transform.try_inverse()?.transpose();
+        Ok(Mesh {
+            vertices: self.vertices.iter().map(|v| transform * v).collect(),
+            normals: self.normals.iter().map(|n| {
+                let mut normal = normal_transform * *n;
+                normal.w = 0.;
+                normal.normalize()
+            }).collect(),
+            // A mirroring transform turns the faces inside out, so the winding is reversed to
+            // keep them counter clockwise seen from where the normals point
+            faces: if transform.determinant() < 0. {
+                self.faces.iter().map(|&[a, b, c]| [a, c, b]).collect()
+            } else {
+                self.faces.clone()
+            }
+        })
+    }
+
+    // Faces whose winding disagrees with the normals of their vertices. Programs that take the
+    // side a face is seen from from its winding show them inside out, e g as black patches.
+    pub fn inverted_faces(&self) -> Vec<usize> {
+        (0..self.faces.len()).filter(|&i| self.is_inverted(i)).collect()
+    }
+
+    // Reverses the winding of the inverted faces. If most faces are inverted it is taken to be
+    // the normals that are wrong, as when a whole mesh was made inside out, and the normals are
+    // flipped instead before the faces that still disagree are reversed. Returns the number of
+    // reversed faces.
+    pub fn fix_winding(&mut self) -> usize {
+        if self.inverted_faces().len() * 2 > self.faces.len() {
+            for n in &mut self.normals {
+                *n = -*n;
+            }
+        }
+        let inverted = self.inverted_faces();
+        for &i in &inverted {
+            let [a, b, c] = self.faces[i];
+            self.faces[i] = [a, c, b];
+        }
+        inverted.len()
+    }
+
+    fn is_inverted(&self, face: usize) -> bool {
+        let [a, b, c] = self.faces[face];
+        let winding = (self.vertices[b] - self.vertices[a]).cross(&(self.vertices[c] - self.vertices[a]));
+        winding.dot(&(self.normals[a] + self.normals[b] + self.normals[c])) < 0.
+    }
+
+    // Rings of latitude from the north pole (+y) to the south pole, with segments around and
+    // half as many rings
+    pub fn unit_sphere(segments: usize) -> Mesh {
+        let rings = segments.div_ceil(2);
+        let mut mesh = Mesh::default();
+        for ring in 0..=rings {
+            let theta = PI * ring as f64 / rings as f64;
+            for segment in 0..=segments {
+                let phi = 2. * PI * segment as f64 / segments as f64;
+                let normal = Tuple::vector(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin());
+                mesh.vertices.push(Tuple::point(normal.x, normal.y, normal.z));
+                mesh.normals.push(normal);
+            }
+        }
+        let index = |ring: usize, segment: usize| ring * (segments + 1) + segment;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let (a, b) = (index(ring, segment), index(ring, segment + 1));
+                let (c, d) = (index(ring + 1, segment), index(ring + 1, segment + 1));
+                if ring > 0 {
+                    mesh.faces.push([a, c, b]);
+                }
+                if ring < rings - 1 {
+                    mesh.faces.push([b, c, d]);
+                }
+            }
+        }
+        mesh
+    }
+}
+
+// How much transform scales object space up at most, taken as the longest transformed axis.
+// Exact for rotation and scaling, an estimate with shearing.
+pub fn largest_scale(transform: &Matrix) -> f64 {
+    [Tuple::vector(1., 0., 0.), Tuple::vector(0., 1., 0.), Tuple::vector(0., 0., 1.)].iter()
+        .map(|axis| (transform * axis).magnitude())
+        .fold(0., f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use crate::approx_eq;
+
+    fn face_normal(mesh: &Mesh, face: &[usize; 3]) -> Tuple {
+        let [a, b, c] = face.map(|i| mesh.vertices[i]);
+        (b - a).cross(&(c - a))
+    }
+
+    #[test]
+    fn segments_follow_chord_error() {
+        let coarse = TessellationQuality { segments_per_revolution: 8, max_chord_error: 0.1 };
+        let fine = TessellationQuality { segments_per_revolution: 8, max_chord_error: 0.0001 };
+
+        assert_eq!(coarse.segments(1.), 10);
+        assert!(fine.segments(1.) > 300);
+        assert!(fine.segments(10.) > fine.segments(1.));
+        assert_eq!(TessellationQuality { segments_per_revolution: 64, max_chord_error: 0.1 }.segments(1.), 64);
+    }
+
+    #[test]
+    fn quality_in_object_space() {
+        let q = TessellationQuality::default().in_object_space(4.);
+
+        assert_eq!(q.max_chord_error, DEFAULT_MAX_CHORD_ERROR / 4.);
+        assert_eq!(q.segments(1.), TessellationQuality::default().segments(4.));
+    }
+
+    #[test]
+    fn unit_sphere_stays_within_chord_error() {
+        let q = TessellationQuality { segments_per_revolution: 8, max_chord_error: 0.01 };
+        let mesh = Mesh::unit_sphere(q.segments(1.));
+
+        for v in &mesh.vertices {
+            assert!(approx_eq(v.magnitude() - 1., 0.));
+        }
+        for face in &mesh.faces {
+            let [a, b, c] = face.map(|i| mesh.vertices[i] - Tuple::point(0., 0., 0.));
+            for p in [(a + b + c) / 3., (a + b) / 2., (b + c) / 2., (c + a) / 2.] {
+                assert!(1. - p.magnitude() <= q.max_chord_error, "{:?} is too far inside", p);
+            }
+        }
+    }
+
+    #[test]
+    fn unit_sphere_faces_point_outwards() {
+        let mesh = Mesh::unit_sphere(12);
+
+        assert_eq!(mesh.faces.len(), 12 * 6 * 2 - 2 * 12);
+        for face in &mesh.faces {
+            let outward = mesh.vertices[face[0]] - Tuple::point(0., 0., 0.);
+            assert!(face_normal(&mesh, face).dot(&outward) > 0.);
+        }
+    }
+
+    #[test]
+    fn transforming_mesh() {
+        let mesh = Mesh::unit_sphere(4).transform(&Matrix::scaling(2., 1., 1.)).unwrap();
+
+        assert!(mesh.vertices.iter().any(|v| *v == Tuple::point(2., 0., 0.)));
+        assert!(mesh.normals.iter().all(|n| approx_eq(n.magnitude(), 1.)));
+    }
+
+    #[test]
+    fn mirroring_keeps_faces_pointing_outwards() {
+        let mesh = Mesh::unit_sphere(8).transform(&Matrix::scaling(-1., 2., 1.)).unwrap();
+
+        assert!(mesh.inverted_faces().is_empty());
+        for face in &mesh.faces {
+            let outward = mesh.vertices[face[0]] - Tuple::point(0., 0., 0.);
+            assert!(face_normal(&mesh, face).dot(&outward) > 0.);
+        }
+    }
+
+    #[test]
+    fn fixing_inverted_faces() {
+        let sphere = Mesh::unit_sphere(8);
+        let mut mesh = sphere.clone();
+        for i in [0, 5, 17] {
+            let [a, b, c] = mesh.faces[i];
+            mesh.faces[i] = [a, c, b];
+        }
+
+        assert_eq!(mesh.inverted_faces(), vec![0, 5, 17]);
+        assert_eq!(mesh.fix_winding(), 3);
+        assert_eq!(mesh, sphere);
+        assert_eq!(mesh.fix_winding(), 0);
+    }
+
+    #[test]
+    fn mostly_inverted_mesh_gets_its_normals_flipped() {
+        let sphere = Mesh::unit_sphere(8);
+        let mut mesh = sphere.clone();
+        mesh.normals.iter_mut().for_each(|n| *n = -*n);
+        let [a, b, c] = mesh.faces[3];
+        mesh.faces[3] = [a, c, b];
+
+        assert_eq!(mesh.inverted_faces().len(), sphere.faces.len() - 1);
+        assert_eq!(mesh.fix_winding(), 1);
+        assert_eq!(mesh, sphere);
+    }
+
+    #[test]
+    fn largest_scale_of_transform() {
+        let t = Matrix::translation(5., 0., 0.) * Matrix::rotation_y(rad(1.)) * Matrix::scaling(1., 3., 2.);
+
+        assert!(approx_eq(largest_scale(&t), 3.));
+    }
+}
@@ -0,0 +1,405 @@
+use std::any::Any;
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::color::Color;
+use super::matrix::Matrix;
+use super::pattern::{BoxPattern, Pattern};
+use super::shape::inverse_transform_parameter;
+use super::tuple::Tuple;
+
+// Patterns in two dimensions, laid onto a surface by mapping points of the surface to (u, v) in
+// [0, 1]. Unlike a 3D pattern cut by the surface, a UV pattern follows the surface, so a
+// checkerboard on a sphere keeps its squares (shrinking towards the poles) and a checkerboard on a
+// cube lines up with the edges instead of flickering where the faces meet.
+
+pub trait UvPattern: Any + fmt::Debug + Send + Sync {
+    fn box_clone(&self) -> BoxUvPattern;
+    fn box_eq(&self, other: &dyn Any) -> bool;
+    fn as_any(&self) -> &dyn Any;
+    fn uv_pattern_at(&self, u: f64, v: f64) -> Color;
+}
+
+pub type BoxUvPattern = Box<dyn UvPattern>;
+
+impl Clone for BoxUvPattern {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+impl PartialEq for BoxUvPattern {
+    fn eq(&self, other: &BoxUvPattern) -> bool {
+        self.box_eq(other.as_any())
+    }
+}
+
+// width by height squares, alternating between a and b, starting with a at (0, 0)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UvCheckers {
+    width: f64,
+    height: f64,
+    a: Color,
+    b: Color
+}
+
+impl UvCheckers {
+    pub fn new(width: f64, height: f64, a: Color, b: Color) -> Self {
+        Self { width, height, a, b }
+    }
+
+    pub fn new_boxed(width: f64, height: f64, a: Color, b: Color) -> BoxUvPattern {
+        Box::new(Self::new(width, height, a, b))
+    }
+}
+
+impl UvPattern for UvCheckers {
+    fn box_clone(&self) -> BoxUvPattern {
+        Box::new(*self)
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn uv_pattern_at(&self, u: f64, v: f64) -> Color {
+        let u2 = (u * self.width).floor() as i64;
+        let v2 = (v * self.height).floor() as i64;
+        if (u2 + v2).rem_euclid(2) == 0 { self.a } else { self.b }
+    }
+}
+
+// How points of a surface, in pattern space, become (u, v)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UvMapping {
+    // Longitude and latitude of the point, for spheres around the origin. u goes once around the
+    // y axis starting at -z, v goes from the bottom to the top.
+    Spherical,
+    // x and z, repeating every unit, for planes
+    Planar,
+    // Around the y axis like Spherical, with y repeating every unit, for cylinders
+    Cylindrical
+}
+
+impl UvMapping {
+    pub fn map(&self, p: Tuple) -> (f64, f64) {
+        match self {
+            UvMapping::Spherical => {
+                let radius = Tuple::vector(p.x, p.y, p.z).magnitude();
+                let phi = (p.y / radius).acos();
+                (around_y(p), 1. - phi / PI)
+            },
+            UvMapping::Planar => (p.x.rem_euclid(1.), p.z.rem_euclid(1.)),
+            UvMapping::Cylindrical => (around_y(p), p.y.rem_euclid(1.))
+        }
+    }
+}
+
+// The angle around the y axis as a fraction of a turn, 0 at -z and increasing clockwise seen
+// from above
+fn around_y(p: Tuple) -> f64 {
+    let theta = p.x.atan2(p.z);
+    1. - (theta / (2. * PI) + 0.5)
+}
+
+// A UV pattern laid onto a surface with one of the mappings
+#[derive(Debug, Clone)]
+pub struct TextureMap {
+    uv_pattern: BoxUvPattern,
+    mapping: UvMapping,
+    transform: Matrix,
+    inverse_transform: Matrix
+}
+
+impl PartialEq for TextureMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.uv_pattern.eq(&other.uv_pattern) &&
+        self.mapping == other.mapping &&
+        self.transform == other.transform
+    }
+}
+
+impl TextureMap {
+    pub fn new(uv_pattern: BoxUvPattern, mapping: UvMapping, transform: Option<Matrix>) -> Self {
+        Self {
+            uv_pattern,
+            mapping,
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
+        }
+    }
+
+    pub fn new_boxed(uv_pattern: BoxUvPattern, mapping: UvMapping, transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(uv_pattern, mapping, transform))
+    }
+}
+
+impl Pattern for TextureMap {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        let (u, v) = self.mapping.map(pattern_point);
+        self.uv_pattern.uv_pattern_at(u, v)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CubeFace {
+    Left,
+    Right,
+    Front,
+    Back,
+    Up,
+    Down
+}
+
+impl CubeFace {
+    // The face of the cube from -1 to 1 that point is on, going by its largest coordinate
+    pub fn from_point(p: Tuple) -> Self {
+        let coord = p.x.abs().max(p.y.abs()).max(p.z.abs());
+        if coord == p.x {
+            CubeFace::Right
+        } else if coord == -p.x {
+            CubeFace::Left
+        } else if coord == p.y {
+            CubeFace::Up
+        } else if coord == -p.y {
+            CubeFace::Down
+        } else if coord == p.z {
+            CubeFace::Front
+        } else {
+            CubeFace::Back
+        }
+    }
+
+    // (u, v) of point on this face, as seen from outside the cube with v up (for the up and
+    // down faces, with the front face below)
+    pub fn uv(&self, p: Tuple) -> (f64, f64) {
+        let unit = |c: f64| c.rem_euclid(2.) / 2.;
+        match self {
+            CubeFace::Front => (unit(p.x + 1.), unit(p.y + 1.)),
+            CubeFace::Back => (unit(1. - p.x), unit(p.y + 1.)),
+            CubeFace::Left => (unit(p.z + 1.), unit(p.y + 1.)),
+            CubeFace::Right => (unit(1. - p.z), unit(p.y + 1.)),
+            CubeFace::Up => (unit(p.x + 1.), unit(1. - p.z)),
+            CubeFace::Down => (unit(p.x + 1.), unit(p.z + 1.))
+        }
+    }
+}
+
+// A UV pattern for each face of a cube from -1 to 1, in the order left, right, front, back, up
+// and down. UvCheckers with an odd number of squares across line up over the edges of the cube,
+// since u and v start over at 0 on the far side of an edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeMap {
+    faces: [BoxUvPattern; 6],
+    transform: Matrix,
+    inverse_transform: Matrix
+}
+
+impl CubeMap {
+    pub fn new(faces: [BoxUvPattern; 6], transform: Option<Matrix>) -> Self {
+        Self {
+            faces,
+            transform: transform.unwrap_or_default(),
+            inverse_transform: inverse_transform_parameter(transform)
+        }
+    }
+
+    pub fn new_boxed(faces: [BoxUvPattern; 6], transform: Option<Matrix>) -> BoxPattern {
+        Box::new(Self::new(faces, transform))
+    }
+
+    // The same pattern on every face
+    pub fn uniform(face: BoxUvPattern, transform: Option<Matrix>) -> Self {
+        Self::new([face.clone(), face.clone(), face.clone(), face.clone(), face.clone(), face], transform)
+    }
+
+    fn face_pattern(&self, face: CubeFace) -> &BoxUvPattern {
+        let index = match face {
+            CubeFace::Left => 0,
+            CubeFace::Right => 1,
+            CubeFace::Front => 2,
+            CubeFace::Back => 3,
+            CubeFace::Up => 4,
+            CubeFace::Down => 5
+        };
+        &self.faces[index]
+    }
+}
+
+impl Pattern for CubeMap {
+    fn box_clone(&self) -> BoxPattern {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>() == Some(self)
+    }
+
+    fn transformation(&self) -> Matrix {
+        self.transform
+    }
+
+    fn inverse_transformation(&self) -> Matrix {
+        self.inverse_transform
+    }
+
+    fn inner_pattern_at(&self, pattern_point: Tuple) -> Color {
+        let face = CubeFace::from_point(pattern_point);
+        let (u, v) = face.uv(pattern_point);
+        self.face_pattern(face).uv_pattern_at(u, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_1_SQRT_2;
+    use crate::color::{BLACK, WHITE, RED, GREEN, BLUE};
+    use crate::approx_eq;
+
+    fn assert_uv(actual: (f64, f64), u: f64, v: f64) {
+        assert!(approx_eq(actual.0, u) && approx_eq(actual.1, v), "{:?} should be ({}, {})", actual, u, v);
+    }
+
+    #[test]
+    fn checkers_in_two_dimensions() {
+        let checkers = UvCheckers::new(2., 2., BLACK, WHITE);
+
+        assert_eq!(checkers.uv_pattern_at(0., 0.), BLACK);
+        assert_eq!(checkers.uv_pattern_at(0.5, 0.), WHITE);
+        assert_eq!(checkers.uv_pattern_at(0., 0.5), WHITE);
+        assert_eq!(checkers.uv_pattern_at(0.5, 0.5), BLACK);
+        assert_eq!(checkers.uv_pattern_at(1., 1.), BLACK);
+    }
+
+    #[test]
+    fn spherical_mapping() {
+        let map = |x, y, z| UvMapping::Spherical.map(Tuple::point(x, y, z));
+
+        assert_uv(map(0., 0., -1.), 0., 0.5);
+        assert_uv(map(1., 0., 0.), 0.25, 0.5);
+        assert_uv(map(0., 0., 1.), 0.5, 0.5);
+        assert_uv(map(-1., 0., 0.), 0.75, 0.5);
+        assert_uv(map(0., 1., 0.), 0.5, 1.);
+        assert_uv(map(0., -1., 0.), 0.5, 0.);
+        assert_uv(map(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.), 0.25, 0.75);
+    }
+
+    #[test]
+    fn planar_and_cylindrical_mapping() {
+        let planar = |x, y, z| UvMapping::Planar.map(Tuple::point(x, y, z));
+        let cylindrical = |x, y, z| UvMapping::Cylindrical.map(Tuple::point(x, y, z));
+
+        assert_uv(planar(0.25, 0., 0.5), 0.25, 0.5);
+        assert_uv(planar(0.25, 0.5, -0.25), 0.25, 0.75);
+        assert_uv(planar(1.25, 0., 0.5), 0.25, 0.5);
+        assert_uv(planar(0.25, 0., -1.75), 0.25, 0.25);
+        assert_uv(planar(1., 0., -1.), 0., 0.);
+        assert_uv(cylindrical(0., 0., -1.), 0., 0.);
+        assert_uv(cylindrical(0., 0.5, -1.), 0., 0.5);
+        assert_uv(cylindrical(1., 1.25, 0.), 0.25, 0.25);
+        assert_uv(cylindrical(-1., -0.25, 0.), 0.75, 0.75);
+    }
+
+    #[test]
+    fn checkers_on_sphere() {
+        let p = TextureMap::new(UvCheckers::new_boxed(16., 8., BLACK, WHITE), UvMapping::Spherical, None);
+        let at = |x, y, z| p.inner_pattern_at(Tuple::point(x, y, z));
+
+        assert_eq!(at(0.4315, 0.4670, 0.7719), WHITE);
+        assert_eq!(at(-0.9654, 0.2552, -0.0534), BLACK);
+        assert_eq!(at(0.1039, 0.7090, 0.6975), WHITE);
+        assert_eq!(at(-0.4986, -0.7856, -0.3663), BLACK);
+        assert_eq!(at(-0.0317, -0.9395, 0.3411), BLACK);
+        assert_eq!(at(0.4809, -0.7721, 0.4154), BLACK);
+        assert_eq!(at(0.0285, -0.9612, -0.2745), BLACK);
+        assert_eq!(at(-0.5734, -0.2162, -0.7903), WHITE);
+        assert_eq!(at(0.7688, -0.1470, 0.6223), BLACK);
+        assert_eq!(at(-0.7652, 0.2175, 0.6060), BLACK);
+    }
+
+    #[test]
+    fn face_of_cube_from_point() {
+        let face = |x, y, z| CubeFace::from_point(Tuple::point(x, y, z));
+
+        assert_eq!(face(-1., 0.5, -0.25), CubeFace::Left);
+        assert_eq!(face(1.1, -0.75, 0.8), CubeFace::Right);
+        assert_eq!(face(0.1, 0.6, 0.9), CubeFace::Front);
+        assert_eq!(face(-0.7, 0., -2.), CubeFace::Back);
+        assert_eq!(face(0.5, 1., 0.9), CubeFace::Up);
+        assert_eq!(face(-0.2, -1.3, 1.1), CubeFace::Down);
+    }
+
+    #[test]
+    fn uv_on_cube_faces() {
+        let uv = |face: CubeFace, x, y, z| face.uv(Tuple::point(x, y, z));
+
+        assert_uv(uv(CubeFace::Front, -0.5, 0.5, 1.), 0.25, 0.75);
+        assert_uv(uv(CubeFace::Front, 0.5, -0.5, 1.), 0.75, 0.25);
+        assert_uv(uv(CubeFace::Back, 0.5, 0.5, -1.), 0.25, 0.75);
+        assert_uv(uv(CubeFace::Back, -0.5, -0.5, -1.), 0.75, 0.25);
+        assert_uv(uv(CubeFace::Left, -1., 0.5, -0.5), 0.25, 0.75);
+        assert_uv(uv(CubeFace::Left, -1., -0.5, 0.5), 0.75, 0.25);
+        assert_uv(uv(CubeFace::Right, 1., 0.5, 0.5), 0.25, 0.75);
+        assert_uv(uv(CubeFace::Right, 1., -0.5, -0.5), 0.75, 0.25);
+        assert_uv(uv(CubeFace::Up, -0.5, 1., -0.5), 0.25, 0.75);
+        assert_uv(uv(CubeFace::Up, 0.5, 1., 0.5), 0.75, 0.25);
+        assert_uv(uv(CubeFace::Down, -0.5, -1., 0.5), 0.25, 0.75);
+        assert_uv(uv(CubeFace::Down, 0.5, -1., -0.5), 0.75, 0.25);
+    }
+
+    #[test]
+    fn cube_map_uses_pattern_of_each_face() {
+        let face = |c: Color| UvCheckers::new_boxed(2., 2., c, BLACK);
+        let cube = CubeMap::new([face(RED), face(GREEN), face(BLUE), face(WHITE), face(Color::new(1., 1., 0.)), face(Color::new(0., 1., 1.))], None);
+        let at = |x, y, z| cube.inner_pattern_at(Tuple::point(x, y, z));
+
+        assert_eq!(at(-1., -0.5, -0.5), RED);
+        assert_eq!(at(1., -0.5, 0.5), GREEN);
+        assert_eq!(at(-0.5, -0.5, 1.), BLUE);
+        assert_eq!(at(0.5, -0.5, -1.), WHITE);
+        assert_eq!(at(-0.5, 1., 0.5), Color::new(1., 1., 0.));
+        assert_eq!(at(-0.5, -1., -0.5), Color::new(0., 1., 1.));
+        assert_eq!(at(-0.5, 0.5, 1.), BLACK);
+    }
+
+    #[test]
+    fn checkers_line_up_across_cube_edges() {
+        let cube = CubeMap::uniform(UvCheckers::new_boxed(3., 3., BLACK, WHITE), None);
+        let at = |x, y, z| cube.inner_pattern_at(Tuple::point(x, y, z));
+
+        // Either side of the edge between the front and the right face, and of the one between
+        // the front and the top
+        assert_eq!(at(0.999, 0.25, 1.), at(1., 0.25, 0.999));
+        assert_eq!(at(0.25, 0.999, 1.), at(0.25, 1., 0.999));
+        assert_eq!(at(-0.999, -0.5, 1.), at(-1., -0.5, 0.999));
+    }
+}
@@ -0,0 +1,29 @@
+use std::f64::consts::FRAC_PI_3;
+
+use super::camera::Camera;
+use super::canvas::Canvas;
+use super::color::WHITE;
+use super::light::PointLight;
+use super::matrix::Matrix;
+use super::shape::BoxShape;
+use super::tuple::Tuple;
+use super::world::World;
+
+pub const THUMBNAIL_SIZE: usize = 32;
+
+// The light of thumbnail_world, up to the left behind the camera
+pub fn thumbnail_light() -> PointLight {
+    PointLight::new(Tuple::point(-10., 10., -10.), WHITE)
+}
+
+pub fn thumbnail_world(objects: Vec<BoxShape>) -> World {
+    World::new(Some(thumbnail_light()), objects)
+}
+
+// A THUMBNAIL_SIZE square render of world, for the examples that check a few pixels of the
+// image. The camera is at (0, 1.5, -5) looking at the origin, where a unit sphere covers the
+// pixels from about 12 to 21 in both directions, and a plane at y = -1 everything below row 8.
+pub fn render_thumbnail(world: World) -> Canvas {
+    let view = Matrix::view_transform(Tuple::point(0., 1.5, -5.), Tuple::point(0., 0., 0.), Tuple::vector(0., 1., 0.));
+    Camera::new(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FRAC_PI_3, Some(view)).render(world)
+}
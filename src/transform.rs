@@ -1,5 +1,144 @@
 use super::matrix::{Matrix, IDENTITY_MATRIX};
-use super::tuple::Tuple;
+use super::quaternion::Quaternion;
+use super::angle::Angle;
+use super::error::Error;
+use super::tuple::{Tuple, ORIGO};
+use super::bounding_box::BoundingBox;
+use super::EPSILON;
+
+// The sine of the smallest angle between up and the view direction of a view transform. Closer
+// than this and rounding decides which way is left.
+const MIN_VIEW_UP_SINE: f64 = 1e-6;
+
+// A transformation split into translation * rotation * scaling, see Matrix::decompose
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Decomposition {
+    pub translation: Tuple,
+    pub rotation: Quaternion,
+    pub scale: Tuple,
+    // The transformation contains shearing, which the decomposition cannot represent, so the
+    // rotation and scale are only approximate
+    pub sheared: bool
+}
+
+impl Decomposition {
+    // The inverse of decompose, exact unless the decomposed matrix was sheared
+    pub fn recompose(&self) -> Matrix {
+        Matrix::translation(self.translation.x, self.translation.y, self.translation.z) *
+            self.rotation.to_matrix() *
+            Matrix::scaling(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    // The rotation as angles in radians for rotation_z * rotation_y * rotation_x, i e rotated
+    // around x first, which is easier to read than a quaternion
+    pub fn euler_angles(&self) -> Tuple {
+        let m = self.rotation.to_matrix();
+        let ry = (-m[2][0]).clamp(-1., 1.).asin();
+        let (rx, rz) = if m[2][0].abs() < 1. - EPSILON {
+            (m[2][1].atan2(m[2][2]), m[1][0].atan2(m[0][0]))
+        } else {
+            // Gimbal lock, only the sum of the x and z rotations is known so put it all in x
+            ((-m[1][2]).atan2(m[1][1]), 0.)
+        };
+        Tuple::vector(rx, ry, rz)
+    }
+}
+
+// A transformation that changes during the exposure, from start at time 0 to end at time 1.
+// Translation, rotation and scale are interpolated separately, so a rotating shape turns at an
+// even rate instead of shrinking halfway like it would if the matrices were interpolated, and
+// the inverse at any time follows from the parts without inverting a matrix. Checked on
+// creation so that the transformation can be inverted at every time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Motion {
+    start: Decomposition,
+    end: Decomposition
+}
+
+impl Motion {
+    // Panics if the shape can't move from start to end, see try_new
+    pub fn new(start: &Matrix, end: &Matrix) -> Self {
+        Motion::try_new(start, end).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Both have to be invertible without shearing, and a mirroring can't come or go since the
+    // scale would pass 0 on the way
+    pub fn try_new(start: &Matrix, end: &Matrix) -> Result<Self, Error> {
+        start.try_inverse()?;
+        end.try_inverse()?;
+        let (start, end) = (start.decompose(), end.decompose());
+        if start.sheared || end.sheared {
+            return Err(Error::InvalidMotion("sheared transformations can't be interpolated"));
+        }
+        let (a, b) = (start.scale, end.scale);
+        if a.x * b.x <= 0. || a.y * b.y <= 0. || a.z * b.z <= 0. {
+            return Err(Error::InvalidMotion("the scale would pass 0 between start and end"));
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn end_transformation(&self) -> Matrix {
+        self.end.recompose()
+    }
+
+    fn at(&self, time: f64) -> (Tuple, Matrix, Tuple) {
+        let translation = self.start.translation.lerp(&self.end.translation, time);
+        let rotation = self.start.rotation.slerp(&self.end.rotation, time).to_matrix();
+        (translation, rotation, self.start.scale.lerp(&self.end.scale, time))
+    }
+
+    pub fn transformation_at(&self, time: f64) -> Matrix {
+        let (t, r, s) = self.at(time);
+        let mut m = IDENTITY_MATRIX;
+        for (row, &offset) in [t.x, t.y, t.z].iter().enumerate() {
+            for (column, &scale) in [s.x, s.y, s.z].iter().enumerate() {
+                m.set(row, column, r[row][column] * scale);
+            }
+            m.set(row, 3, offset);
+        }
+        m
+    }
+
+    // (T R S)^-1 = S^-1 R^T T^-1, where no scale is 0
+    pub fn inverse_transformation_at(&self, time: f64) -> Matrix {
+        let (t, r, s) = self.at(time);
+        let mut m = IDENTITY_MATRIX;
+        for (row, &scale) in [s.x, s.y, s.z].iter().enumerate() {
+            let mut offset = 0.;
+            for (column, &translation) in [t.x, t.y, t.z].iter().enumerate() {
+                let v = r[column][row] / scale;
+                m.set(row, column, v);
+                offset -= v * translation;
+            }
+            m.set(row, 3, offset);
+        }
+        m
+    }
+
+    // Around everything within bounds (in object space) during the motion. Without rotation
+    // every point moves along a straight line, so the boxes at the start and the end will do.
+    // A rotating shape is kept within reach of where its origin is, which the longest scaled
+    // distance from the origin to a corner of bounds gives.
+    pub fn parent_space_bounds(&self, bounds: &BoundingBox) -> BoundingBox {
+        let at_ends = bounds.transform(&self.transformation_at(0.)).merge(&bounds.transform(&self.transformation_at(1.)));
+        if self.start.rotation.dot(&self.end.rotation).abs() > 1. - EPSILON * EPSILON || !bounds.is_bounded() {
+            return at_ends;
+        }
+        let mut reach: f64 = 0.;
+        for x in [bounds.min.x, bounds.max.x] {
+            for y in [bounds.min.y, bounds.max.y] {
+                for z in [bounds.min.z, bounds.max.z] {
+                    for s in [self.start.scale, self.end.scale] {
+                        reach = reach.max(Tuple::vector(x * s.x, y * s.y, z * s.z).magnitude());
+                    }
+                }
+            }
+        }
+        let (a, b) = (self.start.translation, self.end.translation);
+        let r = Tuple::vector(reach, reach, reach);
+        BoundingBox::new(ORIGO + a.min(&b) - r, ORIGO + a.max(&b) + r)
+    }
+}
 
 impl Matrix {
     pub fn translation(x: f64, y: f64, z: f64) -> Matrix {
@@ -18,7 +157,9 @@ impl Matrix {
         m
     }
 
-    pub fn rotation_x(rad: f64) -> Matrix {
+    // The rotations take an Angle, e g deg(90.) or rad(PI / 2.)
+    pub fn rotation_x(angle: Angle) -> Matrix {
+        let rad = angle.radians();
         let mut m = IDENTITY_MATRIX;
         m.set(1, 1, rad.cos());
         m.set(1, 2, -rad.sin());
@@ -27,7 +168,8 @@ impl Matrix {
         m
     }
 
-    pub fn rotation_y(rad: f64) -> Matrix {
+    pub fn rotation_y(angle: Angle) -> Matrix {
+        let rad = angle.radians();
         let mut m = IDENTITY_MATRIX;
         m.set(0, 0, rad.cos());
         m.set(0, 2, rad.sin());
@@ -36,7 +178,8 @@ impl Matrix {
         m
     }
 
-    pub fn rotation_z(rad: f64) -> Matrix {
+    pub fn rotation_z(angle: Angle) -> Matrix {
+        let rad = angle.radians();
         let mut m = IDENTITY_MATRIX;
         m.set(0, 0, rad.cos());
         m.set(0, 1, -rad.sin());
@@ -56,16 +199,57 @@ impl Matrix {
         m
     }
 
+    // Panics if from and to are the same point, or up is parallel to the view direction
     pub fn view_transform(from: Tuple, to: Tuple, up: Tuple) -> Self {
-        let forward = (to - from).normalize();
-        let left = forward.cross(&up.normalize());
+        Self::try_view_transform(from, to, up).unwrap()
+    }
+
+    // The basis is made orthonormal whatever the angle between up and the view direction, as
+    // long as it isn't too close to 0 to tell which way is left. Without normalizing, the x and
+    // y axes would shrink with the sine of the angle, widening the view as up gets closer to
+    // the view direction.
+    pub fn try_view_transform(from: Tuple, to: Tuple, up: Tuple) -> Result<Self, Error> {
+        let view = to - from;
+        if view.magnitude() < EPSILON {
+            return Err(Error::InvalidView("from and to are the same point"));
+        }
+        let forward = view.normalize();
+        let left = forward.cross(&up);
+        if up.magnitude() < EPSILON || left.magnitude() < MIN_VIEW_UP_SINE * up.magnitude() {
+            return Err(Error::InvalidView("up is parallel to the view direction"));
+        }
+        let left = left.normalize();
         let true_up = left.cross(&forward);
         let orientation = Matrix::new(
             [    left.x,     left.y,     left.z, 0.],
             [ true_up.x,  true_up.y,  true_up.z, 0.],
             [-forward.x, -forward.y, -forward.z, 0.],
             [        0.,         0.,         0., 1.]);
-        orientation * Matrix::translation(-from.x, -from.y, -from.z)
+        Ok(orientation * Matrix::translation(-from.x, -from.y, -from.z))
+    }
+
+    // Assumes an affine transformation, i e the bottom row is 0 0 0 1. A mirroring is given as
+    // a negative x scale.
+    pub fn decompose(&self) -> Decomposition {
+        let column = |c: usize| Tuple::vector(self[0][c], self[1][c], self[2][c]);
+        let (x, y, z) = (column(0), column(1), column(2));
+        let mirrored = x.cross(&y).dot(&z) < 0.;
+        let sx = if mirrored { -x.magnitude() } else { x.magnitude() };
+        let scale = Tuple::vector(sx, y.magnitude(), z.magnitude());
+        let (x, y, z) = (x / scale.x, y / scale.y, z / scale.z);
+        let sheared = x.dot(&y).abs() > EPSILON || x.dot(&z).abs() > EPSILON || y.dot(&z).abs() > EPSILON;
+        // The columns of the rotation matrix are x, y and z
+        let rotation = Matrix::new(
+            [x.x, y.x, z.x, 0.],
+            [x.y, y.y, z.y, 0.],
+            [x.z, y.z, z.z, 0.],
+            [0., 0., 0., 1.]);
+        Decomposition {
+            translation: Tuple::vector(self[0][3], self[1][3], self[2][3]),
+            rotation: Quaternion::from_matrix(&rotation),
+            scale,
+            sheared
+        }
     }
 }
 
@@ -73,7 +257,8 @@ impl Matrix {
 mod tests {
     use super::*;
     use std::f64::consts::*;
-    use crate::tuple::ORIGO;
+    use crate::quaternion::IDENTITY_QUATERNION;
+    use crate::angle::{deg, rad};
 
     #[test]
     fn multiply_by_translation_matrix() {
@@ -149,8 +334,8 @@ mod tests {
     #[test]
     fn rotate_point_around_x_axis() {
         let p = Tuple::point(0., 1., 0.);
-        let half_quarter = Matrix::rotation_x(FRAC_PI_4);
-        let full_quarter = Matrix::rotation_x(FRAC_PI_2);
+        let half_quarter = Matrix::rotation_x(rad(FRAC_PI_4));
+        let full_quarter = Matrix::rotation_x(rad(FRAC_PI_2));
         let actual_half_quarter = half_quarter * p;
         let actual_full_quarter = full_quarter * p;
 
@@ -161,7 +346,7 @@ mod tests {
     #[test]
     fn inverse_rotate_point_around_x_axis() {
         let p = Tuple::point(0., 1., 0.);
-        let half_quarter = Matrix::rotation_x(FRAC_PI_4);
+        let half_quarter = Matrix::rotation_x(rad(FRAC_PI_4));
         let inv = half_quarter.inverse().unwrap();
         let actual = inv * p;
         let expected = Tuple::point(0., 2.0_f64.sqrt()/2., -2.0_f64.sqrt()/2.);
@@ -169,11 +354,18 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn rotation_in_degrees_or_radians() {
+        assert_eq!(Matrix::rotation_x(deg(90.)), Matrix::rotation_x(rad(PI / 2.)));
+        assert_eq!(Matrix::rotation_y(deg(-45.)), Matrix::rotation_y(rad(-PI / 4.)));
+        assert_eq!(Matrix::rotation_z(deg(180.)), Matrix::rotation_z(rad(PI)));
+    }
+
     #[test]
     fn rotate_point_around_y_axis() {
         let p = Tuple::point(0., 0., 1.);
-        let half_quarter = Matrix::rotation_y(FRAC_PI_4);
-        let full_quarter = Matrix::rotation_y(FRAC_PI_2);
+        let half_quarter = Matrix::rotation_y(rad(FRAC_PI_4));
+        let full_quarter = Matrix::rotation_y(rad(FRAC_PI_2));
         let actual_half_quarter = half_quarter * p;
         let actual_full_quarter = full_quarter * p;
 
@@ -184,8 +376,8 @@ mod tests {
     #[test]
     fn rotate_point_around_z_axis() {
         let p = Tuple::point(0., 1., 0.);
-        let half_quarter = Matrix::rotation_z(FRAC_PI_4);
-        let full_quarter = Matrix::rotation_z(FRAC_PI_2);
+        let half_quarter = Matrix::rotation_z(rad(FRAC_PI_4));
+        let full_quarter = Matrix::rotation_z(rad(FRAC_PI_2));
         let actual_half_quarter = half_quarter * p;
         let actual_full_quarter = full_quarter * p;
 
@@ -256,7 +448,7 @@ mod tests {
     #[test]
     fn individual_transformations_applied_in_sequence() {
         let p = Tuple::point(1., 0., 1.);
-        let a = Matrix::rotation_x(FRAC_PI_2);
+        let a = Matrix::rotation_x(rad(FRAC_PI_2));
         let b = Matrix::scaling(5., 5., 5.);
         let c = Matrix::translation(10., 5., 7.);
 
@@ -273,7 +465,7 @@ mod tests {
     #[test]
     fn chained_transformations_applied_in_reverse_order() {
         let p = Tuple::point(1., 0., 1.);
-        let a = Matrix::rotation_x(FRAC_PI_2);
+        let a = Matrix::rotation_x(rad(FRAC_PI_2));
         let b = Matrix::scaling(5., 5., 5.);
         let c = Matrix::translation(10., 5., 7.);
 
@@ -312,18 +504,147 @@ mod tests {
         assert_eq!(t, Matrix::translation(0., 0., -8.));
     }
 
+    #[test]
+    fn decompose_translation_rotation_and_scaling() {
+        let m = Matrix::translation(1., 2., 3.) *
+            Matrix::rotation_z(rad(0.3)) * Matrix::rotation_y(rad(-0.2)) * Matrix::rotation_x(rad(FRAC_PI_4)) *
+            Matrix::scaling(2., 3., 4.);
+        let d = m.decompose();
+
+        assert_eq!(d.translation, Tuple::vector(1., 2., 3.));
+        assert_eq!(d.euler_angles(), Tuple::vector(FRAC_PI_4, -0.2, 0.3));
+        assert_eq!(d.rotation.to_matrix(), Matrix::rotation_z(rad(0.3)) * Matrix::rotation_y(rad(-0.2)) * Matrix::rotation_x(rad(FRAC_PI_4)));
+        assert_eq!(d.scale, Tuple::vector(2., 3., 4.));
+        assert!(!d.sheared);
+        assert_eq!(d.recompose(), m);
+    }
+
+    #[test]
+    fn decompose_identity() {
+        let d = IDENTITY_MATRIX.decompose();
+
+        assert_eq!(d.translation, Tuple::vector(0., 0., 0.));
+        assert_eq!(d.rotation, IDENTITY_QUATERNION);
+        assert_eq!(d.euler_angles(), Tuple::vector(0., 0., 0.));
+        assert_eq!(d.scale, Tuple::vector(1., 1., 1.));
+    }
+
+    #[test]
+    fn decompose_mirroring_and_gimbal_lock() {
+        let m = Matrix::rotation_y(rad(FRAC_PI_2)) * Matrix::rotation_x(rad(0.5)) * Matrix::scaling(-1., 1., 1.);
+        let d = m.decompose();
+        let angles = d.euler_angles();
+        let from_angles = Matrix::rotation_z(rad(angles.z)) * Matrix::rotation_y(rad(angles.y)) *
+            Matrix::rotation_x(rad(angles.x)) * Matrix::scaling(d.scale.x, d.scale.y, d.scale.z);
+
+        assert_eq!(d.scale, Tuple::vector(-1., 1., 1.));
+        assert_eq!(from_angles, m);
+        assert_eq!(d.recompose(), m);
+    }
+
+    #[test]
+    fn decompose_detects_shearing() {
+        let d = Matrix::shearing(1., 0., 0., 0., 0., 0.).decompose();
+
+        assert!(d.sheared);
+    }
+
+    #[test]
+    fn interpolating_decomposed_transforms() {
+        let a = (Matrix::translation(0., 1., 0.) * Matrix::rotation_y(rad(0.2))).decompose();
+        let b = (Matrix::translation(4., 1., 0.) * Matrix::rotation_y(rad(1.0)) * Matrix::scaling(3., 3., 3.)).decompose();
+        let halfway = Decomposition {
+            translation: (a.translation + b.translation) * 0.5,
+            rotation: a.rotation.slerp(&b.rotation, 0.5),
+            scale: (a.scale + b.scale) * 0.5,
+            sheared: false
+        };
+
+        assert_eq!(halfway.recompose(), Matrix::translation(2., 1., 0.) * Matrix::rotation_y(rad(0.6)) * Matrix::scaling(2., 2., 2.));
+    }
+
     #[test]
     fn arbitrary_view_transformation_matrix() {
         let from = Tuple::point(1., 3., 2.);
         let to = Tuple::point(4., -2., 8.);
         let up = Tuple::vector(1., 1., 0.);
         let t = Matrix::view_transform(from, to, up);
+        // The book has the first two rows shorter by the sine of the angle between up and the
+        // view direction, since it doesn't normalize them
         let expected = Matrix::new(
-            [-0.50709, 0.50709,  0.67612, -2.36643],
-            [ 0.76772, 0.60609,  0.12122, -2.82842],
+            [-0.51450, 0.51450,  0.68599, -2.40098],
+            [ 0.77892, 0.61494,  0.12299, -2.86972],
             [-0.35857, 0.59761, -0.71714,  0.00000],
             [ 0.00000, 0.00000,  0.00000,  1.00000]);
 
         assert_eq!(t, expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn view_transformation_is_orthonormal_for_any_up() {
+        let from = Tuple::point(0., 0., 5.);
+        for up in [Tuple::vector(0., 1., 0.), Tuple::vector(0., 5., 0.), Tuple::vector(0., 1., -0.999), Tuple::vector(0.001, 0., -1.)] {
+            let t = Matrix::view_transform(from, ORIGO, up);
+            let d = t.decompose();
+
+            assert_eq!(d.scale, Tuple::vector(1., 1., 1.), "up {:?}", up);
+            assert!(!d.sheared);
+            assert_eq!(t * ORIGO, Tuple::point(0., 0., -5.));
+        }
+    }
+
+    #[test]
+    fn degenerate_view_transformations() {
+        let up = Tuple::vector(0., 1., 0.);
+        let same_point = Matrix::try_view_transform(ORIGO, ORIGO, up);
+        let parallel_up = Matrix::try_view_transform(ORIGO, Tuple::point(0., 3., 0.), up);
+        let opposite_up = Matrix::try_view_transform(ORIGO, Tuple::point(0., 3., 0.), -up);
+        let no_up = Matrix::try_view_transform(ORIGO, Tuple::point(0., 0., 1.), Tuple::vector(0., 0., 0.));
+
+        for result in [same_point, parallel_up, opposite_up, no_up] {
+            assert!(matches!(result, Err(Error::InvalidView(_))));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn view_transformation_panics_for_parallel_up() {
+        Matrix::view_transform(ORIGO, Tuple::point(0., 0., -1.), Tuple::vector(0., 0., 2.));
+    }
+
+    #[test]
+    fn motion_interpolates_translation_rotation_and_scale() {
+        let start = Matrix::translation(1., 0., 0.);
+        let end = Matrix::translation(3., 2., 0.) * Matrix::rotation_y(rad(FRAC_PI_2)) * Matrix::scaling(3., 3., 3.);
+        let m = Motion::new(&start, &end);
+        let halfway = Matrix::translation(2., 1., 0.) * Matrix::rotation_y(rad(FRAC_PI_4)) * Matrix::scaling(2., 2., 2.);
+
+        assert_eq!(m.transformation_at(0.), start);
+        assert_eq!(m.transformation_at(1.), end);
+        assert_eq!(m.transformation_at(0.5), halfway);
+        assert_eq!(m.inverse_transformation_at(0.5), halfway.inverse().unwrap());
+        assert_eq!(m.end_transformation(), end);
+    }
+
+    #[test]
+    fn motion_that_cant_be_inverted_all_the_way() {
+        let shear = Matrix::shearing(1., 0., 0., 0., 0., 0.);
+
+        assert!(matches!(Motion::try_new(&IDENTITY_MATRIX, &Matrix::scaling(-1., 1., 1.)), Err(Error::InvalidMotion(_))));
+        assert!(matches!(Motion::try_new(&IDENTITY_MATRIX, &shear), Err(Error::InvalidMotion(_))));
+        assert!(matches!(Motion::try_new(&IDENTITY_MATRIX, &Matrix::scaling(1., 0., 1.)), Err(Error::NotInvertible(_))));
+        assert!(Motion::try_new(&Matrix::scaling(-1., 2., 1.), &Matrix::scaling(-3., 1., 1.)).is_ok());
+    }
+
+    #[test]
+    fn bounds_of_rotating_motion_cover_every_time() {
+        let bounds = BoundingBox::new(Tuple::point(-1., -0.1, -0.1), Tuple::point(1., 0.1, 0.1));
+        let m = Motion::new(&Matrix::translation(1., 0., 0.), &(Matrix::translation(1., 0., 1.) * Matrix::rotation_z(rad(FRAC_PI_2))));
+        let covered = m.parent_space_bounds(&bounds);
+
+        for i in 0..=10 {
+            let at = bounds.transform(&m.transformation_at(i as f64 / 10.));
+            assert!(covered.contains_point(at.min) && covered.contains_point(at.max), "time {}", i);
+        }
+    }
+}
@@ -80,6 +80,16 @@ impl ops::Div<f64> for Tuple {
     }
 }
 
+forward_ref_binop! { impl Add, add for Tuple, Tuple }
+forward_ref_binop! { impl Sub, sub for Tuple, Tuple }
+forward_ref_binop! { impl Mul, mul for Tuple, f64 }
+forward_ref_binop! { impl Div, div for Tuple, f64 }
+forward_ref_unop! { impl Neg, neg for Tuple }
+assign_op! { impl AddAssign, add_assign from Add, add for Tuple, Tuple }
+assign_op! { impl SubAssign, sub_assign from Sub, sub for Tuple, Tuple }
+assign_op! { impl MulAssign, mul_assign from Mul, mul for Tuple, f64 }
+assign_op! { impl DivAssign, div_assign from Div, div for Tuple, f64 }
+
 impl From<[f64; 4]> for Tuple {
     fn from(item: [f64; 4]) -> Self {
         Tuple { x: item[0], y: item[1], z: item[2], w: item[3] }
@@ -133,6 +143,37 @@ impl Tuple {
     pub fn reflect(&self, normal: Tuple) -> Self {
         *self - normal * 2. * self.dot(&normal)
     }
+
+    // Two unit vectors that make a right handed orthonormal basis together with self, which
+    // has to be a unit vector, see sampling::orthonormal_basis
+    pub fn build_onb(&self) -> (Tuple, Tuple) {
+        super::sampling::orthonormal_basis(*self)
+    }
+
+    // Component-wise interpolation, t = 0 gives self and t = 1 gives other. Between two points
+    // it gives a point.
+    pub fn lerp(&self, other: &Tuple, t: f64) -> Self {
+        *self + (*other - *self) * t
+    }
+
+    // Component-wise, e g the corner of a box around two points
+    pub fn min(&self, other: &Tuple) -> Self {
+        Tuple::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z), self.w.min(other.w))
+    }
+
+    pub fn max(&self, other: &Tuple) -> Self {
+        Tuple::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z), self.w.max(other.w))
+    }
+
+    pub fn abs(&self) -> Self {
+        Tuple::new(self.x.abs(), self.y.abs(), self.z.abs(), self.w.abs())
+    }
+
+    // In radians, in [0, pi]. Precise for vectors that are almost parallel as well, unlike the
+    // arc cosine of the dot product. 0 if either is the zero vector.
+    pub fn angle_between(&self, other: &Tuple) -> f64 {
+        self.cross(other).magnitude().atan2(self.x * other.x + self.y * other.y + self.z * other.z)
+    }
 }
 
 #[cfg(test)]
@@ -372,4 +413,70 @@ mod tests {
 
         assert_eq!(r, Tuple::vector(1., 0., 0.));
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[allow(clippy::op_ref)]
+    fn operators_on_references() {
+        let p = Tuple::point(3., 2., 1.);
+        let v = Tuple::vector(5., 6., 7.);
+
+        assert_eq!(p + v, &p + &v);
+        assert_eq!(p - v, &p - v);
+        assert_eq!(p * 2., &p * 2.);
+        assert_eq!(v / 2., &v / 2.);
+        assert_eq!(-v, -&v);
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let mut t = Tuple::point(3., 2., 1.);
+        t += Tuple::vector(1., 1., 1.);
+        t -= &Tuple::vector(0., 1., 2.);
+        t *= 2.;
+        t /= 4.;
+
+        assert_eq!(Tuple::new(2., 1., 0., 0.5), t);
+    }
+
+    #[test]
+    fn lerp_between_points() {
+        let a = Tuple::point(1., 2., 3.);
+        let b = Tuple::point(3., -2., 4.);
+
+        assert_eq!(a.lerp(&b, 0.), a);
+        assert_eq!(a.lerp(&b, 1.), b);
+        assert_eq!(a.lerp(&b, 0.25), Tuple::point(1.5, 1., 3.25));
+        assert!(a.lerp(&b, 0.7).is_point());
+    }
+
+    #[test]
+    fn component_wise_min_max_and_abs() {
+        let a = Tuple::point(1., -2., 3.);
+        let b = Tuple::point(-1., 5., 3.);
+
+        assert_eq!(a.min(&b), Tuple::point(-1., -2., 3.));
+        assert_eq!(a.max(&b), Tuple::point(1., 5., 3.));
+        assert_eq!(Tuple::vector(-1., 2., -3.).abs(), Tuple::vector(1., 2., 3.));
+    }
+
+    #[test]
+    fn orthonormal_basis_around_vector() {
+        let n = Tuple::vector(1., 2., 3.).normalize();
+        let (t, b) = n.build_onb();
+
+        assert!(crate::approx_eq(t.magnitude(), 1.) && crate::approx_eq(b.magnitude(), 1.));
+        assert!(crate::approx_eq(t.dot(&n), 0.) && crate::approx_eq(b.dot(&n), 0.) && crate::approx_eq(t.dot(&b), 0.));
+        assert_eq!(t.cross(&b), n);
+    }
+
+    #[test]
+    fn angle_between_vectors() {
+        let x = Tuple::vector(1., 0., 0.);
+
+        assert_eq!(x.angle_between(&Tuple::vector(0., 2., 0.)), std::f64::consts::FRAC_PI_2);
+        assert_eq!(x.angle_between(&Tuple::vector(-3., 0., 0.)), std::f64::consts::PI);
+        assert!(crate::approx_eq(x.angle_between(&Tuple::vector(1., 1., 0.)), std::f64::consts::FRAC_PI_4));
+        assert_eq!(x.angle_between(&Tuple::vector(1., 1e-10, 0.)), 1e-10);
+        assert_eq!(x.angle_between(&Tuple::vector(0., 0., 0.)), 0.);
+    }
+}
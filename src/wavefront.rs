@@ -0,0 +1,200 @@
+use std::ops::Range;
+
+use super::camera::{Camera, RenderMode};
+use super::canvas::Canvas;
+use super::color::{Color, BLACK, WHITE};
+use super::logging::PhaseTimer;
+use super::precomputed_data::PrecomputedData;
+use super::prepared_scene::PreparedScene;
+use super::ray::Ray;
+use super::spectral::sample_weight;
+use super::world::{World, MAX_REFLECTION_DEPTH};
+
+pub const DEFAULT_BATCH_SIZE: usize = 4096;
+
+// An alternative to Camera::render_scene for Whitted ray tracing, that traces rays in batches
+// one stage at a time: generate the camera rays for some pixels, intersect all of them, cast the
+// shadow rays for all hits, shade, and let the reflection and refraction rays form the next batch. Each stage is
+// a loop over an array instead of a recursion per ray, which keeps the data for a stage together
+// in the caches and is the shape SIMD or a GPU would need. Gives the same image as render_scene
+// up to rounding, but doesn't detect non finite colors.
+pub struct WavefrontRenderer {
+    // Camera rays per batch, taken for whole pixels so that a batch never holds part of a pixel
+    pub batch_size: usize
+}
+
+impl Default for WavefrontRenderer {
+    fn default() -> Self {
+        Self { batch_size: DEFAULT_BATCH_SIZE }
+    }
+}
+
+// A ray on its way through the scene. What it sees is added to pixel, weighted by throughput,
+// the product of the reflectance of the mirrors it has bounced off and the transparency of what
+// it has gone through.
+struct PathState {
+    pixel: usize,
+    ray: Ray,
+    throughput: Color,
+    remaining: usize
+}
+
+impl WavefrontRenderer {
+    // Panics for path traced cameras
+    pub fn render(&self, camera: &Camera, scene: &PreparedScene) -> Canvas {
+        if camera.render_mode != RenderMode::Whitted {
+            panic!("The wavefront renderer only does Whitted ray tracing");
+        }
+        let timer = PhaseTimer::start("render_wavefront");
+        let world = scene.world();
+        let samples = camera.sampler.samples_per_pixel();
+        let pixel_count = camera.hsize() * camera.vsize();
+        let pixels_per_batch = (self.batch_size / samples.max(1)).max(1);
+        let mut sums = vec![BLACK; pixel_count];
+        let mut waves = 0;
+        for first in (0..pixel_count).step_by(pixels_per_batch) {
+            let mut wave = generate(camera, first..(first + pixels_per_batch).min(pixel_count));
+            while !wave.is_empty() {
+                wave = trace_wave(world, &wave, &mut sums);
+                waves += 1;
+            }
+        }
+        let mut image = Canvas::new(camera.hsize(), camera.vsize());
+        for (pixel, sum) in image.enumerate_pixels_mut().map(|(_, _, p)| p).zip(sums) {
+            *pixel = camera.develop(sum * (1. / samples as f64));
+        }
+        timer.finish(&format!("width={} height={} samples_per_pixel={} batch_size={} waves={}",
+            camera.hsize(), camera.vsize(), samples, self.batch_size, waves));
+        image
+    }
+}
+
+fn generate(camera: &Camera, pixels: Range<usize>) -> Vec<PathState> {
+    let samples = camera.sampler.samples_per_pixel();
+    let mut wave = Vec::with_capacity(pixels.len() * samples);
+    for pixel in pixels {
+        for i in 0..samples {
+            if let Some(ray) = camera.sample_ray(pixel % camera.hsize(), pixel / camera.hsize(), i) {
+                wave.push(PathState { pixel, ray, throughput: sample_weight(&ray), remaining: MAX_REFLECTION_DEPTH });
+            }
+        }
+    }
+    wave
+}
+
+// Adds what the rays of wave see to sums, and returns the reflection and refraction rays they
+// spawn
+fn trace_wave(world: &World, wave: &[PathState], sums: &mut [Color]) -> Vec<PathState> {
+    let hits: Vec<Option<PrecomputedData>> = wave.iter()
+        .map(|path| {
+            let xs = world.intersect(path.ray);
+            xs.hit().map(|h| h.prepare_computations_with(path.ray, &xs))
+        })
+        .collect();
+    // Unlit surfaces need no shadow ray, like in World::shade_hit
+    let light_transmission: Vec<Color> = hits.iter().map(|hit| hit.as_ref().map_or(WHITE, |comps| {
+        let lit = world.settings.resolve_material(comps.object.material()).shading_flags().lit;
+        if lit { world.light_transmission_at(comps) } else { WHITE }
+    })).collect();
+    let mut next = vec![];
+    for ((path, hit), &transmission) in wave.iter().zip(&hits).zip(&light_transmission) {
+        let comps = match hit {
+            Some(comps) => comps,
+            None => {
+                sums[path.pixel] += world.background_color(path.ray) * path.throughput;
+                continue;
+            }
+        };
+        sums[path.pixel] += world.surface_color(comps, transmission) * path.throughput;
+        let material = world.settings.resolve_material(comps.object.material());
+        if path.remaining > 0 && material.is_reflective() {
+            next.push(PathState {
+                pixel: path.pixel,
+                ray: comps.reflected_ray(),
+                throughput: path.throughput * material.reflective.unwrap(),
+                remaining: path.remaining - 1
+            });
+        }
+        if path.remaining > 0 && material.transparency > 0. {
+            if let Some(ray) = comps.refracted_ray() {
+                next.push(PathState {
+                    pixel: path.pixel,
+                    ray,
+                    throughput: path.throughput * material.transparency,
+                    remaining: path.remaining - 1
+                });
+            }
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::FRAC_PI_3;
+    use crate::environment::Environment;
+    use crate::film::FilmResponse;
+    use crate::material::Material;
+    use crate::matrix::Matrix;
+    use crate::plane::Plane;
+    use crate::sampler::StratifiedSampler;
+    use crate::sphere::Sphere;
+    use crate::tuple::Tuple;
+
+    fn camera() -> Camera {
+        let view = Matrix::view_transform(Tuple::point(0., 1.5, -5.), Tuple::point(0., 0.5, 0.), Tuple::vector(0., 1., 0.));
+        Camera::new(16, 12, rad(FRAC_PI_3), Some(view))
+    }
+
+    // Mirrors facing each other, so that rays reflect to the full depth, with a sky behind
+    fn mirror_scene() -> PreparedScene {
+        let mirror = Material { reflective: Some(Color::new(0.8, 0.8, 0.9)), ..Material::new(BLACK, 0., 0.2, 0.9, 200., None) };
+        let mut w = World::default_world();
+        w.add_object(Plane::new_boxed(Some(mirror.clone()), Some(Matrix::translation(0., -1., 0.))));
+        w.add_object(Sphere::new_boxed(Some(mirror), Some(Matrix::translation(2., 0.5, 0.5) * Matrix::scaling(0.7, 0.7, 0.7))));
+        w.environment = Some(Environment::SkyGradient { horizon: Color::new(0.9, 0.9, 1.), zenith: Color::new(0.3, 0.5, 1.) });
+        PreparedScene::new(w)
+    }
+
+    // A ball of glass with a smaller one inside it, in front of the default world
+    fn glass_scene() -> PreparedScene {
+        let glass = Material::default().with_transparency(0.9).with_refractive_index(1.5).with_reflective(Some(Color::new(0.1, 0.1, 0.1)));
+        let mut w = World::default_world();
+        w.add_object(Sphere::new_boxed(Some(glass.clone()), Some(Matrix::translation(0.5, 0.5, -2.) * Matrix::scaling(0.8, 0.8, 0.8))));
+        w.add_object(Sphere::new_boxed(Some(glass.with_refractive_index(1.)), Some(Matrix::translation(0.5, 0.5, -2.) * Matrix::scaling(0.3, 0.3, 0.3))));
+        PreparedScene::new(w)
+    }
+
+    #[test]
+    fn wavefront_render_matches_recursive_render() {
+        let c = camera();
+        for scene in [PreparedScene::new(World::default_world()), mirror_scene(), glass_scene()] {
+            let image = WavefrontRenderer::default().render(&c, &scene);
+
+            assert!(image.diff(&c.render_scene(&scene)).approx_eq(1e-9));
+        }
+    }
+
+    #[test]
+    fn batch_size_does_not_change_the_image() {
+        let mut c = camera();
+        c.sampler = StratifiedSampler::new_boxed(2);
+        c.film_response = Some(FilmResponse::filmic());
+        let scene = mirror_scene();
+        let small = WavefrontRenderer { batch_size: 3 }.render(&c, &scene);
+        let large = WavefrontRenderer { batch_size: 100000 }.render(&c, &scene);
+
+        assert!(small.diff(&large).approx_eq(0.));
+        assert!(small.diff(&c.render_scene(&scene)).approx_eq(1e-9));
+    }
+
+    #[test]
+    #[should_panic]
+    fn path_traced_cameras_are_not_supported() {
+        let mut c = camera();
+        c.render_mode = RenderMode::PathTraced;
+        WavefrontRenderer::default().render(&c, &PreparedScene::new(World::default_world()));
+    }
+}
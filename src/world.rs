@@ -1,27 +1,207 @@
 use super::sphere::Sphere;
-use super::shape::BoxShape;
+use super::shape::{BoxShape, SharedShape, Shape};
 use super::color::{Color, WHITE, BLACK};
 use super::tuple::Tuple;
 use super::matrix::Matrix;
 use super::ray::Ray;
-use super::material::{Material, DEFAULT_AMBIENT, DEFAULT_SHININESS};
+use super::material::Material;
 use super::intersection::Intersections;
 use super::precomputed_data::PrecomputedData;
 
 use super::light::PointLight;
+use super::render_settings::RenderSettings;
+use super::environment::Environment;
+use super::rng::Rng;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use log::warn;
+use super::sampling::random_cosine_hemisphere;
+use super::bvh::Bvh;
+use super::logging::PhaseTimer;
 
+pub const MAX_REFLECTION_DEPTH: usize = 5;
+// Path tracing: hard limit on the number of bounces, and the number of bounces after which
+// paths start being ended at random
+pub const MAX_PATH_DEPTH: usize = 50;
+pub const RUSSIAN_ROULETTE_DEPTH: usize = 3;
+
+// Identifies an object of a world, for render passes, picking and the like. Ids are handed out
+// in order as objects are added and never reused, so removing an object leaves the ids of the
+// others as they were.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(u32);
+
+impl ObjectId {
+    // The number the id was handed out as, which is also the index of the object in
+    // World::objects as long as none has been removed
+    pub fn number(self) -> usize {
+        self.0 as usize
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_number(number: usize) -> Self {
+        ObjectId(number as u32)
+    }
+}
+
+// The separate contributions to the color of a hit, as used by render passes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadingComponents {
+    pub surface: Color,
+    pub reflected: Color,
+    pub refracted: Color,
+    pub in_shadow: bool,
+    pub object: Option<ObjectId>
+}
+
+impl ShadingComponents {
+    pub fn color(&self) -> Color {
+        self.surface + self.reflected + self.refracted
+    }
+
+    // With the light scaled by weight, e g that of a spectral sample
+    pub fn weighted(self, weight: Color) -> Self {
+        Self { surface: self.surface * weight, reflected: self.reflected * weight, refracted: self.refracted * weight, ..self }
+    }
+}
+
+/// A small sphere above a floor, casting a shadow down to the right away from the light:
+///
+/// ```
+/// # #[cfg(feature = "render-examples")] {
+/// use raytracer::matrix::Matrix;
+/// use raytracer::plane::Plane;
+/// use raytracer::sphere::Sphere;
+/// use raytracer::thumbnail::{render_thumbnail, thumbnail_world};
+///
+/// let floor = Plane::new_boxed(None, Some(Matrix::translation(0., -1., 0.)));
+/// let ball = Sphere::new_boxed(None, Some(Matrix::scaling(0.5, 0.5, 0.5)));
+/// let image = render_thumbnail(thumbnail_world(vec![floor, ball]));
+/// assert!(image.pixel_at(20, 18).r < 0.15);
+/// assert!(image.pixel_at(28, 18).r > 0.5);
+/// # }
+/// ```
 pub struct World {
     pub light: Option<PointLight>,
-    pub objects: Vec<BoxShape>
+    // Private so that the bvh can't be left behind by changes to the objects. Shared, so that
+    // cloning a world doesn't copy its objects, object_mut copies an object if it has to.
+    objects: Vec<SharedShape>,
+    // The id of each object, in increasing order
+    ids: Vec<ObjectId>,
+    next_id: u32,
+    pub settings: RenderSettings,
+    // Seen by rays that miss all objects, black if there is none
+    pub environment: Option<Environment>,
+    // Use the environment in the direction of the normal as ambient light instead of the light
+    pub environment_lighting: bool,
+    // Fill light reaching every surface from everywhere, shadowed or not, on top of the ambient
+    // part of the light. Scaled by the color of the surface but not by its ambient factor, so
+    // that dark corners of a scene can be lifted without touching the materials.
+    pub ambient_light: Color,
+    // Number of non finite numbers found while settings.detect_non_finite is on
+    non_finite_count: AtomicUsize,
+    // Built by build_bvh, ignored from any change to the objects until it is built or refitted
+    // again
+    bvh: Option<Bvh>,
+    bvh_current: bool
+}
+
+// Cheap, the objects are shared between the copies until either changes them
+impl Clone for World {
+    fn clone(&self) -> Self {
+        World {
+            light: self.light,
+            objects: self.objects.clone(),
+            ids: self.ids.clone(),
+            next_id: self.next_id,
+            settings: self.settings,
+            environment: self.environment.clone(),
+            environment_lighting: self.environment_lighting,
+            ambient_light: self.ambient_light,
+            non_finite_count: AtomicUsize::new(self.non_finite_count()),
+            bvh: self.bvh.clone(),
+            bvh_current: self.bvh_current
+        }
+    }
 }
 
 impl World {
     pub fn new(light: Option<PointLight>, objects: Vec<BoxShape>) -> Self {
-        World { light, objects }
+        let mut world = World {
+            light,
+            objects: vec![],
+            ids: vec![],
+            next_id: 0,
+            settings: RenderSettings::default(), 
+            environment: None, 
+            environment_lighting: false,
+            ambient_light: BLACK,
+            non_finite_count: AtomicUsize::new(0),
+            bvh: None,
+            bvh_current: false
+        };
+        world.set_objects(objects);
+        world
+    }
+
+    pub fn objects(&self) -> &[SharedShape] {
+        &self.objects
+    }
+
+    // The ids of the objects, in the same order as objects()
+    pub fn ids(&self) -> &[ObjectId] {
+        &self.ids
+    }
+
+    // Replaces all objects, which get new ids
+    pub fn set_objects(&mut self, objects: Vec<BoxShape>) {
+        self.objects.clear();
+        self.ids.clear();
+        for o in objects {
+            self.add_object(o);
+        }
+        self.bvh_current = false;
+    }
+
+    pub fn add_object(&mut self, object: BoxShape) -> ObjectId {
+        let id = ObjectId(self.next_id);
+        self.next_id = self.next_id.checked_add(1).expect("a world should have fewer than 2^32 objects");
+        self.objects.push(SharedShape::from(object));
+        self.ids.push(id);
+        self.bvh_current = false;
+        id
+    }
+
+    // The object removed, None if there is no object with the id
+    pub fn remove_object(&mut self, id: ObjectId) -> Option<SharedShape> {
+        let index = self.position(id)?;
+        self.ids.remove(index);
+        self.bvh_current = false;
+        Some(self.objects.remove(index))
+    }
+
+    pub fn object(&self, id: ObjectId) -> Option<&dyn Shape> {
+        self.position(id).map(|i| &*self.objects[i])
+    }
+
+    // For changing an object in place, e g moving it. An object shared with a clone of the
+    // world is copied first, so that the clone keeps it as it was. The bvh is out of date
+    // afterwards, until refit_bvh or build_bvh is called.
+    pub fn object_mut(&mut self, id: ObjectId) -> Option<&mut dyn Shape> {
+        let index = self.position(id)?;
+        self.bvh_current = false;
+        let object = &mut self.objects[index];
+        if SharedShape::get_mut(object).is_none() {
+            *object = SharedShape::from(object.box_clone());
+        }
+        SharedShape::get_mut(object)
+    }
+
+    fn position(&self, id: ObjectId) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
     }
 
     fn default_objects() -> Vec<BoxShape> {
-        let m = Material::new(Color::new(0.8, 1., 0.6), DEFAULT_AMBIENT, 0.7, 0.2, DEFAULT_SHININESS, None);
+        let m = Material::default().with_color(Color::new(0.8, 1., 0.6)).with_diffuse(0.7).with_specular(0.2);
         let s1 = Sphere::new_boxed(Some(m), None);
         let tr = Matrix::scaling(0.5, 0.5, 0.5);
         let s2 = Sphere::new_boxed(None, Some(tr));
@@ -34,58 +214,331 @@ impl World {
     }
 
     pub fn color_at(&self, ray: Ray) -> Color {
+        self.color_at_depth(ray, MAX_REFLECTION_DEPTH)
+    }
+
+    // Like color_at, but tells a miss apart from an object that happens to be black
+    pub fn hit_color_at(&self, ray: Ray) -> Option<Color> {
+        self.hit_color_at_depth(ray, MAX_REFLECTION_DEPTH)
+    }
+
+    // None when the ray hits nothing. With an environment a miss is shaded as a surface showing
+    // the environment, that isn't any of the objects.
+    pub fn shading_components_at(&self, ray: Ray) -> Option<ShadingComponents> {
         let xs = self.intersect(ray);
-        match xs.hit() {
-            None => BLACK,
-            Some(i) => { 
-                let comps = i.prepare_computations(ray);
-                self.shade_hit(comps)
+        if xs.hit().is_none() {
+            return self.environment.as_ref().map(|e| ShadingComponents {
+                surface: e.color_in_direction(ray.direction),
+                reflected: BLACK,
+                refracted: BLACK,
+                in_shadow: false,
+                object: None
+            });
+        }
+        xs.hit().map(|i| {
+            let comps = i.prepare_computations_with(ray, &xs);
+            let light_transmission = self.light_transmission_at(&comps);
+            let in_shadow = light_transmission != WHITE;
+            ShadingComponents {
+                surface: self.surface_color(&comps, light_transmission),
+                reflected: self.reflected_color(&comps, MAX_REFLECTION_DEPTH),
+                refracted: self.refracted_color(&comps, MAX_REFLECTION_DEPTH),
+                in_shadow,
+                object: i.object_id
             }
+        })
+    }
+
+    // Like color_at_pathtraced for a camera ray, but tells a miss apart from a black object
+    pub fn hit_color_at_pathtraced(&self, ray: Ray, rng: &mut Rng) -> Option<Color> {
+        self.intersect(ray).hit()?;
+        Some(self.color_at_pathtraced(ray, 0, rng))
+    }
+
+    // Global illumination by following one random path, average many calls for a pixel. depth is
+    // the number of bounces so far, 0 for camera rays. Diffuse surfaces bounce in a cosine
+    // weighted direction, reflective ones in the mirror direction and transparent ones go on
+    // through. The point light can't be hit by chance, so it is sampled directly at every
+    // bounce. Once a path is
+    // RUSSIAN_ROULETTE_DEPTH bounces long it is ended at random, with the surviving paths
+    // weighted up to keep the result unbiased.
+    pub fn color_at_pathtraced(&self, ray: Ray, depth: usize, rng: &mut Rng) -> Color {
+        if depth >= MAX_PATH_DEPTH {
+            return BLACK;
+        }
+        let xs = self.intersect(ray);
+        let comps = match xs.hit() {
+            Some(i) => i.prepare_computations_with(ray, &xs),
+            None => return self.background_color(ray)
+        };
+        let material = self.settings.resolve_material(comps.object.material());
+        let albedo = material.color_at_hit(comps.object, comps.point, comps.primitive.as_ref()) * material.diffuse;
+        let reflectance = if material.is_reflective() { material.reflective.unwrap() } else { BLACK };
+        let color = material.emission() + self.direct_light(&comps, albedo);
+
+        let transparency = material.transparency;
+        let throughput = albedo + reflectance + WHITE * transparency;
+        let survival = if depth < RUSSIAN_ROULETTE_DEPTH { 1. } else { throughput.max_component().min(1.) };
+        if survival <= 0. || rng.next_f64() >= survival {
+            return color;
+        }
+        let mut indirect = BLACK;
+        if albedo != BLACK {
+            let bounce = comps.spawn_ray(comps.over_point, random_cosine_hemisphere(comps.normalv, rng));
+            indirect += albedo * self.color_at_pathtraced(bounce, depth + 1, rng);
+        }
+        if reflectance != BLACK {
+            indirect += reflectance * self.color_at_pathtraced(comps.reflected_ray(), depth + 1, rng);
+        }
+        if transparency > 0. {
+            if let Some(refracted) = comps.refracted_ray() {
+                indirect += self.color_at_pathtraced(refracted, depth + 1, rng) * transparency;
+            }
+        }
+        self.check_color(color + indirect * (1. / survival), comps.object, ray)
+    }
+
+    // Diffuse light arriving directly from the point light
+    fn direct_light(&self, comps: &PrecomputedData, albedo: Color) -> Color {
+        let light = match self.light {
+            Some(light) => light,
+            None => return BLACK
+        };
+        let light_dot_normal = (light.position - comps.over_point).normalize().dot(&comps.normalv);
+        if albedo == BLACK || light_dot_normal <= 0. {
+            return BLACK;
+        }
+        albedo * light.intensity_at(comps.over_point) * light_dot_normal * self.light_transmission_at(comps)
+    }
+
+    fn color_at_depth(&self, ray: Ray, remaining: usize) -> Color {
+        self.hit_color_at_depth(ray, remaining).unwrap_or_else(|| self.background_color(ray))
+    }
+
+    pub(crate) fn background_color(&self, ray: Ray) -> Color {
+        match &self.environment {
+            Some(e) => e.color_in_direction(ray.direction),
+            None => BLACK
+        }
+    }
+
+    fn hit_color_at_depth(&self, ray: Ray, remaining: usize) -> Option<Color> {
+        let xs = self.intersect(ray);
+        xs.hit().map(|i| {
+            let comps = i.prepare_computations_with(ray, &xs);
+            self.check_color(self.shade_hit(comps, remaining), i.object, ray)
+        })
+    }
+
+    pub fn intersect(&self, ray: Ray) -> Intersections<'_> {
+        let mut xs = Intersections::empty();
+        for i in self.candidates(&ray) {
+            let mut object_xs = self.intersect_object(&self.objects[i], ray);
+            object_xs.set_object_id(self.ids[i]);
+            xs.extend(object_xs);
+        }
+        xs
+    }
+
+    // Builds a bounding volume hierarchy as set up in settings.bvh, so that rays are only
+    // tested against objects they may hit. Has to be called again after adding or removing
+    // objects, after moving them refit_bvh will do.
+    pub fn build_bvh(&mut self) {
+        let timer = PhaseTimer::start("bvh");
+        let bvh = Bvh::new(&self.objects, self.settings.bvh);
+        timer.finish(&format!("objects={} unbounded={} nodes={} split={:?} expected_cost={:.3}",
+            bvh.object_count(), bvh.unbounded_count(), bvh.node_count(), bvh.options().split, bvh.expected_cost()));
+        self.bvh = Some(bvh);
+        self.bvh_current = true;
+    }
+
+    // Updates the bvh to how the objects are now, e g for the next frame of an animation. Builds
+    // one if there is none yet, or if objects have been added or removed. Refitting stays
+    // correct whatever happened to the objects in place, an object that has become unbounded
+    // just makes the nodes above it unbounded too, but the tree gets slower the more they change.
+    pub fn refit_bvh(&mut self) {
+        let bvh = match &mut self.bvh {
+            Some(bvh) if bvh.object_count() == self.objects.len() => bvh,
+            _ => return self.build_bvh()
+        };
+        let timer = PhaseTimer::start("refit");
+        bvh.refit(&self.objects);
+        timer.finish(&format!("objects={} expected_cost={:.3}", bvh.object_count(), bvh.expected_cost()));
+        self.bvh_current = true;
+    }
+
+    pub fn has_bvh(&self) -> bool {
+        self.bvh.is_some() && self.bvh_current
+    }
+
+    // Indices of the objects that ray may hit, all of them without a bvh
+    fn candidates(&self, ray: &Ray) -> Vec<usize> {
+        match &self.bvh {
+            Some(bvh) if self.has_bvh() => bvh.candidates(ray),
+            _ => (0..self.objects.len()).collect()
         }
     }
 
-    fn intersect(&self, ray: Ray) -> Intersections {
-        let mut xs = Intersections::new(vec![]);
-        for o in self.objects.iter() {
-            xs.extend(o.intersect(ray));
+    fn intersect_object<'a>(&self, object: &'a SharedShape, ray: Ray) -> Intersections<'a> {
+        let xs = object.intersect(ray);
+        if self.settings.checks_non_finite() {
+            for i in 0..xs.len() {
+                if !xs[i].t.is_finite() {
+                    self.report_non_finite(&format!("intersection t = {}", xs[i].t), &**object, ray);
+                }
+            }
         }
         xs
     }
 
-    fn shade_hit(&self, comps: PrecomputedData) -> Color {
-        comps.object.material().lighting(
-            &*(comps.object),
-            &self.light.unwrap(), 
-            comps.point, 
-            comps.eyev, 
-            comps.normalv, 
-            self.is_shadowed(comps.over_point))
+    // Indices of the objects with materials that give back more light than they receive
+    pub fn energy_violations(&self) -> Vec<usize> {
+        (0..self.objects.len()).filter(|&i| !self.objects[i].material().is_energy_conserving()).collect()
+    }
+
+    pub fn non_finite_count(&self) -> usize {
+        self.non_finite_count.load(Ordering::Relaxed)
+    }
+
+    fn report_non_finite(&self, what: &str, object: &dyn Shape, ray: Ray) {
+        let message = format!("Non finite {} for {:?} hit by {:?}", what, object, ray);
+        if self.settings.halt_on_non_finite {
+            panic!("{}", message);
+        }
+        warn!("{}", message);
+        self.non_finite_count.fetch_add(1, Ordering::Relaxed);
     }
 
-    fn is_shadowed(&self, point: Tuple) -> bool {
+    fn check_color(&self, color: Color, object: &dyn Shape, ray: Ray) -> Color {
+        if self.settings.checks_non_finite() && !color.is_finite() {
+            self.report_non_finite(&format!("color {:?}", color), object, ray);
+        }
+        color
+    }
+
+    fn shade_hit(&self, comps: PrecomputedData, remaining: usize) -> Color {
+        let flags = self.settings.resolve_material(comps.object.material()).shading_flags();
+        // Shadows only hide diffuse and specular light, so unlit surfaces need no shadow ray
+        let light_transmission = if flags.lit { self.light_transmission_at(&comps) } else { WHITE };
+        let surface = self.surface_color(&comps, light_transmission);
+        let refracted = self.refracted_color(&comps, remaining);
+        if flags.reflective {
+            surface + self.reflected_color(&comps, remaining) + refracted
+        } else {
+            surface + refracted
+        }
+    }
+
+    pub(crate) fn surface_color(&self, comps: &PrecomputedData, light_transmission: Color) -> Color {
+        let material = self.settings.resolve_material(comps.object.material());
+        let light = self.light.unwrap();
+        let surface_color = material.color_at_hit(comps.object, comps.point, comps.primitive.as_ref());
+        let color = material.lighting_at(comps, &light, light_transmission) + surface_color * self.ambient_light;
+        match &self.environment {
+            // Swap the ambient contribution of the light for that of the environment
+            Some(e) if self.environment_lighting => {
+                let ambient_light = e.color_in_direction(comps.normalv) - light.intensity_at(comps.point);
+                color + surface_color * ambient_light * material.ambient
+            },
+            _ => color
+        }
+    }
+
+    // The reflectance is a color rather than a scalar so that metallic mirrors (gold, copper)
+    // can tint what they reflect.
+    fn reflected_color(&self, comps: &PrecomputedData, remaining: usize) -> Color {
+        let material = self.settings.resolve_material(comps.object.material());
+        if remaining == 0 || !material.is_reflective() {
+            return BLACK;
+        }
+        self.color_at_depth(comps.reflected_ray(), remaining - 1) * material.reflective.unwrap()
+    }
+
+    // What is seen through a transparent surface, bent by the change of refractive index. For
+    // rays of a single wavelength the bend depends on it, which is what splits white light into
+    // its colors in a prism. Shares the depth limit with reflections.
+    fn refracted_color(&self, comps: &PrecomputedData, remaining: usize) -> Color {
+        let transparency = self.settings.resolve_material(comps.object.material()).transparency;
+        if remaining == 0 || transparency == 0. {
+            return BLACK;
+        }
+        match comps.refracted_ray() {
+            Some(r) => self.color_at_depth(r, remaining - 1) * transparency,
+            None => BLACK
+        }
+    }
+
+    // Shadow rays are cast at the same time as the ray that found point, so that moving objects
+    // cast their shadows from where they are at that moment. Whether no light at all reaches
+    // point.
+    pub fn is_shadowed(&self, point: Tuple, time: f64) -> bool {
+        self.light_transmission(point, time) == BLACK
+    }
+
+    // The part of the light, per channel, that reaches point. Each transparent object in the way
+    // lets its transparency through, tinted by its color, however far the light travels in it.
+    pub(crate) fn light_transmission(&self, point: Tuple, time: f64) -> Color {
         let v = self.light.unwrap().position - point;
         let distance = v.magnitude();
-        let direction = v.normalize();
-        let r = Ray::new(point, direction);
-        let intersections = self.intersect(r);
-        let h = intersections.hit();
-        h != None && h.unwrap().t < distance
+        let r = Ray::new(point, v).with_unit_direction().with_time(time);
+        let mut transmission = WHITE;
+        // An opaque object in the way will do, so there is no need to look further than the first
+        let mut blocks_light = |i: usize| {
+            let object = &self.objects[i];
+            if !object.material().casts_shadow || !self.occludes(object, r, distance) {
+                return false;
+            }
+            let material = self.settings.resolve_material(object.material());
+            transmission *= material.color * material.transparency;
+            transmission == BLACK
+        };
+        let blocked = match &self.bvh {
+            Some(bvh) if self.has_bvh() => bvh.any_candidate(&r, &mut blocks_light),
+            _ => (0..self.objects.len()).any(&mut blocks_light)
+        };
+        if blocked { BLACK } else { transmission }
+    }
+
+    // WHITE for objects that don't receive shadows
+    pub(crate) fn light_transmission_at(&self, comps: &PrecomputedData) -> Color {
+        if comps.object.material().receives_shadow {
+            self.light_transmission(comps.over_point, comps.time)
+        } else {
+            WHITE
+        }
+    }
+
+    // Goes through all the intersections when they are to be checked for non finite values
+    fn occludes(&self, object: &SharedShape, ray: Ray, distance: f64) -> bool {
+        if self.settings.checks_non_finite() {
+            self.intersect_object(object, ray).hit().is_some_and(|h| h.t < distance)
+        } else {
+            object.occludes(ray, distance)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::angle::rad;
+    use std::f64::consts::PI;
     use crate::tuple::ORIGO;
-    use crate::material::{DEFAULT_DIFFUSE, DEFAULT_SPECULAR};
+    use crate::color::{RED, BLUE};
+    use crate::approx_eq;
+    use crate::material::{DEFAULT_AMBIENT, DEFAULT_DIFFUSE, DEFAULT_SHININESS, DEFAULT_SPECULAR};
     use crate::intersection::Intersection;
+    use crate::plane::Plane;
+    use crate::pattern::{GradientPattern, StripePattern};
+    use crate::spectral::{REFERENCE_WAVELENGTH, SF11_GLASS};
+    use crate::debug::DebugShape;
 
     #[test]
     fn empty_world()
     {
         let w = World::new(None, vec![]);
 
-        assert_eq!(w.objects.len(), 0);
+        assert_eq!(w.objects().len(), 0);
         assert_eq!(w.light, None);
     }
 
@@ -96,12 +549,12 @@ mod tests {
         let w = World::default_world();
         assert_eq!(w.light.unwrap(), light);
 
-        let m = w.objects[0].material();
+        let m = w.objects()[0].material();
         assert_eq!(m.color, Color::new(0.8, 1., 0.6));
         assert_eq!(m.diffuse, 0.7);
         assert_eq!(m.specular, 0.2);
 
-        let tr = w.objects[1].transformation();
+        let tr = w.objects()[1].transformation();
         assert_eq!(tr, Matrix::scaling(0.5, 0.5, 0.5));
     }
 
@@ -122,10 +575,10 @@ mod tests {
     fn shading_intersection() {
         let w = World::default_world();
         let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
-        let shape = &w.objects[0];
-        let i = Intersection::new(4., shape.clone());
+        let shape = &w.objects()[0];
+        let i = Intersection::new(4., &**shape);
         let comps = i.prepare_computations(r);
-        let c = w.shade_hit(comps);
+        let c = w.shade_hit(comps, MAX_REFLECTION_DEPTH);
 
         assert_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
     }
@@ -135,10 +588,10 @@ mod tests {
         let light = Some(PointLight::new(Tuple::point(0., 0.25, 0.), WHITE));
         let w = World::new(light, World::default_objects());
         let r = Ray::new(ORIGO, Tuple::vector(0., 0., 1.));
-        let shape = &w.objects[1];
-        let i = Intersection::new(0.5, shape.clone());
+        let shape = &w.objects()[1];
+        let i = Intersection::new(0.5, &**shape);
         let comps = i.prepare_computations(r);
-        let c = w.shade_hit(comps);
+        let c = w.shade_hit(comps, MAX_REFLECTION_DEPTH);
 
         assert_eq!(c, Color::new(0.90498, 0.90498, 0.90498));
     }
@@ -152,6 +605,263 @@ mod tests {
         assert_eq!(c, BLACK);
     }
 
+    #[test]
+    fn hit_color_when_ray_misses() {
+        let w = World::default_world();
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 1., 0.));
+
+        assert_eq!(w.hit_color_at(r), None);
+    }
+
+    #[test]
+    fn hit_color_when_ray_hits() {
+        let w = World::default_world();
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        assert_eq!(w.hit_color_at(r), Some(Color::new(0.38066, 0.47583, 0.2855)));
+    }
+
+    #[test]
+    fn objects_by_id() {
+        let mut w = World::default_world();
+        let copy = w.objects()[0].box_clone();
+        let id = w.add_object(copy);
+        let xs = w.intersect(Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)));
+
+        assert_eq!(id, ObjectId(2));
+        assert_eq!(w.ids(), &[ObjectId(0), ObjectId(1), ObjectId(2)]);
+        assert!(*w.object(id).unwrap() == *w.objects()[0]);
+        assert!(w.object(ObjectId(3)).is_none());
+        assert_eq!(xs[0].object_id.map(|i| i.number() % 2), Some(0));
+        assert_eq!(Sphere::default().intersect(Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)))[0].object_id, None);
+    }
+
+    #[test]
+    fn ids_survive_removing_objects() {
+        let mut w = World::default_world();
+        let moved = w.add_object(Sphere::new_boxed(None, Some(Matrix::translation(0., 5., 0.))));
+        let removed = w.remove_object(ObjectId(1)).unwrap();
+
+        assert_eq!(removed.transformation(), Matrix::scaling(0.5, 0.5, 0.5));
+        assert!(w.remove_object(ObjectId(1)).is_none());
+        assert!(w.object(ObjectId(1)).is_none());
+        assert_eq!(w.object(moved).unwrap().transformation(), Matrix::translation(0., 5., 0.));
+        assert_eq!(w.add_object(Sphere::new_boxed(None, None)), ObjectId(3));
+        let hit = w.shading_components_at(Ray::new(Tuple::point(0., 5., -5.), Tuple::vector(0., 0., 1.)));
+        assert_eq!(hit.unwrap().object, Some(moved));
+    }
+
+    #[test]
+    fn cloned_world_shares_objects_until_changed() {
+        let mut w = World::default_world();
+        let copy = w.clone();
+        assert!(std::ptr::addr_eq(&*w.objects()[1], &*copy.objects()[1]));
+
+        w.object_mut(ObjectId(1)).unwrap().set_transform(Matrix::translation(0., 5., 0.));
+
+        assert!(!std::ptr::addr_eq(&*w.objects()[1], &*copy.objects()[1]));
+        assert!(std::ptr::addr_eq(&*w.objects()[0], &*copy.objects()[0]));
+        assert_eq!(copy.objects()[1].transformation(), Matrix::scaling(0.5, 0.5, 0.5));
+        assert_eq!(copy.ids(), w.ids());
+    }
+
+    #[test]
+    fn shading_components_of_hit() {
+        let mut w = World::default_world();
+        w.add_object(reflective_plane(Color::new(0.5, 0.5, 0.5)));
+        let r = ray_towards_plane();
+        let sc = w.shading_components_at(r).unwrap();
+
+        assert_eq!(sc.reflected, Color::new(0.19033, 0.23791, 0.14274));
+        assert_eq!(sc.color(), w.color_at(r));
+        assert!(!sc.in_shadow);
+        assert_eq!(sc.object, Some(ObjectId(2)));
+    }
+
+    #[test]
+    fn disabled_reflections_are_not_traced() {
+        let mut w = World::default_world();
+        let shape = reflective_plane(Color::new(0.5, 0.5, 0.5));
+        w.add_object(shape.clone());
+        w.settings.disable_reflections = true;
+        let i = Intersection::new(2.0f64.sqrt(), &*shape);
+        let comps = i.prepare_computations(ray_towards_plane());
+
+        assert_eq!(w.reflected_color(&comps, MAX_REFLECTION_DEPTH), BLACK);
+    }
+
+    #[test]
+    fn clay_mode_overrides_material_when_shading() {
+        let mut w = World::default_world();
+        w.settings.clay_mode = true;
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let c = w.color_at(r);
+
+        assert_eq!(c, Color::new(0.46656, 0.46656, 0.46656));
+    }
+
+    #[test]
+    fn ambient_only_shows_flat_object_color() {
+        let mut w = World::default_world();
+        w.settings.ambient_only = true;
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        assert_eq!(w.color_at(r), Color::new(0.8, 1., 0.6));
+    }
+
+    #[test]
+    fn shading_uses_pattern_in_object_space() {
+        let stripes = StripePattern::new_boxed(RED, BLUE, None);
+        let m = Material::new(WHITE, 1., 0., 0., 200., Some(stripes));
+        let floor = Plane::new_boxed(Some(m), Some(Matrix::scaling(2., 1., 2.)));
+        let w = World::new(Some(PointLight::new(Tuple::point(0., 10., 0.), WHITE)), vec![floor]);
+        let down = |x: f64| Ray::new(Tuple::point(x, 1., 0.), Tuple::vector(0., -1., 0.));
+
+        assert_eq!(w.color_at(down(1.5)), RED);
+        assert_eq!(w.color_at(down(2.5)), BLUE);
+    }
+
+    #[test]
+    fn bvh_gives_same_colors_as_testing_every_object() {
+        let mut w = World::default_world();
+        w.add_object(Plane::new_boxed(None, Some(Matrix::translation(0., -1., 0.))));
+        let rays: Vec<Ray> = (0..20).map(|i| {
+            let x = i as f64 / 10. - 1.;
+            Ray::new(Tuple::point(x, 0.3, -5.), Tuple::vector(0., -0.1, 1.))
+        }).collect();
+        let without: Vec<Color> = rays.iter().map(|r| w.color_at(*r)).collect();
+        w.build_bvh();
+
+        assert!(w.has_bvh());
+        assert_eq!(rays.iter().map(|r| w.color_at(*r)).collect::<Vec<Color>>(), without);
+    }
+
+    #[test]
+    fn refitted_bvh_finds_moved_object() {
+        let mut w = World::default_world();
+        w.build_bvh();
+        let r = Ray::new(Tuple::point(0., 5., -5.), Tuple::vector(0., 0., 1.));
+        assert_eq!(w.intersect(r).len(), 0);
+
+        w.object_mut(ObjectId(1)).unwrap().set_transform(Matrix::translation(0., 5., 0.) * Matrix::scaling(0.5, 0.5, 0.5));
+        w.refit_bvh();
+        assert!(w.has_bvh());
+        assert_eq!(w.intersect(r).len(), 2);
+    }
+
+    #[test]
+    fn bvh_is_ignored_once_objects_change() {
+        let mut w = World::default_world();
+        w.build_bvh();
+        w.add_object(Sphere::new_boxed(None, Some(Matrix::translation(0., 0., -3.))));
+
+        assert!(!w.has_bvh());
+        assert_eq!(w.intersect(Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.))).len(), 6);
+    }
+
+    #[test]
+    fn bvh_is_ignored_once_object_is_replaced() {
+        let mut w = World::default_world();
+        w.build_bvh();
+        let r = Ray::new(Tuple::point(0., 5., -5.), Tuple::vector(0., 0., 1.));
+        w.remove_object(ObjectId(1));
+        w.add_object(Sphere::new_boxed(None, Some(Matrix::translation(0., 5., 0.))));
+
+        assert!(!w.has_bvh());
+        assert_eq!(w.intersect(r).len(), 2);
+        assert!(!w.is_shadowed(Tuple::point(0., 5., -5.), 0.));
+        w.light = Some(PointLight::new(Tuple::point(0., 5., 5.), WHITE));
+        assert!(w.is_shadowed(Tuple::point(0., 5., -5.), 0.));
+        w.refit_bvh();
+        assert!(w.has_bvh());
+        assert_eq!(w.intersect(r).len(), 2);
+    }
+
+    fn world_with_squashed_sphere_at(y: f64) -> World {
+        let light = Some(PointLight::new(Tuple::point(0., 10., 0.), WHITE));
+        let s = Sphere::new_boxed(None, Some(Matrix::translation(0., y, 0.) * Matrix::scaling(3., 0.2, 3.)));
+        World::new(light, vec![s])
+    }
+
+    #[test]
+    fn non_uniformly_scaled_occluder_between_point_and_light_casts_shadow() {
+        let w = world_with_squashed_sphere_at(9.);
+
+        assert!(w.is_shadowed(ORIGO, 0.));
+    }
+
+    #[test]
+    fn non_uniformly_scaled_object_beyond_light_casts_no_shadow() {
+        // In object space the ray direction is 5 times longer, so comparing object space
+        // distances with the distance to the light would put the sphere in front of the light
+        let w = world_with_squashed_sphere_at(10.5);
+
+        assert!(!w.is_shadowed(ORIGO, 0.));
+    }
+
+    #[test]
+    fn t_of_scaled_object_is_world_distance_for_unit_ray() {
+        let w = world_with_squashed_sphere_at(9.);
+        let r = Ray::new(ORIGO, Tuple::vector(0., 2., 0.)).with_unit_direction();
+        let xs = w.intersect(r);
+
+        assert!(approx_eq(xs.hit().unwrap().t, 8.8));
+    }
+
+    #[test]
+    fn shading_components_of_miss() {
+        let w = World::default_world();
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 1., 0.));
+
+        assert_eq!(w.shading_components_at(r), None);
+    }
+
+    #[test]
+    fn shading_components_of_miss_show_environment() {
+        let mut w = World::default_world();
+        w.environment = Some(Environment::SkyGradient { horizon: WHITE, zenith: BLUE });
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 1., 0.));
+        let sc = w.shading_components_at(r).unwrap();
+
+        assert_eq!(sc.color(), BLUE);
+        assert_eq!(sc.object, None);
+    }
+
+    #[test]
+    fn color_when_ray_misses_shows_environment() {
+        let mut w = World::default_world();
+        w.environment = Some(Environment::SkyGradient { horizon: WHITE, zenith: BLUE });
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 1., 0.));
+
+        assert_eq!(w.color_at(r), BLUE);
+        assert_eq!(w.hit_color_at(r), None);
+    }
+
+    #[test]
+    fn reflection_of_environment() {
+        let mut w = World::new(World::default_world().light, vec![reflective_plane(WHITE)]);
+        w.environment = Some(Environment::SkyGradient { horizon: BLACK, zenith: BLUE });
+        let mut without_sky = w.clone();
+        without_sky.environment = None;
+        let without_sky = without_sky.color_at(ray_towards_plane());
+        let reflected = w.color_at(ray_towards_plane()) - without_sky;
+
+        assert_eq!(reflected, Color::new(0., 0., 2.0f64.sqrt() / 2.));
+    }
+
+    #[test]
+    fn environment_replaces_ambient_light() {
+        let mut w = World::default_world();
+        w.environment = Some(Environment::SkyGradient { horizon: BLACK, zenith: BLACK });
+        w.environment_lighting = true;
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        assert_eq!(w.color_at(r), Color::new(0.30066, 0.37583, 0.2255));
+
+        w.environment = Some(Environment::SkyGradient { horizon: WHITE, zenith: WHITE });
+        assert_eq!(w.color_at(r), Color::new(0.38066, 0.47583, 0.2855));
+    }
+
     #[test]
     fn color_when_ray_hits() {
         let w = World::default_world();
@@ -181,12 +891,28 @@ mod tests {
         assert_eq!(c, color);
     }
 
+    #[test]
+    fn unlit_surface_casts_no_shadow_ray() {
+        let occluder = DebugShape::new(None, Some(Matrix::translation(0., 0., 10.)));
+        let unlit = Material::new(WHITE, 1., 0., 0., DEFAULT_SHININESS, None);
+        let mut w = World::default_world();
+        w.add_object(occluder.box_clone());
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        w.color_at(r);
+        let lit_rays = occluder.received_rays().len();
+        w.object_mut(ObjectId(0)).unwrap().set_material(unlit);
+        w.color_at(r);
+
+        assert_eq!(lit_rays, 2);
+        assert_eq!(occluder.received_rays().len(), 3);
+    }
+
     #[test]
     fn no_shadow_when_nothing_collinear_with_point_and_light() {
         let w = World::default_world();
         let p = Tuple::point(0., 10., 0.);
 
-        assert!(!w.is_shadowed(p));
+        assert!(!w.is_shadowed(p, 0.));
     }
 
     #[test]
@@ -194,7 +920,7 @@ mod tests {
         let w = World::default_world();
         let p = Tuple::point(10., -10., 10.);
 
-        assert!(w.is_shadowed(p));
+        assert!(w.is_shadowed(p, 0.));
     }
 
     #[test]
@@ -202,7 +928,7 @@ mod tests {
         let w = World::default_world();
         let p = Tuple::point(-20., 20., -20.);
 
-        assert!(!w.is_shadowed(p));
+        assert!(!w.is_shadowed(p, 0.));
     }
 
     #[test]
@@ -210,7 +936,59 @@ mod tests {
         let w = World::default_world();
         let p = Tuple::point(-2., 2., -2.);
 
-        assert!(!w.is_shadowed(p));
+        assert!(!w.is_shadowed(p, 0.));
+    }
+
+    #[test]
+    fn shadow_ray_stops_at_first_occluder() {
+        let light = Some(PointLight::new(Tuple::point(0., 0., -10.), WHITE));
+        let first = DebugShape::new(None, None).with_hits(&[-1., 5.]);
+        let second = DebugShape::new(None, None).with_hits(&[5.]);
+        let w = World::new(light, vec![first.box_clone(), second.box_clone()]);
+
+        assert!(w.is_shadowed(ORIGO, 0.));
+        assert_eq!(first.received_rays().len(), 1);
+        assert_eq!(second.received_rays().len(), 0);
+    }
+
+    #[test]
+    fn objects_can_opt_out_of_casting_and_receiving_shadows() {
+        let mut w = World::default_world();
+        let p = Tuple::point(10., -10., 10.);
+        let shadowed = w.is_shadowed(p, 0.);
+        for id in w.ids().to_vec() {
+            let o = w.object_mut(id).unwrap();
+            let m = o.material().clone().with_casts_shadow(false);
+            o.set_material(m);
+        }
+
+        assert!(shadowed);
+        assert!(!w.is_shadowed(p, 0.));
+
+        let floor = Plane::new_boxed(Some(Material::default().with_receives_shadow(false)), Some(Matrix::translation(0., -1., 0.)));
+        let w = World::new(Some(PointLight::new(Tuple::point(0., 10., 0.), WHITE)), vec![Sphere::new_boxed(None, None), floor]);
+        let down = Ray::new(ORIGO, Tuple::vector(0., -1., 0.));
+        let comps = Intersection::new(1., &*w.objects()[1]).prepare_computations(down);
+
+        assert!(w.is_shadowed(comps.over_point, 0.));
+        assert_eq!(w.light_transmission_at(&comps), WHITE);
+    }
+
+    #[test]
+    fn transparent_occluders_tint_the_light() {
+        let light = Some(PointLight::new(Tuple::point(0., 10., 0.), WHITE));
+        let glass = |color: Color, y: f64| Sphere::new_boxed(Some(Material::default().with_color(color).with_transparency(0.5)),
+            Some(Matrix::translation(0., y, 0.)));
+        let mut w = World::new(light, vec![glass(Color::new(1., 0.5, 0.), 3.)]);
+
+        assert_eq!(w.light_transmission(ORIGO, 0.), Color::new(0.5, 0.25, 0.));
+        assert!(!w.is_shadowed(ORIGO, 0.));
+        w.add_object(glass(Color::new(1., 1., 0.5), 6.));
+        assert_eq!(w.light_transmission(ORIGO, 0.), Color::new(0.25, 0.125, 0.));
+        w.add_object(Sphere::new_boxed(None, Some(Matrix::translation(0., 8., 0.))));
+        w.build_bvh();
+        assert_eq!(w.light_transmission(ORIGO, 0.), BLACK);
+        assert!(w.is_shadowed(ORIGO, 0.));
     }
 
     #[test]
@@ -223,10 +1001,356 @@ mod tests {
         let w = World::new(Some(light), vec![s1, s2.clone()]);
 
         let r = Ray::new(Tuple::point(0., 0., 5.), Tuple::vector(0., 0., 1.));
-        let i = Intersection::new(4., s2);
+        let i = Intersection::new(4., &*s2);
         let comps = i.prepare_computations(r);
-        let c = w.shade_hit(comps);
+        let c = w.shade_hit(comps, MAX_REFLECTION_DEPTH);
 
         assert_eq!(c, Color::new(0.1, 0.1, 0.1));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ambient_light_lifts_shadows_by_surface_color() {
+        let light = PointLight::new(Tuple::point(0., 0., -10.), WHITE);
+        let m = Material::default().with_color(Color::new(1., 0.5, 0.)).with_ambient(0.);
+        let s1 = Sphere::default_boxed();
+        let s2 = Sphere::new_boxed(Some(m), Some(Matrix::translation(0., 0., 10.)));
+        let mut w = World::new(Some(light), vec![s1, s2.clone()]);
+        w.ambient_light = Color::new(0.2, 0.2, 0.2);
+
+        let r = Ray::new(Tuple::point(0., 0., 5.), Tuple::vector(0., 0., 1.));
+        let comps = Intersection::new(4., &*s2).prepare_computations(r);
+
+        assert_eq!(w.shade_hit(comps, MAX_REFLECTION_DEPTH), Color::new(0.2, 0.1, 0.));
+    }
+
+    fn reflective_plane(reflective: Color) -> BoxShape {
+        let m = Material { reflective: Some(reflective), ..Material::default() };
+        Plane::new_boxed(Some(m), Some(Matrix::translation(0., -1., 0.)))
+    }
+
+    fn ray_towards_plane() -> Ray {
+        let pv = 2.0f64.sqrt() / 2.0;
+        Ray::new(Tuple::point(0., 0., -3.), Tuple::vector(0., -pv, pv))
+    }
+
+    #[test]
+    fn reflected_color_for_nonreflective_material() {
+        let w = World::default_world();
+        let r = Ray::new(ORIGO, Tuple::vector(0., 0., 1.));
+        let i = Intersection::new(1., &*w.objects()[1]);
+        let comps = i.prepare_computations(r);
+
+        assert_eq!(w.reflected_color(&comps, MAX_REFLECTION_DEPTH), BLACK);
+    }
+
+    #[test]
+    fn reflected_color_for_reflective_material() {
+        let mut w = World::default_world();
+        let shape = reflective_plane(Color::new(0.5, 0.5, 0.5));
+        w.add_object(shape.clone());
+        let i = Intersection::new(2.0f64.sqrt(), &*shape);
+        let comps = i.prepare_computations(ray_towards_plane());
+
+        assert_eq!(w.reflected_color(&comps, MAX_REFLECTION_DEPTH), Color::new(0.19033, 0.23791, 0.14274));
+    }
+
+    #[test]
+    fn shade_hit_with_reflective_material() {
+        let mut w = World::default_world();
+        let shape = reflective_plane(Color::new(0.5, 0.5, 0.5));
+        w.add_object(shape.clone());
+        let i = Intersection::new(2.0f64.sqrt(), &*shape);
+        let comps = i.prepare_computations(ray_towards_plane());
+
+        assert_eq!(w.shade_hit(comps, MAX_REFLECTION_DEPTH), Color::new(0.87676, 0.92434, 0.82917));
+    }
+
+    #[test]
+    fn tinted_reflection_filters_each_channel() {
+        let mut w = World::default_world();
+        let shape = reflective_plane(Color::new(1., 0.5, 0.));
+        w.add_object(shape.clone());
+        let i = Intersection::new(2.0f64.sqrt(), &*shape);
+        let comps = i.prepare_computations(ray_towards_plane());
+
+        assert_eq!(w.reflected_color(&comps, MAX_REFLECTION_DEPTH), Color::new(0.38066, 0.23791, 0.));
+    }
+
+    #[test]
+    fn color_at_with_mutually_reflective_surfaces() {
+        let m = Material { reflective: Some(WHITE), ..Material::default() };
+        let lower = Plane::new_boxed(Some(m.clone()), Some(Matrix::translation(0., -1., 0.)));
+        let upper = Plane::new_boxed(Some(m), Some(Matrix::translation(0., 1., 0.)));
+        let light = Some(PointLight::new(ORIGO, WHITE));
+        let w = World::new(light, vec![lower, upper]);
+        let r = Ray::new(ORIGO, Tuple::vector(0., 1., 0.));
+
+        // Terminating at all is the point of this test
+        w.color_at(r);
+    }
+
+    #[test]
+    fn reflected_color_at_maximum_recursive_depth() {
+        let mut w = World::default_world();
+        let shape = reflective_plane(Color::new(0.5, 0.5, 0.5));
+        w.add_object(shape.clone());
+        let i = Intersection::new(2.0f64.sqrt(), &*shape);
+        let comps = i.prepare_computations(ray_towards_plane());
+
+        assert_eq!(w.reflected_color(&comps, 0), BLACK);
+    }
+
+    #[test]
+    fn shadow_of_moving_object_follows_time() {
+        let s = Sphere::new(None, None).with_motion(Matrix::translation(10., 0., 0.));
+        let w = World::new(Some(PointLight::new(Tuple::point(0., 10., 0.), WHITE)), vec![Box::new(s)]);
+        let p = Tuple::point(0., -5., 0.);
+
+        assert!(w.is_shadowed(p, 0.));
+        assert!(!w.is_shadowed(p, 1.));
+    }
+
+    fn scaled_default_world(k: f64) -> World {
+        let m = World::default_objects()[0].material().clone();
+        let s1 = Sphere::new_boxed(Some(m), Some(Matrix::scaling(k, k, k)));
+        let s2 = Sphere::new_boxed(None, Some(Matrix::scaling(0.5 * k, 0.5 * k, 0.5 * k)));
+        let light = Some(PointLight::new(Tuple::point(-10. * k, 10. * k, -10. * k), WHITE));
+        World::new(light, vec![s1, s2])
+    }
+
+    #[test]
+    fn shading_is_independent_of_scene_scale() {
+        for &k in &[1e-4, 1., 1e6] {
+            let w = scaled_default_world(k);
+            let r = Ray::new(Tuple::point(0., 0., -5. * k), Tuple::vector(0., 0., 1.));
+
+            assert_eq!(w.color_at(r), Color::new(0.38066, 0.47583, 0.2855), "scale {}", k);
+        }
+    }
+
+    #[test]
+    fn shadows_are_independent_of_scene_scale() {
+        for &k in &[1e-4, 1., 1e6] {
+            let light = PointLight::new(Tuple::point(0., 0., -10. * k), WHITE);
+            let s1 = Sphere::new_boxed(None, Some(Matrix::scaling(k, k, k)));
+            let s2 = Sphere::new_boxed(None, Some(Matrix::translation(0., 0., 10. * k) * Matrix::scaling(k, k, k)));
+            let w = World::new(Some(light), vec![s1, s2]);
+            let r = Ray::new(Tuple::point(0., 0., 5. * k), Tuple::vector(0., 0., 1.));
+
+            assert_eq!(w.color_at(r), Color::new(0.1, 0.1, 0.1), "scale {}", k);
+        }
+    }
+
+    #[test]
+    fn no_light_leak_past_thin_occluder_in_tiny_scene() {
+        for &k in &[1e-4, 1.] {
+            let floor = Plane::new_boxed(None, Some(Matrix::scaling(k, k, k)));
+            let occluder = Sphere::new_boxed(None, Some(Matrix::translation(0., 0.05 * k, 0.) * Matrix::scaling(0.5 * k, 0.01 * k, 0.5 * k)));
+            let light = PointLight::new(Tuple::point(0., 10. * k, 0.), WHITE);
+            let w = World::new(Some(light), vec![floor, occluder]);
+            let r = Ray::new(Tuple::point(0.1 * k, 0.02 * k, 0.), Tuple::vector(0., -1., 0.));
+
+            assert_eq!(w.color_at(r), Color::new(0.1, 0.1, 0.1), "scale {}", k);
+        }
+    }
+
+    #[test]
+    fn no_acne_on_huge_floor_far_from_origin() {
+        for &k in &[1., 1e6] {
+            let floor = Plane::new_boxed(None, Some(Matrix::scaling(k, k, k)));
+            let light = PointLight::new(Tuple::point(1000. * k, 10. * k, 1000. * k), WHITE);
+            let w = World::new(Some(light), vec![floor]);
+            let r = Ray::new(Tuple::point(1000. * k, 1. * k, 1000. * k), Tuple::vector(0., -1., 0.));
+            let expected = Color::new(1., 1., 1.) * (DEFAULT_AMBIENT + DEFAULT_DIFFUSE) + Color::new(DEFAULT_SPECULAR, DEFAULT_SPECULAR, DEFAULT_SPECULAR);
+
+            assert_eq!(w.color_at(r), expected, "scale {}", k);
+        }
+    }
+
+    fn average_pathtraced(w: &World, r: Ray, samples: u64) -> Color {
+        let mut rng = Rng::new(7);
+        let total = (0..samples).fold(BLACK, |c, _| c + w.color_at_pathtraced(r, 0, &mut rng));
+        total * (1. / samples as f64)
+    }
+
+    #[test]
+    fn pathtraced_miss_is_background() {
+        let mut w = World::default_world();
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 1., 0.));
+
+        assert_eq!(w.color_at_pathtraced(r, 0, &mut Rng::new(1)), BLACK);
+        w.environment = Some(Environment::SkyGradient { horizon: WHITE, zenith: BLUE });
+        assert_eq!(w.color_at_pathtraced(r, 0, &mut Rng::new(1)), BLUE);
+    }
+
+    #[test]
+    fn emissive_surface_is_seen_without_light() {
+        let m = Material { emissive: Some(Color::new(0.5, 0.5, 0.)), diffuse: 0., ..Material::default() };
+        let w = World::new(None, vec![Sphere::new_boxed(Some(m), None)]);
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+
+        assert_eq!(w.color_at_pathtraced(r, 0, &mut Rng::new(1)), Color::new(0.5, 0.5, 0.));
+    }
+
+    #[test]
+    fn pathtraced_mirror_reflects_emissive_surface() {
+        let glow = Material { emissive: Some(RED), diffuse: 0., ..Material::default() };
+        let mirror = Material { reflective: Some(WHITE), diffuse: 0., ..Material::default() };
+        let w = World::new(None, vec![
+            Plane::new_boxed(Some(mirror), Some(Matrix::translation(0., -1., 0.))),
+            Plane::new_boxed(Some(glow), Some(Matrix::translation(0., 0., 10.) * Matrix::rotation_x(rad(PI / 2.))))]);
+
+        assert_eq!(w.color_at_pathtraced(ray_towards_plane(), 0, &mut Rng::new(1)), RED);
+    }
+
+    #[test]
+    fn white_furnace_converges_to_geometric_series() {
+        // Inside a closed sphere that emits 1 and reflects half of the light diffusely, the light
+        // bouncing around adds up to 1 + 1/2 + 1/4 + ... = 2
+        let m = Material { color: WHITE, diffuse: 0.5, emissive: Some(WHITE), ..Material::default() };
+        let w = World::new(None, vec![Sphere::new_boxed(Some(m), None)]);
+        let r = Ray::new(ORIGO, Tuple::vector(0., 0., 1.));
+        let c = average_pathtraced(&w, r, 4000);
+
+        assert!((c.r - 2.).abs() < 0.1, "{:?}", c);
+    }
+
+    #[test]
+    fn pathtraced_direct_light_matches_diffuse_lighting() {
+        let m = Material { color: WHITE, diffuse: 0.5, ..Material::default() };
+        let light = Some(PointLight::new(Tuple::point(0., 10., 0.), WHITE));
+        let w = World::new(light, vec![Plane::new_boxed(Some(m), None)]);
+        let r = Ray::new(Tuple::point(0., 1., 0.), Tuple::vector(0., -1., 0.));
+
+        assert_eq!(w.color_at_pathtraced(r, 0, &mut Rng::new(1)), Color::new(0.5, 0.5, 0.5));
+    }
+
+    fn world_with_nan_light(settings: RenderSettings) -> World {
+        let mut w = World::default_world();
+        w.light = Some(PointLight::new(Tuple::point(-10., 10., -10.), Color::new(f64::NAN, 1., 1.)));
+        w.settings = settings;
+        w
+    }
+
+    fn glass(transparency: f64, refractive_index: f64) -> Material {
+        Material::default().with_transparency(transparency).with_refractive_index(refractive_index)
+    }
+
+    #[test]
+    fn refracted_color_with_opaque_surface() {
+        let w = World::default_world();
+        let shape = &*w.objects()[0];
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let xs = Intersections::new(vec![Intersection::new(4., shape), Intersection::new(6., shape)]);
+        let comps = xs[0].prepare_computations_with(r, &xs);
+
+        assert_eq!(w.refracted_color(&comps, 5), BLACK);
+    }
+
+    #[test]
+    fn refracted_color_at_maximum_depth() {
+        let mut w = World::default_world();
+        let id = w.ids()[0];
+        w.object_mut(id).unwrap().set_material(glass(1., 1.5));
+        let shape = &*w.objects()[0];
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let xs = Intersections::new(vec![Intersection::new(4., shape), Intersection::new(6., shape)]);
+        let comps = xs[0].prepare_computations_with(r, &xs);
+
+        assert_eq!(w.refracted_color(&comps, 0), BLACK);
+    }
+
+    #[test]
+    fn refracted_color_under_total_internal_reflection() {
+        let mut w = World::default_world();
+        let id = w.ids()[0];
+        w.object_mut(id).unwrap().set_material(glass(1., 1.5));
+        let shape = &*w.objects()[0];
+        let pv = 2.0f64.sqrt() / 2.;
+        let r = Ray::new(Tuple::point(0., 0., pv), Tuple::vector(0., 1., 0.));
+        let xs = Intersections::new(vec![Intersection::new(-pv, shape), Intersection::new(pv, shape)]);
+        let comps = xs[1].prepare_computations_with(r, &xs);
+
+        assert_eq!(w.refracted_color(&comps, 5), BLACK);
+    }
+
+    #[test]
+    fn shade_hit_with_transparent_material() {
+        let mut w = World::default_world();
+        let floor = Plane::new_boxed(Some(glass(0.5, 1.5)), Some(Matrix::translation(0., -1., 0.)));
+        // Unlit, so that it shows the same ambient color whether the floor shadows it or lets
+        // half of the light through
+        let ball = Material::new(RED, 0.5, 0., 0., DEFAULT_SHININESS, None);
+        w.add_object(floor.clone());
+        w.add_object(Sphere::new_boxed(Some(ball), Some(Matrix::translation(0., -3.5, -0.5))));
+        let xs = Intersections::new(vec![Intersection::new(2.0f64.sqrt(), &*floor)]);
+        let comps = xs[0].prepare_computations_with(ray_towards_plane(), &xs);
+
+        assert_eq!(w.shade_hit(comps, 5), Color::new(0.93642, 0.68642, 0.68642));
+    }
+
+    // The color at 450 and 650 nm of a ray through the side of a ball of glass, in front of a
+    // wall that goes from black to white left to right
+    fn colors_through_ball(glass: Material) -> (Color, Color) {
+        let gradient = GradientPattern::new_boxed(BLACK, WHITE, Some(Matrix::translation(-2., 0., 0.) * Matrix::scaling(4., 1., 1.)));
+        let wall = Material::new(WHITE, 1., 0., 0., DEFAULT_SHININESS, Some(gradient));
+        let wall_transform = Matrix::translation(0., 0., 5.) * Matrix::rotation_x(rad(PI / 2.));
+        let glass = Material { ambient: 0., diffuse: 0., specular: 0., ..glass };
+        let w = World::new(World::default_world().light, vec![
+            Plane::new_boxed(Some(wall), Some(wall_transform)),
+            Sphere::new_boxed(Some(glass), None)]);
+        let r = Ray::new(Tuple::point(0.5, 0., -5.), Tuple::vector(0., 0., 1.));
+
+        (w.color_at(r.with_wavelength(450.)), w.color_at(r.with_wavelength(650.)))
+    }
+
+    #[test]
+    fn dispersive_glass_bends_wavelengths_apart() {
+        let (blue, red) = colors_through_ball(Material::default().with_transparency(1.).with_dispersion(SF11_GLASS));
+        let (plain_blue, plain_red) = colors_through_ball(glass(1., SF11_GLASS.at(REFERENCE_WAVELENGTH)));
+
+        assert!((blue.r - red.r).abs() > 0.01, "{:?} and {:?}", blue, red);
+        assert_eq!(plain_blue, plain_red);
+        assert!(plain_blue != BLACK);
+    }
+
+    #[test]
+    fn objects_with_too_bright_materials() {
+        let mut w = World::default_world();
+        let mirror = Material { reflective: Some(WHITE), ..Material::default() };
+        w.add_object(Sphere::new_boxed(Some(mirror), None));
+
+        assert_eq!(w.energy_violations(), vec![2]);
+    }
+
+    #[test]
+    fn non_finite_color_is_counted_when_detecting() {
+        let r = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let quiet = world_with_nan_light(RenderSettings::default());
+        let detecting = world_with_nan_light(RenderSettings { detect_non_finite: true, ..RenderSettings::default() });
+        quiet.color_at(r);
+        detecting.color_at(r);
+
+        assert_eq!(quiet.non_finite_count(), 0);
+        assert_eq!(detecting.non_finite_count(), 1);
+    }
+
+    #[test]
+    fn non_finite_intersection_is_counted_when_detecting() {
+        // So thin that the object space ray overflows
+        let sliver = Sphere::new_boxed(None, Some(Matrix::scaling(1e-160, 1., 1.)));
+        let mut w = World::new(World::default_world().light, vec![sliver]);
+        w.settings.detect_non_finite = true;
+        let r = Ray::new(Tuple::point(-5., 0., 0.), Tuple::vector(1., 0., 0.));
+
+        assert!(w.hit_color_at(r).is_none());
+        assert_eq!(w.non_finite_count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Non finite color")]
+    fn halt_on_non_finite_panics() {
+        let w = world_with_nan_light(RenderSettings { halt_on_non_finite: true, ..RenderSettings::default() });
+        w.color_at(Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.)));
+    }
+}